@@ -0,0 +1,490 @@
+/*
+  Basic-block control-flow graph of a Lua 5.1 function prototype
+*/
+
+use crate::parser::bytecode::{FunctionPrototype, Instruction, Opcode};
+use std::collections::BTreeSet;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// How control gets from one block to the next
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum EdgeKind {
+    Fallthrough, // Execution continues with the following instruction
+    Taken,       // A jump, or a skip over the following instruction
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Edge {
+    pub from: usize, // Block indices
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions entered only at its first one and left only after its last one
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct BasicBlock {
+    pub start: usize, // First pc
+    pub end: usize,   // One past the last pc
+    pub successors: Vec<Edge>,
+    pub predecessors: Vec<Edge>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>, // Ordered by pc, the entry block first
+    pub edges: Vec<Edge>,
+    block_of: Vec<usize>, // Block index of each pc
+}
+
+/// Immediate (post-)dominator of every block, `None` for the root and unreachable blocks
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Dominators {
+    idom: Vec<Option<usize>>,
+    reachable: Vec<bool>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl ControlFlowGraph {
+    pub fn new(proto: &FunctionPrototype) -> Self {
+        let code = &proto.code;
+
+        let mut leaders = BTreeSet::new();
+        if !code.is_empty() {
+            leaders.insert(0);
+        }
+        for (pc, instr) in code.iter().enumerate() {
+            if proto.is_pseudo_instruction(pc) {
+                continue;
+            }
+            let (targets, terminates) = branches(pc, instr);
+            if terminates {
+                leaders.extend(targets.into_iter().map(|(target, _)| target));
+                leaders.insert(pc + 1);
+            }
+        }
+        leaders.retain(|&pc| pc < code.len());
+
+        let starts: Vec<usize> = leaders.into_iter().collect();
+        let mut block_of = vec![0; code.len()];
+        let mut blocks = Vec::with_capacity(starts.len());
+        for (index, &start) in starts.iter().enumerate() {
+            let end = starts.get(index + 1).copied().unwrap_or(code.len());
+            block_of[start..end].fill(index);
+            blocks.push(BasicBlock {
+                start,
+                end,
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+            });
+        }
+
+        let mut edges = Vec::new();
+        for (from, block) in blocks.iter().enumerate() {
+            let last = block.end - 1;
+            let targets = match proto.is_pseudo_instruction(last) {
+                true => vec![(last + 1, EdgeKind::Fallthrough)],
+                false => branches(last, &code[last]).0,
+            };
+            for (target, kind) in targets {
+                if target < code.len() {
+                    edges.push(Edge {
+                        from,
+                        to: block_of[target],
+                        kind,
+                    });
+                }
+            }
+        }
+        for edge in &edges {
+            blocks[edge.from].successors.push(*edge);
+            blocks[edge.to].predecessors.push(*edge);
+        }
+
+        Self {
+            blocks,
+            edges,
+            block_of,
+        }
+    }
+
+    /// Index of the block holding `pc`
+    pub fn block_at(&self, pc: usize) -> Option<usize> {
+        self.block_of.get(pc).copied()
+    }
+
+    /// Blocks ending the function (RETURN or TAILCALL), the roots of post-dominance
+    pub fn exits(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.blocks.len()).filter(|&b| self.blocks[b].successors.is_empty())
+    }
+
+    /// Blocks in reverse postorder from the entry, skipping unreachable ones
+    pub fn reverse_postorder(&self) -> Vec<usize> {
+        let successors = |b: usize| self.blocks[b].successors.iter().map(|e| e.to).collect();
+        match self.blocks.is_empty() {
+            true => Vec::new(),
+            false => reverse_postorder(self.blocks.len(), &[0], successors),
+        }
+    }
+
+    pub fn dominators(&self) -> Dominators {
+        let count = self.blocks.len();
+        let successors = |b: usize| self.blocks[b].successors.iter().map(|e| e.to).collect();
+        let predecessors = |b: usize| self.blocks[b].predecessors.iter().map(|e| e.from).collect();
+        match count {
+            0 => Dominators::empty(),
+            _ => compute_dominators(count, &[0], successors, predecessors),
+        }
+    }
+
+    /// Post-dominators, rooted at a virtual node following every exit block
+    pub fn post_dominators(&self) -> Dominators {
+        let count = self.blocks.len();
+        let exits: Vec<usize> = self.exits().collect();
+        let successors = |b: usize| self.blocks[b].predecessors.iter().map(|e| e.from).collect();
+        let predecessors = |b: usize| self.blocks[b].successors.iter().map(|e| e.to).collect();
+        compute_dominators(count, &exits, successors, predecessors)
+    }
+}
+
+impl Dominators {
+    const fn empty() -> Self {
+        Self {
+            idom: Vec::new(),
+            reachable: Vec::new(),
+        }
+    }
+
+    /// Immediate dominator of `block`
+    pub fn immediate(&self, block: usize) -> Option<usize> {
+        self.idom.get(block).copied().flatten()
+    }
+
+    /// Whether `block` is reachable from the root of the tree
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.reachable.get(block).copied().unwrap_or(false)
+    }
+
+    /// Whether every path from the root to `b` goes through `a` (a block dominates itself)
+    pub fn dominates(&self, a: usize, b: usize) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        let mut current = Some(b);
+        while let Some(block) = current {
+            if block == a {
+                return true;
+            }
+            current = self.immediate(block);
+        }
+        false
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Where control may go after the instruction at `pc`, and whether it ends its block
+fn branches(pc: usize, instr: &Instruction) -> (Vec<(usize, EdgeKind)>, bool) {
+    let jump = (pc as i64 + 1 + i64::from(instr.sbx())).max(0) as usize;
+    let next = (pc + 1, EdgeKind::Fallthrough);
+    let skip = (pc + 2, EdgeKind::Taken);
+
+    match Opcode::try_from(instr.op()) {
+        Ok(Opcode::JMP | Opcode::FORPREP) => (vec![(jump, EdgeKind::Taken)], true),
+        Ok(Opcode::FORLOOP) => (vec![next, (jump, EdgeKind::Taken)], true),
+        // lvm.c: the instruction after these is a JMP, skipped on the other outcome
+        Ok(Opcode::EQ | Opcode::LT | Opcode::LE | Opcode::TEST | Opcode::TESTSET) => {
+            (vec![next, skip], true)
+        }
+        Ok(Opcode::TFORLOOP) => (vec![next, skip], true),
+        Ok(Opcode::LOADBOOL) if instr.c() != 0 => (vec![skip], true),
+        Ok(Opcode::RETURN | Opcode::TAILCALL) => (Vec::new(), true),
+        _ => (vec![next], false),
+    }
+}
+
+/// Depth-first reverse postorder over `count` nodes from `roots`
+fn reverse_postorder(
+    count: usize,
+    roots: &[usize],
+    successors: impl Fn(usize) -> Vec<usize>,
+) -> Vec<usize> {
+    let mut visited = vec![false; count];
+    let mut order = Vec::with_capacity(count);
+    for &root in roots {
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        let mut stack = vec![(root, successors(root), 0)];
+        while let Some((node, next, index)) = stack.last_mut() {
+            match next.get(*index) {
+                Some(&succ) => {
+                    *index += 1;
+                    if !visited[succ] {
+                        visited[succ] = true;
+                        let succs = successors(succ);
+                        stack.push((succ, succs, 0));
+                    }
+                }
+                None => {
+                    order.push(*node);
+                    stack.pop();
+                }
+            }
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Iterative dominator computation (Cooper, Harvey and Kennedy). Several roots are
+/// joined under a virtual node, which is left out of the result
+fn compute_dominators(
+    count: usize,
+    roots: &[usize],
+    successors: impl Fn(usize) -> Vec<usize>,
+    predecessors: impl Fn(usize) -> Vec<usize>,
+) -> Dominators {
+    let order = reverse_postorder(count, roots, &successors);
+    let virtual_root = count;
+    let mut position = vec![usize::MAX; count + 1];
+    for (index, &node) in order.iter().enumerate() {
+        position[node] = index + 1;
+    }
+    position[virtual_root] = 0;
+
+    let mut idom: Vec<Option<usize>> = vec![None; count + 1];
+    idom[virtual_root] = Some(virtual_root);
+    for &root in roots {
+        idom[root] = Some(virtual_root);
+    }
+
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while position[a] > position[b] {
+                a = idom[a].expect("processed node");
+            }
+            while position[b] > position[a] {
+                b = idom[b].expect("processed node");
+            }
+        }
+        a
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in &order {
+            if roots.contains(&node) {
+                continue;
+            }
+            let mut new_idom = None;
+            for pred in predecessors(node) {
+                if idom[pred].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => pred,
+                    Some(current) => intersect(&idom, pred, current),
+                });
+            }
+            if new_idom.is_some() && idom[node] != new_idom {
+                idom[node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+
+    let reachable = (0..count).map(|node| idom[node].is_some()).collect();
+    let idom = (0..count)
+        .map(|node| idom[node].filter(|&d| d != virtual_root))
+        .collect();
+    Dominators { idom, reachable }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use EdgeKind::*;
+
+    fn graph(listing: &str) -> ControlFlowGraph {
+        ControlFlowGraph::new(&assemble(listing).expect("valid listing"))
+    }
+
+    fn starts(cfg: &ControlFlowGraph) -> Vec<(usize, usize)> {
+        cfg.blocks.iter().map(|b| (b.start, b.end)).collect()
+    }
+
+    fn edges(cfg: &ControlFlowGraph) -> Vec<(usize, usize, EdgeKind)> {
+        cfg.edges.iter().map(|e| (e.from, e.to, e.kind)).collect()
+    }
+
+    fn idoms(dominators: &Dominators, count: usize) -> Vec<Option<usize>> {
+        (0..count).map(|b| dominators.immediate(b)).collect()
+    }
+
+    // if c then x = c else x = c end
+    const DIAMOND: &str = r#"
+        .const "c"
+            GETGLOBAL 0 K0
+            TEST      0 0 0
+            JMP       L5
+            LOADK     1 K0
+            JMP       L6
+        L5:
+            LOADK     1 K0
+        L6:
+            RETURN    0 1
+        "#;
+
+    #[test]
+    fn if_else_diamond() {
+        let cfg = graph(DIAMOND);
+        assert_eq!(starts(&cfg), [(0, 2), (2, 3), (3, 5), (5, 6), (6, 7)]);
+        assert_eq!(
+            edges(&cfg),
+            [
+                (0, 1, Fallthrough),
+                (0, 2, Taken),
+                (1, 3, Taken),
+                (2, 4, Taken),
+                (3, 4, Fallthrough),
+            ]
+        );
+        assert_eq!(cfg.exits().collect::<Vec<_>>(), [4]);
+        assert_eq!(cfg.block_at(4), Some(2));
+        assert_eq!(cfg.block_at(7), None);
+
+        let dominators = cfg.dominators();
+        assert_eq!(
+            idoms(&dominators, 5),
+            [None, Some(0), Some(0), Some(1), Some(0)]
+        );
+        assert!(dominators.dominates(0, 3));
+        assert!(!dominators.dominates(1, 4));
+
+        let post = cfg.post_dominators();
+        assert_eq!(idoms(&post, 5), [Some(4), Some(3), Some(4), Some(4), None]);
+        assert!(post.dominates(4, 0));
+        assert!(!post.dominates(3, 0));
+    }
+
+    #[test]
+    fn while_loop_back_edge() {
+        // while c do c = c end
+        let cfg = graph(
+            r#"
+            .const "c"
+            L0:
+                GETGLOBAL 0 K0
+                TEST      0 0 0
+                JMP       L5
+                SETGLOBAL 0 K0
+                JMP       L0
+            L5:
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(starts(&cfg), [(0, 2), (2, 3), (3, 5), (5, 6)]);
+        assert_eq!(
+            edges(&cfg),
+            [
+                (0, 1, Fallthrough),
+                (0, 2, Taken),
+                (1, 3, Taken),
+                (2, 0, Taken)
+            ]
+        );
+        assert_eq!(cfg.blocks[0].predecessors, [cfg.edges[3]]);
+        assert_eq!(cfg.reverse_postorder(), [0, 2, 1, 3]);
+
+        let dominators = cfg.dominators();
+        assert_eq!(idoms(&dominators, 4), [None, Some(0), Some(0), Some(1)]);
+        // The loop header dominates the source of its back edge
+        assert!(dominators.dominates(0, 2));
+
+        let post = cfg.post_dominators();
+        assert_eq!(idoms(&post, 4), [Some(1), Some(3), Some(0), None]);
+    }
+
+    #[test]
+    fn numeric_for_edges() {
+        // for i = 1, 1 do end
+        let cfg = graph(
+            r#"
+            .const 1
+                LOADK     0 K0
+                LOADK     1 K0
+                LOADK     2 K0
+                FORPREP   0 L4
+            L4:
+                FORLOOP   0 L4
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(starts(&cfg), [(0, 4), (4, 5), (5, 6)]);
+        assert_eq!(
+            edges(&cfg),
+            [(0, 1, Taken), (1, 2, Fallthrough), (1, 1, Taken)]
+        );
+        assert!(cfg.dominators().dominates(1, 2));
+    }
+
+    #[test]
+    fn skips_and_pseudo_instructions() {
+        // LOADBOOL with C skips the next instruction, and the batch word of a
+        // SETLIST with C = 0 is data rather than a leader
+        let cfg = graph(
+            r#"
+                LOADBOOL  0 1 1
+                LOADBOOL  0 0 0
+                NEWTABLE  1 0 0
+                SETLIST   1 0 0
+                .word     1
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(starts(&cfg), [(0, 1), (1, 2), (2, 6)]);
+        assert_eq!(edges(&cfg), [(0, 2, Taken), (1, 2, Fallthrough)]);
+        assert!(!cfg.dominators().is_reachable(1));
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let cfg = graph(
+            r#"
+                JMP       L2
+                LOADNIL   0 0
+            L2:
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(starts(&cfg), [(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(edges(&cfg), [(0, 2, Taken), (1, 2, Fallthrough)]);
+        assert_eq!(cfg.reverse_postorder(), [0, 2]);
+
+        let dominators = cfg.dominators();
+        assert!(!dominators.is_reachable(1));
+        assert_eq!(dominators.immediate(1), None);
+        assert!(!dominators.dominates(0, 1));
+        assert_eq!(dominators.immediate(2), Some(0));
+
+        // Every block reaches the exit, so all of them have a post-dominator
+        let post = cfg.post_dominators();
+        assert!(post.is_reachable(1));
+        assert_eq!(idoms(&post, 3), [Some(2), Some(2), None]);
+    }
+
+    #[test]
+    fn empty_function() {
+        let cfg = graph("");
+        assert!(cfg.blocks.is_empty());
+        assert!(cfg.reverse_postorder().is_empty());
+        assert!(!cfg.dominators().is_reachable(0));
+    }
+}
@@ -0,0 +1,218 @@
+/*
+  Register dataflow of a Lua 5.1 function prototype: liveness and reaching definitions
+*/
+
+use super::cfg::ControlFlowGraph;
+use super::usage::{opcode, reads_at, writes_at};
+use crate::parser::bytecode::{FunctionPrototype, Opcode};
+use std::collections::BTreeSet;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// A set of registers, which Lua 5.1 numbers below 256
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RegisterSet([u64; 4]);
+
+/// Registers whose current value may still be read, before and after each instruction
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    live_in: Vec<RegisterSet>,
+    live_out: Vec<RegisterSet>,
+}
+
+/// Where the value held by a register may have been stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Definition {
+    Entry,     // Parameters, and registers not written yet
+    Pc(usize), // The instruction at this pc
+}
+
+/// Definitions reaching each instruction, per register. Only the ones entering each
+/// block are kept, the rest come from the writes before an instruction in its block
+#[derive(Debug, Clone)]
+pub struct ReachingDefinitions {
+    entry: Vec<Vec<BTreeSet<Definition>>>, // Indexed by block, then register
+    writes: Vec<Vec<usize>>,               // Pcs writing each register, in order
+    block_of: Vec<usize>,
+    starts: Vec<usize>, // First pc of each block
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl RegisterSet {
+    pub fn contains(&self, reg: u32) -> bool {
+        reg < 256 && self.0[reg as usize / 64] & (1 << (reg % 64)) != 0
+    }
+
+    pub fn insert(&mut self, reg: u32) {
+        if reg < 256 {
+            self.0[reg as usize / 64] |= 1 << (reg % 64);
+        }
+    }
+
+    pub fn remove(&mut self, reg: u32) {
+        if reg < 256 {
+            self.0[reg as usize / 64] &= !(1 << (reg % 64));
+        }
+    }
+
+    pub fn union(&self, other: &RegisterSet) -> RegisterSet {
+        RegisterSet(std::array::from_fn(|i| self.0[i] | other.0[i]))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..256).filter(|&reg| self.contains(reg))
+    }
+}
+
+impl Liveness {
+    pub fn new(proto: &FunctionPrototype, cfg: &ControlFlowGraph) -> Self {
+        let count = proto.code.len();
+        let mut live_in = vec![RegisterSet::default(); count];
+        let mut live_out = vec![RegisterSet::default(); count];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in cfg.blocks.iter().rev() {
+                let last = block.end - 1;
+                let mut live = RegisterSet::default();
+                for edge in &block.successors {
+                    let target = cfg.blocks[edge.to].start;
+                    let mut incoming = live_in[target];
+                    if let Some(reg) = conditional_store(proto, last)
+                        && target == last + 1
+                    {
+                        incoming.remove(reg);
+                    }
+                    live = live.union(&incoming);
+                }
+                for pc in (block.start..block.end).rev() {
+                    live_out[pc] = live;
+                    if conditional_store(proto, pc).is_none() {
+                        for reg in writes_at(proto, pc) {
+                            live.remove(reg);
+                        }
+                    }
+                    for reg in reads_at(proto, pc) {
+                        live.insert(reg);
+                    }
+                    if live_in[pc] != live {
+                        live_in[pc] = live;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        Self { live_in, live_out }
+    }
+
+    /// Registers live before the instruction at `pc`
+    pub fn live_in(&self, pc: usize) -> RegisterSet {
+        self.live_in.get(pc).copied().unwrap_or_default()
+    }
+
+    /// Registers live after the instruction at `pc`
+    pub fn live_out(&self, pc: usize) -> RegisterSet {
+        self.live_out.get(pc).copied().unwrap_or_default()
+    }
+}
+
+impl ReachingDefinitions {
+    pub fn new(proto: &FunctionPrototype, cfg: &ControlFlowGraph) -> Self {
+        let registers = usize::from(proto.max_stack_size);
+        let mut writes = vec![Vec::new(); registers];
+        for pc in 0..proto.code.len() {
+            for reg in writes_at(proto, pc) {
+                if let Some(pcs) = writes.get_mut(reg as usize) {
+                    pcs.push(pc);
+                }
+            }
+        }
+
+        // Last write of each register in each block, leaving out a final TESTSET
+        let stores: Vec<Vec<(u32, usize)>> = cfg
+            .blocks
+            .iter()
+            .map(|block| {
+                let mut last = vec![None; registers];
+                for pc in block.start..block.end {
+                    if pc + 1 == block.end && conditional_store(proto, pc).is_some() {
+                        break;
+                    }
+                    for reg in writes_at(proto, pc).filter(|&r| (r as usize) < registers) {
+                        last[reg as usize] = Some(pc);
+                    }
+                }
+                (0..registers as u32)
+                    .filter_map(|reg| last[reg as usize].map(|pc| (reg, pc)))
+                    .collect()
+            })
+            .collect();
+
+        let mut entry = vec![vec![BTreeSet::new(); registers]; cfg.blocks.len()];
+        if let Some(first) = entry.first_mut() {
+            first.fill(BTreeSet::from([Definition::Entry]));
+        }
+        let order = cfg.reverse_postorder();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &index in &order {
+                let block = &cfg.blocks[index];
+                for edge in &block.predecessors {
+                    let last = cfg.blocks[edge.from].end - 1;
+                    let mut out = entry[edge.from].clone();
+                    for &(reg, pc) in &stores[edge.from] {
+                        out[reg as usize] = BTreeSet::from([Definition::Pc(pc)]);
+                    }
+                    if let Some(reg) = conditional_store(proto, last)
+                        && block.start == last + 1
+                        && let Some(set) = out.get_mut(reg as usize)
+                    {
+                        *set = BTreeSet::from([Definition::Pc(last)]);
+                    }
+                    for (set, incoming) in entry[index].iter_mut().zip(out) {
+                        let before = set.len();
+                        set.extend(incoming);
+                        changed |= set.len() != before;
+                    }
+                }
+            }
+        }
+
+        let block_of = (0..proto.code.len())
+            .map(|pc| cfg.block_at(pc).unwrap_or(0))
+            .collect();
+        let starts = cfg.blocks.iter().map(|b| b.start).collect();
+        Self {
+            entry,
+            writes,
+            block_of,
+            starts,
+        }
+    }
+
+    /// Definitions of `reg` that may reach the instruction at `pc`
+    pub fn at(&self, pc: usize, reg: u32) -> BTreeSet<Definition> {
+        let (Some(&block), Some(writes)) = (self.block_of.get(pc), self.writes.get(reg as usize))
+        else {
+            return BTreeSet::new();
+        };
+        let before = writes.partition_point(|&write| write < pc);
+        match before.checked_sub(1).map(|i| writes[i]) {
+            Some(write) if write >= self.starts[block] => BTreeSet::from([Definition::Pc(write)]),
+            _ => self.entry[block][reg as usize].clone(),
+        }
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Register stored by the conditional TESTSET at `pc`, only when the test holds and
+/// the jump after it runs
+fn conditional_store(proto: &FunctionPrototype, pc: usize) -> Option<u32> {
+    let instr = &proto.code[pc];
+    (opcode(instr) == Some(Opcode::TESTSET)).then(|| instr.a())
+}
@@ -0,0 +1,283 @@
+/*
+  Structural comparison of two function prototype trees
+*/
+
+use crate::parser::bytecode::{Constant, FunctionPrototype, Instruction, LocalVariable};
+use std::fmt;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// What differs between two prototypes. Values are rendered as text, old first
+#[derive(Debug, Clone, PartialEq)]
+pub enum DifferenceKind {
+    Field {
+        name: &'static str,
+        old: String,
+        new: String,
+    },
+    Instruction {
+        old: Option<u32>, // `None` past the end of the shorter code
+        new: Option<u32>,
+    },
+    Constant {
+        index: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Line {
+        old: Option<u32>,
+        new: Option<u32>,
+    },
+    Local {
+        index: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    UpvalueName {
+        index: usize,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Function {
+        index: usize,
+        old: bool, // Whether each side has the child
+        new: bool,
+    },
+}
+
+/// A difference found by `diff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path: Vec<usize>, // Child indices leading from the compared function, outermost first
+    pub pc: Option<usize>, // Instruction or line entry that differs, `None` for the rest
+    pub kind: DifferenceKind,
+}
+
+/// Comparison of one pair of functions, collecting differences as they are found
+struct Differ<'a> {
+    old: &'a FunctionPrototype,
+    new: &'a FunctionPrototype,
+    path: &'a [usize],
+    differences: &'a mut Vec<Difference>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Differ<'_> {
+    fn report(&mut self, pc: Option<usize>, kind: DifferenceKind) {
+        self.differences.push(Difference {
+            path: self.path.to_vec(),
+            pc,
+            kind,
+        });
+    }
+
+    fn field<T: PartialEq + fmt::Display>(&mut self, name: &'static str, old: T, new: T) {
+        if old != new {
+            let (old, new) = (old.to_string(), new.to_string());
+            self.report(None, DifferenceKind::Field { name, old, new });
+        }
+    }
+
+    fn header(&mut self) {
+        let (old, new) = (self.old, self.new);
+        // Names are compared as bytes, which their lossy rendering may hide
+        if old.source_name != new.source_name {
+            let (old, new) = (format!("{:?}", old.source()), format!("{:?}", new.source()));
+            let name = "source";
+            self.report(None, DifferenceKind::Field { name, old, new });
+        }
+        self.field("line defined", old.line_defined, new.line_defined);
+        self.field(
+            "last line defined",
+            old.last_line_defined,
+            new.last_line_defined,
+        );
+        self.field("upvalues", old.num_upvalues, new.num_upvalues);
+        self.field("parameters", old.num_params, new.num_params);
+        self.field("vararg flags", old.is_vararg, new.is_vararg);
+        self.field("stack size", old.max_stack_size, new.max_stack_size);
+    }
+
+    fn code(&mut self) {
+        let (old, new) = (&self.old.code, &self.new.code);
+        for pc in 0..old.len().max(new.len()) {
+            let (old, new) = (
+                old.get(pc).map(Instruction::raw),
+                new.get(pc).map(Instruction::raw),
+            );
+            if old != new {
+                self.report(Some(pc), DifferenceKind::Instruction { old, new });
+            }
+        }
+    }
+
+    fn constants(&mut self) {
+        let (old, new) = (&self.old.constants, &self.new.constants);
+        for index in 0..old.len().max(new.len()) {
+            let (old, new) = (old.get(index), new.get(index));
+            if !same_constant(old, new) {
+                let (old, new) = (old.map(constant), new.map(constant));
+                self.report(None, DifferenceKind::Constant { index, old, new });
+            }
+        }
+    }
+
+    fn debug_info(&mut self) {
+        let (old, new) = (&self.old.debug_info, &self.new.debug_info);
+        for pc in 0..old.lineinfo.len().max(new.lineinfo.len()) {
+            let (old, new) = (old.lineinfo.get(pc).copied(), new.lineinfo.get(pc).copied());
+            if old != new {
+                self.report(Some(pc), DifferenceKind::Line { old, new });
+            }
+        }
+
+        let local =
+            |local: &LocalVariable| format!("{} {}-{}", local.name(), local.startpc, local.endpc);
+        for index in 0..old.locals.len().max(new.locals.len()) {
+            let (old, new) = (old.locals.get(index), new.locals.get(index));
+            if old != new {
+                let (old, new) = (old.map(local), new.map(local));
+                self.report(None, DifferenceKind::Local { index, old, new });
+            }
+        }
+
+        for index in 0..old.upvalues.len().max(new.upvalues.len()) {
+            if old.upvalues.get(index) != new.upvalues.get(index) {
+                let (old, new) = (
+                    old.upvalue_name(index).map(String::from),
+                    new.upvalue_name(index).map(String::from),
+                );
+                self.report(None, DifferenceKind::UpvalueName { index, old, new });
+            }
+        }
+    }
+}
+
+impl fmt::Display for DifferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DifferenceKind::Field { name, old, new } => write!(f, "{name} {old} != {new}"),
+            DifferenceKind::Instruction { old, new } => {
+                let word = |word: &Option<u32>| match word {
+                    Some(word) => format!("0x{word:08x}"),
+                    None => "none".to_string(),
+                };
+                write!(f, "instruction {} != {}", word(old), word(new))
+            }
+            DifferenceKind::Constant { index, old, new } => {
+                write!(f, "constant K{index} {} != {}", or_none(old), or_none(new))
+            }
+            DifferenceKind::Line { old, new } => {
+                let line = |line: &Option<u32>| line.map_or("none".to_string(), |l| l.to_string());
+                write!(f, "line {} != {}", line(old), line(new))
+            }
+            DifferenceKind::Local { index, old, new } => {
+                write!(f, "local {index} {} != {}", or_none(old), or_none(new))
+            }
+            DifferenceKind::UpvalueName { index, old, new } => {
+                write!(
+                    f,
+                    "upvalue name {index} {} != {}",
+                    or_none(old),
+                    or_none(new)
+                )
+            }
+            DifferenceKind::Function { index, old, new } => {
+                let side = |present: &bool| match present {
+                    true => "present",
+                    false => "missing",
+                };
+                write!(f, "function {index} {} != {}", side(old), side(new))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "main")?;
+        for index in &self.path {
+            write!(f, " > proto[{index}]")?;
+        }
+        if let Some(pc) = self.pc {
+            write!(f, " at pc {pc}")?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Compares two functions and, when `recursive` is set, their children pairwise. An
+/// empty result means the prototypes would dump to the same bytes
+pub fn diff(old: &FunctionPrototype, new: &FunctionPrototype, recursive: bool) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    diff_function(old, new, recursive, &mut Vec::new(), &mut differences);
+    differences
+}
+
+fn diff_function(
+    old: &FunctionPrototype,
+    new: &FunctionPrototype,
+    recursive: bool,
+    path: &mut Vec<usize>,
+    differences: &mut Vec<Difference>,
+) {
+    let mut differ = Differ {
+        old,
+        new,
+        path,
+        differences,
+    };
+    differ.header();
+    differ.code();
+    differ.constants();
+    differ.debug_info();
+
+    if !recursive {
+        return;
+    }
+    for index in 0..old.prototypes.len().max(new.prototypes.len()) {
+        match (old.prototypes.get(index), new.prototypes.get(index)) {
+            (Some(old), Some(new)) => {
+                path.push(index);
+                diff_function(old, new, recursive, path, differences);
+                path.pop();
+            }
+            (old, new) => differences.push(Difference {
+                path: path.clone(),
+                pc: None,
+                kind: DifferenceKind::Function {
+                    index,
+                    old: old.is_some(),
+                    new: new.is_some(),
+                },
+            }),
+        }
+    }
+}
+
+/// Constants compare by type and exact value, numbers bit for bit
+fn same_constant(old: Option<&Constant>, new: Option<&Constant>) -> bool {
+    match (old, new) {
+        (None, None) => true,
+        (Some(old), Some(new)) => match (old, new) {
+            (Constant::Nil, Constant::Nil) => true,
+            (Constant::Boolean(a), Constant::Boolean(b)) => a == b,
+            (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+            (Constant::Integer(a), Constant::Integer(b)) => a == b,
+            (Constant::String(a), Constant::String(b)) => a == b,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn constant(constant: &Constant) -> String {
+    format!("{constant:?}")
+}
+
+fn or_none(value: &Option<String>) -> &str {
+    value.as_deref().unwrap_or("none")
+}
@@ -0,0 +1,766 @@
+/*
+  Local variable inference for stripped Lua 5.1 functions (luac -s), which carry no
+  `DebugInfo.locals`. Values sharing a register and flowing into the same reads are
+  grouped into webs, and every web that is not an expression temporary becomes a local
+*/
+
+use super::cfg::{ControlFlowGraph, Dominators};
+use super::dataflow::{Definition, Liveness, ReachingDefinitions};
+use super::usage::{jump_target, opcode, reads_at, writes_at};
+use crate::parser::bytecode::{FunctionPrototype, LocalVariable, Opcode};
+use std::collections::{BTreeMap, BTreeSet};
+
+//////////////////////////////// Variables ////////////////////////////////
+
+const VARARG_NEEDSARG: u8 = 4; // lobject.h:VARARG_NEEDSARG
+
+const NUMERIC_FOR: [&str; 3] = ["(for index)", "(for limit)", "(for step)"];
+const GENERIC_FOR: [&str; 3] = ["(for generator)", "(for state)", "(for control)"];
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// Registers range of one inferred variable, like a `LocalVariable` with a register
+#[derive(Debug, Clone)]
+struct Scope {
+    reg: u32,
+    start: usize, // First pc the variable is active at
+    end: usize,   // One past the last
+    name: String,
+    fixed: bool, // Parameters and loop variables, which keep their name when merged
+}
+
+/// Definitions of a register reaching common reads, and the pcs reading them
+#[derive(Debug, Default)]
+struct Web {
+    defs: BTreeSet<Definition>,
+    uses: BTreeSet<usize>,
+}
+
+struct Inference<'a> {
+    proto: &'a FunctionPrototype,
+    cfg: ControlFlowGraph,
+    dominators: Dominators,
+    liveness: Liveness,
+    reaching: ReachingDefinitions,
+    depth: usize,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl<'a> Inference<'a> {
+    fn new(proto: &'a FunctionPrototype, depth: usize) -> Self {
+        let cfg = ControlFlowGraph::new(proto);
+        let dominators = cfg.dominators();
+        let liveness = Liveness::new(proto, &cfg);
+        let reaching = ReachingDefinitions::new(proto, &cfg);
+        Self {
+            proto,
+            cfg,
+            dominators,
+            liveness,
+            reaching,
+            depth,
+        }
+    }
+
+    fn is_reachable(&self, pc: usize) -> bool {
+        self.cfg
+            .block_at(pc)
+            .is_some_and(|b| self.dominators.is_reachable(b))
+    }
+
+    /// Parameters, the `arg` table of old-style varargs and the variables of `for`
+    /// loops, whose registers are set by the calling convention and loop instructions
+    fn fixed_scopes(&self) -> Vec<Scope> {
+        let count = self.proto.code.len();
+        let params = u32::from(self.proto.num_params);
+        let mut scopes: Vec<Scope> = (0..params)
+            .map(|reg| fixed(reg, 0, count, param_name(self.depth, reg)))
+            .collect();
+        if self.proto.is_vararg & VARARG_NEEDSARG != 0 {
+            scopes.push(fixed(params, 0, count, "arg".to_string()));
+        }
+
+        for (pc, instr) in self.proto.code.iter().enumerate() {
+            if self.proto.is_pseudo_instruction(pc) {
+                continue;
+            }
+            let a = instr.a();
+            match opcode(instr) {
+                // lparser.c:fornum
+                Some(Opcode::FORPREP) => {
+                    let forloop = jump_target(pc, instr);
+                    for (reg, name) in (a..).zip(NUMERIC_FOR) {
+                        scopes.push(fixed(reg, pc, forloop + 1, name.to_string()));
+                    }
+                    scopes.push(fixed(a + 3, pc + 1, forloop, local_name(self.depth, a + 3)));
+                }
+                // lparser.c:forlist
+                Some(Opcode::JMP) => {
+                    let tforloop = jump_target(pc, instr);
+                    let Some(call) = self.proto.code.get(tforloop) else {
+                        continue;
+                    };
+                    let back = self.proto.code.get(tforloop + 1);
+                    if opcode(call) != Some(Opcode::TFORLOOP)
+                        || back.is_none_or(|jmp| jump_target(tforloop + 1, jmp) != pc + 1)
+                    {
+                        continue;
+                    }
+                    let a = call.a();
+                    for (reg, name) in (a..).zip(GENERIC_FOR) {
+                        scopes.push(fixed(reg, pc, tforloop + 2, name.to_string()));
+                    }
+                    for reg in a + 3..a + 3 + call.c() {
+                        scopes.push(fixed(reg, pc + 1, tforloop, local_name(self.depth, reg)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        scopes
+    }
+
+    /// Whether the SETTABLE or SETLIST at `pc` fills the table constructor in `reg`,
+    /// which does not read its value
+    fn is_fill(&self, pc: usize, reg: u32) -> bool {
+        let instr = &self.proto.code[pc];
+        if instr.a() != reg {
+            return false;
+        }
+        match opcode(instr) {
+            Some(Opcode::SETLIST) => true,
+            Some(Opcode::SETTABLE) => {
+                let defs = self.reaching.at(pc, reg);
+                !defs.is_empty()
+                    && defs.iter().all(|def| match def {
+                        Definition::Pc(p) => opcode(&self.proto.code[*p]) == Some(Opcode::NEWTABLE),
+                        Definition::Entry => false,
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    /// Groups the definitions of each register into webs
+    fn webs(&self) -> Vec<(u32, Web)> {
+        let registers = u32::from(self.proto.max_stack_size);
+        let mut nodes: BTreeMap<(u32, Definition), usize> = BTreeMap::new();
+        let mut parent: Vec<usize> = Vec::new();
+        let mut node = |key: (u32, Definition), parent: &mut Vec<usize>| {
+            *nodes.entry(key).or_insert_with(|| {
+                parent.push(parent.len());
+                parent.len() - 1
+            })
+        };
+
+        let mut reads = Vec::new();
+        for pc in (0..self.proto.code.len()).filter(|&pc| self.is_reachable(pc)) {
+            for reg in writes_at(self.proto, pc).filter(|&r| r < registers) {
+                node((reg, Definition::Pc(pc)), &mut parent);
+            }
+            for reg in reads_at(self.proto, pc)
+                .into_iter()
+                .filter(|&r| r < registers)
+            {
+                let defs = self.reaching.at(pc, reg);
+                let Some(&first) = defs.first() else {
+                    continue;
+                };
+                let root = node((reg, first), &mut parent);
+                for &def in &defs {
+                    let other = node((reg, def), &mut parent);
+                    union(&mut parent, root, other);
+                }
+                reads.push((reg, defs, pc));
+            }
+        }
+
+        // An unread value meeting others where control flow joins belongs to their
+        // variable, like the pair of LOADBOOL materializing a comparison
+        let read: BTreeSet<(u32, Definition)> = reads
+            .iter()
+            .flat_map(|(reg, defs, _)| defs.iter().map(|&def| (*reg, def)))
+            .collect();
+        for block in &self.cfg.blocks {
+            if block.predecessors.len() < 2 || !self.is_reachable(block.start) {
+                continue;
+            }
+            for reg in 0..registers {
+                let defs: Vec<Definition> = self
+                    .reaching
+                    .at(block.start, reg)
+                    .into_iter()
+                    .filter(|&def| def != Definition::Entry)
+                    .collect();
+                if defs.iter().all(|&def| read.contains(&(reg, def))) {
+                    continue;
+                }
+                let mut defs = defs.into_iter();
+                let Some(first) = defs.next() else {
+                    continue;
+                };
+                let root = node((reg, first), &mut parent);
+                for def in defs {
+                    let other = node((reg, def), &mut parent);
+                    union(&mut parent, root, other);
+                }
+            }
+        }
+
+        let mut webs: BTreeMap<usize, (u32, Web)> = BTreeMap::new();
+        for (&(reg, def), &index) in &nodes {
+            let root = find(&mut parent, index);
+            webs.entry(root)
+                .or_insert_with(|| (reg, Web::default()))
+                .1
+                .defs
+                .insert(def);
+        }
+        for (reg, defs, pc) in reads {
+            let first = *defs.first().expect("read with a definition");
+            let root = find(&mut parent, nodes[&(reg, first)]);
+            if !self.is_fill(pc, reg) {
+                webs.get_mut(&root)
+                    .expect("web of a read")
+                    .1
+                    .uses
+                    .insert(pc);
+            }
+        }
+        webs.into_values().collect()
+    }
+
+    /// Whether the web is managed by a `for` loop or holds a parameter
+    fn is_reserved(&self, reg: u32, web: &Web) -> bool {
+        let looping = |pc: usize| {
+            matches!(
+                opcode(&self.proto.code[pc]),
+                Some(Opcode::FORPREP | Opcode::FORLOOP | Opcode::TFORLOOP)
+            )
+        };
+        let mut first_free = u32::from(self.proto.num_params);
+        if self.proto.is_vararg & VARARG_NEEDSARG != 0 {
+            first_free += 1;
+        }
+
+        web.uses.iter().any(|&pc| looping(pc))
+            || web.defs.iter().any(|def| match def {
+                Definition::Pc(pc) => looping(*pc),
+                Definition::Entry => reg < first_free,
+            })
+    }
+
+    /// Whether the web only holds results of a call or `...` left open for the next
+    /// instruction (lcode.c:luaK_setmultret), past the ones actually read
+    fn is_unread_result(&self, web: &Web) -> bool {
+        web.uses.is_empty()
+            && web.defs.iter().all(|def| match def {
+                Definition::Pc(pc) => {
+                    let instr = &self.proto.code[*pc];
+                    match opcode(instr) {
+                        Some(Opcode::CALL) => instr.c() == 0,
+                        Some(Opcode::VARARG) => instr.b() == 0,
+                        _ => false,
+                    }
+                }
+                Definition::Entry => false,
+            })
+    }
+
+    /// Whether the web is an expression temporary: values read once by the statement
+    /// computing them (lcode.c:freereg releases the register right after), besides
+    /// the tests of `a and b or c` jumping with them
+    fn is_temporary(&self, reg: u32, web: &Web) -> bool {
+        let (Some(&read), Some(&Definition::Pc(first))) = (web.uses.last(), web.defs.first())
+        else {
+            return false;
+        };
+        let tests = |pc: &usize| {
+            let instr = &self.proto.code[*pc];
+            opcode(instr) == Some(Opcode::TEST) && instr.a() == reg
+        };
+        if !web.uses.range(..read).all(tests) {
+            return false;
+        }
+        let before = |def: &Definition| matches!(def, Definition::Pc(pc) if *pc < read);
+        if !web.defs.iter().all(before) || self.proto.is_pseudo_instruction(read) {
+            return false;
+        }
+        if self.liveness.live_out(read).contains(reg) && !writes_at(self.proto, read).contains(&reg)
+        {
+            return false;
+        }
+        let Some(start) = self.expression_start(first, read) else {
+            return false;
+        };
+        if start < first && self.tests_above(start, first, reg, web) {
+            return false;
+        }
+        (start..read)
+            .filter(|&pc| pc != first)
+            .all(|pc| !self.ends_statement(pc, reg))
+    }
+
+    /// Whether the tests of a chain starting at `start` are computed above `reg`. An
+    /// expression puts its operands from its own register up (lcode.c:luaK_exp2nextreg),
+    /// so this is an `if` assigning a local in each branch instead
+    fn tests_above(&self, start: usize, first: usize, reg: u32, web: &Web) -> bool {
+        let block = self
+            .cfg
+            .block_at(start)
+            .map_or(start, |b| self.cfg.blocks[b].start);
+        let from = (block..start)
+            .rev()
+            .find(|&pc| self.ends_statement(pc, reg))
+            .map_or(block, |pc| pc + 1);
+        (from..first)
+            .filter(|&pc| !web.defs.contains(&Definition::Pc(pc)))
+            .flat_map(|pc| writes_at(self.proto, pc))
+            .min()
+            .is_some_and(|lowest| lowest > reg)
+    }
+
+    /// Start of the code between `first` and `read` when it is only entered from the
+    /// top and only left through `read`, like the jumps of `a and b or c`
+    fn expression_start(&self, first: usize, read: usize) -> Option<usize> {
+        let mut start = first;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, block) in self.cfg.blocks.iter().enumerate() {
+                if !self.dominators.is_reachable(index) {
+                    continue;
+                }
+                let last = block.end - 1;
+                for edge in &block.successors {
+                    let target = self.cfg.blocks[edge.to].start;
+                    let inside = start < target && target <= read;
+                    if last < start && inside {
+                        start = last;
+                        changed = true;
+                    } else if (start..read).contains(&last) && !inside {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(start)
+    }
+
+    /// Whether the instruction at `pc` completes a statement while `reg` holds a
+    /// temporary, so that the temporary outlives a statement and must be a local
+    fn ends_statement(&self, pc: usize, reg: u32) -> bool {
+        let instr = &self.proto.code[pc];
+        match opcode(instr) {
+            Some(Opcode::SETGLOBAL | Opcode::SETUPVAL) => return true,
+            Some(Opcode::SETTABLE) if !self.is_fill(pc, instr.a()) => return true,
+            Some(Opcode::CALL) if instr.c() == 1 => return true,
+            _ => {}
+        }
+        let writes = writes_at(self.proto, pc);
+        !self.proto.is_pseudo_instruction(pc) && !writes.is_empty() && writes.start < reg
+    }
+
+    /// Pcs where a value of the web is live, walking forward from each definition
+    /// until `reg` is written again
+    fn live_range(&self, reg: u32, web: &Web) -> BTreeSet<usize> {
+        // TESTSET stores only on the way to the jump after it
+        let testset = |pc: usize| opcode(&self.proto.code[pc]) == Some(Opcode::TESTSET);
+        let mut live = BTreeSet::new();
+        let mut pending: Vec<usize> = Vec::new();
+        for def in &web.defs {
+            match *def {
+                Definition::Entry => pending.push(0),
+                Definition::Pc(pc) if testset(pc) => pending.push(pc + 1),
+                Definition::Pc(pc) => pending.extend(self.successors(pc)),
+            }
+        }
+        while let Some(pc) = pending.pop() {
+            if !self.liveness.live_in(pc).contains(reg) || !live.insert(pc) {
+                continue;
+            }
+            let next = self.successors(pc);
+            if self.proto.is_pseudo_instruction(pc) || !writes_at(self.proto, pc).contains(&reg) {
+                pending.extend(next);
+            } else if testset(pc) {
+                pending.extend(next.into_iter().filter(|&next| next != pc + 1));
+            }
+        }
+        live
+    }
+
+    /// Pcs that may run right after the one at `pc`
+    fn successors(&self, pc: usize) -> Vec<usize> {
+        let Some(block) = self.cfg.block_at(pc) else {
+            return Vec::new();
+        };
+        let block = &self.cfg.blocks[block];
+        match pc + 1 < block.end {
+            true => vec![pc + 1],
+            false => block
+                .successors
+                .iter()
+                .map(|edge| self.cfg.blocks[edge.to].start)
+                .collect(),
+        }
+    }
+
+    /// Where the jumps of `a and b or c` computing the first value of the web meet,
+    /// which is where a local initialized with it is declared
+    fn chain_end(&self, reg: u32, web: &Web, pcs: &BTreeSet<usize>) -> Option<usize> {
+        let Some(&Definition::Pc(first)) = web.defs.first() else {
+            return None;
+        };
+        let block = (first + 1..=*pcs.last()?).find_map(|pc| {
+            let block = self.cfg.block_at(pc)?;
+            let later = pcs.range(pc..).all(|&later| {
+                self.cfg
+                    .block_at(later)
+                    .is_some_and(|b| self.dominators.dominates(block, b))
+            });
+            (self.cfg.blocks[block].start == pc && later).then_some(block)
+        })?;
+
+        let join = self.cfg.blocks[block].start;
+        let start = self.expression_start(first, join)?;
+        let tests = (start..join).any(|pc| {
+            let instr = &self.proto.code[pc];
+            instr.a() == reg
+                && match opcode(instr) {
+                    Some(Opcode::TEST | Opcode::TESTSET) => true,
+                    Some(Opcode::LOADBOOL) => instr.c() != 0,
+                    _ => false,
+                }
+        });
+        let statements = (start..join)
+            .any(|pc| !web.defs.contains(&Definition::Pc(pc)) && self.ends_statement(pc, reg));
+        (tests && !statements).then_some(join)
+    }
+
+    /// Pcs the web is active at, from its first definition to its last read
+    fn scope(&self, reg: u32, web: &Web) -> Scope {
+        let count = self.proto.code.len();
+        let mut pcs: BTreeSet<usize> = web.uses.clone();
+        for def in &web.defs {
+            pcs.insert(match def {
+                Definition::Entry => 0,
+                Definition::Pc(pc) => (pc + 1).min(count.saturating_sub(1)),
+            });
+        }
+        pcs.extend(self.live_range(reg, web));
+
+        // A table constructor is declared once filled in
+        for def in &web.defs {
+            if let Definition::Pc(pc) = *def
+                && let Some(last) = self.constructor_end(pc, reg)
+            {
+                let declared = match self.proto.is_setlist_batch(last + 1) {
+                    true => last + 2,
+                    false => last + 1,
+                };
+                pcs.retain(|&p| p <= pc || p >= declared);
+                pcs.insert(declared);
+            }
+        }
+
+        let mut start = pcs.first().copied().unwrap_or(0);
+        let end = pcs.last().map_or(start + 1, |pc| pc + 1);
+
+        // `local function f` is active in its own body to let it call itself
+        for def in &web.defs {
+            if let Definition::Pc(pc) = *def
+                && self.captures(pc, reg)
+            {
+                start = start.min(pc);
+            }
+        }
+
+        // A variable assigned in several branches is declared before them
+        if let Some(mut block) = self.cfg.block_at(start) {
+            let blocks: BTreeSet<usize> =
+                pcs.iter().filter_map(|&pc| self.cfg.block_at(pc)).collect();
+            let dominated =
+                |block: usize| blocks.iter().all(|&b| self.dominators.dominates(block, b));
+            if !dominated(block)
+                && let Some(join) = self.chain_end(reg, web, &pcs)
+            {
+                start = join;
+            } else if !dominated(block) {
+                while !dominated(block) {
+                    match self.dominators.immediate(block) {
+                        Some(idom) => block = idom,
+                        None => {
+                            block = 0;
+                            break;
+                        }
+                    }
+                }
+                start = self.cfg.blocks[block].start;
+            }
+        }
+
+        Scope {
+            reg,
+            start,
+            end: end.max(start + 1),
+            name: local_name(self.depth, reg),
+            fixed: false,
+        }
+    }
+
+    /// Last SETTABLE or SETLIST filling the table constructed into `reg` by the
+    /// NEWTABLE at `pc`, before the table is otherwise used
+    fn constructor_end(&self, pc: usize, reg: u32) -> Option<usize> {
+        if opcode(&self.proto.code[pc]) != Some(Opcode::NEWTABLE) {
+            return None;
+        }
+        let block = self.cfg.block_at(pc)?;
+        let mut last = None;
+        for next in pc + 1..self.cfg.blocks[block].end {
+            if self.proto.is_pseudo_instruction(next) {
+                continue;
+            }
+            if self.is_fill(next, reg) {
+                last = Some(next);
+            } else if reads_at(self.proto, next).contains(&reg)
+                || writes_at(self.proto, next).contains(&reg)
+            {
+                break;
+            }
+        }
+        last
+    }
+
+    /// Whether the CLOSURE at `pc` captures `reg`, the register it is stored to
+    fn captures(&self, pc: usize, reg: u32) -> bool {
+        let instr = &self.proto.code[pc];
+        opcode(instr) == Some(Opcode::CLOSURE)
+            && instr.a() == reg
+            && self.proto.closure_upvalues(pc).is_some_and(|upvalues| {
+                upvalues
+                    .iter()
+                    .any(|u| u.instack && u32::from(u.idx) == reg)
+            })
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Infers the local variables of a stripped function, in the order luac would list
+/// them. `depth` is the nesting level of the function, 0 for the main chunk, which
+/// keeps generated names apart from the upvalues of enclosing functions
+pub fn infer_locals(proto: &FunctionPrototype, depth: usize) -> Vec<LocalVariable> {
+    let inference = Inference::new(proto, depth);
+    let mut scopes = inference.fixed_scopes();
+    let mut temporaries = Vec::new();
+    for (reg, web) in inference.webs() {
+        if inference.is_reserved(reg, &web) || inference.is_unread_result(&web) {
+            continue;
+        }
+        let scope = inference.scope(reg, &web);
+        match inference.is_temporary(reg, &web) {
+            true => temporaries.push(scope),
+            false => scopes.push(scope),
+        }
+    }
+
+    let mut scopes = close_prefixes(merge(scopes), &temporaries, proto.code.len(), depth);
+
+    scopes.sort_by_key(|scope| (scope.start, scope.reg));
+    scopes
+        .into_iter()
+        .map(|scope| LocalVariable {
+            varname: scope.name.into_bytes(),
+            startpc: scope.start as u32,
+            endpc: scope.end as u32,
+        })
+        .collect()
+}
+
+/// Generated name of a local variable
+pub fn local_name(depth: usize, reg: u32) -> String {
+    format!("l_{depth}_{reg}")
+}
+
+/// Generated name of a parameter, counted from 1
+pub fn param_name(depth: usize, reg: u32) -> String {
+    match depth {
+        0 | 1 => format!("arg{}", reg + 1),
+        _ => format!("arg{depth}_{}", reg + 1),
+    }
+}
+
+fn fixed(reg: u32, start: usize, end: usize, name: String) -> Scope {
+    Scope {
+        reg,
+        start,
+        end,
+        name,
+        fixed: true,
+    }
+}
+
+/// Joins the overlapping scopes of each register
+fn merge(mut scopes: Vec<Scope>) -> Vec<Scope> {
+    scopes.sort_by_key(|scope| (scope.reg, scope.start));
+    let mut merged: Vec<Scope> = Vec::with_capacity(scopes.len());
+    for scope in scopes {
+        match merged.last_mut() {
+            Some(last) if last.reg == scope.reg && scope.start < last.end => {
+                last.end = last.end.max(scope.end);
+                if scope.fixed && !last.fixed {
+                    last.name = scope.name;
+                    last.fixed = true;
+                }
+            }
+            _ => merged.push(scope),
+        }
+    }
+    merged
+}
+
+/// Makes the registers of the locals active at each pc a prefix starting at 0,
+/// declared in register order, as the code generator allocates them
+/// (lparser.c:adjustlocalvars). Registers are settled from the highest down, each
+/// one covering the pcs where any register above it is active
+fn close_prefixes(
+    scopes: Vec<Scope>,
+    temporaries: &[Scope],
+    count: usize,
+    depth: usize,
+) -> Vec<Scope> {
+    // Malformed loops can give scopes that end before they start, which cover nothing
+    let scopes: Vec<Scope> = scopes.into_iter().filter(|s| s.start < s.end).collect();
+    let top = scopes.iter().map(|s| s.reg as usize + 1).max().unwrap_or(0);
+    let mut by_reg: Vec<Vec<Scope>> = vec![Vec::new(); top];
+    for scope in scopes {
+        by_reg[scope.reg as usize].push(scope);
+    }
+
+    let mut needed = vec![false; count];
+    for reg in (0..top).rev() {
+        let (lower, higher) = by_reg.split_at_mut(reg + 1);
+        let own = &mut lower[reg];
+
+        // A temporary held across the declaration of a local is a local too
+        for temp in temporaries.iter().filter(|t| t.reg as usize == reg) {
+            if needed[pcs(temp, count)].contains(&true) {
+                own.push(temp.clone());
+            }
+        }
+        *own = merge(std::mem::take(own));
+
+        let mut covered = vec![false; count];
+        for scope in own.iter() {
+            covered[pcs(scope, count)].fill(true);
+        }
+        let mut pc = 0;
+        while pc < count {
+            if !needed[pc] || covered[pc] {
+                pc += 1;
+                continue;
+            }
+            let end = (pc..count)
+                .find(|&p| !needed[p] || covered[p])
+                .unwrap_or(count);
+            fill(own, reg as u32, pc, end, depth);
+            pc = end;
+        }
+
+        // Declared no later than the register above while both are active
+        for above in higher.first().into_iter().flatten() {
+            let overlapping = |s: &Scope| s.start < above.end && above.start < s.end;
+            if let Some(scope) = own.iter_mut().find(|s| overlapping(s)) {
+                scope.start = scope.start.min(above.start);
+                scope.end = scope.end.max(above.end);
+            }
+        }
+        *own = merge(std::mem::take(own));
+
+        for scope in own.iter() {
+            needed[pcs(scope, count)].fill(true);
+        }
+    }
+    by_reg.into_iter().flatten().collect()
+}
+
+/// The pcs of `scope` within code of `count` instructions, empty when it has none
+fn pcs(scope: &Scope, count: usize) -> std::ops::Range<usize> {
+    let end = scope.end.min(count);
+    scope.start.min(end)..end
+}
+
+/// Covers the pcs in `start..end` of `reg`, stretching its nearest scope
+fn fill(scopes: &mut Vec<Scope>, reg: u32, start: usize, end: usize, depth: usize) {
+    let before = scopes
+        .iter_mut()
+        .filter(|s| s.end <= start)
+        .max_by_key(|s| s.end);
+    if let Some(scope) = before {
+        scope.end = end;
+        return;
+    }
+    let after = scopes
+        .iter_mut()
+        .filter(|s| s.start >= end)
+        .min_by_key(|s| s.start);
+    if let Some(scope) = after {
+        scope.start = start;
+        return;
+    }
+    scopes.push(Scope {
+        reg,
+        start,
+        end,
+        name: local_name(depth, reg),
+        fixed: false,
+    });
+}
+
+fn find(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    if a != b {
+        parent[b.max(a)] = a.min(b);
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn skips_loop_scopes_that_end_before_they_start() {
+        // A FORPREP jumping backward, which verify rejects but must not panic here
+        let proto = assemble(
+            r#"
+            .const 1
+            .const "print"
+                LOADK     0 K0
+                LOADK     1 K0
+                LOADK     2 K0
+            L3:
+                GETGLOBAL 4 K1
+                MOVE      5 3
+                CALL      4 2 1
+                FORLOOP   0 L3
+                FORPREP   0 L3
+                RETURN    0 1
+            "#,
+        )
+        .expect("valid listing");
+        let locals = infer_locals(&proto, 0);
+        assert!(locals.iter().all(|local| local.startpc < local.endpc));
+    }
+}
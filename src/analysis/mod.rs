@@ -0,0 +1,16 @@
+/*
+  Analyses of Lua 5.1 function prototypes shared by the decompiler and the tools
+*/
+
+pub mod cfg;
+pub mod dataflow;
+pub mod diff;
+pub mod locals;
+pub mod usage;
+pub mod verify;
+
+pub use cfg::{BasicBlock, ControlFlowGraph, Dominators, Edge, EdgeKind};
+pub use dataflow::{Definition, Liveness, ReachingDefinitions, RegisterSet};
+pub use diff::{Difference, DifferenceKind, diff};
+pub use locals::infer_locals;
+pub use verify::{Diagnostic, DiagnosticKind, verify};
@@ -0,0 +1,157 @@
+/*
+  Registers read and written by each Lua 5.1 instruction
+*/
+
+use crate::parser::bytecode::{FunctionPrototype, Instruction, Opcode};
+use std::ops::Range;
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Decodes the opcode of `instr`, or `None` for words that are not instructions
+pub fn opcode(instr: &Instruction) -> Option<Opcode> {
+    Opcode::try_from(instr.op()).ok()
+}
+
+/// Registers read by the instruction at `pc`. Operands running "up to the top" of the
+/// stack (B or C of 0) end with the multi-result value produced just before
+pub fn reads_at(proto: &FunctionPrototype, pc: usize) -> Vec<u32> {
+    let instr = &proto.code[pc];
+    if proto.is_pseudo_instruction(pc) {
+        // A closure capturing a register keeps it alive
+        return match opcode(instr) {
+            Some(Opcode::MOVE) if !proto.is_setlist_batch(pc) => vec![instr.b()],
+            _ => Vec::new(),
+        };
+    }
+    reads(instr, top(proto, pc))
+}
+
+/// Registers written by the instruction at `pc`
+pub fn writes_at(proto: &FunctionPrototype, pc: usize) -> Range<u32> {
+    match proto.is_pseudo_instruction(pc) {
+        true => 0..0,
+        false => writes(&proto.code[pc], u32::from(proto.max_stack_size)),
+    }
+}
+
+fn reads(instr: &Instruction, top: u32) -> Vec<u32> {
+    let (a, b, c) = (instr.a(), instr.b(), instr.c());
+    let rk_b = (!instr.b_isk()).then_some(b);
+    let rk_c = (!instr.c_isk()).then_some(c);
+
+    let Some(opcode) = opcode(instr) else {
+        return Vec::new();
+    };
+    match opcode {
+        Opcode::MOVE | Opcode::UNM | Opcode::NOT | Opcode::LEN => vec![b],
+        Opcode::GETTABLE | Opcode::SELF => [Some(b), rk_c].into_iter().flatten().collect(),
+        Opcode::SETGLOBAL | Opcode::SETUPVAL | Opcode::TEST => vec![a],
+        Opcode::SETTABLE => [Some(a), rk_b, rk_c].into_iter().flatten().collect(),
+        Opcode::ADD
+        | Opcode::SUB
+        | Opcode::MUL
+        | Opcode::DIV
+        | Opcode::MOD
+        | Opcode::POW
+        | Opcode::EQ
+        | Opcode::LT
+        | Opcode::LE => [rk_b, rk_c].into_iter().flatten().collect(),
+        Opcode::CONCAT => (b..=c).collect(),
+        Opcode::TESTSET => vec![b],
+        Opcode::CALL | Opcode::TAILCALL => match b {
+            0 => (a..top).collect(),
+            _ => (a..a + b).collect(),
+        },
+        Opcode::RETURN => match b {
+            0 => (a..top).collect(),
+            _ => (a..a + b - 1).collect(),
+        },
+        Opcode::FORLOOP | Opcode::FORPREP => vec![a, a + 1, a + 2],
+        Opcode::TFORLOOP => vec![a, a + 1, a + 2],
+        Opcode::SETLIST => match b {
+            0 => (a..top).collect(),
+            _ => (a..=a + b).collect(),
+        },
+        Opcode::LOADK
+        | Opcode::LOADBOOL
+        | Opcode::LOADNIL
+        | Opcode::GETUPVAL
+        | Opcode::GETGLOBAL
+        | Opcode::NEWTABLE
+        | Opcode::JMP
+        | Opcode::CLOSE
+        | Opcode::CLOSURE
+        | Opcode::VARARG => Vec::new(),
+    }
+}
+
+/// Registers written by `instr`. Open results are assumed to reach `top`
+fn writes(instr: &Instruction, top: u32) -> Range<u32> {
+    let (a, b, c) = (instr.a(), instr.b(), instr.c());
+
+    let Some(opcode) = opcode(instr) else {
+        return 0..0;
+    };
+    match opcode {
+        Opcode::MOVE
+        | Opcode::LOADK
+        | Opcode::LOADBOOL
+        | Opcode::GETUPVAL
+        | Opcode::GETGLOBAL
+        | Opcode::GETTABLE
+        | Opcode::NEWTABLE
+        | Opcode::ADD
+        | Opcode::SUB
+        | Opcode::MUL
+        | Opcode::DIV
+        | Opcode::MOD
+        | Opcode::POW
+        | Opcode::UNM
+        | Opcode::NOT
+        | Opcode::LEN
+        | Opcode::CONCAT
+        | Opcode::TESTSET
+        | Opcode::FORPREP
+        | Opcode::CLOSURE => a..a + 1,
+        Opcode::LOADNIL => a..b + 1,
+        Opcode::SELF => a..a + 2,
+        Opcode::CALL => match c {
+            0 => a..top,
+            _ => a..a + c - 1,
+        },
+        Opcode::VARARG => match b {
+            0 => a..top,
+            _ => a..a + b - 1,
+        },
+        Opcode::FORLOOP => a..a + 4,
+        Opcode::TFORLOOP => a + 2..a + 3 + c,
+        Opcode::SETGLOBAL
+        | Opcode::SETUPVAL
+        | Opcode::SETTABLE
+        | Opcode::JMP
+        | Opcode::EQ
+        | Opcode::LT
+        | Opcode::LE
+        | Opcode::TEST
+        | Opcode::TAILCALL
+        | Opcode::RETURN
+        | Opcode::SETLIST
+        | Opcode::CLOSE => 0..0,
+    }
+}
+
+/// Top of the stack for an instruction at `pc` reading "up to the top": one past the
+/// open CALL or VARARG before it, otherwise the whole frame
+fn top(proto: &FunctionPrototype, pc: usize) -> u32 {
+    let previous = pc.checked_sub(1).map(|prev| &proto.code[prev]);
+    match previous.map(|instr| (instr, opcode(instr))) {
+        Some((instr, Some(Opcode::CALL))) if instr.c() == 0 => instr.a() + 1,
+        Some((instr, Some(Opcode::VARARG))) if instr.b() == 0 => instr.a() + 1,
+        _ => u32::from(proto.max_stack_size),
+    }
+}
+
+/// Target of a jump-like instruction at `pc`, relative to the instruction after it
+pub fn jump_target(pc: usize, instr: &Instruction) -> usize {
+    (pc as i64 + 1 + i64::from(instr.sbx())) as usize
+}
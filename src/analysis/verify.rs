@@ -0,0 +1,735 @@
+/*
+  Structural checks of Lua 5.1 function prototypes, after ldebug.c:luaG_checkcode
+*/
+
+use super::usage::opcode;
+use crate::parser::bytecode::{
+    Constant, FunctionPrototype, Instruction, InstructionFormat, LUA51_OPCODES, Opcode, OperandMask,
+};
+use std::fmt;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// llimits.h:MAXSTACK
+const MAX_STACK: u8 = 250;
+
+// lobject.h:VARARG_*
+const VARARG_HASARG: u8 = 1;
+const VARARG_ISVARARG: u8 = 2;
+const VARARG_NEEDSARG: u8 = 4;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// What is wrong with a prototype or one of its instructions
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    StackTooLarge(u8),
+    ParamsExceedStack,
+    InvalidVarargFlags(u8),
+    TooManyUpvalueNames(usize),
+    LineInfoMismatch(usize),
+    MissingReturn,
+    UnknownOpcode(u8),
+    RegisterOutOfRange { operand: &'static str, reg: u32 },
+    ConstantOutOfRange { operand: &'static str, index: u32 },
+    UnusedOperand { operand: &'static str, value: u32 },
+    UpvalueOutOfRange(u32),
+    GlobalNameNotString(u32),
+    JumpOutOfRange(i64),
+    JumpIntoSetlistBatch(usize),
+    MissingJump,    // A test or comparison not followed by its JMP
+    SkipOutOfRange, // A skip of the next instruction past the end of the code
+    SkipIntoSetlistBatch,
+    EmptyConcat,
+    NoLoopVariables,      // TFORLOOP with C = 0
+    UnmatchedForLoop,     // FORPREP and FORLOOP that do not jump to each other
+    UnconsumedOpenResult, // Open CALL or VARARG not followed by an instruction reading it
+    MissingSetlistBatch,
+    ClosureOutOfRange(u32),
+    MissingClosureBindings,
+    InvalidClosureBinding(usize),
+    VarargInFixedFunction,
+}
+
+/// A problem found by `verify`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub path: Vec<usize>, // Child indices leading from the main function, outermost first
+    pub pc: Option<usize>, // Instruction at fault, `None` for the prototype itself
+    pub kind: DiagnosticKind,
+}
+
+/// Checks of one function, collecting diagnostics as they are found
+struct Verifier<'a> {
+    proto: &'a FunctionPrototype,
+    path: &'a [usize],
+    diagnostics: &'a mut Vec<Diagnostic>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Verifier<'_> {
+    fn report(&mut self, pc: Option<usize>, kind: DiagnosticKind) {
+        self.diagnostics.push(Diagnostic {
+            path: self.path.to_vec(),
+            pc,
+            kind,
+        });
+    }
+
+    /// ldebug.c:precheck
+    fn header(&mut self) {
+        let proto = self.proto;
+        if proto.max_stack_size > MAX_STACK {
+            self.report(None, DiagnosticKind::StackTooLarge(proto.max_stack_size));
+        }
+        let has_arg = proto.is_vararg & VARARG_HASARG;
+        if u32::from(proto.num_params) + u32::from(has_arg) > u32::from(proto.max_stack_size) {
+            self.report(None, DiagnosticKind::ParamsExceedStack);
+        }
+        if proto.is_vararg & VARARG_NEEDSARG != 0 && has_arg == 0 {
+            self.report(None, DiagnosticKind::InvalidVarargFlags(proto.is_vararg));
+        }
+        let names = proto.debug_info.upvalues.len();
+        if names > usize::from(proto.num_upvalues) {
+            self.report(None, DiagnosticKind::TooManyUpvalueNames(names));
+        }
+        let lines = proto.debug_info.lineinfo.len();
+        if lines != 0 && lines != proto.code.len() {
+            self.report(None, DiagnosticKind::LineInfoMismatch(lines));
+        }
+        let last = proto.code.last().and_then(opcode);
+        if last != Some(Opcode::RETURN) {
+            self.report(None, DiagnosticKind::MissingReturn);
+        }
+    }
+
+    /// ldebug.c:symbexec, without the symbolic tracking of a register
+    fn code(&mut self) {
+        let mut pc = 0;
+        while pc < self.proto.code.len() {
+            pc = self.instruction(pc) + 1;
+        }
+    }
+
+    /// Checks the instruction at `pc`, returning the last pc it spans
+    fn instruction(&mut self, pc: usize) -> usize {
+        let proto = self.proto;
+        let instr = &proto.code[pc];
+        let Some(op) = opcode(instr) else {
+            self.report(Some(pc), DiagnosticKind::UnknownOpcode(instr.op()));
+            return pc;
+        };
+        let (format, b_mode, c_mode) = LUA51_OPCODES.modes[op as usize];
+        let (a, b, c) = (instr.a(), instr.b(), instr.c());
+
+        self.register(pc, "A", a);
+        match format {
+            InstructionFormat::IABC => {
+                self.operand(pc, "B", b, b_mode);
+                self.operand(pc, "C", c, c_mode);
+            }
+            InstructionFormat::IABx if b_mode == OperandMask::OpArgK => {
+                self.constant(pc, "Bx", instr.bx());
+            }
+            InstructionFormat::IAsBx if b_mode == OperandMask::OpArgR => self.jump(pc, instr),
+            _ => {}
+        }
+
+        // Tests skip their JMP, so one must follow
+        if matches!(
+            op,
+            Opcode::EQ | Opcode::LT | Opcode::LE | Opcode::TEST | Opcode::TESTSET
+        ) {
+            match proto.code.get(pc + 1).and_then(opcode) {
+                _ if pc + 2 >= proto.code.len() => {
+                    self.report(Some(pc), DiagnosticKind::SkipOutOfRange)
+                }
+                Some(Opcode::JMP) => {}
+                _ => self.report(Some(pc), DiagnosticKind::MissingJump),
+            }
+        }
+
+        match op {
+            Opcode::LOADBOOL if c != 0 => {
+                if pc + 2 >= proto.code.len() {
+                    self.report(Some(pc), DiagnosticKind::SkipOutOfRange);
+                } else if is_open_setlist(&proto.code[pc + 1]) {
+                    self.report(Some(pc), DiagnosticKind::SkipIntoSetlistBatch);
+                }
+            }
+            Opcode::GETUPVAL | Opcode::SETUPVAL if b >= u32::from(proto.num_upvalues) => {
+                self.report(Some(pc), DiagnosticKind::UpvalueOutOfRange(b));
+            }
+            Opcode::GETGLOBAL | Opcode::SETGLOBAL => {
+                let index = instr.bx();
+                let constant = proto.constants.get(index as usize);
+                if constant.is_some_and(|k| !matches!(k, Constant::String(_))) {
+                    self.report(Some(pc), DiagnosticKind::GlobalNameNotString(index));
+                }
+            }
+            Opcode::SELF => self.register(pc, "A", a + 1),
+            Opcode::CONCAT if b >= c => self.report(Some(pc), DiagnosticKind::EmptyConcat),
+            Opcode::TFORLOOP => {
+                if c == 0 {
+                    self.report(Some(pc), DiagnosticKind::NoLoopVariables);
+                }
+                self.register(pc, "A", a + 2 + c);
+            }
+            Opcode::FORLOOP | Opcode::FORPREP => {
+                self.register(pc, "A", a + 3);
+                self.for_loop(pc, op);
+            }
+            Opcode::CALL | Opcode::TAILCALL => {
+                if b != 0 {
+                    self.register(pc, "B", a + b - 1);
+                }
+                match c {
+                    0 => self.open_result(pc),
+                    1 => {}
+                    _ => self.register(pc, "C", a + c - 2),
+                }
+            }
+            Opcode::RETURN if b > 1 => self.register(pc, "B", a + b - 2),
+            Opcode::SETLIST => {
+                if b > 0 {
+                    self.register(pc, "B", a + b);
+                }
+                if c == 0 {
+                    // The batch number is the next word, and cannot end the code
+                    if pc + 2 >= proto.code.len() {
+                        self.report(Some(pc), DiagnosticKind::MissingSetlistBatch);
+                    }
+                    return pc + 1;
+                }
+            }
+            Opcode::CLOSURE => return self.closure(pc, instr.bx()),
+            Opcode::VARARG => {
+                if proto.is_vararg & VARARG_ISVARARG == 0 || proto.is_vararg & VARARG_NEEDSARG != 0
+                {
+                    self.report(Some(pc), DiagnosticKind::VarargInFixedFunction);
+                }
+                match b {
+                    0 => self.open_result(pc),
+                    1 => {}
+                    _ => self.register(pc, "B", a + b - 2),
+                }
+            }
+            _ => {}
+        }
+        pc
+    }
+
+    /// ldebug.c:checkArgMode
+    fn operand(&mut self, pc: usize, operand: &'static str, value: u32, mode: OperandMask) {
+        match mode {
+            OperandMask::OpArgN if value != 0 => {
+                self.report(Some(pc), DiagnosticKind::UnusedOperand { operand, value });
+            }
+            OperandMask::OpArgR => self.register(pc, operand, value),
+            OperandMask::OpArgK if value & (1 << 8) != 0 => {
+                self.constant(pc, operand, value & !(1 << 8));
+            }
+            OperandMask::OpArgK => self.register(pc, operand, value),
+            _ => {}
+        }
+    }
+
+    fn register(&mut self, pc: usize, operand: &'static str, reg: u32) {
+        if reg >= u32::from(self.proto.max_stack_size) {
+            self.report(
+                Some(pc),
+                DiagnosticKind::RegisterOutOfRange { operand, reg },
+            );
+        }
+    }
+
+    fn constant(&mut self, pc: usize, operand: &'static str, index: u32) {
+        if index as usize >= self.proto.constants.len() {
+            self.report(
+                Some(pc),
+                DiagnosticKind::ConstantOutOfRange { operand, index },
+            );
+        }
+    }
+
+    /// A jump must land on an instruction, which a SETLIST batch number is not
+    fn jump(&mut self, pc: usize, instr: &Instruction) {
+        let code = &self.proto.code;
+        let target = pc as i64 + 1 + i64::from(instr.sbx());
+        if target < 0 || target >= code.len() as i64 {
+            self.report(Some(pc), DiagnosticKind::JumpOutOfRange(target));
+            return;
+        }
+
+        // A word that looks like an open SETLIST may itself be the batch number of an
+        // earlier one, so count the whole run of them back
+        let target = target as usize;
+        let run = code[..target]
+            .iter()
+            .rev()
+            .take_while(|&word| is_open_setlist(word))
+            .count();
+        if run % 2 == 1 {
+            self.report(Some(pc), DiagnosticKind::JumpIntoSetlistBatch(target));
+        }
+    }
+
+    /// A FORPREP jumps forward to the FORLOOP of its loop, which jumps back to the
+    /// instruction after the FORPREP (lparser.c:forbody)
+    fn for_loop(&mut self, pc: usize, op: Opcode) {
+        let code = &self.proto.code;
+        let target = pc as i64 + 1 + i64::from(code[pc].sbx());
+        let (prep, forloop) = match op {
+            Opcode::FORPREP => (pc as i64, target),
+            _ => (target - 1, pc as i64),
+        };
+        let paired = |at: i64, expected: Opcode| {
+            usize::try_from(at)
+                .ok()
+                .and_then(|at| code.get(at))
+                .filter(|instr| opcode(instr) == Some(expected) && instr.a() == code[pc].a())
+                .map(|instr| at + 1 + i64::from(instr.sbx()))
+        };
+        let matched = prep < forloop
+            && paired(prep, Opcode::FORPREP) == Some(forloop)
+            && paired(forloop, Opcode::FORLOOP) == Some(prep + 1);
+        if !matched {
+            self.report(Some(pc), DiagnosticKind::UnmatchedForLoop);
+        }
+    }
+
+    /// ldebug.c:checkopenop, the next instruction must take every result up to the top
+    fn open_result(&mut self, pc: usize) {
+        let next = self.proto.code.get(pc + 1);
+        let consumed = next.is_some_and(|next| {
+            matches!(
+                opcode(next),
+                Some(Opcode::CALL | Opcode::TAILCALL | Opcode::RETURN | Opcode::SETLIST)
+            ) && next.b() == 0
+        });
+        if !consumed {
+            self.report(Some(pc), DiagnosticKind::UnconsumedOpenResult);
+        }
+    }
+
+    /// Checks a CLOSURE and the binding words after it, returning the last one
+    fn closure(&mut self, pc: usize, index: u32) -> usize {
+        let proto = self.proto;
+        let Some(child) = proto.prototypes.get(index as usize) else {
+            self.report(Some(pc), DiagnosticKind::ClosureOutOfRange(index));
+            return pc;
+        };
+        let count = usize::from(child.num_upvalues);
+        if pc + count >= proto.code.len() {
+            self.report(Some(pc), DiagnosticKind::MissingClosureBindings);
+            return pc;
+        }
+        for binding in pc + 1..=pc + count {
+            let instr = &proto.code[binding];
+            match opcode(instr) {
+                Some(Opcode::MOVE) => self.register(binding, "B", instr.b()),
+                Some(Opcode::GETUPVAL) if instr.b() >= u32::from(proto.num_upvalues) => {
+                    self.report(Some(binding), DiagnosticKind::UpvalueOutOfRange(instr.b()));
+                }
+                Some(Opcode::GETUPVAL) => {}
+                _ => self.report(
+                    Some(binding),
+                    DiagnosticKind::InvalidClosureBinding(binding - pc - 1),
+                ),
+            }
+        }
+        pc + count
+    }
+}
+
+impl fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::StackTooLarge(size) => {
+                write!(f, "stack size {size} is above the limit of {MAX_STACK}")
+            }
+            DiagnosticKind::ParamsExceedStack => write!(f, "parameters do not fit in the stack"),
+            DiagnosticKind::InvalidVarargFlags(flags) => write!(f, "invalid vararg flags {flags}"),
+            DiagnosticKind::TooManyUpvalueNames(count) => {
+                write!(f, "{count} upvalue names for fewer upvalues")
+            }
+            DiagnosticKind::LineInfoMismatch(count) => {
+                write!(f, "{count} line entries do not match the code size")
+            }
+            DiagnosticKind::MissingReturn => write!(f, "code does not end with RETURN"),
+            DiagnosticKind::UnknownOpcode(op) => write!(f, "unknown opcode {op}"),
+            DiagnosticKind::RegisterOutOfRange { operand, reg } => {
+                write!(f, "{operand} register {reg} is outside the stack")
+            }
+            DiagnosticKind::ConstantOutOfRange { operand, index } => {
+                write!(f, "{operand} constant {index} is out of range")
+            }
+            DiagnosticKind::UnusedOperand { operand, value } => {
+                write!(f, "unused {operand} operand is {value} instead of 0")
+            }
+            DiagnosticKind::UpvalueOutOfRange(index) => {
+                write!(f, "upvalue {index} is out of range")
+            }
+            DiagnosticKind::GlobalNameNotString(index) => {
+                write!(f, "global name constant {index} is not a string")
+            }
+            DiagnosticKind::JumpOutOfRange(target) => {
+                write!(f, "jump target {target} is out of range")
+            }
+            DiagnosticKind::JumpIntoSetlistBatch(target) => {
+                write!(f, "jump target {target} is a SETLIST batch number")
+            }
+            DiagnosticKind::MissingJump => write!(f, "test is not followed by a JMP"),
+            DiagnosticKind::SkipOutOfRange => write!(f, "skips past the end of the code"),
+            DiagnosticKind::SkipIntoSetlistBatch => {
+                write!(f, "skips into a SETLIST batch number")
+            }
+            DiagnosticKind::EmptyConcat => write!(f, "CONCAT range is empty"),
+            DiagnosticKind::NoLoopVariables => write!(f, "TFORLOOP has no loop variables"),
+            DiagnosticKind::UnmatchedForLoop => {
+                write!(f, "FORPREP and FORLOOP do not jump to each other")
+            }
+            DiagnosticKind::UnconsumedOpenResult => {
+                write!(f, "open results are not consumed by the next instruction")
+            }
+            DiagnosticKind::MissingSetlistBatch => write!(f, "SETLIST batch number is missing"),
+            DiagnosticKind::ClosureOutOfRange(index) => {
+                write!(f, "CLOSURE prototype {index} is out of range")
+            }
+            DiagnosticKind::MissingClosureBindings => {
+                write!(f, "CLOSURE upvalue bindings run past the end of the code")
+            }
+            DiagnosticKind::InvalidClosureBinding(index) => {
+                write!(
+                    f,
+                    "CLOSURE upvalue binding {index} is not a MOVE or GETUPVAL"
+                )
+            }
+            DiagnosticKind::VarargInFixedFunction => {
+                write!(f, "VARARG in a function without usable varargs")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "main")?;
+        for index in &self.path {
+            write!(f, " > proto[{index}]")?;
+        }
+        if let Some(pc) = self.pc {
+            write!(f, " at pc {pc}")?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Checks a function and its children the way Lua 5.1 does before running a chunk, so
+/// malformed or hostile code is reported instead of reaching the decompiler. An empty
+/// result means every check passed
+pub fn verify(proto: &FunctionPrototype) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    verify_function(proto, &mut Vec::new(), &mut diagnostics);
+    diagnostics
+}
+
+fn verify_function(
+    proto: &FunctionPrototype,
+    path: &mut Vec<usize>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut verifier = Verifier {
+        proto,
+        path,
+        diagnostics,
+    };
+    verifier.header();
+    verifier.code();
+
+    for (index, child) in proto.prototypes.iter().enumerate() {
+        path.push(index);
+        verify_function(child, path, diagnostics);
+        path.pop();
+    }
+}
+
+fn is_open_setlist(instr: &Instruction) -> bool {
+    opcode(instr) == Some(Opcode::SETLIST) && instr.c() == 0
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use DiagnosticKind::*;
+
+    fn check(listing: &str) -> Vec<(Option<usize>, DiagnosticKind)> {
+        let proto = assemble(listing).expect("valid listing");
+        verify_proto(&proto)
+    }
+
+    fn verify_proto(proto: &FunctionPrototype) -> Vec<(Option<usize>, DiagnosticKind)> {
+        verify(proto)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.pc, diagnostic.kind))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_code() {
+        let listing = r#"
+            .vararg 2
+            .stack 7
+            .const "print"
+            .const 1
+            .func
+                .upvals 1
+                .stack 1
+                GETUPVAL 0 0
+                RETURN   0 2
+            .end
+                CLOSURE   0 0
+                MOVE      0 0
+                GETGLOBAL 1 K0
+                VARARG    2 0
+                CALL      1 0 1
+                LOADK     3 K1
+                LOADK     4 K1
+                LOADK     5 K1
+                FORPREP   3 test
+            body:
+                MOVE      1 6
+            test:
+                FORLOOP   3 body
+                RETURN    0 1
+        "#;
+        assert_eq!(check(listing), []);
+    }
+
+    #[test]
+    fn stack_too_large() {
+        assert_eq!(
+            check(".stack 251\nRETURN 0 1"),
+            [(None, StackTooLarge(251))]
+        );
+    }
+
+    #[test]
+    fn params_exceed_stack() {
+        assert_eq!(
+            check(".params 2\n.stack 1\nRETURN 0 1"),
+            [(None, ParamsExceedStack)]
+        );
+    }
+
+    #[test]
+    fn invalid_vararg_flags() {
+        assert_eq!(
+            check(".vararg 4\n.stack 1\nRETURN 0 1"),
+            [(None, InvalidVarargFlags(4))]
+        );
+    }
+
+    #[test]
+    fn too_many_upvalue_names() {
+        assert_eq!(
+            check(".upvals 0 \"a\"\n.stack 1\nRETURN 0 1"),
+            [(None, TooManyUpvalueNames(1))]
+        );
+    }
+
+    #[test]
+    fn line_info_mismatch() {
+        let mut proto = assemble(".stack 1\n.line 1\nRETURN 0 1").expect("valid listing");
+        proto.debug_info.lineinfo.push(1);
+        assert_eq!(verify_proto(&proto), [(None, LineInfoMismatch(2))]);
+    }
+
+    #[test]
+    fn missing_return() {
+        assert_eq!(check(".stack 1\nLOADNIL 0 0"), [(None, MissingReturn)]);
+    }
+
+    #[test]
+    fn unknown_opcode() {
+        assert_eq!(
+            check(".stack 1\n.word 0x0000003f\nRETURN 0 1"),
+            [(Some(0), UnknownOpcode(63))]
+        );
+    }
+
+    #[test]
+    fn register_out_of_range() {
+        let operand = "A";
+        assert_eq!(
+            check(".stack 1\nMOVE 1 0\nRETURN 0 1"),
+            [(Some(0), RegisterOutOfRange { operand, reg: 1 })]
+        );
+    }
+
+    #[test]
+    fn constant_out_of_range() {
+        let operand = "Bx";
+        assert_eq!(
+            check(".stack 1\nLOADK 0 K0\nRETURN 0 1"),
+            [(Some(0), ConstantOutOfRange { operand, index: 0 })]
+        );
+    }
+
+    #[test]
+    fn unused_operand() {
+        let operand = "C";
+        assert_eq!(
+            check(".stack 1\nLOADNIL 0 0 1\nRETURN 0 1"),
+            [(Some(0), UnusedOperand { operand, value: 1 })]
+        );
+    }
+
+    #[test]
+    fn upvalue_out_of_range() {
+        assert_eq!(
+            check(".stack 1\nGETUPVAL 0 0\nRETURN 0 1"),
+            [(Some(0), UpvalueOutOfRange(0))]
+        );
+    }
+
+    #[test]
+    fn global_name_not_string() {
+        assert_eq!(
+            check(".stack 1\n.const 1\nGETGLOBAL 0 K0\nRETURN 0 1"),
+            [(Some(0), GlobalNameNotString(0))]
+        );
+    }
+
+    #[test]
+    fn jump_out_of_range() {
+        assert_eq!(
+            check(".stack 1\nJMP 5\nRETURN 0 1"),
+            [(Some(0), JumpOutOfRange(6))]
+        );
+    }
+
+    #[test]
+    fn jump_into_setlist_batch() {
+        let listing = ".stack 2\nJMP L2\nSETLIST 0 1 0\nL2:\n.word 1\nRETURN 0 1";
+        assert_eq!(check(listing), [(Some(0), JumpIntoSetlistBatch(2))]);
+    }
+
+    #[test]
+    fn missing_jump() {
+        assert_eq!(
+            check(".stack 1\nTEST 0 0 1\nLOADNIL 0 0\nRETURN 0 1"),
+            [(Some(0), MissingJump)]
+        );
+    }
+
+    #[test]
+    fn skip_out_of_range() {
+        assert_eq!(
+            check(".stack 1\nLOADBOOL 0 1 1\nRETURN 0 1"),
+            [(Some(0), SkipOutOfRange)]
+        );
+    }
+
+    #[test]
+    fn skip_into_setlist_batch() {
+        let listing = ".stack 2\nLOADBOOL 0 1 1\nSETLIST 0 1 0\n.word 1\nRETURN 0 1";
+        assert_eq!(check(listing), [(Some(0), SkipIntoSetlistBatch)]);
+    }
+
+    #[test]
+    fn empty_concat() {
+        assert_eq!(
+            check(".stack 2\nCONCAT 0 1 1\nRETURN 0 1"),
+            [(Some(0), EmptyConcat)]
+        );
+    }
+
+    #[test]
+    fn no_loop_variables() {
+        assert_eq!(
+            check(".stack 3\nTFORLOOP 0 0 0\nRETURN 0 1"),
+            [(Some(0), NoLoopVariables)]
+        );
+    }
+
+    #[test]
+    fn unmatched_for_loop() {
+        assert_eq!(
+            check(".stack 4\nL0:\nFORPREP 0 L0\nRETURN 0 1"),
+            [(Some(0), UnmatchedForLoop)]
+        );
+        let listing = ".stack 5\nFORPREP 0 L2\nL1:\nMOVE 4 3\nL2:\nFORLOOP 1 L1\nRETURN 0 1";
+        assert_eq!(
+            check(listing),
+            [(Some(0), UnmatchedForLoop), (Some(2), UnmatchedForLoop)]
+        );
+    }
+
+    #[test]
+    fn unconsumed_open_result() {
+        assert_eq!(
+            check(".stack 1\nCALL 0 1 0\nRETURN 0 1"),
+            [(Some(0), UnconsumedOpenResult)]
+        );
+    }
+
+    #[test]
+    fn missing_setlist_batch() {
+        assert_eq!(
+            check(".stack 2\nSETLIST 0 1 0\nRETURN 0 1"),
+            [(Some(0), MissingSetlistBatch)]
+        );
+    }
+
+    #[test]
+    fn closure_out_of_range() {
+        assert_eq!(
+            check(".stack 1\nCLOSURE 0 0\nRETURN 0 1"),
+            [(Some(0), ClosureOutOfRange(0))]
+        );
+    }
+
+    #[test]
+    fn missing_closure_bindings() {
+        let listing =
+            ".func\n.upvals 2\n.stack 1\nRETURN 0 1\n.end\n.stack 1\nCLOSURE 0 0\nRETURN 0 1";
+        assert_eq!(check(listing), [(Some(0), MissingClosureBindings)]);
+    }
+
+    #[test]
+    fn invalid_closure_binding() {
+        let listing = ".func\n.upvals 1\n.stack 1\nRETURN 0 1\n.end\n\
+                       .stack 1\nCLOSURE 0 0\nLOADNIL 0 0\nRETURN 0 1";
+        assert_eq!(check(listing), [(Some(1), InvalidClosureBinding(0))]);
+    }
+
+    #[test]
+    fn vararg_in_fixed_function() {
+        assert_eq!(
+            check(".stack 1\nVARARG 0 2\nRETURN 0 1"),
+            [(Some(0), VarargInFixedFunction)]
+        );
+    }
+
+    #[test]
+    fn reports_the_path_of_children() {
+        let proto = assemble(".func\n.stack 1\nRETURN 1 1\n.end\n.stack 1\nRETURN 0 1")
+            .expect("valid listing");
+        let operand = "A";
+        assert_eq!(
+            verify(&proto),
+            [Diagnostic {
+                path: vec![0],
+                pc: Some(0),
+                kind: RegisterOutOfRange { operand, reg: 1 },
+            }]
+        );
+    }
+}
@@ -0,0 +1,650 @@
+/*
+  Assembles a text listing into Lua 5.1 function prototypes
+*/
+
+use super::lexer::{Token, tokenize};
+use crate::analysis::usage::{reads_at, writes_at};
+use crate::parser::bytecode::{
+    Constant, DebugInfo, FunctionPrototype, Instruction, InstructionFormat, LUA51_OPCODES,
+    LocalVariable, Opcode, OperandMask,
+};
+use std::collections::HashMap;
+use std::fmt;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// lparser.c:open_func, registers 0 and 1 are always available
+const MIN_STACK_SIZE: u32 = 2;
+
+// lopcodes.h:MAXINDEXRK, highest constant an RK operand can hold
+const MAX_INDEX_RK: i64 = 255;
+
+// lopcodes.h:BITRK
+const BIT_RK: u32 = 1 << 8;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// The reason assembling stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownDirective(String),
+    UnknownOpcode(String),
+    InvalidOperand(String),
+    MissingOperand,
+    TooManyOperands,
+    OutOfRange { operand: &'static str, value: i64 },
+    DuplicateLabel(String),
+    DuplicateFunction(String),
+    UndefinedLabel(String),
+    UndefinedFunction(String),
+    UnterminatedString,
+    InvalidEscape,
+    UnexpectedEnd,        // `.end` outside of a `.func`
+    UnterminatedFunction, // `.func` still open at the end of the listing
+}
+
+/// Error returned by `assemble`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    pub kind: AssembleErrorKind,
+    pub line: usize, // Line of the listing, starting at 1
+}
+
+/// A reference resolved once the function holding it is complete
+enum Fixup {
+    Jump(String),    // sBx of a jump to a label
+    Closure(String), // Bx of a CLOSURE naming a child function
+}
+
+/// A pc given as a number or as a label
+enum Position {
+    Pc(u32),
+    Label(String),
+}
+
+/// A local declared by `.local`, whose range may name labels defined later
+struct PendingLocal {
+    line: usize,
+    name: Vec<u8>,
+    start: Position,
+    end: Position,
+}
+
+/// A function whose `.end` has not been reached yet
+struct Function {
+    proto: FunctionPrototype,
+    name: Option<String>, // Name given to `.func`, for CLOSURE operands
+    stack: Option<u8>,    // Set by `.stack`, computed from the code otherwise
+    lines: Option<u32>,   // Line of the next instructions, once a `.line` is seen
+    labels: HashMap<String, usize>,
+    children: HashMap<String, usize>,
+    fixups: Vec<(usize, usize, Fixup)>, // Pc, listing line and reference
+    locals: Vec<PendingLocal>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Function {
+    fn new(name: Option<String>) -> Self {
+        Self {
+            proto: FunctionPrototype {
+                source_name: None,
+                line_defined: 0,
+                last_line_defined: 0,
+                num_upvalues: 0,
+                num_params: 0,
+                is_vararg: 0,
+                max_stack_size: 0,
+                code: Vec::new(),
+                constants: Vec::new(),
+                prototypes: Vec::new(),
+                upvalue_descriptors: Vec::new(),
+                debug_info: DebugInfo {
+                    lineinfo: Vec::new(),
+                    abslineinfo: Vec::new(),
+                    locals: Vec::new(),
+                    upvalues: Vec::new(),
+                },
+            },
+            name,
+            stack: None,
+            lines: None,
+            labels: HashMap::new(),
+            children: HashMap::new(),
+            fixups: Vec::new(),
+            locals: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, instr: Instruction) {
+        self.proto.code.push(instr);
+        self.proto.debug_info.lineinfo.push(self.lines.unwrap_or(0));
+    }
+
+    fn directive(&mut self, name: &str, args: &[Token]) -> Result<(), AssembleErrorKind> {
+        match name {
+            ".source" => self.proto.source_name = Some(bytes(single(args)?)),
+            ".linedefined" => self.proto.line_defined = number(single(args)?, "line")?,
+            ".lastlinedefined" => self.proto.last_line_defined = number(single(args)?, "line")?,
+            ".params" => self.proto.num_params = number(single(args)?, "params")?,
+            ".vararg" => self.proto.is_vararg = number(single(args)?, "vararg")?,
+            ".stack" => self.stack = Some(number(single(args)?, "stack")?),
+            ".upvals" => {
+                let (count, names) = args
+                    .split_first()
+                    .ok_or(AssembleErrorKind::MissingOperand)?;
+                self.proto.num_upvalues = number(count, "upvals")?;
+                self.proto.debug_info.upvalues = names.iter().map(bytes).collect();
+            }
+            ".const" => {
+                let constant = literal(single(args)?)?;
+                self.proto.constants.push(constant);
+            }
+            ".line" => self.lines = Some(number(single(args)?, "line")?),
+            ".word" => {
+                let word = single(args)?;
+                let value = number::<u32>(word, "word")?;
+                self.push(Instruction::new(value));
+            }
+            _ => return Err(AssembleErrorKind::UnknownDirective(name.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Records a `.local NAME START END`, which takes the line for error reporting
+    fn local(&mut self, args: &[Token], line: usize) -> Result<(), AssembleErrorKind> {
+        let [name, start, end] = args else {
+            return Err(operand_count(args.len(), 3));
+        };
+        self.locals.push(PendingLocal {
+            line,
+            name: bytes(name),
+            start: position(start)?,
+            end: position(end)?,
+        });
+        Ok(())
+    }
+
+    fn label(&mut self, name: &str) -> Result<(), AssembleErrorKind> {
+        let pc = self.proto.code.len();
+        match self.labels.insert(name.to_string(), pc) {
+            Some(_) => Err(AssembleErrorKind::DuplicateLabel(name.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Encodes an instruction, its missing trailing operands being 0
+    fn instruction(
+        &mut self,
+        mnemonic: &str,
+        operands: &[Token],
+        line: usize,
+    ) -> Result<(), AssembleErrorKind> {
+        let Some(index) = LUA51_OPCODES
+            .names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(mnemonic))
+        else {
+            return Err(AssembleErrorKind::UnknownOpcode(mnemonic.to_string()));
+        };
+        let (format, b_mode, c_mode) = LUA51_OPCODES.modes[index];
+        let op = index as u8;
+        let pc = self.proto.code.len();
+        let operand = |i: usize| operands.get(i);
+
+        let instr = match format {
+            InstructionFormat::IABC => {
+                if operands.len() > 3 {
+                    return Err(AssembleErrorKind::TooManyOperands);
+                }
+                let a = register(operand(0))?;
+                let b = self.rk(operand(1), b_mode, "B")?;
+                let c = self.rk(operand(2), c_mode, "C")?;
+                Instruction::abc(op, a, b, c)
+            }
+            InstructionFormat::IABx => {
+                if operands.len() > 2 {
+                    return Err(AssembleErrorKind::TooManyOperands);
+                }
+                let a = register(operand(0))?;
+                let bx = match operand(1) {
+                    None => 0,
+                    Some(token) if b_mode == OperandMask::OpArgK => self.constant(token)?,
+                    Some(Token::Word(word))
+                        if op == Opcode::CLOSURE as u8 && integer(word).is_none() =>
+                    {
+                        self.fixups.push((pc, line, Fixup::Closure(word.clone())));
+                        0
+                    }
+                    Some(token) => ranged(token, "Bx", Instruction::MAXARG_BX)?,
+                };
+                Instruction::abx(op, a, bx)
+            }
+            InstructionFormat::IAsBx => {
+                let (a, target) = match operands {
+                    [target] => (0, target),
+                    [a, target] => (register(Some(a))?, target),
+                    [] => return Err(AssembleErrorKind::MissingOperand),
+                    _ => return Err(AssembleErrorKind::TooManyOperands),
+                };
+                let sbx = match target {
+                    Token::Word(word) => match integer(word) {
+                        Some(value) => jump_offset(value)?,
+                        None => {
+                            self.fixups.push((pc, line, Fixup::Jump(word.clone())));
+                            0
+                        }
+                    },
+                    Token::Str(_) => return Err(invalid(target)),
+                };
+                Instruction::asbx(op, a, sbx)
+            }
+            InstructionFormat::IAx | InstructionFormat::IsJ => {
+                unreachable!("Lua 5.1 instructions have no Ax or sJ operand")
+            }
+        };
+        self.push(instr);
+        Ok(())
+    }
+
+    /// Reads a B or C operand: a register, or a constant when the operand mode allows one
+    fn rk(
+        &mut self,
+        token: Option<&Token>,
+        mode: OperandMask,
+        operand: &'static str,
+    ) -> Result<u32, AssembleErrorKind> {
+        let Some(token) = token else {
+            return Ok(0);
+        };
+        if mode == OperandMask::OpArgK && !is_register(token) {
+            let index = self.constant(token)?;
+            if i64::from(index) > MAX_INDEX_RK {
+                return Err(AssembleErrorKind::OutOfRange {
+                    operand,
+                    value: index.into(),
+                });
+            }
+            return Ok(index | BIT_RK);
+        }
+        ranged(token, operand, Instruction::MAXARG_B)
+    }
+
+    /// Index of a constant operand, written `K3` or as a string, `nil`, `true` or `false`
+    /// literal added to the constants. Numbers need a `.const`, as a bare integer is an index
+    fn constant(&mut self, token: &Token) -> Result<u32, AssembleErrorKind> {
+        if let Token::Word(word) = token
+            && let Some(index) = word.strip_prefix('K').and_then(integer)
+        {
+            return match u32::try_from(index) {
+                Ok(index) if index <= Instruction::MAXARG_BX => Ok(index),
+                _ => Err(AssembleErrorKind::OutOfRange {
+                    operand: "constant",
+                    value: index,
+                }),
+            };
+        }
+        let value = match token {
+            Token::Str(bytes) => Constant::String(bytes.clone()),
+            Token::Word(word) => match word.as_str() {
+                "nil" => Constant::Nil,
+                "true" => Constant::Boolean(true),
+                "false" => Constant::Boolean(false),
+                _ => return ranged(token, "Bx", Instruction::MAXARG_BX),
+            },
+        };
+        let constants = &mut self.proto.constants;
+        let index = match constants.iter().position(|k| same_constant(k, &value)) {
+            Some(index) => index,
+            None => {
+                constants.push(value);
+                constants.len() - 1
+            }
+        };
+        Ok(index as u32)
+    }
+
+    /// Resolves labels and child names, then fills in what the listing left out
+    fn finish(mut self) -> Result<FunctionPrototype, AssembleError> {
+        for (pc, line, fixup) in std::mem::take(&mut self.fixups) {
+            let at = |kind| AssembleError { kind, line };
+            let instr = &self.proto.code[pc];
+            self.proto.code[pc] = match fixup {
+                Fixup::Jump(label) => {
+                    let target = self.resolve(&label).map_err(at)?;
+                    let sbx = jump_offset(target as i64 - pc as i64 - 1).map_err(at)?;
+                    Instruction::asbx(instr.op(), instr.a(), sbx)
+                }
+                Fixup::Closure(name) => match self.children.get(&name) {
+                    Some(&index) => Instruction::abx(instr.op(), instr.a(), index as u32),
+                    None => return Err(at(AssembleErrorKind::UndefinedFunction(name))),
+                },
+            };
+        }
+
+        for local in std::mem::take(&mut self.locals) {
+            let at = |kind| AssembleError {
+                kind,
+                line: local.line,
+            };
+            let startpc = self.pc(&local.start).map_err(at)?;
+            let endpc = self.pc(&local.end).map_err(at)?;
+            self.proto.debug_info.locals.push(LocalVariable {
+                varname: local.name,
+                startpc,
+                endpc,
+            });
+        }
+
+        if self.lines.is_none() {
+            self.proto.debug_info.lineinfo.clear();
+        }
+        self.proto.max_stack_size = match self.stack {
+            Some(stack) => stack,
+            None => stack_size(&self.proto),
+        };
+        Ok(self.proto)
+    }
+
+    fn resolve(&self, label: &str) -> Result<usize, AssembleErrorKind> {
+        self.labels
+            .get(label)
+            .copied()
+            .ok_or_else(|| AssembleErrorKind::UndefinedLabel(label.to_string()))
+    }
+
+    fn pc(&self, position: &Position) -> Result<u32, AssembleErrorKind> {
+        match position {
+            Position::Pc(pc) => Ok(*pc),
+            Position::Label(label) => self.resolve(label).map(|pc| pc as u32),
+        }
+    }
+}
+
+impl fmt::Display for AssembleErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssembleErrorKind::UnknownDirective(name) => write!(f, "unknown directive {name}"),
+            AssembleErrorKind::UnknownOpcode(name) => write!(f, "unknown opcode {name}"),
+            AssembleErrorKind::InvalidOperand(text) => write!(f, "invalid operand {text}"),
+            AssembleErrorKind::MissingOperand => write!(f, "missing operand"),
+            AssembleErrorKind::TooManyOperands => write!(f, "too many operands"),
+            AssembleErrorKind::OutOfRange { operand, value } => {
+                write!(f, "{operand} operand {value} is out of range")
+            }
+            AssembleErrorKind::DuplicateLabel(name) => write!(f, "label {name} is defined twice"),
+            AssembleErrorKind::DuplicateFunction(name) => {
+                write!(f, "function {name} is defined twice")
+            }
+            AssembleErrorKind::UndefinedLabel(name) => write!(f, "undefined label {name}"),
+            AssembleErrorKind::UndefinedFunction(name) => write!(f, "undefined function {name}"),
+            AssembleErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            AssembleErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+            AssembleErrorKind::UnexpectedEnd => write!(f, ".end without a matching .func"),
+            AssembleErrorKind::UnterminatedFunction => write!(f, ".func without a matching .end"),
+        }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Assembles a listing into the main function of a chunk. Lines hold directives,
+/// `label:` definitions and instructions, with `;` starting a comment:
+///
+/// ```text
+/// .source "@demo.lua"
+/// .vararg 2
+///     GETGLOBAL 0 "print"
+///     LOADK 1 "hello"
+///     CALL 0 2 1
+/// .func add
+///     .params 2
+///     ADD 2 0 1
+///     RETURN 2 2
+/// .end
+///     CLOSURE 0 add
+///     RETURN 0 1
+/// ```
+///
+/// Functions take `.source`, `.linedefined`, `.lastlinedefined`, `.params`, `.vararg`,
+/// `.upvals COUNT NAME...`, `.stack`, `.const VALUE`, `.local NAME START END` (pcs or
+/// labels), `.line` for the following instructions and `.word` for raw words, and nest
+/// with `.func [NAME]` ... `.end`. Constant operands are `K3` or a literal, jumps take a
+/// label or an sBx offset, and CLOSURE takes a child index or name. The stack size is
+/// computed from the registers used unless `.stack` is given
+pub fn assemble(source: &str) -> Result<FunctionPrototype, AssembleError> {
+    let mut functions = vec![Function::new(None)];
+    let mut last = 0;
+    for (index, source) in source.lines().enumerate() {
+        let line = index + 1;
+        last = line;
+        statement(&mut functions, source, line)?;
+    }
+
+    let main = functions.remove(0);
+    if !functions.is_empty() {
+        return Err(AssembleError {
+            kind: AssembleErrorKind::UnterminatedFunction,
+            line: last,
+        });
+    }
+    main.finish()
+}
+
+/// Assembles one line into the innermost open function
+fn statement(
+    functions: &mut Vec<Function>,
+    source: &str,
+    line: usize,
+) -> Result<(), AssembleError> {
+    let at = |kind| AssembleError { kind, line };
+    let tokens = tokenize(source).map_err(at)?;
+    let mut rest = tokens.as_slice();
+    let function = functions.last_mut().expect("the main function stays open");
+
+    // Labels come first, and may share the line with an instruction
+    while let Some((Token::Word(word), tail)) = rest.split_first()
+        && let Some(label) = word.strip_suffix(':')
+    {
+        function.label(label).map_err(at)?;
+        rest = tail;
+    }
+    let Some((head, args)) = rest.split_first() else {
+        return Ok(());
+    };
+    let Token::Word(head) = head else {
+        return Err(at(invalid(head)));
+    };
+
+    match head.as_str() {
+        ".func" => {
+            let name = match args {
+                [] => None,
+                [name] => Some(text(name)),
+                _ => return Err(at(AssembleErrorKind::TooManyOperands)),
+            };
+            functions.push(Function::new(name));
+        }
+        ".end" => {
+            if functions.len() < 2 {
+                return Err(at(AssembleErrorKind::UnexpectedEnd));
+            }
+            let child = functions.pop().expect("checked above");
+            let name = child.name.clone();
+            let proto = child.finish()?;
+            let parent = functions.last_mut().expect("checked above");
+            if let Some(name) = name
+                && parent
+                    .children
+                    .insert(name.clone(), parent.proto.prototypes.len())
+                    .is_some()
+            {
+                return Err(at(AssembleErrorKind::DuplicateFunction(name)));
+            }
+            parent.proto.prototypes.push(proto);
+        }
+        ".local" => function.local(args, line).map_err(at)?,
+        directive if directive.starts_with('.') => {
+            function.directive(directive, args).map_err(at)?
+        }
+        mnemonic => function.instruction(mnemonic, args, line).map_err(at)?,
+    }
+    Ok(())
+}
+
+/// Default stack size: one past the highest register read or written
+fn stack_size(proto: &FunctionPrototype) -> u8 {
+    let mut size = MIN_STACK_SIZE;
+    for pc in 0..proto.code.len() {
+        let read = reads_at(proto, pc)
+            .into_iter()
+            .max()
+            .map_or(0, |reg| reg + 1);
+        size = size.max(read).max(writes_at(proto, pc).end);
+    }
+    size.min(u32::from(u8::MAX)) as u8
+}
+
+fn single(args: &[Token]) -> Result<&Token, AssembleErrorKind> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err(operand_count(args.len(), 1)),
+    }
+}
+
+fn operand_count(given: usize, expected: usize) -> AssembleErrorKind {
+    match given < expected {
+        true => AssembleErrorKind::MissingOperand,
+        false => AssembleErrorKind::TooManyOperands,
+    }
+}
+
+fn invalid(token: &Token) -> AssembleErrorKind {
+    AssembleErrorKind::InvalidOperand(match token {
+        Token::Word(word) => word.clone(),
+        Token::Str(bytes) => format!("\"{}\"", String::from_utf8_lossy(bytes)),
+    })
+}
+
+/// Parses a decimal or `0x` hexadecimal integer
+fn integer(word: &str) -> Option<i64> {
+    let (negative, digits) = match word.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, word),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+        None if digits.bytes().all(|b| b.is_ascii_digit()) => digits.parse().ok()?,
+        None => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_register(token: &Token) -> bool {
+    matches!(token, Token::Word(word) if integer(word).is_some())
+}
+
+/// Reads an integer operand of a directive
+fn number<T: TryFrom<i64>>(token: &Token, operand: &'static str) -> Result<T, AssembleErrorKind> {
+    let Token::Word(word) = token else {
+        return Err(invalid(token));
+    };
+    let value = integer(word).ok_or_else(|| invalid(token))?;
+    T::try_from(value).map_err(|_| AssembleErrorKind::OutOfRange { operand, value })
+}
+
+/// Reads an integer operand no larger than `max`
+fn ranged(token: &Token, operand: &'static str, max: u32) -> Result<u32, AssembleErrorKind> {
+    let value = number::<u32>(token, operand)?;
+    match value <= max {
+        true => Ok(value),
+        false => Err(AssembleErrorKind::OutOfRange {
+            operand,
+            value: value.into(),
+        }),
+    }
+}
+
+fn register(token: Option<&Token>) -> Result<u32, AssembleErrorKind> {
+    token.map_or(Ok(0), |token| ranged(token, "A", Instruction::MAXARG_A))
+}
+
+fn jump_offset(value: i64) -> Result<i32, AssembleErrorKind> {
+    let max = i64::from(Instruction::MAXARG_SBX);
+    match (-max..=max + 1).contains(&value) {
+        true => Ok(value as i32),
+        false => Err(AssembleErrorKind::OutOfRange {
+            operand: "sBx",
+            value,
+        }),
+    }
+}
+
+fn position(token: &Token) -> Result<Position, AssembleErrorKind> {
+    match token {
+        Token::Word(word) => match integer(word) {
+            Some(_) => number(token, "pc").map(Position::Pc),
+            None => Ok(Position::Label(word.clone())),
+        },
+        Token::Str(_) => Err(invalid(token)),
+    }
+}
+
+/// Reads a name, quoted or bare
+fn text(token: &Token) -> String {
+    match token {
+        Token::Word(word) => word.clone(),
+        Token::Str(bytes) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Reads a name kept in the chunk as raw bytes, quoted or bare
+fn bytes(token: &Token) -> Vec<u8> {
+    match token {
+        Token::Word(word) => word.clone().into_bytes(),
+        Token::Str(bytes) => bytes.clone(),
+    }
+}
+
+/// Reads the value of a `.const`: `nil`, `true`, `false`, a number or a quoted string
+fn literal(token: &Token) -> Result<Constant, AssembleErrorKind> {
+    let word = match token {
+        Token::Str(bytes) => return Ok(Constant::String(bytes.clone())),
+        Token::Word(word) => word.as_str(),
+    };
+    match word {
+        "nil" => Ok(Constant::Nil),
+        "true" => Ok(Constant::Boolean(true)),
+        "false" => Ok(Constant::Boolean(false)),
+        _ => match integer(word) {
+            Some(value) => Ok(Constant::Integer(value)),
+            None => word
+                .parse()
+                .map(Constant::Number)
+                .map_err(|_| invalid(token)),
+        },
+    }
+}
+
+/// Whether two constants are interchangeable, comparing numbers by their bits
+fn same_constant(a: &Constant, b: &Constant) -> bool {
+    match (a, b) {
+        (Constant::Nil, Constant::Nil) => true,
+        (Constant::Boolean(a), Constant::Boolean(b)) => a == b,
+        (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+        (Constant::Integer(a), Constant::Integer(b)) => a == b,
+        (Constant::String(a), Constant::String(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,111 @@
+/*
+  Tokens of an assembly listing line, and the string escapes shared with the listing
+*/
+
+use super::assemble::AssembleErrorKind;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String), // Directives, opcodes, numbers, labels and operand references
+    Str(Vec<u8>), // Quoted string, with its escapes decoded
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Splits a line into tokens. Commas separate like whitespace and `;` starts a comment
+pub fn tokenize(line: &str) -> Result<Vec<Token>, AssembleErrorKind> {
+    let bytes = line.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b';' => break,
+            b if b.is_ascii_whitespace() || b == b',' => pos += 1,
+            b'"' => {
+                let (value, end) = string(bytes, pos + 1)?;
+                tokens.push(Token::Str(value));
+                pos = end;
+            }
+            _ => {
+                let start = pos;
+                while pos < bytes.len()
+                    && !matches!(bytes[pos], b';' | b'"' | b',')
+                    && !bytes[pos].is_ascii_whitespace()
+                {
+                    pos += 1;
+                }
+                tokens.push(Token::Word(line[start..pos].to_string()));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Decodes a string body starting after its opening quote, returning the position
+/// after the closing one. Escapes follow Lua 5.1 (llex.c:read_string)
+fn string(bytes: &[u8], mut pos: usize) -> Result<(Vec<u8>, usize), AssembleErrorKind> {
+    let mut value = Vec::new();
+    loop {
+        match bytes.get(pos) {
+            None => return Err(AssembleErrorKind::UnterminatedString),
+            Some(b'"') => return Ok((value, pos + 1)),
+            Some(b'\\') => {
+                let escape = bytes.get(pos + 1).copied();
+                pos += 2;
+                value.push(match escape {
+                    Some(b'a') => 7,
+                    Some(b'b') => 8,
+                    Some(b'f') => 12,
+                    Some(b'n') => b'\n',
+                    Some(b'r') => b'\r',
+                    Some(b't') => b'\t',
+                    Some(b'v') => 11,
+                    Some(b @ (b'\\' | b'"' | b'\'')) => b,
+                    Some(b) if b.is_ascii_digit() => {
+                        let mut code = u32::from(b - b'0');
+                        for _ in 0..2 {
+                            match bytes.get(pos) {
+                                Some(&d) if d.is_ascii_digit() => {
+                                    code = code * 10 + u32::from(d - b'0')
+                                }
+                                _ => break,
+                            }
+                            pos += 1;
+                        }
+                        u8::try_from(code).map_err(|_| AssembleErrorKind::InvalidEscape)?
+                    }
+                    _ => return Err(AssembleErrorKind::InvalidEscape),
+                });
+            }
+            Some(&b) => {
+                value.push(b);
+                pos += 1;
+            }
+        }
+    }
+}
+
+/// Quotes raw string bytes for a listing, escaping everything but printable ASCII
+pub fn quote_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(b as char),
+            // Pad to three digits when a digit follows, so it is not read as part of it
+            _ if bytes.get(i + 1).is_some_and(u8::is_ascii_digit) => {
+                out.push_str(&format!("\\{b:03}"))
+            }
+            _ => out.push_str(&format!("\\{b}")),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,167 @@
+/*
+  Renders Lua 5.1 function prototypes as listings the assembler reads back
+*/
+
+use super::lexer::quote_bytes;
+use crate::analysis::usage::{jump_target, opcode};
+use crate::parser::bytecode::{
+    Constant, FunctionPrototype, Instruction, InstructionFormat, LUA51_OPCODES, Opcode, OperandMask,
+};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+const INDENT: &str = "    ";
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Writes a function and its children as a listing, which `assemble` turns back into
+/// the same prototypes
+pub fn disassemble(proto: &FunctionPrototype) -> String {
+    let mut out = String::new();
+    function(&mut out, proto, 0);
+    out
+}
+
+fn function(out: &mut String, proto: &FunctionPrototype, depth: usize) {
+    let indent = INDENT.repeat(depth);
+
+    if let Some(source) = &proto.source_name {
+        let source = quote_bytes(source);
+        let _ = writeln!(out, "{indent}.source {source}");
+    }
+    if proto.line_defined != 0 || proto.last_line_defined != 0 {
+        let _ = writeln!(out, "{indent}.linedefined {}", proto.line_defined);
+        let _ = writeln!(out, "{indent}.lastlinedefined {}", proto.last_line_defined);
+    }
+    if proto.num_params != 0 {
+        let _ = writeln!(out, "{indent}.params {}", proto.num_params);
+    }
+    if proto.is_vararg != 0 {
+        let _ = writeln!(out, "{indent}.vararg {}", proto.is_vararg);
+    }
+    let names = &proto.debug_info.upvalues;
+    if proto.num_upvalues != 0 || !names.is_empty() {
+        let _ = write!(out, "{indent}.upvals {}", proto.num_upvalues);
+        for name in names {
+            let _ = write!(out, " {}", quote_bytes(name));
+        }
+        out.push('\n');
+    }
+    let _ = writeln!(out, "{indent}.stack {}", proto.max_stack_size);
+
+    for (index, constant) in proto.constants.iter().enumerate() {
+        let _ = writeln!(out, "{indent}.const {:<23} ; K{index}", literal(constant));
+    }
+    for local in &proto.debug_info.locals {
+        let name = quote_bytes(&local.varname);
+        let _ = writeln!(
+            out,
+            "{indent}.local {name} {} {}",
+            local.startpc, local.endpc
+        );
+    }
+    for (index, child) in proto.prototypes.iter().enumerate() {
+        let _ = writeln!(out, "{indent}.func{INDENT}; F{index}");
+        function(out, child, depth + 1);
+        let _ = writeln!(out, "{indent}.end");
+    }
+
+    let count = proto.code.len();
+    let targets: BTreeSet<usize> = (0..count)
+        .filter(|&pc| is_jump(proto, pc))
+        .map(|pc| jump_target(pc, &proto.code[pc]))
+        .filter(|&target| target <= count)
+        .collect();
+    let lines = &proto.debug_info.lineinfo;
+    let lines = (lines.len() == count).then_some(lines);
+
+    let mut line = None;
+    for (pc, instr) in proto.code.iter().enumerate() {
+        if targets.contains(&pc) {
+            let _ = writeln!(out, "{indent}L{pc}:");
+        }
+        if let Some(lines) = lines
+            && line != Some(lines[pc])
+        {
+            line = Some(lines[pc]);
+            let _ = writeln!(out, "{indent}.line {}", lines[pc]);
+        }
+        let text = instruction(proto, pc, instr, count);
+        let _ = writeln!(out, "{indent}{INDENT}{text}");
+    }
+    if targets.contains(&count) {
+        let _ = writeln!(out, "{indent}L{count}:");
+    }
+}
+
+/// Whether `pc` holds an instruction with an sBx jump offset
+fn is_jump(proto: &FunctionPrototype, pc: usize) -> bool {
+    let instr = &proto.code[pc];
+    opcode(instr).is_some_and(|op| LUA51_OPCODES.modes[op as usize].0 == InstructionFormat::IAsBx)
+        && !proto.is_pseudo_instruction(pc)
+}
+
+fn instruction(proto: &FunctionPrototype, pc: usize, instr: &Instruction, count: usize) -> String {
+    let Some(op) = opcode(instr).filter(|_| !proto.is_setlist_batch(pc)) else {
+        return match proto.is_setlist_batch(pc) {
+            true => format!(".word {}", instr.raw()),
+            false => format!(".word 0x{:08x}    ; UNKNOWN({})", instr.raw(), instr.op()),
+        };
+    };
+    let name = LUA51_OPCODES.names[op as usize];
+    let (format, b_mode, c_mode) = LUA51_OPCODES.modes[op as usize];
+    let a = instr.a();
+
+    let operands = match format {
+        InstructionFormat::IABC => {
+            let mut operands = vec![a.to_string(), rk(instr.b(), b_mode), rk(instr.c(), c_mode)];
+            // Operands left out read back as 0, so only unused zeros are dropped
+            if c_mode == OperandMask::OpArgN && instr.c() == 0 {
+                operands.pop();
+                if b_mode == OperandMask::OpArgN && instr.b() == 0 {
+                    operands.pop();
+                }
+            }
+            operands
+        }
+        InstructionFormat::IABx => match b_mode {
+            OperandMask::OpArgK => vec![a.to_string(), format!("K{}", instr.bx())],
+            _ => vec![a.to_string(), instr.bx().to_string()],
+        },
+        InstructionFormat::IAsBx => {
+            let target = match jump_target(pc, instr) {
+                target if target <= count && is_jump(proto, pc) => format!("L{target}"),
+                _ => instr.sbx().to_string(),
+            };
+            match (op, a) {
+                (Opcode::JMP, 0) => vec![target],
+                _ => vec![a.to_string(), target],
+            }
+        }
+        InstructionFormat::IAx | InstructionFormat::IsJ => {
+            unreachable!("Lua 5.1 instructions have no Ax or sJ operand")
+        }
+    };
+    format!("{name:<9} {}", operands.join(" "))
+}
+
+/// A B or C operand, naming constants when the operand mode reads one
+fn rk(value: u32, mode: OperandMask) -> String {
+    match mode == OperandMask::OpArgK && value & (1 << 8) != 0 {
+        true => format!("K{}", value & !(1 << 8)),
+        false => value.to_string(),
+    }
+}
+
+/// A constant as written after `.const`
+fn literal(constant: &Constant) -> String {
+    match constant {
+        Constant::Nil => "nil".to_string(),
+        Constant::Boolean(value) => value.to_string(),
+        Constant::Number(value) => format!("{value:?}"),
+        Constant::Integer(value) => value.to_string(),
+        Constant::String(bytes) => quote_bytes(bytes),
+    }
+}
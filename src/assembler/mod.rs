@@ -0,0 +1,157 @@
+/*
+  Text assembly for Lua 5.1 bytecode: listings the disassembler writes and the
+  assembler reads, so dumps can be edited by hand and rebuilt
+*/
+
+mod assemble;
+mod lexer;
+mod listing;
+
+pub use assemble::{AssembleError, AssembleErrorKind, assemble};
+pub use lexer::quote_bytes;
+pub use listing::disassemble;
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::bytecode::{Constant, Header};
+    use crate::parser::parse_lua_bytecode;
+    use crate::writer::write_lua_bytecode;
+
+    /// A listing in the form `disassemble` writes, using every directive
+    const LISTING: &str = r#".source "@\255round.lua"
+.vararg 2
+.stack 5
+.const "t"                     ; K0
+.const 3                       ; K1
+.const 3.0                     ; K2
+.const -0.5                    ; K3
+.const true                    ; K4
+.const nil                     ; K5
+.const "a\0b"                  ; K6
+.local "t\200" 2 12
+.func    ; F0
+    .linedefined 4
+    .lastlinedefined 6
+    .params 1
+    .upvals 1 "u"
+    .stack 2
+    .line 5
+        GETUPVAL  0 0
+        RETURN    0 2
+        RETURN    0 1
+.end
+.line 1
+    NEWTABLE  0 3 0
+    LOADK     1 K1
+    LOADK     2 K2
+    LOADK     3 K3
+    SETLIST   0 3 1
+    CLOSURE   1 0
+    MOVE      0 0
+.line 2
+    TEST      0 0 1
+    JMP       L10
+    LOADBOOL  2 1 0
+L10:
+    EQ        0 K4 K5
+    JMP       L10
+    SETGLOBAL 0 K0
+    RETURN    0 1
+"#;
+
+    #[test]
+    fn listing_round_trips() {
+        let main = assemble(LISTING).expect("valid listing");
+        assert_eq!(disassemble(&main), LISTING);
+    }
+
+    #[test]
+    fn chunk_round_trips() {
+        let header = Header::lua51();
+        let main = assemble(LISTING).expect("valid listing");
+        let bytecode = write_lua_bytecode(&header, &main).expect("writable chunk");
+
+        let (header, main) = parse_lua_bytecode(&bytecode).expect("valid chunk");
+        let main = assemble(&disassemble(&main)).expect("valid listing");
+        assert_eq!(write_lua_bytecode(&header, &main), Ok(bytecode));
+    }
+
+    fn error(listing: &str) -> AssembleError {
+        assemble(listing).expect_err("invalid listing")
+    }
+
+    #[test]
+    fn rejects_undefined_label() {
+        assert_eq!(
+            error("    JMP L5\n    RETURN 0 1"),
+            AssembleError {
+                kind: AssembleErrorKind::UndefinedLabel("L5".to_string()),
+                line: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_label() {
+        assert_eq!(
+            error("L1:\n    JMP L1\nL1:\n    RETURN 0 1"),
+            AssembleError {
+                kind: AssembleErrorKind::DuplicateLabel("L1".to_string()),
+                line: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_operand_out_of_range() {
+        let err = error("    MOVE 256 0");
+        assert_eq!(
+            err.kind,
+            AssembleErrorKind::OutOfRange {
+                operand: "A",
+                value: 256
+            }
+        );
+        assert_eq!(err.to_string(), "line 1: A operand 256 is out of range");
+    }
+
+    #[test]
+    fn rejects_unclosed_function() {
+        assert_eq!(
+            error(".func\n    RETURN 0 1\n.func\n.end"),
+            AssembleError {
+                kind: AssembleErrorKind::UnterminatedFunction,
+                line: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn keeps_integer_constants() {
+        let main = assemble(".const 3\n.const 3.0\n.const 1e300").expect("valid listing");
+        match &main.constants[..] {
+            [
+                Constant::Integer(3),
+                Constant::Number(a),
+                Constant::Number(b),
+            ] if *a == 3.0 && *b == 1e300 => {}
+            constants => panic!("unexpected constants {constants:?}"),
+        }
+
+        // Integral chunks keep integers that no float could hold exactly
+        let header = Header {
+            integral_flag: true,
+            ..Header::lua51()
+        };
+        let main = assemble(".const 9007199254740993").expect("valid listing");
+        let bytecode = write_lua_bytecode(&header, &main).expect("writable chunk");
+        let (_, main) = parse_lua_bytecode(&bytecode).expect("valid chunk");
+        assert!(matches!(
+            main.constants[..],
+            [Constant::Integer(9_007_199_254_740_993)]
+        ));
+    }
+}
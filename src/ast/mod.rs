@@ -0,0 +1,9 @@
+/*
+  Lua source syntax tree, built by the decompiler and rendered by the printer
+*/
+
+mod nodes;
+mod printer;
+
+pub use nodes::{BinOp, Block, Expr, Field, Function, FunctionName, Stmt, UnOp};
+pub use printer::{Printer, PrinterConfig, as_identifier, format_number, is_identifier, quote};
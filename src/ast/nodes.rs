@@ -0,0 +1,158 @@
+/*
+  Statement and expression nodes of Lua source
+*/
+
+//////////////////////////////// Structs ////////////////////////////////
+
+pub type Block = Vec<Stmt>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+    Len,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Integer(i64),    // Lua 5.3 and later
+    String(Vec<u8>), // Raw bytes, which need not be UTF-8
+    VarArg,
+    Name(String),   // Local or upvalue
+    Global(String), // Field of the function environment
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    MethodCall(Box<Expr>, String, Vec<Expr>),
+    Function(Box<Function>),
+    Table(Vec<Field>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Local(Vec<String>, Vec<Expr>),
+    Assign(Vec<Expr>, Vec<Expr>),
+    Call(Expr),
+    If(Expr, Block, Block),
+    While(Expr, Block),
+    Repeat(Block, Expr),
+    NumericFor(String, Expr, Expr, Option<Expr>, Block),
+    GenericFor(Vec<String>, Vec<Expr>, Block),
+    FunctionDef(FunctionName, Box<Function>), // `function a.b:c() end`
+    LocalFunction(String, Box<Function>),
+    Return(Vec<Expr>),
+    Break,
+    Goto(String), // Lua 5.2 syntax, for jumps with no structured equivalent
+    Label(String),
+    Comment(String), // Something the decompiler could not express
+}
+
+/// A field of a table constructor
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Positional(Expr),
+    Keyed(Expr, Expr), // `name = value` for identifier strings, `[key] = value` otherwise
+}
+
+/// Name of a function statement. With a method, the function's first parameter is
+/// the implicit `self`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionName {
+    pub path: Vec<String>, // `a.b`, a local or global then fields
+    pub method: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub params: Vec<String>,
+    pub is_vararg: bool,
+    pub body: Block,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Expr {
+    /// Whether the expression may be evaluated several times or not at all without
+    /// changing what the program does
+    pub fn is_pure(&self) -> bool {
+        match self {
+            Expr::Nil
+            | Expr::Boolean(_)
+            | Expr::Number(_)
+            | Expr::Integer(_)
+            | Expr::String(_)
+            | Expr::VarArg
+            | Expr::Name(_)
+            | Expr::Global(_) => true,
+            Expr::Index(table, key) => table.is_pure() && key.is_pure(),
+            Expr::Binary(_, lhs, rhs) => lhs.is_pure() && rhs.is_pure(),
+            Expr::Unary(_, operand) => operand.is_pure(),
+            Expr::Call(..) | Expr::MethodCall(..) | Expr::Function(_) | Expr::Table(_) => false,
+        }
+    }
+
+    /// Whether the expression reads the local or upvalue `name`
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Expr::Name(n) => n == name,
+            Expr::Index(table, key) => table.mentions(name) || key.mentions(name),
+            Expr::Call(func, args) => func.mentions(name) || args.iter().any(|a| a.mentions(name)),
+            Expr::MethodCall(obj, _, args) => {
+                obj.mentions(name) || args.iter().any(|a| a.mentions(name))
+            }
+            Expr::Table(fields) => fields.iter().any(|f| f.mentions(name)),
+            Expr::Binary(_, lhs, rhs) => lhs.mentions(name) || rhs.mentions(name),
+            Expr::Unary(_, operand) => operand.mentions(name),
+            _ => false,
+        }
+    }
+
+    /// Negates a condition, folding comparisons where Lua allows it. Ordered comparisons
+    /// stay wrapped, as `not (a < b)` differs from `a >= b` for NaN
+    pub fn negate(self) -> Expr {
+        match self {
+            Expr::Binary(BinOp::Eq, lhs, rhs) => Expr::Binary(BinOp::Ne, lhs, rhs),
+            Expr::Binary(BinOp::Ne, lhs, rhs) => Expr::Binary(BinOp::Eq, lhs, rhs),
+            Expr::Binary(BinOp::And, lhs, rhs) => {
+                Expr::Binary(BinOp::Or, Box::new(lhs.negate()), Box::new(rhs.negate()))
+            }
+            Expr::Binary(BinOp::Or, lhs, rhs) => {
+                Expr::Binary(BinOp::And, Box::new(lhs.negate()), Box::new(rhs.negate()))
+            }
+            Expr::Unary(UnOp::Not, operand) => *operand,
+            other => Expr::Unary(UnOp::Not, Box::new(other)),
+        }
+    }
+}
+
+impl Field {
+    pub fn mentions(&self, name: &str) -> bool {
+        match self {
+            Field::Positional(value) => value.mentions(name),
+            Field::Keyed(key, value) => key.mentions(name) || value.mentions(name),
+        }
+    }
+}
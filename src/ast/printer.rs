@@ -0,0 +1,639 @@
+/*
+  Renders AST nodes as Lua source
+*/
+
+use super::nodes::{BinOp, Block, Expr, Field, Function, FunctionName, Stmt, UnOp};
+use std::fmt::Write;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// llex.c:luaX_tokens
+const KEYWORDS: [&str; 21] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local",
+    "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+const UNARY_PRIORITY: u8 = 8; // lparser.c:UNARY_PRIORITY
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrinterConfig {
+    pub indent: String,    // Inserted once per nesting level
+    pub line_width: usize, // Only table constructors wrap, a line per field past this
+}
+
+pub struct Printer {
+    config: PrinterConfig,
+    out: String,
+    depth: usize,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Default for PrinterConfig {
+    fn default() -> Self {
+        Self {
+            indent: "    ".to_owned(),
+            line_width: 100,
+        }
+    }
+}
+
+impl Default for Printer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::with_config(PrinterConfig::default())
+    }
+
+    pub fn with_config(config: PrinterConfig) -> Self {
+        Self {
+            config,
+            out: String::new(),
+            depth: 0,
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn indentation(&self, depth: usize) -> String {
+        self.config.indent.repeat(depth)
+    }
+
+    fn line(&mut self, text: &str) {
+        let indentation = self.indentation(self.depth);
+        self.out.push_str(&indentation);
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn nested(&mut self, block: &Block) {
+        self.depth += 1;
+        self.block(block);
+        self.depth -= 1;
+    }
+
+    pub fn block(&mut self, block: &Block) {
+        for stmt in block {
+            self.stmt(stmt);
+        }
+    }
+
+    pub fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Local(names, values) => {
+                let mut text = format!("local {}", names.join(", "));
+                if !values.is_empty() {
+                    text.push_str(" = ");
+                    text.push_str(&self.list(values));
+                }
+                self.line(&text);
+            }
+            Stmt::Assign(targets, values) => {
+                let text = format!("{} = {}", self.list(targets), self.list(values));
+                self.line(&text);
+            }
+            Stmt::Call(call) => {
+                let text = self.expr(call);
+                self.line(&text);
+            }
+            Stmt::If(cond, then, otherwise) => {
+                let text = format!("if {} then", self.expr(cond));
+                self.line(&text);
+                self.nested(then);
+                self.else_chain(otherwise);
+                self.line("end");
+            }
+            Stmt::While(cond, body) => {
+                let text = format!("while {} do", self.expr(cond));
+                self.line(&text);
+                self.nested(body);
+                self.line("end");
+            }
+            Stmt::Repeat(body, cond) => {
+                self.line("repeat");
+                self.nested(body);
+                let text = format!("until {}", self.expr(cond));
+                self.line(&text);
+            }
+            Stmt::NumericFor(var, start, limit, step, body) => {
+                let mut text = format!("for {var} = {}, {}", self.expr(start), self.expr(limit));
+                if let Some(step) = step {
+                    write!(text, ", {}", self.expr(step)).unwrap();
+                }
+                text.push_str(" do");
+                self.line(&text);
+                self.nested(body);
+                self.line("end");
+            }
+            Stmt::GenericFor(vars, exprs, body) => {
+                let text = format!("for {} in {} do", vars.join(", "), self.list(exprs));
+                self.line(&text);
+                self.nested(body);
+                self.line("end");
+            }
+            Stmt::FunctionDef(name, function) => {
+                let head = format!("function {}", function_name(name));
+                let skip_self = usize::from(name.method.is_some());
+                let text = self.function(&head, function, skip_self);
+                self.line(&text);
+            }
+            Stmt::LocalFunction(name, function) => {
+                let text = self.function(&format!("local function {name}"), function, 0);
+                self.line(&text);
+            }
+            Stmt::Return(values) => match values.is_empty() {
+                true => self.line("return"),
+                false => {
+                    let text = format!("return {}", self.list(values));
+                    self.line(&text);
+                }
+            },
+            Stmt::Break => self.line("break"),
+            Stmt::Goto(label) => self.line(&format!("goto {label}")),
+            Stmt::Label(label) => self.line(&format!("::{label}::")),
+            Stmt::Comment(text) => self.line(&format!("-- {text}")),
+        }
+    }
+
+    /// Prints `else` branches, turning a lone nested `if` into `elseif`
+    fn else_chain(&mut self, otherwise: &Block) {
+        match otherwise.as_slice() {
+            [] => {}
+            [Stmt::If(cond, then, rest)] => {
+                let text = format!("elseif {} then", self.expr(cond));
+                self.line(&text);
+                self.nested(then);
+                self.else_chain(rest);
+            }
+            _ => {
+                self.line("else");
+                self.nested(otherwise);
+            }
+        }
+    }
+
+    fn list(&mut self, exprs: &[Expr]) -> String {
+        let items: Vec<String> = exprs.iter().map(|e| self.expr(e)).collect();
+        items.join(", ")
+    }
+
+    /// Formats a function body after `head`, leaving out the first `skip` parameters
+    fn function(&mut self, head: &str, function: &Function, skip: usize) -> String {
+        let mut params: Vec<String> = function.params.iter().skip(skip).cloned().collect();
+        if function.is_vararg {
+            params.push("...".to_owned());
+        }
+
+        let mut inner = Printer {
+            config: self.config.clone(),
+            out: String::new(),
+            depth: self.depth + 1,
+        };
+        inner.block(&function.body);
+
+        let mut text = format!("{head}({})\n", params.join(", "));
+        text.push_str(&inner.out);
+        text.push_str(&self.indentation(self.depth));
+        text.push_str("end");
+        text
+    }
+
+    /// Formats a table constructor on one line, or a line per field if that is too long
+    fn table(&mut self, fields: &[Field]) -> String {
+        if fields.is_empty() {
+            return "{}".to_owned();
+        }
+
+        self.depth += 1;
+        let items: Vec<String> = fields.iter().map(|f| self.field(f)).collect();
+        self.depth -= 1;
+
+        let inline = format!("{{{}}}", items.join(", "));
+        let width = self.indentation(self.depth).len() + inline.len();
+        if width <= self.config.line_width && !inline.contains('\n') {
+            return inline;
+        }
+
+        let inner = self.indentation(self.depth + 1);
+        let mut text = "{\n".to_owned();
+        for item in items {
+            writeln!(text, "{inner}{item},").unwrap();
+        }
+        text.push_str(&self.indentation(self.depth));
+        text.push('}');
+        text
+    }
+
+    fn field(&mut self, field: &Field) -> String {
+        match field {
+            Field::Positional(value) => self.expr(value),
+            Field::Keyed(Expr::String(name), value) if as_identifier(name).is_some() => {
+                let name = String::from_utf8_lossy(name);
+                format!("{name} = {}", self.expr(value))
+            }
+            Field::Keyed(key, value) => format!("[{}] = {}", self.expr(key), self.expr(value)),
+        }
+    }
+
+    pub fn expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Nil => "nil".to_owned(),
+            Expr::Boolean(value) => value.to_string(),
+            Expr::Number(value) => format_number(*value),
+            // The literal 9223372036854775808 overflows to a float
+            Expr::Integer(i64::MIN) => "math.mininteger".to_owned(),
+            Expr::Integer(value) => value.to_string(),
+            Expr::String(value) => quote(value),
+            Expr::VarArg => "...".to_owned(),
+            Expr::Name(name) => name.clone(),
+            Expr::Global(name) if is_identifier(name) => name.clone(),
+            Expr::Global(name) => format!("_G[{}]", quote(name.as_bytes())),
+            Expr::Index(table, key) => {
+                let table = self.prefix(table);
+                let field = match key.as_ref() {
+                    Expr::String(name) => as_identifier(name),
+                    _ => None,
+                };
+                match field {
+                    Some(name) => format!("{table}.{name}"),
+                    None => format!("{table}[{}]", self.expr(key)),
+                }
+            }
+            Expr::Call(func, args) => {
+                format!("{}({})", self.prefix(func), self.list(args))
+            }
+            Expr::MethodCall(obj, method, args) => {
+                format!("{}:{method}({})", self.prefix(obj), self.list(args))
+            }
+            Expr::Function(function) => self.function("function", function, 0),
+            Expr::Table(fields) => self.table(fields),
+            Expr::Binary(op, lhs, rhs) => {
+                let (left, right) = priority(*op);
+                // The left operand keeps this operator out of its right side, and the
+                // right operand binds tighter than this operator's right priority
+                let lhs = match binding(lhs) {
+                    Some((_, inner)) if inner < left => format!("({})", self.expr(lhs)),
+                    _ => self.expr(lhs),
+                };
+                let rhs = match binding(rhs) {
+                    Some((inner, _)) if inner <= right && !is_unary(rhs) => {
+                        format!("({})", self.expr(rhs))
+                    }
+                    _ => self.expr(rhs),
+                };
+                format!("{lhs} {} {rhs}", binop_symbol(*op))
+            }
+            Expr::Unary(op, operand) => {
+                let text = match binding(operand) {
+                    Some((inner, _)) if inner <= UNARY_PRIORITY && !is_unary(operand) => {
+                        format!("({})", self.expr(operand))
+                    }
+                    _ => self.expr(operand),
+                };
+                // `--` would start a comment
+                match *op == UnOp::Neg && text.starts_with('-') {
+                    true => format!("-({text})"),
+                    false => format!("{}{text}", unop_symbol(*op)),
+                }
+            }
+        }
+    }
+
+    /// Formats the left side of an index or call, which must be a prefix expression
+    fn prefix(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Name(_)
+            | Expr::Global(_)
+            | Expr::Index(..)
+            | Expr::Call(..)
+            | Expr::MethodCall(..) => self.expr(expr),
+            _ => format!("({})", self.expr(expr)),
+        }
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    let starts_well = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    starts_well && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !KEYWORDS.contains(&name)
+}
+
+/// A string constant as a name, if it is a valid identifier
+pub fn as_identifier(bytes: &[u8]) -> Option<&str> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .filter(|name| is_identifier(name))
+}
+
+/// Quotes a string constant as a Lua literal that reads back to the same bytes.
+/// Bytes that are not valid UTF-8 are written as decimal escapes
+pub fn quote(value: &[u8]) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    let mut pos = 0;
+    for chunk in value.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            pos += c.len_utf8();
+            match c {
+                '"' => out.push_str("\\\""),
+                '\\' => out.push_str("\\\\"),
+                '\n' => out.push_str("\\n"),
+                '\r' => out.push_str("\\r"),
+                '\t' => out.push_str("\\t"),
+                '\x07' => out.push_str("\\a"),
+                '\x08' => out.push_str("\\b"),
+                '\x0b' => out.push_str("\\v"),
+                '\x0c' => out.push_str("\\f"),
+                c if (c as u32) < 0x20 || c as u32 == 0x7f => {
+                    escape(&mut out, c as u8, value.get(pos))
+                }
+                c => out.push(c),
+            }
+        }
+        for &b in chunk.invalid() {
+            pos += 1;
+            escape(&mut out, b, value.get(pos));
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Writes `byte` as a decimal escape, padded when the `next` byte is a digit
+fn escape(out: &mut String, byte: u8, next: Option<&u8>) {
+    // llex.c:read_string reads up to three digits
+    match next.is_some_and(u8::is_ascii_digit) {
+        true => write!(out, "\\{byte:03}").unwrap(),
+        false => write!(out, "\\{byte}").unwrap(),
+    }
+}
+
+/// Formats a number constant as a Lua literal that reads back to the same value
+pub fn format_number(value: f64) -> String {
+    if value.is_nan() {
+        "0/0".to_owned()
+    } else if value.is_infinite() {
+        match value > 0.0 {
+            true => "math.huge".to_owned(),
+            false => "-math.huge".to_owned(),
+        }
+    } else if value.fract() == 0.0 && value.abs() < 1e16 {
+        format!("{value:.0}")
+    } else {
+        // Both forms are the shortest that round-trip
+        let plain = format!("{value}");
+        let exponent = format!("{value:e}");
+        match exponent.len() < plain.len() {
+            true => exponent,
+            false => plain,
+        }
+    }
+}
+
+fn function_name(name: &FunctionName) -> String {
+    let mut text = name.path.join(".");
+    if let Some(method) = &name.method {
+        write!(text, ":{method}").unwrap();
+    }
+    text
+}
+
+/// Left and right priorities of a binary operator (lparser.c:priority)
+const fn priority(op: BinOp) -> (u8, u8) {
+    match op {
+        BinOp::Or => (1, 1),
+        BinOp::And => (2, 2),
+        BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => (3, 3),
+        BinOp::Concat => (5, 4), // Right associative
+        BinOp::Add | BinOp::Sub => (6, 6),
+        BinOp::Mul | BinOp::Div | BinOp::Mod => (7, 7),
+        BinOp::Pow => (10, 9), // Right associative
+    }
+}
+
+/// Priorities an expression is printed with, `None` for ones that never need parentheses
+fn binding(expr: &Expr) -> Option<(u8, u8)> {
+    match expr {
+        Expr::Binary(op, ..) => Some(priority(*op)),
+        Expr::Number(value) if value.is_nan() => Some(priority(BinOp::Div)),
+        _ if is_unary(expr) => Some((UNARY_PRIORITY, UNARY_PRIORITY)),
+        _ => None,
+    }
+}
+
+/// Whether an expression prints starting with a unary operator
+fn is_unary(expr: &Expr) -> bool {
+    match expr {
+        Expr::Unary(..) => true,
+        Expr::Number(value) => value.is_sign_negative() && !value.is_nan(),
+        Expr::Integer(value) => *value < 0 && *value != i64::MIN,
+        _ => false,
+    }
+}
+
+const fn binop_symbol(op: BinOp) -> &'static str {
+    match op {
+        BinOp::Add => "+",
+        BinOp::Sub => "-",
+        BinOp::Mul => "*",
+        BinOp::Div => "/",
+        BinOp::Mod => "%",
+        BinOp::Pow => "^",
+        BinOp::Concat => "..",
+        BinOp::Eq => "==",
+        BinOp::Ne => "~=",
+        BinOp::Lt => "<",
+        BinOp::Le => "<=",
+        BinOp::Gt => ">",
+        BinOp::Ge => ">=",
+        BinOp::And => "and",
+        BinOp::Or => "or",
+    }
+}
+
+const fn unop_symbol(op: UnOp) -> &'static str {
+    match op {
+        UnOp::Neg => "-",
+        UnOp::Not => "not ",
+        UnOp::Len => "#",
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(expr: &Expr) -> String {
+        Printer::new().expr(expr)
+    }
+
+    #[test]
+    fn quotes_escapes_and_raw_bytes() {
+        let value = b"tab\t\"q\" \\ \x01x \x012 caf\xc3\xa9 \xff\xfe9".to_vec();
+        assert_eq!(
+            print(&Expr::String(value)),
+            r#""tab\t\"q\" \\ \1x \0012 café \255\2549""#
+        );
+    }
+
+    #[test]
+    fn non_utf8_keys_are_not_names() {
+        let table = Expr::Global("t".to_owned());
+        let index = |key: &[u8]| {
+            Expr::Index(
+                Box::new(table.clone()),
+                Box::new(Expr::String(key.to_vec())),
+            )
+        };
+        assert_eq!(print(&index(b"field")), "t.field");
+        assert_eq!(print(&index(b"end")), r#"t["end"]"#);
+        assert_eq!(print(&index(b"f\xe9")), r#"t["f\233"]"#);
+    }
+
+    #[test]
+    fn prints_integers_exactly() {
+        assert_eq!(print(&Expr::Integer(i64::MAX)), "9223372036854775807");
+        assert_eq!(print(&Expr::Integer(i64::MIN)), "math.mininteger");
+        let neg = Expr::Unary(UnOp::Neg, Box::new(Expr::Integer(-1)));
+        assert_eq!(print(&neg), "-(-1)");
+    }
+
+    fn name(name: &str) -> Box<Expr> {
+        Box::new(Expr::Name(name.to_owned()))
+    }
+
+    fn binary(op: BinOp, lhs: Box<Expr>, rhs: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::Binary(op, lhs, rhs))
+    }
+
+    fn unary(op: UnOp, operand: Box<Expr>) -> Box<Expr> {
+        Box::new(Expr::Unary(op, operand))
+    }
+
+    #[test]
+    fn right_associative_operators() {
+        use BinOp::{Concat, Pow};
+        let right = binary(Concat, name("a"), binary(Concat, name("b"), name("c")));
+        let left = binary(Concat, binary(Concat, name("a"), name("b")), name("c"));
+        assert_eq!(print(&right), "a .. b .. c");
+        assert_eq!(print(&left), "(a .. b) .. c");
+
+        let right = binary(Pow, name("a"), binary(Pow, name("b"), name("c")));
+        let left = binary(Pow, binary(Pow, name("a"), name("b")), name("c"));
+        assert_eq!(print(&right), "a ^ b ^ c");
+        assert_eq!(print(&left), "(a ^ b) ^ c");
+    }
+
+    #[test]
+    fn left_associative_operators() {
+        use BinOp::{Add, Mul, Or, Sub};
+        let left = binary(Sub, binary(Sub, name("a"), name("b")), name("c"));
+        let right = binary(Sub, name("a"), binary(Sub, name("b"), name("c")));
+        assert_eq!(print(&left), "a - b - c");
+        assert_eq!(print(&right), "a - (b - c)");
+
+        let sum = binary(Mul, binary(Add, name("a"), name("b")), name("c"));
+        assert_eq!(print(&sum), "(a + b) * c");
+        let product = binary(Add, name("a"), binary(Mul, name("b"), name("c")));
+        assert_eq!(print(&product), "a + b * c");
+        let or = binary(BinOp::And, binary(Or, name("a"), name("b")), name("c"));
+        assert_eq!(print(&or), "(a or b) and c");
+    }
+
+    #[test]
+    fn unary_operators_and_pow() {
+        use BinOp::Pow;
+        // `^` binds tighter than a unary operator on its left, looser on its right
+        let neg_pow = unary(UnOp::Neg, binary(Pow, name("a"), name("b")));
+        assert_eq!(print(&neg_pow), "-a ^ b");
+        let pow_of_neg = binary(Pow, unary(UnOp::Neg, name("a")), name("b"));
+        assert_eq!(print(&pow_of_neg), "(-a) ^ b");
+        let pow_neg = binary(Pow, name("a"), unary(UnOp::Neg, name("b")));
+        assert_eq!(print(&pow_neg), "a ^ -b");
+        let literal = binary(
+            Pow,
+            Box::new(Expr::Number(-2.0)),
+            Box::new(Expr::Number(2.0)),
+        );
+        assert_eq!(print(&literal), "(-2) ^ 2");
+
+        let not_eq = unary(UnOp::Not, binary(BinOp::Eq, name("a"), name("b")));
+        assert_eq!(print(&not_eq), "not (a == b)");
+        let len_len = unary(UnOp::Len, unary(UnOp::Len, name("t")));
+        assert_eq!(print(&len_len), "##t");
+        let neg_neg = unary(UnOp::Neg, unary(UnOp::Neg, name("a")));
+        assert_eq!(print(&neg_neg), "-(-a)");
+    }
+
+    #[test]
+    fn prefix_expressions() {
+        let method = Expr::MethodCall(
+            Box::new(Expr::String(b"abc".to_vec())),
+            "len".to_owned(),
+            Vec::new(),
+        );
+        assert_eq!(print(&method), r#"("abc"):len()"#);
+        let index = Expr::Index(Box::new(Expr::Table(Vec::new())), name("k"));
+        assert_eq!(print(&index), "({})[k]");
+        let global = Expr::Global("not a name".to_owned());
+        assert_eq!(print(&global), r#"_G["not a name"]"#);
+    }
+
+    #[test]
+    fn formats_numbers() {
+        assert_eq!(format_number(1.0), "1");
+        assert_eq!(format_number(-0.0), "-0");
+        assert_eq!(format_number(0.5), "0.5");
+        assert_eq!(format_number(0.1), "0.1");
+        assert_eq!(format_number(1e-7), "1e-7");
+        assert_eq!(format_number(1e15), "1000000000000000");
+        assert_eq!(format_number(1e16), "1e16");
+        assert_eq!(format_number(2f64.powi(53) + 2.0), "9007199254740994");
+        assert_eq!(format_number(1.2345678901234568e17), "123456789012345680");
+        assert_eq!(format_number(1.5e300), "1.5e300");
+        assert_eq!(format_number(f64::NAN), "0/0");
+        assert_eq!(format_number(f64::INFINITY), "math.huge");
+        assert_eq!(format_number(f64::NEG_INFINITY), "-math.huge");
+    }
+
+    #[test]
+    fn line_width_splits_table_constructors_only() {
+        let config = PrinterConfig {
+            indent: "  ".to_owned(),
+            line_width: 20,
+        };
+        let numbers = |count: usize| {
+            (0..count)
+                .map(|n| Field::Positional(Expr::Number(n as f64)))
+                .collect::<Vec<_>>()
+        };
+        let short = Expr::Table(numbers(3));
+        let long = Expr::Table(numbers(12));
+        let call = Expr::Call(name("f"), vec![Expr::Table(numbers(3)); 4]);
+
+        let mut printer = Printer::with_config(config);
+        printer.stmt(&Stmt::Local(vec!["t".to_owned()], vec![short]));
+        printer.stmt(&Stmt::Local(vec!["u".to_owned()], vec![long]));
+        printer.stmt(&Stmt::Call(call));
+        assert_eq!(
+            printer.finish(),
+            "local t = {0, 1, 2}\n\
+             local u = {\n  0,\n  1,\n  2,\n  3,\n  4,\n  5,\n  6,\n  7,\n  8,\n  9,\n  10,\n  11,\n}\n\
+             f({0, 1, 2}, {0, 1, 2}, {0, 1, 2}, {0, 1, 2})\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+/*
+  Folding of short-circuit branch chains back into `and`/`or` expressions
+*/
+
+use crate::ast::{BinOp, Expr, UnOp};
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// A conditional jump of a chain (lcode.c:luaK_goiftrue and friends). Control goes
+/// to `target` when `value` has the truthiness `jumps_if`, and falls through to the
+/// next branch otherwise
+#[derive(Debug, Clone)]
+pub struct Branch {
+    pub start: usize, // First pc of the code computing `value`
+    pub value: Expr,
+    pub jumps_if: bool,
+    pub target: usize,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Branch {
+    /// Condition under which control falls through the branch
+    pub fn fallthrough_condition(self) -> Expr {
+        match self.jumps_if {
+            false => self.value,
+            true => self.value.negate(),
+        }
+    }
+
+    /// Value of an expression whose jump carries `value` past a tail computing `tail`
+    pub fn with_tail(self, tail: Expr) -> Expr {
+        let op = match self.jumps_if {
+            false => BinOp::And,
+            true => BinOp::Or,
+        };
+        Expr::Binary(op, Box::new(self.value), Box::new(tail))
+    }
+
+    /// Makes the value a boolean that is truthy exactly when the branch jumps to a
+    /// `LOADBOOL` of `loads` (lcode.c:exp2reg), flipping `jumps_if` to match
+    pub fn materialize(&mut self, loads: bool, is_boolean: bool) {
+        if !is_boolean {
+            self.value = not(std::mem::replace(&mut self.value, Expr::Nil));
+            self.jumps_if = !self.jumps_if;
+        }
+        if self.jumps_if != loads {
+            self.value = not(std::mem::replace(&mut self.value, Expr::Nil));
+            self.jumps_if = loads;
+        }
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Folds a chain whose last branch falls through to `fall` into a single branch, or
+/// returns `None` if the jumps do not nest like a short-circuit expression
+pub fn fold(mut chain: Vec<Branch>, fall: usize) -> Option<Branch> {
+    while chain.len() > 1 {
+        let index = (0..chain.len() - 1).find(|&k| mergeable(&chain, k, fall))?;
+        let y = chain.remove(index + 1);
+        let x = chain[index].clone();
+
+        let (op, lhs, jumps_if) = match (x.target == y.target, x.jumps_if, y.jumps_if) {
+            // Both leave for the same place on the same outcome
+            (true, false, false) => (BinOp::And, x.value, false),
+            (true, true, true) => (BinOp::Or, x.value, true),
+            // `x` jumps over `y`, to where `y` falls through
+            (_, false, true) => (BinOp::And, x.value, true),
+            (_, true, false) => (BinOp::Or, x.value, false),
+            (_, false, false) => (BinOp::Or, x.value.negate(), false),
+            (_, true, true) => (BinOp::And, x.value.negate(), true),
+        };
+        chain[index] = Branch {
+            start: x.start,
+            value: Expr::Binary(op, Box::new(lhs), Box::new(y.value)),
+            jumps_if,
+            target: y.target,
+        };
+    }
+    chain.pop()
+}
+
+/// Whether branches `k` and `k + 1` form one operation: nothing else enters the second
+/// one, and the first one either leaves like it or skips it
+fn mergeable(chain: &[Branch], k: usize, fall: usize) -> bool {
+    let (x, y) = (&chain[k], &chain[k + 1]);
+    let y_fall = chain.get(k + 2).map_or(fall, |b| b.start);
+    let entered = chain
+        .iter()
+        .enumerate()
+        .any(|(j, b)| j != k && b.target == y.start);
+
+    !entered && ((x.target == y.target && x.jumps_if == y.jumps_if) || x.target == y_fall)
+}
+
+/// Boolean negation, kept even where a condition could drop it
+fn not(value: Expr) -> Expr {
+    match value {
+        Expr::Binary(BinOp::Eq, lhs, rhs) => Expr::Binary(BinOp::Ne, lhs, rhs),
+        Expr::Binary(BinOp::Ne, lhs, rhs) => Expr::Binary(BinOp::Eq, lhs, rhs),
+        other => Expr::Unary(UnOp::Not, Box::new(other)),
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use crate::assembler::assemble;
+    use crate::decompile::decompile;
+
+    /// Decompiles `code` after constants for the globals `a`, `b`, `c` and `x`
+    fn decompile_code(code: &str) -> String {
+        let listing = format!(".const \"a\"\n.const \"b\"\n.const \"c\"\n.const \"x\"\n{code}");
+        decompile(&assemble(&listing).expect("valid listing")).expect("valid bytecode")
+    }
+
+    #[test]
+    fn and_or() {
+        let source = decompile_code(
+            r#"
+                GETGLOBAL 0 K0
+                TEST      0 0 0
+                JMP       L6
+                GETGLOBAL 0 K1
+                TEST      0 0 1
+                JMP       L7
+            L6:
+                GETGLOBAL 0 K2
+            L7:
+                SETGLOBAL 0 K3
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "x = a and b or c\n");
+    }
+
+    #[test]
+    fn or_binds_looser_than_and() {
+        let source = decompile_code(
+            r#"
+                GETGLOBAL 0 K0
+                TEST      0 0 1
+                JMP       L7
+                GETGLOBAL 0 K1
+                TEST      0 0 0
+                JMP       L7
+                GETGLOBAL 0 K2
+            L7:
+                SETGLOBAL 0 K3
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "x = a or b and c\n");
+    }
+
+    #[test]
+    fn parenthesized_or() {
+        let source = decompile_code(
+            r#"
+                GETGLOBAL 0 K0
+                TEST      0 0 1
+                JMP       L6
+                GETGLOBAL 0 K1
+                TEST      0 0 0
+                JMP       L7
+            L6:
+                GETGLOBAL 0 K2
+            L7:
+                SETGLOBAL 0 K3
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "x = (a or b) and c\n");
+    }
+
+    #[test]
+    fn testset_into_a_local() {
+        // local a = a; local x = a and b; c = x
+        let source = decompile_code(
+            r#"
+            .local "a" 1 6
+            .local "x" 4 6
+                GETGLOBAL 0 K0
+                TESTSET   1 0 0
+                JMP       L4
+                GETGLOBAL 1 K1
+            L4:
+                SETGLOBAL 1 K2
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "local a = a\nlocal x = a and b\nc = x\n");
+    }
+
+    #[test]
+    fn not_chains() {
+        // x = not not a; if not (a and b) then c = a end, whose test compiles like
+        // `not a or not b`
+        let source = decompile_code(
+            r#"
+                GETGLOBAL 0 K0
+                NOT       0 0
+                NOT       0 0
+                SETGLOBAL 0 K3
+                GETGLOBAL 0 K0
+                TEST      0 0 0
+                JMP       L10
+                GETGLOBAL 0 K1
+                TEST      0 0 1
+                JMP       L12
+            L10:
+                GETGLOBAL 0 K0
+                SETGLOBAL 0 K2
+            L12:
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(
+            source,
+            "x = not not a\nif not a or not b then\n    c = a\nend\n"
+        );
+    }
+
+    #[test]
+    fn comparison_as_value() {
+        // x = a < b
+        let source = decompile_code(
+            r#"
+                GETGLOBAL 0 K0
+                GETGLOBAL 1 K1
+                LT        1 0 1
+                JMP       L5
+                LOADBOOL  0 0 1
+            L5:
+                LOADBOOL  0 1 0
+                SETGLOBAL 0 K3
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "x = a < b\n");
+    }
+
+    #[test]
+    fn comparison_in_and() {
+        // x = a == b and c
+        let source = decompile_code(
+            r#"
+                GETGLOBAL 0 K0
+                GETGLOBAL 1 K1
+                EQ        0 0 1
+                JMP       L6
+                GETGLOBAL 0 K2
+                JMP       L8
+            L6:
+                LOADBOOL  0 0 1
+                LOADBOOL  0 1 0
+            L8:
+                SETGLOBAL 0 K3
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "x = a == b and c\n");
+    }
+}
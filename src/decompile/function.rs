@@ -0,0 +1,1489 @@
+/*
+  Translates one Lua 5.1 function prototype into statements
+*/
+
+use super::condition::{Branch, fold};
+use crate::analysis::locals::{local_name, param_name};
+use crate::analysis::usage::{jump_target, opcode, reads_at, writes_at};
+use crate::analysis::{ControlFlowGraph, Dominators, Liveness, RegisterSet, infer_locals};
+use crate::ast::{
+    BinOp, Block, Expr, Field, Function, FunctionName, Stmt, UnOp, as_identifier, is_identifier,
+};
+use crate::listing::print_function;
+use crate::parser::bytecode::{Constant, FunctionPrototype, Instruction, LocalVariable, Opcode};
+use std::collections::{BTreeSet, HashSet};
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// Number of reads for a value that is still needed after a jump
+const ESCAPES: usize = usize::MAX;
+
+const FIELDS_PER_FLUSH: u32 = 50; // lopcodes.h:LFIELDS_PER_FLUSH
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// What a register holds while its value has not been written out as a statement
+#[derive(Debug, Clone)]
+enum Slot {
+    Empty,
+    Value(Expr),
+    Method(Expr, Expr), // SELF: the method, with the object copied to the next register
+    Continued,          // Further result of the multi-result value in a lower register
+}
+
+/// State of the translation, cloned to try a translation and roll it back
+#[derive(Clone)]
+struct State {
+    slots: Vec<Slot>,
+    uses: Vec<usize>,       // Reads left before a pending value is consumed
+    open: Option<u32>,      // Register of the last value when it has an open result count
+    declared: Vec<bool>,    // Per entry of `locals`
+    spilled: BTreeSet<u32>, // Registers given a generated name, declared at the top
+    gotos: BTreeSet<usize>, // Targets of the `goto` statements emitted
+}
+
+/// A test gathered into a chain, with the state from before its code was translated
+struct Link {
+    branch: Branch,
+    test: usize,
+    before: State,
+}
+
+pub struct FunctionDecompiler<'a> {
+    proto: &'a FunctionPrototype,
+    cfg: ControlFlowGraph,
+    dominators: Dominators,
+    liveness: Liveness,
+    leaders: HashSet<usize>,        // First instructions of basic blocks
+    loops: Vec<usize>,              // Exit pc of each enclosing loop, innermost last
+    labels: BTreeSet<usize>,        // Targets of `goto` found by an earlier attempt
+    upvalues: Vec<String>,          // Names of the upvalues
+    repeat: Option<(usize, usize)>, // Header and back edge of the `repeat` being translated
+    until: Option<Expr>,            // Its condition, once found
+    capture: Option<u32>,           // Register a value chain is computed into
+    locals: Vec<LocalVariable>,     // Debug information, or inferred when stripped
+    depth: usize,                   // Nesting level, 0 for the main chunk
+    state: State,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl<'a> FunctionDecompiler<'a> {
+    pub fn new(proto: &'a FunctionPrototype, depth: usize) -> Self {
+        let locals = match proto.debug_info.locals.is_empty() {
+            true => infer_locals(proto, depth),
+            false => proto.debug_info.locals.clone(),
+        };
+        let size = usize::from(proto.max_stack_size).max(1) + 3;
+        let cfg = ControlFlowGraph::new(proto);
+        let dominators = cfg.dominators();
+        let liveness = Liveness::new(proto, &cfg);
+        let leaders = cfg
+            .blocks
+            .iter()
+            .map(|b| b.start)
+            .filter(|&pc| pc != 0)
+            .collect();
+
+        Self {
+            proto,
+            cfg,
+            dominators,
+            liveness,
+            leaders,
+            loops: Vec::new(),
+            labels: BTreeSet::new(),
+            upvalues: upvalue_names(&proto.debug_info.upvalues),
+            repeat: None,
+            until: None,
+            capture: None,
+            state: State {
+                slots: vec![Slot::Empty; size],
+                uses: vec![0; size],
+                open: None,
+                declared: vec![false; locals.len()],
+                spilled: BTreeSet::new(),
+                gotos: BTreeSet::new(),
+            },
+            locals,
+            depth,
+        }
+    }
+
+    /// Decompiles the whole prototype. A `goto` reaching an instruction that was not
+    /// labelled yet makes the translation start over with a label there
+    pub fn decompile(mut self) -> Function {
+        let params = (0..u32::from(self.proto.num_params))
+            .map(|reg| {
+                self.mark_declared(reg, 0);
+                self.register_name(reg, 0)
+            })
+            .collect();
+
+        let mut body = self.block(0, self.proto.code.len());
+        if !self.state.gotos.is_subset(&self.labels) {
+            let mut retry = FunctionDecompiler::new(self.proto, self.depth);
+            retry.labels = self.labels.union(&self.state.gotos).copied().collect();
+            retry.upvalues = self.upvalues.clone();
+            return retry.decompile();
+        }
+
+        if !gotos_resolve(&body, &mut Vec::new()) {
+            return Function {
+                params,
+                is_vararg: self.proto.is_vararg != 0,
+                body: self.listing(),
+            };
+        }
+
+        if let Some(Stmt::Return(values)) = body.last()
+            && values.is_empty()
+        {
+            body.pop();
+        }
+
+        if !self.state.spilled.is_empty() {
+            let names = self
+                .state
+                .spilled
+                .iter()
+                .map(|r| self.temp_name(*r))
+                .collect();
+            body.insert(0, Stmt::Local(names, Vec::new()));
+        }
+
+        Function {
+            params,
+            is_vararg: self.proto.is_vararg != 0,
+            body,
+        }
+    }
+
+    /// The instructions as comments, for control flow whose `goto` statements would
+    /// not see their labels
+    fn listing(&self) -> Block {
+        let code = print_function(self.proto, false, false);
+        let lines = code
+            .lines()
+            .filter(|line| line.starts_with('\t'))
+            .map(|line| Stmt::Comment(line.trim_start().replace('\t', " ")));
+        std::iter::once(Stmt::Comment("irreducible control flow:".to_owned()))
+            .chain(lines)
+            .collect()
+    }
+
+    //////////////// Locals ////////////////
+
+    /// Index into `locals` of the variable held by `reg` at `pc`
+    fn local_at(&self, reg: u32, pc: usize) -> Option<usize> {
+        self.locals
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.startpc as usize <= pc && pc < l.endpc as usize)
+            .nth(reg as usize)
+            .map(|(i, _)| i)
+    }
+
+    /// Name of the declared local held by `reg` at `pc`
+    fn active_local(&self, reg: u32, pc: usize) -> Option<String> {
+        let index = self.local_at(reg, pc)?;
+        let local = &self.locals[index];
+        (self.state.declared[index] && !local.varname.starts_with(b"("))
+            .then(|| local.name().into_owned())
+    }
+
+    /// Name of `reg` at `pc`, falling back to a generated one
+    fn register_name(&self, reg: u32, pc: usize) -> String {
+        match self.local_at(reg, pc) {
+            Some(index) => self.locals[index].name().into_owned(),
+            None if reg < u32::from(self.proto.num_params) => param_name(self.depth, reg),
+            None => self.temp_name(reg),
+        }
+    }
+
+    /// Generated name of a register holding a value across control flow that is not
+    /// one of the `locals`, named like the inferred ones
+    fn temp_name(&self, reg: u32) -> String {
+        local_name(self.depth, reg)
+    }
+
+    fn mark_declared(&mut self, reg: u32, pc: usize) {
+        if let Some(index) = self.local_at(reg, pc) {
+            self.state.declared[index] = true;
+        }
+    }
+
+    /// Emits `local` statements for the variables starting at `pc`. With `scoped_only`,
+    /// only variables that also end there (declared by the last statement of a block)
+    fn declare_locals(&mut self, pc: usize, scoped_only: bool, block: &mut Block) {
+        let mut starting = Vec::new();
+        let mut reg = 0;
+        for (index, local) in self.locals.iter().enumerate() {
+            let (start, end) = (local.startpc as usize, local.endpc as usize);
+            if start > pc || (end <= pc && start != pc) {
+                continue;
+            }
+            if start == pc && !self.state.declared[index] && (!scoped_only || end == pc) {
+                // `local function f` declares f before the closure it is set to
+                let closure = self.instr(pc).filter(|(_, op)| *op == Opcode::CLOSURE);
+                if closure.is_none_or(|(instr, _)| instr.a() != reg) {
+                    starting.push((index, reg));
+                }
+            }
+            reg += 1;
+        }
+
+        let mut names = Vec::new();
+        let mut values = Vec::new();
+        for (index, reg) in starting {
+            self.state.declared[index] = true;
+            let local = &self.locals[index];
+            if local.varname.starts_with(b"(") {
+                continue;
+            }
+
+            names.push(local.name().into_owned());
+            match std::mem::replace(&mut self.state.slots[reg as usize], Slot::Empty) {
+                Slot::Value(value) => values.push(value),
+                Slot::Method(obj, key) => values.push(Expr::Index(Box::new(obj), Box::new(key))),
+                Slot::Continued => {}
+                Slot::Empty => values.push(Expr::Nil),
+            }
+        }
+
+        if names.is_empty() {
+            return;
+        }
+        while values.last() == Some(&Expr::Nil) {
+            values.pop();
+        }
+        block.push(Stmt::Local(names, values));
+    }
+
+    //////////////// Registers ////////////////
+
+    /// Number of times the value written to `reg` at `pc` is read before being replaced
+    fn count_reads(&self, reg: u32, pc: usize) -> usize {
+        let constructor = opcode(&self.proto.code[pc]) == Some(Opcode::NEWTABLE);
+        let mut count = 0;
+        for (next, instr) in self.proto.code.iter().enumerate().skip(pc + 1) {
+            if self.proto.is_pseudo_instruction(next) {
+                continue;
+            }
+            // A local declared there takes the value as its initializer
+            let declared = self.local_at(reg, next).map(|i| &self.locals[i]);
+            if declared.is_some_and(|local| local.startpc as usize == next) {
+                return count + 1;
+            }
+            if self.leaders.contains(&next) {
+                return self.escaping(reg, self.liveness.live_in(next), count);
+            }
+            // SETLIST and hash field stores fill the pending constructor rather than reading it
+            let fills = match opcode(instr) {
+                Some(Opcode::SETLIST) => true,
+                Some(Opcode::SETTABLE) => constructor,
+                _ => false,
+            };
+            if !fills || instr.a() != reg {
+                count += reads_at(self.proto, next)
+                    .iter()
+                    .filter(|&&r| r == reg)
+                    .count();
+            }
+            match opcode(instr) {
+                Some(Opcode::FORPREP) => return count,
+                Some(Opcode::JMP) if self.is_generic_for(next) => return count,
+                Some(
+                    Opcode::JMP
+                    | Opcode::EQ
+                    | Opcode::LT
+                    | Opcode::LE
+                    | Opcode::TEST
+                    | Opcode::TESTSET
+                    | Opcode::FORLOOP
+                    | Opcode::TFORLOOP
+                    | Opcode::LOADBOOL
+                    | Opcode::RETURN
+                    | Opcode::TAILCALL,
+                ) => return self.escaping(reg, self.liveness.live_out(next), count),
+                _ => {}
+            }
+            if writes_at(self.proto, next).contains(&reg) {
+                return count;
+            }
+        }
+        count
+    }
+
+    /// Read count of a value reaching a jump, with `live` the registers still read past
+    /// it. Temporaries are dead there, as the code generator frees them after their
+    /// statement (lcode.c:freereg)
+    fn escaping(&self, reg: u32, live: RegisterSet, count: usize) -> usize {
+        match live.contains(reg) {
+            true => ESCAPES,
+            false => count,
+        }
+    }
+
+    /// Reads `reg` as an expression
+    fn reg(&mut self, reg: u32, pc: usize) -> Expr {
+        if let Some(name) = self.active_local(reg, pc) {
+            return Expr::Name(name);
+        }
+        let index = reg as usize;
+        match &self.state.slots[index] {
+            Slot::Value(_) if self.state.uses[index] == 1 => {
+                match std::mem::replace(&mut self.state.slots[index], Slot::Empty) {
+                    Slot::Value(value) => value,
+                    _ => unreachable!(),
+                }
+            }
+            Slot::Value(value) => {
+                if self.state.uses[index] != ESCAPES {
+                    self.state.uses[index] -= 1;
+                }
+                value.clone()
+            }
+            Slot::Method(obj, key) => Expr::Index(Box::new(obj.clone()), Box::new(key.clone())),
+            Slot::Empty | Slot::Continued => Expr::Name(self.temp_name(reg)),
+        }
+    }
+
+    /// Leaves `value` pending in `reg` until it has been read `uses` times
+    fn pend(&mut self, reg: u32, value: Expr, uses: usize) {
+        self.state.slots[reg as usize] = Slot::Value(value);
+        self.state.uses[reg as usize] = uses;
+    }
+
+    /// Reads the B operand, a register or a constant
+    fn rk_b(&mut self, instr: &Instruction, pc: usize) -> Expr {
+        match instr.b_isk() {
+            true => self.constant(instr.bk()),
+            false => self.reg(instr.b(), pc),
+        }
+    }
+
+    /// Reads the C operand, a register or a constant
+    fn rk_c(&mut self, instr: &Instruction, pc: usize) -> Expr {
+        match instr.c_isk() {
+            true => self.constant(instr.ck()),
+            false => self.reg(instr.c(), pc),
+        }
+    }
+
+    fn constant(&self, index: u32) -> Expr {
+        match self.proto.constants.get(index as usize) {
+            Some(Constant::Nil) | None => Expr::Nil,
+            Some(Constant::Boolean(value)) => Expr::Boolean(*value),
+            Some(Constant::Number(value)) => Expr::Number(*value),
+            Some(Constant::Integer(value)) => Expr::Integer(*value),
+            Some(Constant::String(bytes)) => Expr::String(bytes.clone()),
+        }
+    }
+
+    fn constant_name(&self, index: u32) -> String {
+        match self
+            .proto
+            .constants
+            .get(index as usize)
+            .and_then(Constant::as_str)
+        {
+            Some(name) => name.into_owned(),
+            None => format!("K{index}"),
+        }
+    }
+
+    fn upvalue_name(&self, index: u32) -> String {
+        match self.upvalues.get(index as usize) {
+            Some(name) => name.clone(),
+            None => format!("u{index}"),
+        }
+    }
+
+    /// Reads registers `from..to`, then the open multi-result value if `to` is `None`
+    fn range(&mut self, from: u32, to: Option<u32>, pc: usize) -> Vec<Expr> {
+        let to = match to {
+            Some(to) => to,
+            None => self.state.open.take().map_or(from, |open| open + 1),
+        };
+        (from..to).map(|r| self.reg(r, pc)).collect()
+    }
+
+    /// Stores `value` into `reg`, as an assignment if it holds a declared local
+    fn write(&mut self, reg: u32, value: Expr, pc: usize, block: &mut Block) {
+        if self.capture == Some(reg) {
+            self.pend(reg, value, 1);
+            return;
+        }
+        if let Some(name) = self.active_local(reg, pc) {
+            self.spill_mentions(&name, block);
+            assign(Expr::Name(name), value, block);
+            return;
+        }
+
+        let uses = self.count_reads(reg, pc);
+        if uses != 1 && !value.is_pure() {
+            self.state.spilled.insert(reg);
+            block.push(Stmt::Assign(
+                vec![Expr::Name(self.temp_name(reg))],
+                vec![value],
+            ));
+            self.state.slots[reg as usize] = Slot::Empty;
+        } else if uses == 0 {
+            self.state.slots[reg as usize] = Slot::Empty;
+        } else {
+            self.pend(reg, value, uses);
+        }
+    }
+
+    /// Stores a value with `count` results into the registers starting at `reg`
+    fn write_multi(&mut self, reg: u32, count: u32, value: Expr, pc: usize, block: &mut Block) {
+        let regs = reg..reg + count;
+        let starting = regs.clone().all(|r| {
+            self.local_at(r, pc + 1)
+                .is_some_and(|i| self.locals[i].startpc as usize == pc + 1)
+        });
+        if starting {
+            self.pend(reg, value, 1);
+            for r in reg + 1..reg + count {
+                self.state.slots[r as usize] = Slot::Continued;
+            }
+            return;
+        }
+
+        let targets = regs
+            .map(|r| match self.active_local(r, pc) {
+                Some(name) => Expr::Name(name),
+                None => {
+                    self.state.spilled.insert(r);
+                    self.state.slots[r as usize] = Slot::Empty;
+                    Expr::Name(self.temp_name(r))
+                }
+            })
+            .collect();
+        block.push(Stmt::Assign(targets, vec![value]));
+    }
+
+    /// Writes out the pending value of `reg` under its generated name
+    fn spill(&mut self, reg: u32, block: &mut Block) {
+        let value = match std::mem::replace(&mut self.state.slots[reg as usize], Slot::Empty) {
+            Slot::Value(value) => value,
+            Slot::Method(obj, key) => Expr::Index(Box::new(obj), Box::new(key)),
+            Slot::Empty | Slot::Continued => return,
+        };
+
+        let mut targets = vec![reg];
+        while let Some(Slot::Continued) = self.state.slots.get(reg as usize + targets.len()) {
+            let next = reg + targets.len() as u32;
+            self.state.slots[next as usize] = Slot::Empty;
+            targets.push(next);
+        }
+        if value == Expr::Name(self.temp_name(reg)) {
+            return;
+        }
+
+        self.state.spilled.extend(&targets);
+        let targets = targets
+            .into_iter()
+            .map(|r| Expr::Name(self.temp_name(r)))
+            .collect();
+        block.push(Stmt::Assign(targets, vec![value]));
+    }
+
+    /// Writes out every pending value, so that they survive a change of control flow
+    fn spill_all(&mut self, block: &mut Block) {
+        self.state.open = None;
+        for reg in 0..self.state.slots.len() as u32 {
+            self.spill(reg, block);
+        }
+    }
+
+    /// Writes out pending values reading `name` before it gets reassigned
+    fn spill_mentions(&mut self, name: &str, block: &mut Block) {
+        for reg in 0..self.state.slots.len() as u32 {
+            let mentions = match &self.state.slots[reg as usize] {
+                Slot::Value(value) => value.mentions(name),
+                Slot::Method(obj, key) => obj.mentions(name) || key.mentions(name),
+                _ => false,
+            };
+            if mentions {
+                self.spill(reg, block);
+            }
+        }
+    }
+
+    //////////////// Control flow ////////////////
+
+    fn instr(&self, pc: usize) -> Option<(&'a Instruction, Opcode)> {
+        let instr = self.proto.code.get(pc)?;
+        opcode(instr).map(|op| (instr, op))
+    }
+
+    /// Whether the instruction before `pc` is a TAILCALL on register `a`
+    fn follows_tail_call(&self, pc: usize, a: u32) -> bool {
+        pc.checked_sub(1)
+            .and_then(|prev| self.instr(prev))
+            .is_some_and(|(prev, op)| op == Opcode::TAILCALL && prev.a() == a)
+    }
+
+    fn is_test(&self, pc: usize) -> bool {
+        matches!(
+            self.instr(pc),
+            Some((_, Opcode::EQ | Opcode::LT | Opcode::LE | Opcode::TEST))
+        )
+    }
+
+    /// Target of the JMP at `pc`, if it is one
+    fn jump_at(&self, pc: usize) -> Option<usize> {
+        match self.instr(pc) {
+            Some((instr, Opcode::JMP)) => Some(jump_target(pc, instr)),
+            _ => None,
+        }
+    }
+
+    /// Whether the JMP at `pc` enters a generic `for` loop (lparser.c:forbody)
+    /// Whether `reg` holds a table constructor still being filled in
+    fn is_constructor(&self, reg: u32, pc: usize) -> bool {
+        self.active_local(reg, pc).is_none()
+            && matches!(self.state.slots[reg as usize], Slot::Value(Expr::Table(_)))
+    }
+
+    fn is_generic_for(&self, pc: usize) -> bool {
+        let Some(target) = self.jump_at(pc) else {
+            return false;
+        };
+        target > pc
+            && matches!(self.instr(target), Some((_, Opcode::TFORLOOP)))
+            && self.jump_at(target + 1) == Some(pc + 1)
+    }
+
+    /// Reads the test at `pc` as a branch whose code starts at `start`
+    fn branch(&mut self, start: usize, pc: usize) -> Branch {
+        let (instr, op) = self.instr(pc).expect("branch on a test instruction");
+        let target = self.jump_at(pc + 1).expect("test followed by a jump");
+        let (value, jumps_if) = match op {
+            Opcode::TEST => (self.reg(instr.a(), pc), instr.c() != 0),
+            Opcode::TESTSET => (self.reg(instr.b(), pc), instr.c() != 0),
+            _ => (self.comparison(pc, instr, op), instr.a() != 0),
+        };
+        Branch {
+            start,
+            value,
+            jumps_if,
+            target,
+        }
+    }
+
+    /// EQ, LT or LE as an expression, with a constant operand moved to the right
+    fn comparison(&mut self, pc: usize, instr: &Instruction, op: Opcode) -> Expr {
+        let lhs = self.rk_b(instr, pc);
+        let rhs = self.rk_c(instr, pc);
+        let swap = instr.b_isk() && !instr.c_isk();
+        let op = match (op, swap) {
+            (Opcode::EQ, _) => BinOp::Eq,
+            (Opcode::LT, false) => BinOp::Lt,
+            (Opcode::LT, true) => BinOp::Gt,
+            (_, false) => BinOp::Le,
+            (_, true) => BinOp::Ge,
+        };
+        match swap {
+            false => Expr::Binary(op, Box::new(lhs), Box::new(rhs)),
+            true => Expr::Binary(op, Box::new(rhs), Box::new(lhs)),
+        }
+    }
+
+    /// The test of a chain at or after `start`, if only value computations come first
+    fn next_test(&self, start: usize, end: usize, with_testset: bool) -> Option<usize> {
+        for pc in start..end {
+            if pc > start && self.leaders.contains(&pc) {
+                return None;
+            }
+            match self.instr(pc) {
+                Some((_, Opcode::EQ | Opcode::LT | Opcode::LE | Opcode::TEST)) => {}
+                Some((_, Opcode::TESTSET)) if with_testset => {}
+                Some((instr, Opcode::LOADBOOL)) if instr.c() == 0 => continue,
+                Some((
+                    _,
+                    Opcode::JMP
+                    | Opcode::TESTSET
+                    | Opcode::LOADBOOL
+                    | Opcode::FORLOOP
+                    | Opcode::FORPREP
+                    | Opcode::TFORLOOP
+                    | Opcode::RETURN
+                    | Opcode::TAILCALL,
+                ))
+                | None => return None,
+                Some(_) => continue,
+            }
+            return (self.jump_at(pc + 1).is_some() && pc + 2 <= end).then_some(pc);
+        }
+        None
+    }
+
+    /// Collects the tests from the one at `pc` on, as long as the code between them
+    /// only computes values and is entered from the chain alone
+    fn gather(&mut self, pc: usize, end: usize, with_testset: bool) -> Vec<Link> {
+        let before = self.state.clone();
+        let branch = self.branch(pc, pc);
+        let mut chain = vec![Link {
+            branch,
+            test: pc,
+            before,
+        }];
+
+        loop {
+            let start = chain[chain.len() - 1].test + 2;
+            let Some(test) = self.next_test(start, end, with_testset) else {
+                break;
+            };
+            let Some(entry) = self.cfg.block_at(start) else {
+                break;
+            };
+            let from_chain = self.cfg.blocks[entry].predecessors.iter().all(|edge| {
+                let last = self.cfg.blocks[edge.from].end - 1;
+                chain.iter().any(|l| last == l.test || last == l.test + 1)
+            });
+            if !from_chain {
+                break;
+            }
+
+            let before = self.state.clone();
+            if !self.block(start, test).is_empty() {
+                self.state = before;
+                break;
+            }
+            let branch = self.branch(start, test);
+            chain.push(Link {
+                branch,
+                test,
+                before,
+            });
+        }
+        chain
+    }
+
+    /// Folds the longest prefix of `chain` that `accept` takes, given the folded branch
+    /// and where it falls through to. The translation of the rest is rolled back
+    fn fold_prefix(
+        &mut self,
+        chain: &[Link],
+        accept: impl Fn(&Branch, usize) -> bool,
+    ) -> Option<(Branch, usize)> {
+        for len in (1..=chain.len()).rev() {
+            let fall = chain[len - 1].test + 2;
+            let branches = chain[..len].iter().map(|l| l.branch.clone()).collect();
+            if let Some(branch) = fold(branches, fall)
+                && accept(&branch, fall)
+            {
+                if let Some(rest) = chain.get(len) {
+                    self.state = rest.before.clone();
+                }
+                return Some((branch, fall));
+            }
+        }
+        self.state = chain[0].before.clone();
+        None
+    }
+
+    /// A condition made of the tests from `pc` on, folded into a single branch
+    fn condition_chain(
+        &mut self,
+        pc: usize,
+        end: usize,
+        accept: impl Fn(&Branch, usize) -> bool,
+    ) -> Option<(Branch, usize)> {
+        let chain = self.gather(pc, end, false);
+        self.fold_prefix(&chain, accept)
+    }
+
+    /// Register of a `LOADBOOL R 0 1; LOADBOOL R 1 0` pair at `pc` (lcode.c:exp2reg)
+    fn loadbool_pair(&self, pc: usize) -> Option<u32> {
+        match (self.instr(pc), self.instr(pc + 1)) {
+            (Some((f, Opcode::LOADBOOL)), Some((t, Opcode::LOADBOOL)))
+                if f.a() == t.a() && (f.b(), f.c()) == (0, 1) && (t.b(), t.c()) == (1, 0) =>
+            {
+                Some(f.a())
+            }
+            _ => None,
+        }
+    }
+
+    /// Translates `start..end`, which must only leave a value in `reg`
+    fn capture(&mut self, start: usize, end: usize, reg: u32) -> Option<Expr> {
+        let outer = self.capture.replace(reg);
+        let stmts = self.block(start, end);
+        self.capture = outer;
+        if !stmts.is_empty() {
+            return None;
+        }
+        match std::mem::replace(&mut self.state.slots[reg as usize], Slot::Empty) {
+            Slot::Value(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Expressions like `a and b or c` or `a < b` used as values: tests whose jumps
+    /// leave the result in one register, set by TESTSET or a LOADBOOL pair
+    fn value_chain(&mut self, pc: usize, end: usize, block: &mut Block) -> Option<usize> {
+        let chain = self.gather(pc, end, true);
+        let full = self.state.clone();
+        for len in (1..=chain.len()).rev() {
+            self.state = chain
+                .get(len)
+                .map_or_else(|| full.clone(), |l| l.before.clone());
+            if let Some((reg, value, exit)) = self.value_prefix(pc, &chain[..len], end) {
+                self.write(reg, value, exit - 1, block);
+                return Some(exit);
+            }
+        }
+        self.state = chain[0].before.clone();
+        None
+    }
+
+    fn value_prefix(
+        &mut self,
+        pc: usize,
+        chain: &[Link],
+        end: usize,
+    ) -> Option<(u32, Expr, usize)> {
+        let tail_start = chain[chain.len() - 1].test + 2;
+        let far = chain.iter().map(|l| l.branch.target).max()?;
+        if far < tail_start {
+            return None;
+        }
+
+        // Jumps land on `R = false`, `R = true`, or after them with R set by TESTSET
+        let (loads, exit) = match far.checked_sub(1).and_then(|lf| self.loadbool_pair(lf)) {
+            Some(_) => (Some(far - 1), far + 1),
+            None => match far.checked_sub(2).and_then(|lf| self.loadbool_pair(lf)) {
+                Some(_) => (Some(far - 2), far),
+                None => match self.loadbool_pair(far) {
+                    Some(_) => (Some(far), far + 2),
+                    None => (None, far),
+                },
+            },
+        };
+        if exit > end || loads.is_some_and(|lf| lf < tail_start) {
+            return None;
+        }
+
+        let mut reg = loads.and_then(|lf| self.loadbool_pair(lf));
+        let mut explicit = reg.is_some();
+        let mut branches = Vec::with_capacity(chain.len());
+        for link in chain {
+            let (instr, op) = self.instr(link.test)?;
+            let mut branch = link.branch.clone();
+            if branch.target == exit {
+                // The tested value is the result
+                if !matches!(op, Opcode::TEST | Opcode::TESTSET) {
+                    return None;
+                }
+                explicit |= op == Opcode::TESTSET;
+                if *reg.get_or_insert(instr.a()) != instr.a() {
+                    return None;
+                }
+            } else if let Some(lf) = loads
+                && (branch.target == lf || branch.target == lf + 1)
+            {
+                if op == Opcode::TESTSET {
+                    return None;
+                }
+                branch.materialize(branch.target == lf + 1, op != Opcode::TEST);
+                branch.target = exit;
+            }
+            branches.push(branch);
+        }
+        let reg = reg?;
+        if !explicit && self.active_local(reg, pc).is_some() {
+            // `x = x and y` reads better as the `if` it also compiles from
+            return None;
+        }
+
+        let (tail, fall) = match loads {
+            Some(lf) if tail_start == lf => {
+                // The last comparison gives the value, falling through to `R = false`
+                if chain[chain.len() - 1].branch.target != lf + 1 {
+                    return None;
+                }
+                let last = branches.pop()?;
+                (last.value, last.start)
+            }
+            Some(lf) => {
+                if self.jump_at(lf - 1) != Some(exit) {
+                    return None;
+                }
+                (self.capture(tail_start, lf - 1, reg)?, tail_start)
+            }
+            None => (self.capture(tail_start, exit, reg)?, tail_start),
+        };
+
+        let value = match branches.is_empty() {
+            true => tail,
+            false => {
+                let branch = fold(branches, fall)?;
+                if branch.target != exit {
+                    return None;
+                }
+                branch.with_tail(tail)
+            }
+        };
+        Some((reg, value, exit))
+    }
+
+    /// Translates a block whose values are then written out
+    fn body(&mut self, start: usize, end: usize) -> Block {
+        let mut block = self.block(start, end);
+        self.spill_all(&mut block);
+        block
+    }
+
+    /// Translates the instructions in `start..end`
+    fn block(&mut self, start: usize, end: usize) -> Block {
+        let mut block = Block::new();
+        let mut pc = start;
+        while pc < end {
+            self.declare_locals(pc, false, &mut block);
+            if self.labels.contains(&pc) {
+                self.spill_all(&mut block);
+                block.push(Stmt::Label(label_name(pc)));
+            }
+            pc = self.statement(pc, end, &mut block);
+            fold_declaration(&mut block);
+        }
+        self.declare_locals(end, true, &mut block);
+        block
+    }
+
+    /// Translates the construct starting at `pc`, returning where the next one starts
+    fn statement(&mut self, pc: usize, end: usize, block: &mut Block) -> usize {
+        if let Some(next) = self.loop_at(pc, end, block) {
+            return next;
+        }
+
+        let Some((instr, op)) = self.instr(pc) else {
+            block.push(Stmt::Comment(format!(
+                "unknown instruction {:08x} at pc {pc}",
+                self.proto.code[pc].raw()
+            )));
+            return pc + 1;
+        };
+
+        match op {
+            Opcode::FORPREP => self.numeric_for(pc, instr, end, block),
+            Opcode::JMP if self.is_generic_for(pc) && jump_target(pc, instr) + 2 <= end => {
+                self.generic_for(pc, instr, block)
+            }
+            Opcode::EQ | Opcode::LT | Opcode::LE | Opcode::TEST | Opcode::TESTSET
+                if self.jump_at(pc + 1).is_some() && pc + 2 <= end =>
+            {
+                self.test(pc, op, end, block)
+            }
+            Opcode::JMP => {
+                self.jump(jump_target(pc, instr), block);
+                pc + 1
+            }
+            Opcode::EQ
+            | Opcode::LT
+            | Opcode::LE
+            | Opcode::TEST
+            | Opcode::TESTSET
+            | Opcode::FORLOOP
+            | Opcode::TFORLOOP => self.unstructured(pc, op, block),
+            _ => self.simple(pc, instr, op, block),
+        }
+    }
+
+    /// A chain of tests: the `until` of the enclosing `repeat`, a value, an `if`, or a
+    /// conditional jump
+    fn test(&mut self, pc: usize, op: Opcode, end: usize, block: &mut Block) -> usize {
+        if let Some((header, back)) = self.repeat
+            && let Some((branch, fall)) = self.condition_chain(pc, end, |branch, fall| {
+                branch.target == header && fall == back + 1
+            })
+        {
+            self.until = Some(branch.fallthrough_condition());
+            return fall;
+        }
+        if let Some(next) = self.value_chain(pc, end, block) {
+            return next;
+        }
+        if op == Opcode::TESTSET {
+            return self.unstructured(pc, op, block);
+        }
+
+        let structured = |branch: &Branch, fall: usize| (fall..=end).contains(&branch.target);
+        if let Some((branch, fall)) = self.condition_chain(pc, end, structured) {
+            let target = branch.target;
+            return self.if_statement(branch.fallthrough_condition(), fall, target, end, block);
+        }
+
+        // The JMP is taken when the condition fails
+        let (branch, fall) = self
+            .condition_chain(pc, end, |_, _| true)
+            .expect("a single test always folds");
+        let target = branch.target;
+        let cond = branch.fallthrough_condition().negate();
+        self.spill_all(block);
+        let mut then = Block::new();
+        self.jump(target, &mut then);
+        block.push(Stmt::If(cond, then, Block::new()));
+        fall
+    }
+
+    /// `break` out of the innermost loop, or a `goto` anywhere else
+    fn jump(&mut self, target: usize, block: &mut Block) {
+        self.spill_all(block);
+        if self.loops.last() == Some(&target) {
+            block.push(Stmt::Break);
+        } else {
+            self.state.gotos.insert(target);
+            block.push(Stmt::Goto(label_name(target)));
+        }
+    }
+
+    /// Translates a loop body, in which jumps to `exit` are `break` statements
+    fn loop_body(&mut self, start: usize, end: usize, exit: usize) -> Block {
+        self.loops.push(exit);
+        let body = self.body(start, end);
+        self.loops.pop();
+        body
+    }
+
+    fn unstructured(&mut self, pc: usize, op: Opcode, block: &mut Block) -> usize {
+        let name = format!("{op:?}");
+        let text = match self.jump_at(pc) {
+            Some(target) => format!("{name} to pc {target} at pc {pc}"),
+            None => format!("{name} at pc {pc}"),
+        };
+        self.spill_all(block);
+        block.push(Stmt::Comment(format!("unstructured {text}")));
+        pc + 1
+    }
+
+    /// `if` with an optional `else`, whose then-branch ends with a JMP over it
+    fn if_statement(
+        &mut self,
+        cond: Expr,
+        then_start: usize,
+        target: usize,
+        end: usize,
+        block: &mut Block,
+    ) -> usize {
+        self.spill_all(block);
+
+        let (then_end, exit) = match self.jump_at(target - 1) {
+            Some(exit) if target > then_start && exit > target && exit <= end => (target - 1, exit),
+            _ => (target, target),
+        };
+
+        let then = self.body(then_start, then_end);
+        let otherwise = self.body(target, exit);
+        block.push(Stmt::If(cond, then, otherwise));
+        exit
+    }
+
+    /// Recognizes `while` and `repeat` loops whose header is at `pc`: the target of a
+    /// backward JMP from a block it dominates. Other backward jumps become `goto`
+    fn loop_at(&mut self, pc: usize, end: usize, block: &mut Block) -> Option<usize> {
+        let header = self.cfg.block_at(pc)?;
+        let back = self.cfg.blocks[header]
+            .predecessors
+            .iter()
+            .filter(|edge| self.dominators.dominates(header, edge.from))
+            .map(|edge| self.cfg.blocks[edge.from].end - 1)
+            .filter(|&p| p >= pc && p < end && self.jump_at(p) == Some(pc))
+            .filter(|&p| !self.in_until(pc, p))
+            .max()?;
+        self.spill_all(block);
+        let exit = back + 1;
+
+        // while: the header computes the condition, then leaves past the back edge
+        let test = (pc..back).find(|&p| self.is_test(p) || self.jump_at(p).is_some());
+        if let Some(test) = test
+            && self.is_test(test)
+        {
+            let saved = self.state.clone();
+            let header = self.block(pc, test);
+            if header.is_empty()
+                && let Some((branch, fall)) =
+                    self.condition_chain(test, back, |branch, _| branch.target == exit)
+            {
+                let cond = branch.fallthrough_condition();
+                let body = self.loop_body(fall, back, exit);
+                block.push(Stmt::While(cond, body));
+                return Some(exit);
+            }
+            self.state = saved;
+        }
+
+        // repeat: the back edge is the jump of the `until` condition
+        if back > pc && self.is_test(back - 1) {
+            let saved = self.state.clone();
+            let outer = self.repeat.replace((pc, back));
+            let body = self.loop_body(pc, exit, exit);
+            self.repeat = outer;
+            if let Some(cond) = self.until.take() {
+                block.push(Stmt::Repeat(body, cond));
+                return Some(exit);
+            }
+            self.state = saved;
+        }
+
+        let body = self.loop_body(pc, back, exit);
+        block.push(Stmt::While(Expr::Boolean(true), body));
+        Some(exit)
+    }
+
+    /// Whether the back edge at `p` is a jump of the `until` condition being translated
+    fn in_until(&self, header: usize, p: usize) -> bool {
+        self.repeat
+            .is_some_and(|(h, back)| h == header && p <= back && p > 0 && self.is_test(p - 1))
+    }
+
+    /// `for v = start, limit, step` between FORPREP and its FORLOOP
+    fn numeric_for(
+        &mut self,
+        pc: usize,
+        instr: &Instruction,
+        end: usize,
+        block: &mut Block,
+    ) -> usize {
+        let a = instr.a();
+        let forloop = jump_target(pc, instr);
+        let paired = matches!(self.instr(forloop), Some((next, Opcode::FORLOOP)) if next.a() == a);
+        if forloop <= pc || forloop >= end || !paired {
+            return self.unstructured(pc, Opcode::FORPREP, block);
+        }
+
+        let start = self.reg(a, pc);
+        let limit = self.reg(a + 1, pc);
+        let step = match self.reg(a + 2, pc) {
+            Expr::Number(1.0) | Expr::Integer(1) => None,
+            step => Some(step),
+        };
+        self.spill_all(block);
+
+        self.mark_declared(a + 3, pc + 1);
+        let var = self.register_name(a + 3, pc + 1);
+        let body = self.loop_body(pc + 1, forloop, forloop + 1);
+        block.push(Stmt::NumericFor(var, start, limit, step, body));
+        forloop + 1
+    }
+
+    /// `for vars in explist` between the JMP and its TFORLOOP
+    fn generic_for(&mut self, pc: usize, instr: &Instruction, block: &mut Block) -> usize {
+        let tforloop = jump_target(pc, instr);
+        let (call, _) = self.instr(tforloop).expect("generic for loop");
+        let (a, c) = (call.a(), call.c());
+
+        let mut exprs = Vec::new();
+        for reg in a..a + 3 {
+            match &self.state.slots[reg as usize] {
+                Slot::Continued => {}
+                _ => exprs.push(self.reg(reg, pc)),
+            }
+        }
+        while exprs.len() > 1 && exprs.last() == Some(&Expr::Nil) {
+            exprs.pop();
+        }
+        for reg in a..a + 3 {
+            self.state.slots[reg as usize] = Slot::Empty;
+        }
+        self.spill_all(block);
+
+        let vars = (a + 3..a + 3 + c)
+            .map(|reg| {
+                self.mark_declared(reg, pc + 1);
+                self.register_name(reg, pc + 1)
+            })
+            .collect();
+        let body = self.loop_body(pc + 1, tforloop, tforloop + 2);
+        block.push(Stmt::GenericFor(vars, exprs, body));
+        tforloop + 2
+    }
+
+    //////////////// Instructions ////////////////
+
+    /// Translates an instruction that does not affect control flow
+    fn simple(&mut self, pc: usize, instr: &Instruction, op: Opcode, block: &mut Block) -> usize {
+        let (a, b, c) = (instr.a(), instr.b(), instr.c());
+        match op {
+            Opcode::MOVE => {
+                let value = self.reg(b, pc);
+                self.write(a, value, pc, block);
+            }
+            Opcode::LOADK => {
+                let value = self.constant(instr.bx());
+                self.write(a, value, pc, block);
+            }
+            Opcode::LOADBOOL => {
+                self.write(a, Expr::Boolean(b != 0), pc, block);
+                if c != 0 {
+                    block.push(Stmt::Comment(format!("LOADBOOL skip at pc {pc}")));
+                }
+            }
+            Opcode::LOADNIL => {
+                for reg in a..=b {
+                    self.write(reg, Expr::Nil, pc, block);
+                }
+            }
+            Opcode::GETUPVAL => {
+                let value = Expr::Name(self.upvalue_name(b));
+                self.write(a, value, pc, block);
+            }
+            Opcode::GETGLOBAL => {
+                let value = Expr::Global(self.constant_name(instr.bx()));
+                self.write(a, value, pc, block);
+            }
+            Opcode::GETTABLE => {
+                let table = self.reg(b, pc);
+                let key = self.rk_c(instr, pc);
+                self.write(a, Expr::Index(Box::new(table), Box::new(key)), pc, block);
+            }
+            Opcode::SETGLOBAL => {
+                let value = self.reg(a, pc);
+                let target = Expr::Global(self.constant_name(instr.bx()));
+                assign(target, value, block);
+            }
+            Opcode::SETUPVAL => {
+                let value = self.reg(a, pc);
+                let name = self.upvalue_name(b);
+                self.spill_mentions(&name, block);
+                assign(Expr::Name(name), value, block);
+            }
+            Opcode::SETTABLE if self.is_constructor(a, pc) => {
+                // Positional items before a hash field are still in the registers above
+                // the table, below any register the key and value were computed in
+                let mut count = (a + 1..)
+                    .take_while(|&r| {
+                        matches!(self.state.slots.get(r as usize), Some(Slot::Value(_)))
+                    })
+                    .count() as u32;
+                for operand in [(!instr.b_isk()).then_some(b), (!instr.c_isk()).then_some(c)] {
+                    if let Some(operand) = operand.filter(|&r| r > a) {
+                        count = count.min(operand - a - 1);
+                    }
+                }
+                let items = self.range(a + 1, Some(a + 1 + count), pc);
+                let key = self.rk_b(instr, pc);
+                let value = self.rk_c(instr, pc);
+                if let Slot::Value(Expr::Table(fields)) = &mut self.state.slots[a as usize] {
+                    fields.extend(items.into_iter().map(Field::Positional));
+                    fields.push(Field::Keyed(key, value));
+                }
+            }
+            Opcode::SETTABLE => {
+                let table = self.reg(a, pc);
+                let key = self.rk_b(instr, pc);
+                let value = self.rk_c(instr, pc);
+                let target = Expr::Index(Box::new(table), Box::new(key));
+                assign(target, value, block);
+            }
+            Opcode::NEWTABLE => self.write(a, Expr::Table(Vec::new()), pc, block),
+            Opcode::SELF => {
+                let obj = self.reg(b, pc);
+                let key = self.rk_c(instr, pc);
+                self.pend(a + 1, obj.clone(), 1);
+                self.state.slots[a as usize] = Slot::Method(obj, key);
+            }
+            Opcode::ADD | Opcode::SUB | Opcode::MUL | Opcode::DIV | Opcode::MOD | Opcode::POW => {
+                let op = match op {
+                    Opcode::ADD => BinOp::Add,
+                    Opcode::SUB => BinOp::Sub,
+                    Opcode::MUL => BinOp::Mul,
+                    Opcode::DIV => BinOp::Div,
+                    Opcode::MOD => BinOp::Mod,
+                    _ => BinOp::Pow,
+                };
+                let lhs = self.rk_b(instr, pc);
+                let rhs = self.rk_c(instr, pc);
+                self.write(a, Expr::Binary(op, Box::new(lhs), Box::new(rhs)), pc, block);
+            }
+            Opcode::UNM | Opcode::NOT | Opcode::LEN => {
+                let op = match op {
+                    Opcode::UNM => UnOp::Neg,
+                    Opcode::NOT => UnOp::Not,
+                    _ => UnOp::Len,
+                };
+                let operand = self.reg(b, pc);
+                self.write(a, Expr::Unary(op, Box::new(operand)), pc, block);
+            }
+            Opcode::CONCAT => {
+                let mut parts = self.range(b, Some(c + 1), pc);
+                let mut value = parts.pop().unwrap_or(Expr::Nil);
+                while let Some(part) = parts.pop() {
+                    value = Expr::Binary(BinOp::Concat, Box::new(part), Box::new(value));
+                }
+                self.write(a, value, pc, block);
+            }
+            Opcode::CALL | Opcode::TAILCALL => {
+                let call = self.call(a, b, pc);
+                match (op, c) {
+                    (Opcode::TAILCALL, _) => block.push(Stmt::Return(vec![call])),
+                    (_, 0) => {
+                        self.pend(a, call, 1);
+                        self.state.open = Some(a);
+                    }
+                    (_, 1) => block.push(Stmt::Call(call)),
+                    (_, 2) => self.write(a, call, pc, block),
+                    _ => self.write_multi(a, c - 1, call, pc, block),
+                }
+            }
+            // lcode.c: a tail call is always followed by RETURN A 0, which
+            // only runs if the call falls back to a normal one
+            Opcode::RETURN if b == 0 && self.follows_tail_call(pc, a) => {}
+            Opcode::RETURN => {
+                let values = match b {
+                    0 => self.range(a, None, pc),
+                    _ => self.range(a, Some(a + b - 1), pc),
+                };
+                block.push(Stmt::Return(values));
+            }
+            Opcode::SETLIST => {
+                // lvm.c: with C = 0 the batch number is the next word
+                let (batch, next) = match c {
+                    0 => (
+                        self.proto.code.get(pc + 1).map_or(1, Instruction::raw),
+                        pc + 2,
+                    ),
+                    _ => (c, pc + 1),
+                };
+                let Some(Slot::Value(Expr::Table(fields))) = self.state.slots.get(a as usize)
+                else {
+                    block.push(Stmt::Comment(format!("SETLIST at pc {pc}")));
+                    return next;
+                };
+
+                // Items before a hash field were added with it
+                let added = fields
+                    .iter()
+                    .filter(|f| matches!(f, Field::Positional(_)))
+                    .count() as u32;
+                let skip = added.saturating_sub(batch.saturating_sub(1) * FIELDS_PER_FLUSH);
+                let items = match b {
+                    0 => self.range(a + 1 + skip, None, pc),
+                    _ => self.range(a + 1 + skip.min(b), Some(a + 1 + b), pc),
+                };
+                if let Slot::Value(Expr::Table(fields)) = &mut self.state.slots[a as usize] {
+                    fields.extend(items.into_iter().map(Field::Positional));
+                }
+                return next;
+            }
+            Opcode::CLOSE => {}
+            Opcode::CLOSURE => {
+                let child = &self.proto.prototypes[instr.bx() as usize];
+                let upvalues = self.proto.closure_upvalues(pc).unwrap_or_default();
+
+                // Upvalues without debug names are named after what they are bound to
+                let mut names = upvalue_names(&child.debug_info.upvalues);
+                for upvalue in upvalues.iter().skip(names.len()) {
+                    let index = u32::from(upvalue.idx);
+                    names.push(match upvalue.instack {
+                        true => self.register_name(index, pc),
+                        false => self.upvalue_name(index),
+                    });
+                }
+                // A captured register is a variable, so it needs its value in place
+                for upvalue in upvalues.iter().filter(|u| u.instack) {
+                    let reg = u32::from(upvalue.idx);
+                    if reg != a && matches!(self.state.slots[reg as usize], Slot::Value(_)) {
+                        self.spill(reg, block);
+                    }
+                }
+
+                let mut decompiler = FunctionDecompiler::new(child, self.depth + 1);
+                decompiler.upvalues = names;
+                let function = Box::new(decompiler.decompile());
+                let next = pc + 1 + upvalues.len().max(usize::from(child.num_upvalues));
+
+                // lparser.c:localfunc starts the local after the closure, which may
+                // capture it. Without that capture it is `local f = function`
+                let captured = upvalues.iter().any(|u| u.instack && u32::from(u.idx) == a);
+                let local = match self.local_at(a, pc) {
+                    Some(index) if self.locals[index].startpc as usize == pc => Some(index),
+                    _ => self
+                        .local_at(a, next)
+                        .filter(|&index| captured && self.locals[index].startpc as usize == next),
+                };
+                match local {
+                    Some(index) if !self.state.declared[index] => {
+                        self.state.declared[index] = true;
+                        let name = self.locals[index].name().into_owned();
+                        block.push(Stmt::LocalFunction(name, function));
+                    }
+                    _ => self.write(a, Expr::Function(function), pc, block),
+                }
+                return next;
+            }
+            Opcode::VARARG => match b {
+                0 => {
+                    self.pend(a, Expr::VarArg, 1);
+                    self.state.open = Some(a);
+                }
+                2 => self.write(a, Expr::VarArg, pc, block),
+                _ => self.write_multi(a, b - 1, Expr::VarArg, pc, block),
+            },
+            Opcode::JMP
+            | Opcode::EQ
+            | Opcode::LT
+            | Opcode::LE
+            | Opcode::TEST
+            | Opcode::TESTSET
+            | Opcode::FORLOOP
+            | Opcode::FORPREP
+            | Opcode::TFORLOOP => unreachable!("control flow is handled by `statement`"),
+        }
+        pc + 1
+    }
+
+    /// Builds the call at `a` with `b - 1` arguments (up to the open value if `b` is 0)
+    fn call(&mut self, a: u32, b: u32, pc: usize) -> Expr {
+        let to = match b {
+            0 => None,
+            _ => Some(a + b),
+        };
+
+        let method = match &self.state.slots[a as usize] {
+            Slot::Method(obj, Expr::String(method)) => {
+                as_identifier(method).map(|name| (obj.clone(), name.to_owned()))
+            }
+            _ => None,
+        };
+        let call = match method {
+            Some((obj, method)) => {
+                self.state.slots[a as usize + 1] = Slot::Empty;
+                let args = self.range(a + 2, to, pc);
+                Expr::MethodCall(Box::new(obj), method, args)
+            }
+            None => {
+                let func = self.reg(a, pc);
+                let args = self.range(a + 1, to, pc);
+                Expr::Call(Box::new(func), args)
+            }
+        };
+        self.state.slots[a as usize] = Slot::Empty;
+        call
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+fn label_name(pc: usize) -> String {
+    format!("label_{pc}")
+}
+
+/// Upvalue debug names as source text
+fn upvalue_names(names: &[Vec<u8>]) -> Vec<String> {
+    names
+        .iter()
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect()
+}
+
+fn is_name(name: &str) -> bool {
+    is_identifier(name)
+}
+
+/// Whether every `goto` in `block` sees its label: one in the same or an enclosing
+/// block, and not past a local declared after the `goto` (lparser.c). `outer` holds
+/// the enclosing blocks and the statement being walked in each
+fn gotos_resolve<'b>(block: &'b Block, outer: &mut Vec<(&'b Block, usize)>) -> bool {
+    for index in 0..block.len() {
+        outer.push((block, index));
+        let resolved = match &block[index] {
+            Stmt::Goto(label) => outer
+                .iter()
+                .any(|&(enclosing, at)| label_visible(enclosing, at, label)),
+            Stmt::If(_, then, otherwise) => {
+                gotos_resolve(then, outer) && gotos_resolve(otherwise, outer)
+            }
+            Stmt::While(_, body)
+            | Stmt::Repeat(body, _)
+            | Stmt::NumericFor(.., body)
+            | Stmt::GenericFor(.., body) => gotos_resolve(body, outer),
+            _ => true,
+        };
+        outer.pop();
+        if !resolved {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether a `goto` in statement `at` of `block` can jump to `label` in that block
+fn label_visible(block: &Block, at: usize, label: &str) -> bool {
+    let Some(target) = block
+        .iter()
+        .position(|stmt| matches!(stmt, Stmt::Label(name) if name == label))
+    else {
+        return false;
+    };
+    target <= at
+        || !block[at + 1..target]
+            .iter()
+            .any(|stmt| matches!(stmt, Stmt::Local(..) | Stmt::LocalFunction(..)))
+}
+
+/// `target = value`, as `function a.b:c()` when a function is stored into a name path
+fn assign(target: Expr, value: Expr, block: &mut Block) {
+    match (function_path(&target), value) {
+        (Some(mut path), Expr::Function(function)) => {
+            let is_method = path.len() > 1 && function.params.first().is_some_and(|p| p == "self");
+            let method = is_method.then(|| path.pop()).flatten();
+            block.push(Stmt::FunctionDef(FunctionName { path, method }, function));
+        }
+        (_, value) => block.push(Stmt::Assign(vec![target], vec![value])),
+    }
+}
+
+/// Joins a bare `local x` with the assignment right after it. Inferred locals of
+/// stripped code are declared where all their assignments are in scope
+fn fold_declaration(block: &mut Block) {
+    let [.., Stmt::Local(names, values), last] = block.as_slice() else {
+        return;
+    };
+    let [name] = names.as_slice() else {
+        return;
+    };
+    if !values.is_empty() {
+        return;
+    }
+    let folded = match last {
+        Stmt::Assign(targets, values)
+            if targets.as_slice() == [Expr::Name(name.clone())]
+                && values.len() == 1
+                && !values[0].mentions(name)
+                && !matches!(values[0], Expr::Function(_)) =>
+        {
+            Stmt::Local(vec![name.clone()], values.clone())
+        }
+        Stmt::FunctionDef(FunctionName { path, method: None }, function)
+            if path.as_slice() == [name.clone()] =>
+        {
+            Stmt::LocalFunction(name.clone(), function.clone())
+        }
+        _ => return,
+    };
+    block.truncate(block.len() - 2);
+    block.push(folded);
+}
+
+/// The names of a variable followed by identifier field accesses
+fn function_path(expr: &Expr) -> Option<Vec<String>> {
+    match expr {
+        Expr::Name(name) => Some(vec![name.clone()]),
+        Expr::Global(name) if is_name(name) => Some(vec![name.clone()]),
+        Expr::Index(table, key) => match key.as_ref() {
+            Expr::String(field) => {
+                let field = as_identifier(field)?;
+                let mut path = function_path(table)?;
+                path.push(field.to_owned());
+                Some(path)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
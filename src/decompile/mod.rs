@@ -0,0 +1,598 @@
+/*
+  Decompiler turning Lua 5.1 function prototypes back into Lua source
+*/
+
+mod condition;
+mod function;
+
+use crate::analysis::{Diagnostic, verify};
+use crate::ast::{Printer, PrinterConfig};
+use crate::parser::bytecode::FunctionPrototype;
+use function::FunctionDecompiler;
+use std::fmt;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// Error returned by `decompile` for code that `verify` rejects, such as registers
+/// past the stack size, which the translation relies on being well-formed
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecompileError {
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl fmt::Display for DecompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid bytecode")?;
+        if let Some(first) = self.diagnostics.first() {
+            write!(f, ": {first}")?;
+        }
+        match self.diagnostics.len() {
+            0 | 1 => Ok(()),
+            count => write!(f, " (and {} more)", count - 1),
+        }
+    }
+}
+
+impl std::error::Error for DecompileError {}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Decompiles the main function of a Lua 5.1 chunk into Lua source
+pub fn decompile(proto: &FunctionPrototype) -> Result<String, DecompileError> {
+    decompile_with(proto, PrinterConfig::default())
+}
+
+/// Decompiles the main function of a Lua 5.1 chunk, printed with `config`. The
+/// function and its children are verified first
+pub fn decompile_with(
+    proto: &FunctionPrototype,
+    config: PrinterConfig,
+) -> Result<String, DecompileError> {
+    let diagnostics = verify(proto);
+    if !diagnostics.is_empty() {
+        return Err(DecompileError { diagnostics });
+    }
+
+    let function = FunctionDecompiler::new(proto, 0).decompile();
+    let mut printer = Printer::with_config(config);
+    printer.block(&function.body);
+    Ok(printer.finish())
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    fn decompile_listing(listing: &str) -> String {
+        decompile(&assemble(listing).expect("valid listing")).expect("valid bytecode")
+    }
+
+    #[test]
+    fn stripped_while_keeps_temporaries_inline() {
+        // while x > 0 do x = x - 1 end
+        let source = decompile_listing(
+            r#"
+            .const "x"
+            .const 0
+            .const 1
+            L0:
+                GETGLOBAL 0 K0
+                LT        0 K1 0
+                JMP       L7
+                GETGLOBAL 0 K0
+                SUB       0 0 K2
+                SETGLOBAL 0 K0
+                JMP       L0
+            L7:
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "while x > 0 do\n    x = x - 1\nend\n");
+    }
+
+    #[test]
+    fn stripped_repeat_keeps_temporaries_inline() {
+        // repeat x = x - 1 print("a") until x <= 0
+        let source = decompile_listing(
+            r#"
+            .const "x"
+            .const 1
+            .const "print"
+            .const "a"
+            .const 0
+            L0:
+                GETGLOBAL 0 K0
+                SUB       0 0 K1
+                SETGLOBAL 0 K0
+                GETGLOBAL 0 K2
+                LOADK     1 K3
+                CALL      0 2 1
+                GETGLOBAL 0 K0
+                LE        0 0 K4
+                JMP       L0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(
+            source,
+            "repeat\n    x = x - 1\n    print(\"a\")\nuntil x <= 0\n"
+        );
+    }
+
+    #[test]
+    fn stripped_if_else_keeps_temporaries_inline() {
+        // if c then print("a") else print("b") end
+        let source = decompile_listing(
+            r#"
+            .const "c"
+            .const "print"
+            .const "a"
+            .const "b"
+                GETGLOBAL 0 K0
+                TEST      0 0 0
+                JMP       L7
+                GETGLOBAL 0 K1
+                LOADK     1 K2
+                CALL      0 2 1
+                JMP       L10
+            L7:
+                GETGLOBAL 0 K1
+                LOADK     1 K3
+                CALL      0 2 1
+            L10:
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(
+            source,
+            "if c then\n    print(\"a\")\nelse\n    print(\"b\")\nend\n"
+        );
+    }
+
+    #[test]
+    fn stripped_locals_get_generated_names() {
+        // local v; if c then v = 1 else v = 2 end; print(v)
+        let source = decompile_listing(
+            r#"
+            .const "c"
+            .const 1
+            .const 2
+            .const "print"
+                GETGLOBAL 1 K0
+                TEST      1 0 0
+                JMP       L5
+                LOADK     0 K1
+                JMP       L6
+            L5:
+                LOADK     0 K2
+            L6:
+                GETGLOBAL 1 K3
+                MOVE      2 0
+                CALL      1 2 1
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(
+            source,
+            "local l_0_0\nif c then\n    l_0_0 = 1\nelse\n    l_0_0 = 2\nend\nprint(l_0_0)\n"
+        );
+    }
+
+    #[test]
+    fn rejects_registers_past_the_stack() {
+        // MOVE 200 0 in a function with a stack of 2
+        let proto = assemble(".stack 2\n    MOVE 200 0\n    RETURN 0 1\n").unwrap();
+        let err = decompile(&proto).unwrap_err();
+        assert_eq!(
+            err.diagnostics[0].kind,
+            crate::analysis::DiagnosticKind::RegisterOutOfRange {
+                operand: "A",
+                reg: 200
+            }
+        );
+    }
+
+    /// Decompiles without verifying first, as if verify had missed something
+    fn decompile_unverified(listing: &str) -> String {
+        let proto = assemble(listing).expect("valid listing");
+        let mut printer = Printer::with_config(PrinterConfig::default());
+        printer.block(&FunctionDecompiler::new(&proto, 0).decompile().body);
+        printer.finish()
+    }
+
+    #[test]
+    fn backward_for_prep_is_unstructured() {
+        let source = decompile_unverified(
+            r#"
+            .const 1
+            .const "print"
+                LOADK     0 K0
+                LOADK     1 K0
+                LOADK     2 K0
+            L3:
+                GETGLOBAL 4 K1
+                MOVE      5 3
+                CALL      4 2 1
+                FORLOOP   0 L3
+                FORPREP   0 L3
+                RETURN    0 1
+            "#,
+        );
+        assert!(
+            source.contains("-- unstructured FORPREP at pc 7"),
+            "{source}"
+        );
+    }
+
+    #[test]
+    fn tail_call_returns_once() {
+        // return f(x)
+        let source = decompile_listing(
+            r#"
+            .const "f"
+            .const "x"
+                GETGLOBAL 0 K0
+                GETGLOBAL 1 K1
+                TAILCALL  0 2 0
+                RETURN    0 0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "return f(x)\n");
+    }
+
+    #[test]
+    fn integer_constants_keep_every_digit() {
+        // x = 2^53 + 1
+        let source = decompile_listing(
+            r#"
+            .const "x"
+            .const 9007199254740993
+                LOADK     0 K1
+                SETGLOBAL 0 K0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "x = 9007199254740993\n");
+    }
+
+    #[test]
+    fn numeric_for() {
+        // for i = 1, 3 do print(i) end
+        let source = decompile_listing(
+            r#"
+            .const 1
+            .const 3
+            .const "print"
+            .local "(for index)" 3 8
+            .local "(for limit)" 3 8
+            .local "(for step)" 3 8
+            .local "i" 4 7
+                LOADK     0 K0
+                LOADK     1 K1
+                LOADK     2 K0
+                FORPREP   0 L7
+            L4:
+                GETGLOBAL 4 K2
+                MOVE      5 3
+                CALL      4 2 1
+            L7:
+                FORLOOP   0 L4
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "for i = 1, 3 do\n    print(i)\nend\n");
+    }
+
+    #[test]
+    fn numeric_for_with_step() {
+        // for i = 10, 1, -1 do print(i) end
+        let source = decompile_listing(
+            r#"
+            .const 10
+            .const 1
+            .const -1
+            .const "print"
+            .local "(for index)" 3 8
+            .local "(for limit)" 3 8
+            .local "(for step)" 3 8
+            .local "i" 4 7
+                LOADK     0 K0
+                LOADK     1 K1
+                LOADK     2 K2
+                FORPREP   0 L7
+            L4:
+                GETGLOBAL 4 K3
+                MOVE      5 3
+                CALL      4 2 1
+            L7:
+                FORLOOP   0 L4
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "for i = 10, 1, -1 do\n    print(i)\nend\n");
+    }
+
+    #[test]
+    fn generic_for() {
+        // for k, v in pairs(t) do print(k, v) end
+        let source = decompile_listing(
+            r#"
+            .const "pairs"
+            .const "t"
+            .const "print"
+            .local "(for generator)" 3 10
+            .local "(for state)" 3 10
+            .local "(for control)" 3 10
+            .local "k" 4 8
+            .local "v" 4 8
+                GETGLOBAL 0 K0
+                GETGLOBAL 1 K1
+                CALL      0 2 4
+                JMP       L8
+            L4:
+                GETGLOBAL 5 K2
+                MOVE      6 3
+                MOVE      7 4
+                CALL      5 3 1
+            L8:
+                TFORLOOP  0 0 2
+                JMP       L4
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "for k, v in pairs(t) do\n    print(k, v)\nend\n");
+    }
+
+    #[test]
+    fn break_leaves_the_loop() {
+        // while true do if x then break end end
+        let source = decompile_listing(
+            r#"
+            .const "x"
+            L0:
+                GETGLOBAL 0 K0
+                TEST      0 0 0
+                JMP       L4
+                JMP       L5
+            L4:
+                JMP       L0
+            L5:
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(
+            source,
+            "while true do\n    if x then\n        break\n    end\nend\n"
+        );
+    }
+
+    #[test]
+    fn varargs() {
+        // local a, b = ...; print(...)
+        let source = decompile_listing(
+            r#"
+            .vararg 2
+            .stack 4
+            .const "print"
+            .local "a" 1 5
+            .local "b" 1 5
+                VARARG    0 3
+                GETGLOBAL 2 K0
+                VARARG    3 0
+                CALL      2 0 1
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "local a, b = ...\nprint(...)\n");
+    }
+
+    #[test]
+    fn returns_varargs() {
+        // return ...
+        let source = decompile_listing(
+            r#"
+            .vararg 2
+                VARARG    0 0
+                RETURN    0 0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "return ...\n");
+    }
+
+    #[test]
+    fn rejects_invalid_nested_functions() {
+        // The child jumps past its end
+        let proto = assemble(
+            r#"
+            .func
+                JMP       L2
+                RETURN    0 1
+            L2:
+            .end
+                CLOSURE   0 0
+                RETURN    0 1
+            "#,
+        );
+        let err = decompile(&proto.expect("valid listing")).unwrap_err();
+        assert!(!err.diagnostics.is_empty());
+        assert!(err.to_string().starts_with("invalid bytecode: "), "{err}");
+    }
+
+    #[test]
+    fn irreducible_flow_is_listed() {
+        // Both `a()` and `b()` start the loop, so neither is its only entry
+        let source = decompile_listing(
+            r#"
+            .const "c"
+            .const "a"
+            .const "b"
+                GETGLOBAL 0 K0
+                TEST      0 0 0
+                JMP       L4
+                JMP       L6
+            L4:
+                GETGLOBAL 0 K1
+                CALL      0 1 1
+            L6:
+                GETGLOBAL 0 K2
+                CALL      0 1 1
+                JMP       L4
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(
+            source,
+            "-- irreducible control flow:\n\
+             -- 1 [-] GETGLOBAL 0 -1 ; c\n\
+             -- 2 [-] TEST      0 0 0\n\
+             -- 3 [-] JMP       1 ; to 5\n\
+             -- 4 [-] JMP       2 ; to 7\n\
+             -- 5 [-] GETGLOBAL 0 -2 ; a\n\
+             -- 6 [-] CALL      0 1 1\n\
+             -- 7 [-] GETGLOBAL 0 -3 ; b\n\
+             -- 8 [-] CALL      0 1 1\n\
+             -- 9 [-] JMP       -5 ; to 5\n\
+             -- 10 [-] RETURN    0 1\n"
+        );
+    }
+
+    #[test]
+    fn table_constructor_with_every_field_kind() {
+        // t = {1, 2, x = 3, [k] = v, f()}
+        let source = decompile_listing(
+            r#"
+            .const 1
+            .const 2
+            .const "x"
+            .const 3
+            .const "k"
+            .const "v"
+            .const "f"
+            .const "t"
+                NEWTABLE  0 2 2
+                LOADK     1 K0
+                LOADK     2 K1
+                SETTABLE  0 K2 K3
+                GETGLOBAL 3 K4
+                GETGLOBAL 4 K5
+                SETTABLE  0 3 4
+                GETGLOBAL 3 K6
+                CALL      3 1 0
+                SETLIST   0 0 1
+                SETGLOBAL 0 K7
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "t = {1, 2, x = 3, [k] = v, f()}\n");
+    }
+
+    #[test]
+    fn table_constructor_of_varargs() {
+        // t = {...}
+        let source = decompile_listing(
+            r#"
+            .vararg 2
+            .const "t"
+                NEWTABLE  0 0 0
+                VARARG    1 0
+                SETLIST   0 0 1
+                SETGLOBAL 0 K0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "t = {...}\n");
+    }
+
+    #[test]
+    fn setlist_batch_in_the_next_word() {
+        // With C = 0 the batch number is the following word, which is not an instruction
+        let source = decompile_listing(
+            r#"
+            .const 1
+            .const 2
+            .const "t"
+                NEWTABLE  0 2 0
+                LOADK     1 K0
+                LOADK     2 K1
+                SETLIST   0 2 0
+                .word     1
+                SETGLOBAL 0 K2
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "t = {1, 2}\n");
+    }
+
+    #[test]
+    fn local_function_with_upvalue() {
+        // local function f() return f end
+        let source = decompile_listing(
+            r#"
+            .func
+                .upvals 1 "f"
+                    GETUPVAL  0 0
+                    RETURN    0 2
+                    RETURN    0 1
+            .end
+            .local "f" 2 3
+                CLOSURE   0 0
+                MOVE      0 0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "local function f()\n    return f\nend\n");
+    }
+
+    #[test]
+    fn method_definition() {
+        // function a.b:c(x) return self, x end
+        let source = decompile_listing(
+            r#"
+            .const "a"
+            .const "b"
+            .const "c"
+            .func
+                .params 2
+                .local "self" 0 4
+                .local "x" 0 4
+                    MOVE      2 0
+                    MOVE      3 1
+                    RETURN    2 3
+                    RETURN    0 1
+            .end
+                GETGLOBAL 0 K0
+                GETTABLE  0 0 K1
+                CLOSURE   1 0
+                SETTABLE  0 K2 1
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "function a.b:c(x)\n    return self, x\nend\n");
+    }
+
+    #[test]
+    fn local_set_to_a_function() {
+        // local g = function() return g end, where the body reads the global g
+        let source = decompile_listing(
+            r#"
+            .func
+                .const "g"
+                    GETGLOBAL 0 K0
+                    RETURN    0 2
+                    RETURN    0 1
+            .end
+            .local "g" 1 2
+                CLOSURE   0 0
+                RETURN    0 1
+            "#,
+        );
+        assert_eq!(source, "local g = function()\n    return g\nend\n");
+    }
+}
@@ -0,0 +1,10 @@
+pub mod analysis;
+pub mod assembler;
+pub mod ast;
+pub mod decompile;
+pub mod listing;
+pub mod parser;
+#[cfg(feature = "serde")]
+pub mod schema;
+pub mod strip;
+pub mod writer;
@@ -0,0 +1,543 @@
+/*
+  Lua 5.1 function listings in the format of `luac -l` and `luac -l -l` (print.c)
+*/
+
+use crate::parser::bytecode::{
+    Constant, FunctionPrototype, Instruction, InstructionFormat, LUA51_OPCODES, Opcode, OperandMask,
+};
+use std::fmt::Write;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// luac.c always reports the host instruction size
+const SIZE_INSTRUCTION: usize = 4;
+
+// Source a stripped main function is loaded with (lundump.c:luaU_undump)
+const STRIPPED_SOURCE: &[u8] = b"=?";
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Lists a function like `luac -l`, adding the constant, local and upvalue tables of
+/// `luac -l -l` when `full` is set, followed by its children when `recursive` is
+/// (print.c:PrintFunction). Where luac prints a function's address, functions are
+/// numbered from 1 in the order a recursive listing shows them, so output is stable
+pub fn print_function(proto: &FunctionPrototype, full: bool, recursive: bool) -> String {
+    let mut out = String::new();
+    function(&mut out, proto, 1, STRIPPED_SOURCE, full, recursive);
+    out
+}
+
+fn function(
+    out: &mut String,
+    proto: &FunctionPrototype,
+    id: usize,
+    parent_source: &[u8],
+    full: bool,
+    recursive: bool,
+) {
+    // Nested functions without a source inherit their parent's (lundump.c:LoadFunction)
+    let source = proto.source_name.as_deref().unwrap_or(parent_source);
+    header(out, proto, id, source);
+    code(out, proto, id);
+    if full {
+        debug(out, proto, id);
+    }
+    if !recursive {
+        return;
+    }
+    for (index, child) in proto.prototypes.iter().enumerate() {
+        function(
+            out,
+            child,
+            child_id(proto, id, index),
+            source,
+            full,
+            recursive,
+        );
+    }
+}
+
+/// Number of functions in the tree rooted at `proto`
+fn tree_size(proto: &FunctionPrototype) -> usize {
+    1 + proto.prototypes.iter().map(tree_size).sum::<usize>()
+}
+
+/// Id of child `index` of the function numbered `id`, which follows the trees of its
+/// elder siblings
+fn child_id(proto: &FunctionPrototype, id: usize, index: usize) -> usize {
+    id + 1
+        + proto.prototypes[..index]
+            .iter()
+            .map(tree_size)
+            .sum::<usize>()
+}
+
+/// A function id shaped like the address luac prints
+fn address(id: usize) -> String {
+    format!("0x{id:04x}")
+}
+
+/// print.c:PrintHeader
+fn header(out: &mut String, proto: &FunctionPrototype, id: usize, source: &[u8]) {
+    let source = match source.first() {
+        Some(b'@' | b'=') => String::from_utf8_lossy(&source[1..]),
+        Some(0x1b) => "(bstring)".into(),
+        _ => "(string)".into(),
+    };
+    let kind = match proto.line_defined {
+        0 => "main",
+        _ => "function",
+    };
+    let count = proto.code.len();
+    let _ = writeln!(
+        out,
+        "\n{kind} <{source}:{},{}> ({count} instruction{}, {} bytes at {})",
+        proto.line_defined,
+        proto.last_line_defined,
+        plural(count),
+        count * SIZE_INSTRUCTION,
+        address(id),
+    );
+    let _ = write!(
+        out,
+        "{}{} param{}, {} slot{}, {} upvalue{}, ",
+        proto.num_params,
+        if proto.is_vararg != 0 { "+" } else { "" },
+        plural(proto.num_params.into()),
+        proto.max_stack_size,
+        plural(proto.max_stack_size.into()),
+        proto.num_upvalues,
+        plural(proto.num_upvalues.into()),
+    );
+    let locals = proto.debug_info.locals.len();
+    let constants = proto.constants.len();
+    let functions = proto.prototypes.len();
+    let _ = writeln!(
+        out,
+        "{locals} local{}, {constants} constant{}, {functions} function{}",
+        plural(locals),
+        plural(constants),
+        plural(functions),
+    );
+}
+
+/// print.c:PrintCode
+fn code(out: &mut String, proto: &FunctionPrototype, id: usize) {
+    let mut pc = 0;
+    while pc < proto.code.len() {
+        let instr = &proto.code[pc];
+        let _ = write!(out, "\t{}\t", pc + 1);
+        match proto.debug_info.lineinfo.get(pc) {
+            Some(&line) if line > 0 => {
+                let _ = write!(out, "[{line}]\t");
+            }
+            _ => out.push_str("[-]\t"),
+        }
+
+        let Ok(op) = instr.opcode() else {
+            let name = format!("UNKNOWN({})", instr.op());
+            let _ = writeln!(out, "{name:<9}\t{} {} {}", instr.a(), instr.b(), instr.c());
+            pc += 1;
+            continue;
+        };
+        let (format, b_mode, c_mode) = LUA51_OPCODES.modes[op as usize];
+        let _ = write!(out, "{:<9}\t", LUA51_OPCODES.names[op as usize]);
+        let (a, b, c, bx, sbx) = (instr.a(), instr.b(), instr.c(), instr.bx(), instr.sbx());
+        match format {
+            InstructionFormat::IABC => {
+                let _ = write!(out, "{a}");
+                if b_mode != OperandMask::OpArgN {
+                    let _ = write!(out, " {}", rk(b));
+                }
+                if c_mode != OperandMask::OpArgN {
+                    let _ = write!(out, " {}", rk(c));
+                }
+            }
+            InstructionFormat::IABx => match b_mode {
+                OperandMask::OpArgK => {
+                    let _ = write!(out, "{a} {}", -1 - bx as i64);
+                }
+                _ => {
+                    let _ = write!(out, "{a} {bx}");
+                }
+            },
+            InstructionFormat::IAsBx => match op {
+                Opcode::JMP => {
+                    let _ = write!(out, "{sbx}");
+                }
+                _ => {
+                    let _ = write!(out, "{a} {sbx}");
+                }
+            },
+            InstructionFormat::IAx | InstructionFormat::IsJ => {
+                unreachable!("Lua 5.1 instructions have no Ax or sJ operand")
+            }
+        }
+
+        match op {
+            Opcode::LOADK => {
+                let _ = write!(out, "\t; {}", constant(proto, bx as usize));
+            }
+            Opcode::GETUPVAL | Opcode::SETUPVAL => {
+                let name = proto.debug_info.upvalue_name(b as usize);
+                let name = name.as_deref().unwrap_or("-");
+                let _ = write!(out, "\t; {name}");
+            }
+            Opcode::GETGLOBAL | Opcode::SETGLOBAL => {
+                let _ = write!(out, "\t; {}", global(proto, bx as usize));
+            }
+            Opcode::GETTABLE | Opcode::SELF if instr.c_isk() => {
+                let _ = write!(out, "\t; {}", constant(proto, instr.ck() as usize));
+            }
+            // print.c leaves OP_MOD out of this list
+            Opcode::SETTABLE
+            | Opcode::ADD
+            | Opcode::SUB
+            | Opcode::MUL
+            | Opcode::DIV
+            | Opcode::POW
+            | Opcode::EQ
+            | Opcode::LT
+            | Opcode::LE
+                if instr.b_isk() || instr.c_isk() =>
+            {
+                let operand = |isk: bool, index: u32| match isk {
+                    true => constant(proto, index as usize),
+                    false => "-".to_string(),
+                };
+                let lhs = operand(instr.b_isk(), instr.bk());
+                let rhs = operand(instr.c_isk(), instr.ck());
+                let _ = write!(out, "\t; {lhs} {rhs}");
+            }
+            Opcode::JMP | Opcode::FORLOOP | Opcode::FORPREP => {
+                let _ = write!(out, "\t; to {}", sbx as i64 + pc as i64 + 2);
+            }
+            Opcode::CLOSURE => match (bx as usize) < proto.prototypes.len() {
+                true => {
+                    let child = address(child_id(proto, id, bx as usize));
+                    let _ = write!(out, "\t; {child}");
+                }
+                false => out.push_str("\t; (nil)"),
+            },
+            // The batch word is consumed rather than listed
+            Opcode::SETLIST if c == 0 => {
+                pc += 1;
+                let batch = proto.code.get(pc).map_or(0, Instruction::raw);
+                let _ = write!(out, "\t; {}", batch as i32);
+            }
+            Opcode::SETLIST => {
+                let _ = write!(out, "\t; {c}");
+            }
+            _ => {}
+        }
+        out.push('\n');
+        pc += 1;
+    }
+}
+
+/// print.c:PrintDebug
+fn debug(out: &mut String, proto: &FunctionPrototype, id: usize) {
+    let id = address(id);
+    let _ = writeln!(out, "constants ({}) for {id}:", proto.constants.len());
+    for index in 0..proto.constants.len() {
+        let _ = writeln!(out, "\t{}\t{}", index + 1, constant(proto, index));
+    }
+
+    let locals = &proto.debug_info.locals;
+    let _ = writeln!(out, "locals ({}) for {id}:", locals.len());
+    for (index, local) in locals.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "\t{index}\t{}\t{}\t{}",
+            local.name(),
+            local.startpc + 1,
+            local.endpc + 1
+        );
+    }
+
+    let upvalues = &proto.debug_info.upvalues;
+    let _ = writeln!(out, "upvalues ({}) for {id}:", upvalues.len());
+    for (index, name) in upvalues.iter().enumerate() {
+        let _ = writeln!(out, "\t{index}\t{}", String::from_utf8_lossy(name));
+    }
+}
+
+/// A B or C operand, with constants shown as negative indices from -1
+fn rk(value: u32) -> i64 {
+    match value & (1 << (Instruction::SIZE_B - 1)) != 0 {
+        true => -1 - (value & !(1 << (Instruction::SIZE_B - 1))) as i64,
+        false => value.into(),
+    }
+}
+
+/// print.c:PrintConstant
+fn constant(proto: &FunctionPrototype, index: usize) -> String {
+    match proto.constants.get(index) {
+        Some(Constant::Nil) => "nil".to_string(),
+        Some(Constant::Boolean(value)) => value.to_string(),
+        Some(Constant::Number(value)) => format_g14(*value),
+        Some(Constant::Integer(value)) => value.to_string(),
+        Some(Constant::String(bytes)) => quote(bytes),
+        None => format!("? index={index}"),
+    }
+}
+
+/// A global name as printed with `%s`, which stops at the first NUL
+fn global(proto: &FunctionPrototype, index: usize) -> String {
+    match proto.constants.get(index) {
+        Some(Constant::String(bytes)) => {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        }
+        _ => constant(proto, index),
+    }
+}
+
+/// print.c:PrintString
+fn quote(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() + 2);
+    out.push('"');
+    for &b in bytes {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            7 => out.push_str("\\a"),
+            8 => out.push_str("\\b"),
+            12 => out.push_str("\\f"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            11 => out.push_str("\\v"),
+            b' '..=b'~' => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\{b:03}");
+            }
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Formats a number as C's `%.14g` (luaconf.h:LUA_NUMBER_FMT)
+fn format_g14(value: f64) -> String {
+    const PRECISION: i32 = 14;
+
+    if value.is_nan() {
+        return match value.is_sign_negative() {
+            true => "-nan".to_string(),
+            false => "nan".to_string(),
+        };
+    }
+    if value.is_infinite() {
+        return match value > 0.0 {
+            true => "inf".to_string(),
+            false => "-inf".to_string(),
+        };
+    }
+
+    // The exponent after rounding to the precision decides between the two styles
+    let scientific = format!("{:.*e}", (PRECISION - 1) as usize, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    if (-4..PRECISION).contains(&exponent) {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exponent) as usize, value);
+        trim_zeros(&fixed).to_string()
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim_zeros(mantissa), exponent.abs())
+    }
+}
+
+/// Drops trailing zeros of a fraction, and the point when nothing is left after it
+fn trim_zeros(text: &str) -> &str {
+    match text.contains('.') {
+        true => text.trim_end_matches('0').trim_end_matches('.'),
+        false => text,
+    }
+}
+
+const fn plural(count: usize) -> &'static str {
+    match count {
+        1 => "",
+        _ => "s",
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    /// What luac 5.1 compiles from
+    ///
+    /// ```lua
+    /// local t = {1, 2, "x"}
+    /// function f(a, ...)
+    ///   return a + 1.5, t
+    /// end
+    /// print(f(#t))
+    /// ```
+    const LISTING: &str = r#"
+        .source "@test.lua"
+        .vararg 2
+        .stack 4
+        .const 1.0
+        .const 2.0
+        .const "x"
+        .const "f"
+        .const "print"
+        .local "t" 5 14
+        .func
+            .linedefined 2
+            .lastlinedefined 4
+            .params 1
+            .vararg 7
+            .upvals 1 "t"
+            .stack 4
+            .const 1.5
+            .local "a" 0 4
+            .local "arg" 0 4
+            .line 3
+                ADD       2 0 K0
+                GETUPVAL  3 0
+                RETURN    2 3
+            .line 4
+                RETURN    0 1
+        .end
+        .line 1
+            NEWTABLE  0 3 0
+            LOADK     1 K0
+            LOADK     2 K1
+            LOADK     3 K2
+            SETLIST   0 3 1
+        .line 4
+            CLOSURE   1 0
+            MOVE      0 0
+        .line 2
+            SETGLOBAL 1 K3
+        .line 5
+            GETGLOBAL 1 K4
+            GETGLOBAL 2 K3
+            LEN       3 0
+            CALL      2 2 0
+            CALL      1 0 1
+            RETURN    0 1
+    "#;
+
+    /// `luac -l -l test.lua`, with the function addresses replaced by their ids
+    const LUAC_FULL: &str = "
+main <test.lua:0,0> (14 instructions, 56 bytes at 0x0001)
+0+ params, 4 slots, 0 upvalues, 1 local, 5 constants, 1 function
+\t1\t[1]\tNEWTABLE \t0 3 0
+\t2\t[1]\tLOADK    \t1 -1\t; 1
+\t3\t[1]\tLOADK    \t2 -2\t; 2
+\t4\t[1]\tLOADK    \t3 -3\t; \"x\"
+\t5\t[1]\tSETLIST  \t0 3 1\t; 1
+\t6\t[4]\tCLOSURE  \t1 0\t; 0x0002
+\t7\t[4]\tMOVE     \t0 0
+\t8\t[2]\tSETGLOBAL\t1 -4\t; f
+\t9\t[5]\tGETGLOBAL\t1 -5\t; print
+\t10\t[5]\tGETGLOBAL\t2 -4\t; f
+\t11\t[5]\tLEN      \t3 0
+\t12\t[5]\tCALL     \t2 2 0
+\t13\t[5]\tCALL     \t1 0 1
+\t14\t[5]\tRETURN   \t0 1
+constants (5) for 0x0001:
+\t1\t1
+\t2\t2
+\t3\t\"x\"
+\t4\t\"f\"
+\t5\t\"print\"
+locals (1) for 0x0001:
+\t0\tt\t6\t15
+upvalues (0) for 0x0001:
+
+function <test.lua:2,4> (4 instructions, 16 bytes at 0x0002)
+1+ param, 4 slots, 1 upvalue, 2 locals, 1 constant, 0 functions
+\t1\t[3]\tADD      \t2 0 -1\t; - 1.5
+\t2\t[3]\tGETUPVAL \t3 0\t; t
+\t3\t[3]\tRETURN   \t2 3
+\t4\t[4]\tRETURN   \t0 1
+constants (1) for 0x0002:
+\t1\t1.5
+locals (2) for 0x0002:
+\t0\ta\t1\t5
+\t1\targ\t1\t5
+upvalues (1) for 0x0002:
+\t0\tt
+";
+
+    fn listing(full: bool, recursive: bool) -> String {
+        let main = assemble(LISTING).expect("valid listing");
+        print_function(&main, full, recursive)
+    }
+
+    #[test]
+    fn matches_luac_full_listing() {
+        assert_eq!(listing(true, true), LUAC_FULL);
+    }
+
+    #[test]
+    fn matches_luac_listing() {
+        let main = LUAC_FULL.split("constants (").next().unwrap();
+        assert_eq!(listing(false, false), main);
+    }
+
+    #[test]
+    fn numbers_functions_in_listing_order() {
+        let main = assemble(
+            r#"
+            .func
+                .func
+                    RETURN  0 1
+                .end
+                CLOSURE 0 0
+                RETURN  0 1
+            .end
+            .func
+                RETURN  0 1
+            .end
+            CLOSURE 0 0
+            CLOSURE 0 1
+            RETURN  0 1
+        "#,
+        )
+        .expect("valid listing");
+        let listing = print_function(&main, false, true);
+        let lines: Vec<&str> = listing
+            .lines()
+            .filter(|line| line.contains("CLOSURE") || line.contains(" at "))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "main <?:0,0> (3 instructions, 12 bytes at 0x0001)",
+                "\t1\t[-]\tCLOSURE  \t0 0\t; 0x0002",
+                "\t2\t[-]\tCLOSURE  \t0 1\t; 0x0004",
+                "main <?:0,0> (2 instructions, 8 bytes at 0x0002)",
+                "\t1\t[-]\tCLOSURE  \t0 0\t; 0x0003",
+                "main <?:0,0> (1 instruction, 4 bytes at 0x0003)",
+                "main <?:0,0> (1 instruction, 4 bytes at 0x0004)",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_numbers_like_printf_g14() {
+        let cases = [
+            (1.0, "1"),
+            (0.1, "0.1"),
+            (1e15, "1e+15"),
+            (123456789012345.0, "1.2345678901234e+14"),
+            (0.0001, "0.0001"),
+            (0.00001, "1e-05"),
+            (-2.5, "-2.5"),
+        ];
+        for (value, text) in cases {
+            assert_eq!(format_g14(value), text, "{value}");
+        }
+    }
+}
@@ -1,65 +1,834 @@
-mod parser;
-
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use log::info;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use rluadecomp::analysis::{diff, verify};
+use rluadecomp::assembler::{assemble, disassemble, quote_bytes};
+use rluadecomp::ast::PrinterConfig;
+use rluadecomp::decompile::decompile_with;
+use rluadecomp::listing::print_function;
+use rluadecomp::parser::bytecode::{Constant, FunctionPrototype, Header, Version, luajit};
+use rluadecomp::parser::{ParsedChunk, Registry};
+use rluadecomp::strip::{StripOptions, strip_chunk};
+use rluadecomp::writer::write_lua_bytecode;
+
+//////////////////////////////// Variables ////////////////////////////////
 
-use parser::parse_lua_bytecode;
+const EXIT_CODES: &str = "Exit codes: 0 on success, 1 when verify or diff report findings or \
+                          decompile rejects invalid bytecode, 2 when a file cannot be read, \
+                          parsed or written";
+
+//////////////////////////////// Structs ////////////////////////////////
 
 /// Command-line arguments parser
 #[derive(Parser, Debug)]
 #[clap(
     author = "bytexenon",
     version = "1.0.0",
-    about = "Decompile .luac files and convert them back to Lua source code"
+    about = "Decompile .luac files and convert them back to Lua source code",
+    after_help = EXIT_CODES
 )]
 struct Arguments {
-    /// Paths to the Lua bytecode files to decompile
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Summarize the format and contents of each file
+    Info(Inputs),
+    /// List the instructions of each file
+    Disasm(DisasmArgs),
+    /// Turn listings of `disasm --format asm` back into Lua 5.1 bytecode
+    Assemble(AssembleArgs),
+    /// Convert Lua 5.1 bytecode back to Lua source code, exiting with 1 if it is invalid
+    Decompile(DecompileArgs),
+    /// Check Lua 5.1 bytecode for malformed code, exiting with 1 if any is found
+    Verify(VerifyArgs),
+    /// Remove debug information from Lua 5.1 bytecode, all of it like `luac -s` by default
+    Strip(StripArgs),
+    /// Compare the functions of two Lua files, exiting with 1 if they differ
+    Diff(DiffArgs),
+    /// List the string constants of each file
+    Strings(StringsArgs),
+}
+
+#[derive(Args, Debug)]
+struct Inputs {
+    /// Paths to the Lua bytecode files
     #[clap(
         required = true,
-        help = "One or more Lua bytecode file to decompile.",
         value_name = "FILE",
         value_hint = clap::ValueHint::FilePath
     )]
-    files: Vec<String>,
+    files: Vec<PathBuf>,
 }
 
-/// Reads a Lua bytecode file and returns its contents as a byte vector
-fn read_file(file_path: &str) -> std::io::Result<Vec<u8>> {
-    let data = std::fs::read(file_path)?;
-    Ok(data)
+#[derive(Args, Debug)]
+struct Selection {
+    /// Only work on the nested function at PATH, child indices from the main one (e.g. 0.2)
+    #[clap(long, value_name = "PATH")]
+    function: Option<FunctionPath>,
+
+    /// Leave out the children of the selected function
+    #[clap(long)]
+    no_recurse: bool,
+}
+
+#[derive(Args, Debug)]
+struct Output {
+    /// File to write to, or directory when given several files. Standard output otherwise
+    #[clap(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct DisasmArgs {
+    #[clap(flatten)]
+    inputs: Inputs,
+
+    #[clap(long, value_enum, default_value_t = DisasmFormat::Text)]
+    format: DisasmFormat,
+
+    /// Add the constant, local and upvalue tables to luac listings, like `luac -l -l`
+    #[clap(long)]
+    full: bool,
+
+    #[clap(flatten)]
+    selection: Selection,
+
+    #[clap(flatten)]
+    output: Output,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum DisasmFormat {
+    /// Instructions of every function, for any dialect
+    Text,
+    /// The parsed header and prototypes as Rust structures, for any dialect
+    Debug,
+    /// Lua 5.1 listing that `rluadecomp::assembler` reads back
+    Asm,
+    /// Lua 5.1 listing in the format of `luac -l`
+    Luac,
+    /// One JSON document per file, see `rluadecomp::schema` (needs the `serde` feature)
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+#[derive(Args, Debug)]
+struct AssembleArgs {
+    /// Paths to the listings
+    #[clap(
+        required = true,
+        value_name = "FILE",
+        value_hint = clap::ValueHint::FilePath
+    )]
+    files: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    output: Output,
+}
+
+#[derive(Args, Debug)]
+struct DecompileArgs {
+    #[clap(flatten)]
+    inputs: Inputs,
+
+    /// Spaces per indentation level of the decompiled source
+    #[clap(long, default_value_t = 4)]
+    indent: usize,
+
+    /// Width past which table constructors are split into a line per field. Other
+    /// code is never wrapped
+    #[clap(long, default_value_t = 100)]
+    line_width: usize,
+
+    /// Only decompile the nested function at PATH, child indices from the main one
+    #[clap(long, value_name = "PATH")]
+    function: Option<FunctionPath>,
+
+    #[clap(flatten)]
+    output: Output,
+}
+
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    #[clap(flatten)]
+    inputs: Inputs,
+
+    #[clap(flatten)]
+    selection: Selection,
+}
+
+#[derive(Args, Debug)]
+struct StripArgs {
+    #[clap(flatten)]
+    inputs: Inputs,
+
+    /// File to write to, or directory when given several files
+    #[clap(short, long, value_name = "PATH")]
+    output: PathBuf,
+
+    /// Keep line info and source names, so error messages and tracebacks keep file:line
+    #[clap(long)]
+    keep_lines: bool,
+
+    /// Keep source names
+    #[clap(long)]
+    keep_source: bool,
+
+    /// Keep local names
+    #[clap(long)]
+    keep_locals: bool,
+
+    /// Keep upvalue names
+    #[clap(long)]
+    keep_upvalues: bool,
+}
+
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// The file compared against
+    #[clap(value_name = "OLD", value_hint = clap::ValueHint::FilePath)]
+    old: PathBuf,
+
+    /// The file compared
+    #[clap(value_name = "NEW", value_hint = clap::ValueHint::FilePath)]
+    new: PathBuf,
+
+    #[clap(flatten)]
+    selection: Selection,
 }
 
-fn main() {
+#[derive(Args, Debug)]
+struct StringsArgs {
+    #[clap(flatten)]
+    inputs: Inputs,
+
+    #[clap(long, value_enum, default_value_t = StringsFormat::Text)]
+    format: StringsFormat,
+
+    #[clap(flatten)]
+    selection: Selection,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum StringsFormat {
+    /// One quoted string per line, after its function and constant index
+    Text,
+    /// One JSON object per string (needs the `serde` feature)
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+/// Child indices leading from the main function to a nested one
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FunctionPath(Vec<usize>);
+
+/// Outcome of a command, its exit code. Worse outcomes win over the others
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Success = 0,
+    Findings = 1, // Diagnostics from verify and decompile, differences from diff
+    Failure = 2,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl FromStr for FunctionPath {
+    type Err = String;
+
+    /// Reads `main`, `0.2` or `main.0.2`
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.strip_prefix("main").unwrap_or(text);
+        let text = text.strip_prefix('.').unwrap_or(text);
+        if text.is_empty() {
+            return Ok(Self::default());
+        }
+        text.split('.')
+            .map(|index| {
+                index
+                    .parse()
+                    .map_err(|_| format!("invalid child index `{index}`"))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl std::fmt::Display for FunctionPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "main")?;
+        for index in &self.0 {
+            write!(f, " > proto[{index}]")?;
+        }
+        Ok(())
+    }
+}
+
+impl FunctionPath {
+    /// The function this path leads to from `main`
+    fn select<'a>(&self, main: &'a FunctionPrototype) -> Result<&'a FunctionPrototype, String> {
+        let mut proto = main;
+        for (depth, &index) in self.0.iter().enumerate() {
+            proto = proto.prototypes.get(index).ok_or_else(|| {
+                let parent = FunctionPath(self.0[..depth].to_vec());
+                format!("{parent} has no function {index}")
+            })?;
+        }
+        Ok(proto)
+    }
+}
+
+impl Selection {
+    fn path(&self) -> FunctionPath {
+        self.function.clone().unwrap_or_default()
+    }
+}
+
+impl Output {
+    /// Writes the result for `input` to standard output, the output file, or the file of
+    /// the same name inside the output directory. `extension` replaces the input's own
+    fn write(
+        &self,
+        input: &Path,
+        inputs: usize,
+        extension: Option<&str>,
+        data: &[u8],
+    ) -> Result<(), String> {
+        let Some(output) = &self.output else {
+            return print(data);
+        };
+
+        let path = match inputs > 1 || output.is_dir() {
+            true => {
+                let mut name = PathBuf::from(input.file_name().unwrap_or_default());
+                if let Some(extension) = extension {
+                    name.set_extension(extension);
+                }
+                output.join(name)
+            }
+            false => output.clone(),
+        };
+        std::fs::write(&path, data).map_err(|err| format!("cannot write {}: {err}", path.display()))
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+fn main() -> ExitCode {
     // Initialize logging
     env_logger::init();
 
     // Parse command-line arguments
     let args = Arguments::parse();
-    let file_paths = args.files;
-
-    for file_path in file_paths {
-        info!("Parsing file: {}", file_path);
-
-        // Read the Lua bytecode file
-        let bytecode = read_file(file_path.as_str()).unwrap_or_else(|err| {
-            eprintln!("Error reading file: {}", err);
-            std::process::exit(1);
-        });
-
-        // Parse the Lua bytecode
-        match parse_lua_bytecode(&bytecode) {
-            Ok((header, prototype)) => {
-                info!("Parsed Lua bytecode successfully.");
-
-                println!("Header: {:#?}", header);
-                println!("Function Prototype: {:#?}", prototype);
-                for instr in prototype.code {
-                    println!("{}", instr);
+    let status = match args.command {
+        Command::Info(inputs) => for_each_file(&inputs.files, info_file),
+        Command::Disasm(args) => for_each_file(&args.inputs.files, |file, data| {
+            disasm_file(&args, file, data)
+        }),
+        Command::Assemble(args) => {
+            for_each_file(&args.files, |file, data| assemble_file(&args, file, data))
+        }
+        Command::Decompile(args) => for_each_file(&args.inputs.files, |file, data| {
+            decompile_file(&args, file, data)
+        }),
+        Command::Verify(args) => for_each_file(&args.inputs.files, |file, data| {
+            verify_file(&args, file, data)
+        }),
+        Command::Strip(args) => for_each_file(&args.inputs.files, |file, data| {
+            strip_file(&args, file, data)
+        }),
+        Command::Diff(args) => diff_files(&args).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            Status::Failure
+        }),
+        Command::Strings(args) => {
+            let prefix = args.inputs.files.len() > 1;
+            for_each_file(&args.inputs.files, |file, data| {
+                strings_file(&args, prefix, file, data)
+            })
+        }
+    };
+    ExitCode::from(status as u8)
+}
+
+/// Runs a command on every file, reporting errors and going on with the next one
+fn for_each_file(
+    files: &[PathBuf],
+    mut run: impl FnMut(&Path, &[u8]) -> Result<Status, String>,
+) -> Status {
+    let mut status = Status::Success;
+    for file in files {
+        info!("Parsing file: {}", file.display());
+
+        let result = read_file(file).and_then(|data| run(file, &data));
+        match result {
+            Ok(outcome) => status = status.max(outcome),
+            Err(err) => {
+                eprintln!("{}: {}", file.display(), err);
+                status = Status::Failure;
+            }
+        }
+    }
+    status
+}
+
+/// Writes to standard output. A reader closing the pipe early, as `head` does, is not
+/// an error
+fn print(data: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+    match std::io::stdout().lock().write_all(data) {
+        Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => {
+            Err(format!("cannot write output: {err}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads a Lua bytecode file and returns its contents as a byte vector
+fn read_file(file_path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(file_path).map_err(|err| format!("cannot read file: {err}"))
+}
+
+/// Parses a chunk of any dialect
+fn parse(data: &[u8]) -> Result<ParsedChunk, String> {
+    let registry = Registry::default();
+    registry.parse(data).map_err(|err| {
+        let dialect = registry.detect(data).dialect;
+        format!("cannot parse {dialect} bytecode: {err}")
+    })
+}
+
+/// Parses a chunk of official Lua, which every version shares the structures of
+fn parse_lua(data: &[u8]) -> Result<(Header, FunctionPrototype), String> {
+    match parse(data)? {
+        ParsedChunk::Lua(header, prototype) => Ok((header, prototype)),
+        _ => Err(unsupported(data)),
+    }
+}
+
+fn parse_lua51(data: &[u8]) -> Result<(Header, FunctionPrototype), String> {
+    match parse_lua(data)? {
+        (header, prototype) if header.version == Version::Lua51 => Ok((header, prototype)),
+        _ => Err(unsupported(data)),
+    }
+}
+
+fn unsupported(data: &[u8]) -> String {
+    let dialect = Registry::default().detect(data).dialect;
+    format!("{dialect} is not supported by this command")
+}
+
+fn info_file(file: &Path, data: &[u8]) -> Result<Status, String> {
+    let detected = Registry::default().detect(data);
+    let mut out = format!("{}: {}\n", file.display(), detected);
+
+    let (functions, instructions, stripped) = match parse(data)? {
+        ParsedChunk::Lua(_, prototype) => {
+            let source = match prototype.source_name {
+                None => "none".into(),
+                Some(_) => prototype.source(),
+            };
+            let _ = writeln!(out, "  source: {source}");
+            let mut counts = (0, 0, true);
+            lua_counts(&prototype, &mut counts);
+            counts
+        }
+        ParsedChunk::LuaJIT(_, prototype) => {
+            let mut counts = (0, 0, true);
+            luajit_counts(&prototype, &mut counts);
+            counts
+        }
+        ParsedChunk::Luau(_, chunk) => {
+            let instructions = chunk.prototypes.iter().map(|p| p.code.len()).sum();
+            let stripped = chunk
+                .prototypes
+                .iter()
+                .all(|p| p.debug_info.lineinfo.is_empty());
+            (chunk.prototypes.len(), instructions, stripped)
+        }
+    };
+    let _ = writeln!(
+        out,
+        "  functions: {functions}, instructions: {instructions}"
+    );
+    match stripped {
+        true => out.push_str("  debug info: stripped\n"),
+        false => out.push_str("  debug info: present\n"),
+    }
+    print(out.as_bytes())?;
+    Ok(Status::Success)
+}
+
+fn lua_counts(proto: &FunctionPrototype, counts: &mut (usize, usize, bool)) {
+    counts.0 += 1;
+    counts.1 += proto.code.len();
+    let debug = &proto.debug_info;
+    counts.2 &= debug.lineinfo.is_empty() && debug.locals.is_empty() && debug.upvalues.is_empty();
+    for child in &proto.prototypes {
+        lua_counts(child, counts);
+    }
+}
+
+fn luajit_counts(proto: &luajit::Prototype, counts: &mut (usize, usize, bool)) {
+    counts.0 += 1;
+    counts.1 += proto.code.len();
+    counts.2 &= proto.debug_info.lineinfo.is_empty();
+    for child in &proto.prototypes {
+        luajit_counts(child, counts);
+    }
+}
+
+fn disasm_file(args: &DisasmArgs, file: &Path, data: &[u8]) -> Result<Status, String> {
+    let (text, extension) = match args.format {
+        DisasmFormat::Text => (disasm_text(&args.selection, data)?, "txt"),
+        DisasmFormat::Debug => (format!("{:#?}\n", parse(data)?), "txt"),
+        DisasmFormat::Asm => {
+            let (_, main) = parse_lua51(data)?;
+            (disassemble(args.selection.path().select(&main)?), "s")
+        }
+        DisasmFormat::Luac => {
+            let (_, main) = parse_lua51(data)?;
+            let proto = args.selection.path().select(&main)?;
+            (
+                print_function(proto, args.full, !args.selection.no_recurse),
+                "txt",
+            )
+        }
+        #[cfg(feature = "serde")]
+        DisasmFormat::Json => {
+            let (header, main) = parse_lua51(data)?;
+            let proto = args.selection.path().select(&main)?;
+            let chunk = rluadecomp::schema::Chunk::new(&header, proto);
+            let json = serde_json::to_string(&chunk).map_err(|err| err.to_string())?;
+            (json + "\n", "json")
+        }
+    };
+    let inputs = args.inputs.files.len();
+    args.output
+        .write(file, inputs, Some(extension), text.as_bytes())?;
+    Ok(Status::Success)
+}
+
+/// Instructions of every function, with the operand words of Lua 5.1 CLOSURE and
+/// SETLIST annotated on the instruction using them
+fn disasm_text(selection: &Selection, data: &[u8]) -> Result<String, String> {
+    let mut out = String::new();
+    match parse(data)? {
+        ParsedChunk::Lua(header, main) => {
+            let path = selection.path();
+            let proto = path.select(&main)?;
+            lua_text(
+                &mut out,
+                header.version,
+                proto,
+                &mut path.clone(),
+                selection,
+            );
+        }
+        _ if selection.function.is_some() => {
+            return Err("--function only applies to official Lua chunks".to_string());
+        }
+        ParsedChunk::LuaJIT(header, prototype) => {
+            let path = &mut FunctionPath::default();
+            luajit_text(&mut out, header.version, &prototype, path, selection);
+        }
+        ParsedChunk::Luau(_, chunk) => {
+            for (index, prototype) in chunk.prototypes.iter().enumerate() {
+                let _ = writeln!(out, "; function {index}");
+                let mut code = prototype.code.iter();
+                while let Some(instr) = code.next() {
+                    let aux = match instr.opcode() {
+                        Some(opcode) if opcode.has_aux() => code.next().map(|a| a.raw()),
+                        _ => None,
+                    };
+                    let _ = writeln!(out, "{}", instr.with_aux(aux));
                 }
             }
-            Err(err) => {
-                eprintln!("Error parsing Lua bytecode: {:?}", err);
+        }
+    }
+    Ok(out)
+}
+
+fn lua_text(
+    out: &mut String,
+    version: Version,
+    proto: &FunctionPrototype,
+    path: &mut FunctionPath,
+    selection: &Selection,
+) {
+    let _ = writeln!(out, "; function {path}");
+    for (pc, instr) in proto.code.iter().enumerate() {
+        if version == Version::Lua51 && proto.is_pseudo_instruction(pc) {
+            continue;
+        }
+        let _ = writeln!(out, "{}", instr.display(version));
+        if version != Version::Lua51 {
+            continue;
+        }
+        for (index, upvalue) in proto.closure_upvalues(pc).iter().flatten().enumerate() {
+            match upvalue.instack {
+                true => _ = writeln!(out, "    upvalue {index}: register {}", upvalue.idx),
+                false => _ = writeln!(out, "    upvalue {index}: upvalue {}", upvalue.idx),
             }
         }
+        if proto.is_setlist_batch(pc + 1) {
+            let _ = writeln!(out, "    batch: {}", proto.code[pc + 1].raw());
+        }
+    }
+
+    if selection.no_recurse {
+        return;
+    }
+    for (index, child) in proto.prototypes.iter().enumerate() {
+        path.0.push(index);
+        lua_text(out, version, child, path, selection);
+        path.0.pop();
+    }
+}
+
+/// Assembles a listing into a chunk with the header of `Header::lua51`
+fn assemble_file(args: &AssembleArgs, file: &Path, data: &[u8]) -> Result<Status, String> {
+    let listing = std::str::from_utf8(data).map_err(|err| format!("not a text file: {err}"))?;
+    let main = assemble(listing).map_err(|err| err.to_string())?;
+    let bytecode = write_lua_bytecode(&Header::lua51(), &main).map_err(|err| err.to_string())?;
+    args.output
+        .write(file, args.files.len(), Some("luac"), &bytecode)?;
+    Ok(Status::Success)
+}
+
+fn luajit_text(
+    out: &mut String,
+    version: luajit::DumpVersion,
+    proto: &luajit::Prototype,
+    path: &mut FunctionPath,
+    selection: &Selection,
+) {
+    let _ = writeln!(out, "; function {path}");
+    for instr in &proto.code {
+        let _ = writeln!(out, "{}", instr.display(version));
+    }
+
+    if selection.no_recurse {
+        return;
+    }
+    for (index, child) in proto.prototypes.iter().enumerate() {
+        path.0.push(index);
+        luajit_text(out, version, child, path, selection);
+        path.0.pop();
+    }
+}
+
+fn decompile_file(args: &DecompileArgs, file: &Path, data: &[u8]) -> Result<Status, String> {
+    let (_, main) = parse_lua51(data)?;
+    let path = args.function.clone().unwrap_or_default();
+    let proto = path.select(&main)?;
+
+    let config = PrinterConfig {
+        indent: " ".repeat(args.indent),
+        line_width: args.line_width,
+    };
+    // Malformed code is reported rather than decompiled into nonsense
+    let source = match decompile_with(proto, config) {
+        Ok(source) => source,
+        Err(err) => {
+            for diagnostic in err.diagnostics {
+                eprintln!("{}: invalid bytecode: {}", file.display(), diagnostic);
+            }
+            return Ok(Status::Findings);
+        }
+    };
+    let inputs = args.inputs.files.len();
+    args.output
+        .write(file, inputs, Some("lua"), source.as_bytes())?;
+    Ok(Status::Success)
+}
+
+fn verify_file(args: &VerifyArgs, file: &Path, data: &[u8]) -> Result<Status, String> {
+    let (_, main) = parse_lua51(data)?;
+    let path = args.selection.path();
+    let proto = path.select(&main)?;
+
+    let mut out = String::new();
+    for mut diagnostic in verify(proto) {
+        if args.selection.no_recurse && !diagnostic.path.is_empty() {
+            continue;
+        }
+        diagnostic.path.splice(0..0, path.0.iter().copied());
+        let _ = writeln!(out, "{}: {}", file.display(), diagnostic);
+    }
+    print(out.as_bytes())?;
+    Ok(findings(&out))
+}
+
+fn strip_file(args: &StripArgs, file: &Path, data: &[u8]) -> Result<Status, String> {
+    let (header, main) = parse_lua51(data)?;
+    let options = StripOptions {
+        lines: !args.keep_lines,
+        locals: !args.keep_locals,
+        upvalues: !args.keep_upvalues,
+        source: !(args.keep_source || args.keep_lines),
+    };
+
+    let bytecode = strip_chunk(&header, main, options).map_err(|err| err.to_string())?;
+    let output = Output {
+        output: Some(args.output.clone()),
+    };
+    output.write(file, args.inputs.files.len(), None, &bytecode)?;
+    Ok(Status::Success)
+}
+
+fn diff_files(args: &DiffArgs) -> Result<Status, String> {
+    let load = |file: &Path| {
+        read_file(file)
+            .and_then(|data| Ok((Registry::default().detect(&data), parse_lua(&data)?)))
+            .map_err(|err| format!("{}: {}", file.display(), err))
+    };
+    let (old_format, (_, old_main)) = load(&args.old)?;
+    let (new_format, (_, new_main)) = load(&args.new)?;
+
+    let mut out = String::new();
+    if old_format != new_format {
+        let _ = writeln!(out, "header: {old_format} != {new_format}");
+    }
+
+    let path = args.selection.path();
+    let old = path
+        .select(&old_main)
+        .map_err(|err| format!("{}: {err}", args.old.display()))?;
+    let new = path
+        .select(&new_main)
+        .map_err(|err| format!("{}: {err}", args.new.display()))?;
+    for mut difference in diff(old, new, !args.selection.no_recurse) {
+        difference.path.splice(0..0, path.0.iter().copied());
+        let _ = writeln!(out, "{difference}");
+    }
+    print(out.as_bytes())?;
+    Ok(findings(&out))
+}
+
+fn strings_file(
+    args: &StringsArgs,
+    prefix: bool,
+    file: &Path,
+    data: &[u8],
+) -> Result<Status, String> {
+    let (_, main) = parse_lua(data)?;
+    let path = args.selection.path();
+    let proto = path.select(&main)?;
+
+    let mut out = String::new();
+    let mut strings = Vec::new();
+    collect_strings(
+        proto,
+        &mut path.clone(),
+        !args.selection.no_recurse,
+        &mut strings,
+    );
+    for (path, index, constant) in strings {
+        let Constant::String(bytes) = constant else {
+            continue;
+        };
+        match args.format {
+            StringsFormat::Text if prefix => {
+                let quoted = quote_bytes(bytes);
+                let _ = writeln!(out, "{}: {path} K{index} {quoted}", file.display());
+            }
+            StringsFormat::Text => {
+                let _ = writeln!(out, "{path} K{index} {}", quote_bytes(bytes));
+            }
+            #[cfg(feature = "serde")]
+            StringsFormat::Json => {
+                let entry = serde_json::json!({
+                    "file": file.display().to_string(),
+                    "function": path.0,
+                    "index": index,
+                    "constant": constant,
+                });
+                let _ = writeln!(out, "{entry}");
+            }
+        }
+    }
+    print(out.as_bytes())?;
+    Ok(Status::Success)
+}
+
+/// Findings when a report has anything in it
+fn findings(report: &str) -> Status {
+    match report.is_empty() {
+        true => Status::Success,
+        false => Status::Findings,
+    }
+}
+
+fn collect_strings<'a>(
+    proto: &'a FunctionPrototype,
+    path: &mut FunctionPath,
+    recursive: bool,
+    strings: &mut Vec<(FunctionPath, usize, &'a Constant)>,
+) {
+    for (index, constant) in proto.constants.iter().enumerate() {
+        if matches!(constant, Constant::String(_)) {
+            strings.push((path.clone(), index, constant));
+        }
+    }
+    if !recursive {
+        return;
+    }
+    for (index, child) in proto.prototypes.iter().enumerate() {
+        path.0.push(index);
+        collect_strings(child, path, recursive, strings);
+        path.0.pop();
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_closure_bindings_as_annotations() {
+        let main = assemble(
+            r#"
+            .func
+                .upvals 2 "a" "b"
+                    RETURN    0 1
+            .end
+                LOADNIL   0 0
+                CLOSURE   1 0
+                MOVE      0 0
+                GETUPVAL  0 3
+                RETURN    0 1
+            "#,
+        )
+        .expect("valid listing");
+        let selection = Selection {
+            function: None,
+            no_recurse: true,
+        };
+
+        let mut out = String::new();
+        lua_text(
+            &mut out,
+            Version::Lua51,
+            &main,
+            &mut FunctionPath::default(),
+            &selection,
+        );
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 6, "{out}");
+        assert!(lines[2].contains("opname: CLOSURE"), "{out}");
+        assert_eq!(
+            lines[3..5],
+            ["    upvalue 0: register 0", "    upvalue 1: upvalue 3"]
+        );
+        assert!(lines[5].contains("opname: RETURN"), "{out}");
     }
 }
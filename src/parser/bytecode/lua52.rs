@@ -0,0 +1,99 @@
+/*
+  Constants for Lua 5.2 bytecode
+*/
+
+use super::{InstructionFormat, OpcodeTable, OperandMask};
+use num_enum::TryFromPrimitive;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// lopcodes.h:236
+const TOTAL_OPS: u8 = 40;
+
+// lundump.h:19
+pub const LUAC_TAIL: &[u8] = b"\x19\x93\r\n\x1a\n";
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[allow(clippy::upper_case_acronyms)]
+#[rustfmt::skip]
+#[repr(u8)]
+pub enum Opcode {
+    MOVE,     LOADK,    LOADKX,   LOADBOOL,
+    LOADNIL,  GETUPVAL, GETTABUP, GETTABLE,
+    SETTABUP, SETUPVAL, SETTABLE, NEWTABLE,
+    SELF,     ADD,      SUB,      MUL,
+    DIV,      MOD,      POW,      UNM,
+    NOT,      LEN,      CONCAT,   JMP,
+    EQ,       LT,       LE,       TEST,
+    TESTSET,  CALL,     TAILCALL, RETURN,
+    FORLOOP,  FORPREP,  TFORCALL, TFORLOOP,
+    SETLIST,  CLOSURE,  VARARG,   EXTRAARG,
+}
+
+//////////////////////////////// Lookup Tables ////////////////////////////////
+
+pub const OPCODES: OpcodeTable = OpcodeTable {
+    names: &OPNAMES,
+    modes: &OPMODES,
+};
+
+#[rustfmt::skip]
+const OPMODES: [(InstructionFormat, OperandMask, OperandMask); TOTAL_OPS as usize] = [
+    /*    Opcode Format            Operand B            Operand C         */
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_MOVE
+    (InstructionFormat::IABx, OperandMask::OpArgK, OperandMask::OpArgN),  // OP_LOADK
+    (InstructionFormat::IABx, OperandMask::OpArgN, OperandMask::OpArgN),  // OP_LOADKX
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_LOADBOOL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_LOADNIL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_GETUPVAL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgK),  // OP_GETTABUP
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgK),  // OP_GETTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SETTABUP
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_SETUPVAL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SETTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_NEWTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgK),  // OP_SELF
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_ADD
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SUB
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_MUL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_DIV
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_MOD
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_POW
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_UNM
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_NOT
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_LEN
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgR),  // OP_CONCAT
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_JMP (A - 1 = upvalues to close)
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_EQ
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_LT
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_LE
+    (InstructionFormat::IABC, OperandMask::OpArgN, OperandMask::OpArgU),  // OP_TEST
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgU),  // OP_TESTSET
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_CALL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_TAILCALL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_RETURN
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_FORLOOP
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_FORPREP
+    (InstructionFormat::IABC, OperandMask::OpArgN, OperandMask::OpArgU),  // OP_TFORCALL
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_TFORLOOP
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_SETLIST
+    (InstructionFormat::IABx, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_CLOSURE
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_VARARG
+    (InstructionFormat::IAx, OperandMask::OpArgU, OperandMask::OpArgU),   // OP_EXTRAARG
+];
+
+#[rustfmt::skip]
+const OPNAMES: [&str; TOTAL_OPS as usize] = [
+    "MOVE",     "LOADK",    "LOADKX",   "LOADBOOL",
+    "LOADNIL",  "GETUPVAL", "GETTABUP", "GETTABLE",
+    "SETTABUP", "SETUPVAL", "SETTABLE", "NEWTABLE",
+    "SELF",     "ADD",      "SUB",      "MUL",
+    "DIV",      "MOD",      "POW",      "UNM",
+    "NOT",      "LEN",      "CONCAT",   "JMP",
+    "EQ",       "LT",       "LE",       "TEST",
+    "TESTSET",  "CALL",     "TAILCALL", "RETURN",
+    "FORLOOP",  "FORPREP",  "TFORCALL", "TFORLOOP",
+    "SETLIST",  "CLOSURE",  "VARARG",   "EXTRAARG",
+];
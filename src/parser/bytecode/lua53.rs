@@ -0,0 +1,120 @@
+/*
+  Constants for Lua 5.3 bytecode
+*/
+
+use super::{InstructionFormat, OpcodeTable, OperandMask};
+use num_enum::TryFromPrimitive;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// lopcodes.h:239
+const TOTAL_OPS: u8 = 47;
+
+// lundump.h:18-23
+pub const LUAC_DATA: &[u8] = b"\x19\x93\r\n\x1a\n";
+pub const LUAC_INT: i64 = 0x5678;
+pub const LUAC_NUM: f64 = 370.5;
+
+// lobject.h:56-63 (constant tags)
+pub const TAG_NIL: u8 = 0x00;
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_NUMFLT: u8 = 0x03;
+pub const TAG_NUMINT: u8 = 0x13;
+pub const TAG_SHRSTR: u8 = 0x04;
+pub const TAG_LNGSTR: u8 = 0x14;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[allow(clippy::upper_case_acronyms)]
+#[rustfmt::skip]
+#[repr(u8)]
+pub enum Opcode {
+    MOVE,     LOADK,    LOADKX,   LOADBOOL,
+    LOADNIL,  GETUPVAL, GETTABUP, GETTABLE,
+    SETTABUP, SETUPVAL, SETTABLE, NEWTABLE,
+    SELF,     ADD,      SUB,      MUL,
+    MOD,      POW,      DIV,      IDIV,
+    BAND,     BOR,      BXOR,     SHL,
+    SHR,      UNM,      BNOT,     NOT,
+    LEN,      CONCAT,   JMP,      EQ,
+    LT,       LE,       TEST,     TESTSET,
+    CALL,     TAILCALL, RETURN,   FORLOOP,
+    FORPREP,  TFORCALL, TFORLOOP, SETLIST,
+    CLOSURE,  VARARG,   EXTRAARG,
+}
+
+//////////////////////////////// Lookup Tables ////////////////////////////////
+
+pub const OPCODES: OpcodeTable = OpcodeTable {
+    names: &OPNAMES,
+    modes: &OPMODES,
+};
+
+#[rustfmt::skip]
+const OPMODES: [(InstructionFormat, OperandMask, OperandMask); TOTAL_OPS as usize] = [
+    /*    Opcode Format            Operand B            Operand C         */
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_MOVE
+    (InstructionFormat::IABx, OperandMask::OpArgK, OperandMask::OpArgN),  // OP_LOADK
+    (InstructionFormat::IABx, OperandMask::OpArgN, OperandMask::OpArgN),  // OP_LOADKX
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_LOADBOOL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_LOADNIL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_GETUPVAL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgK),  // OP_GETTABUP
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgK),  // OP_GETTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SETTABUP
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_SETUPVAL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SETTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_NEWTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgK),  // OP_SELF
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_ADD
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SUB
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_MUL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_MOD
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_POW
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_DIV
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_IDIV
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_BAND
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_BOR
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_BXOR
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SHL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SHR
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_UNM
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_BNOT
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_NOT
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_LEN
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgR),  // OP_CONCAT
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_JMP
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_EQ
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_LT
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_LE
+    (InstructionFormat::IABC, OperandMask::OpArgN, OperandMask::OpArgU),  // OP_TEST
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgU),  // OP_TESTSET
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_CALL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_TAILCALL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_RETURN
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_FORLOOP
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_FORPREP
+    (InstructionFormat::IABC, OperandMask::OpArgN, OperandMask::OpArgU),  // OP_TFORCALL
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_TFORLOOP
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_SETLIST
+    (InstructionFormat::IABx, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_CLOSURE
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_VARARG
+    (InstructionFormat::IAx, OperandMask::OpArgU, OperandMask::OpArgU),   // OP_EXTRAARG
+];
+
+#[rustfmt::skip]
+const OPNAMES: [&str; TOTAL_OPS as usize] = [
+    "MOVE",     "LOADK",    "LOADKX",   "LOADBOOL",
+    "LOADNIL",  "GETUPVAL", "GETTABUP", "GETTABLE",
+    "SETTABUP", "SETUPVAL", "SETTABLE", "NEWTABLE",
+    "SELF",     "ADD",      "SUB",      "MUL",
+    "MOD",      "POW",      "DIV",      "IDIV",
+    "BAND",     "BOR",      "BXOR",     "SHL",
+    "SHR",      "UNM",      "BNOT",     "NOT",
+    "LEN",      "CONCAT",   "JMP",      "EQ",
+    "LT",       "LE",       "TEST",     "TESTSET",
+    "CALL",     "TAILCALL", "RETURN",   "FORLOOP",
+    "FORPREP",  "TFORCALL", "TFORLOOP", "SETLIST",
+    "CLOSURE",  "VARARG",   "EXTRAARG",
+];
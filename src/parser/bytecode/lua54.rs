@@ -0,0 +1,288 @@
+/*
+  Constants and instruction decoding for Lua 5.4 bytecode
+*/
+
+use super::{DecodeError, InstructionFormat};
+use num_enum::TryFromPrimitive;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// lopcodes.h:317
+const TOTAL_OPS: u8 = 83;
+
+// lobject.h (constant variant tags)
+pub const TAG_NIL: u8 = 0x00;
+pub const TAG_FALSE: u8 = 0x01;
+pub const TAG_TRUE: u8 = 0x11;
+pub const TAG_NUMINT: u8 = 0x03;
+pub const TAG_NUMFLT: u8 = 0x13;
+pub const TAG_SHRSTR: u8 = 0x04;
+pub const TAG_LNGSTR: u8 = 0x14;
+
+// ldebug.h:21 (marks a lineinfo entry whose line lives in abslineinfo)
+pub const ABSLINEINFO: i8 = -0x80;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[allow(clippy::upper_case_acronyms)]
+#[rustfmt::skip]
+#[repr(u8)]
+pub enum Opcode {
+    MOVE,     LOADI,      LOADF,    LOADK,    LOADKX,   LOADFALSE,
+    LFALSESKIP, LOADTRUE, LOADNIL,  GETUPVAL, SETUPVAL, GETTABUP,
+    GETTABLE, GETI,       GETFIELD, SETTABUP, SETTABLE, SETI,
+    SETFIELD, NEWTABLE,   SELF,     ADDI,     ADDK,     SUBK,
+    MULK,     MODK,       POWK,     DIVK,     IDIVK,    BANDK,
+    BORK,     BXORK,      SHRI,     SHLI,     ADD,      SUB,
+    MUL,      MOD,        POW,      DIV,      IDIV,     BAND,
+    BOR,      BXOR,       SHL,      SHR,      MMBIN,    MMBINI,
+    MMBINK,   UNM,        BNOT,     NOT,      LEN,      CONCAT,
+    CLOSE,    TBC,        JMP,      EQ,       LT,       LE,
+    EQK,      EQI,        LTI,      LEI,      GTI,      GEI,
+    TEST,     TESTSET,    CALL,     TAILCALL, RETURN,   RETURN0,
+    RETURN1,  FORLOOP,    FORPREP,  TFORPREP, TFORCALL, TFORLOOP,
+    SETLIST,  CLOSURE,    VARARG,   VARARGPREP, EXTRAARG,
+}
+
+/// A Lua 5.4 instruction word (7-bit opcode, k flag, signed operands)
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction(u32);
+impl Instruction {
+    pub const SIZE_OP: u32 = 7;
+    pub const SIZE_A: u32 = 8;
+    pub const SIZE_B: u32 = 8;
+    pub const SIZE_C: u32 = 8;
+    pub const SIZE_BX: u32 = Instruction::SIZE_C + Instruction::SIZE_B + 1;
+    pub const SIZE_AX: u32 = Instruction::SIZE_BX + Instruction::SIZE_A;
+    pub const SIZE_SJ: u32 = Instruction::SIZE_BX + Instruction::SIZE_A;
+
+    pub const POS_OP: u32 = 0;
+    pub const POS_A: u32 = Instruction::POS_OP + Instruction::SIZE_OP;
+    pub const POS_K: u32 = Instruction::POS_A + Instruction::SIZE_A;
+    pub const POS_B: u32 = Instruction::POS_K + 1;
+    pub const POS_C: u32 = Instruction::POS_B + Instruction::SIZE_B;
+    pub const POS_BX: u32 = Instruction::POS_K;
+    pub const POS_AX: u32 = Instruction::POS_A;
+    pub const POS_SJ: u32 = Instruction::POS_A;
+
+    pub const OFFSET_SBX: i32 = ((1 << Instruction::SIZE_BX) - 1) >> 1;
+    pub const OFFSET_SJ: i32 = ((1 << Instruction::SIZE_SJ) - 1) >> 1;
+    pub const OFFSET_SC: i32 = ((1 << Instruction::SIZE_C) - 1) >> 1;
+
+    pub const fn new(instr: u32) -> Self {
+        Self(instr)
+    }
+
+    // Utility Functions //
+    const fn extract_bits(start: u32, size: u32, value: u32) -> u32 {
+        (value >> start) & ((1 << size) - 1)
+    }
+
+    // Instruction Info //
+    pub const fn op(&self) -> u8 {
+        Self::extract_bits(Instruction::POS_OP, Instruction::SIZE_OP, self.0) as u8
+    }
+
+    pub fn opcode(&self) -> Result<Opcode, DecodeError> {
+        Opcode::try_from(self.op()).map_err(|_| DecodeError::UnknownOpcode(self.op()))
+    }
+
+    pub fn format(&self) -> Result<InstructionFormat, DecodeError> {
+        Ok(OPMODES[self.opcode()? as usize])
+    }
+
+    // Operands //
+    pub const fn a(&self) -> u32 {
+        Self::extract_bits(Instruction::POS_A, Instruction::SIZE_A, self.0)
+    }
+
+    pub const fn b(&self) -> u32 {
+        Self::extract_bits(Instruction::POS_B, Instruction::SIZE_B, self.0)
+    }
+
+    pub const fn sb(&self) -> i32 {
+        self.b() as i32 - Instruction::OFFSET_SC
+    }
+
+    pub const fn c(&self) -> u32 {
+        Self::extract_bits(Instruction::POS_C, Instruction::SIZE_C, self.0)
+    }
+
+    pub const fn sc(&self) -> i32 {
+        self.c() as i32 - Instruction::OFFSET_SC
+    }
+
+    pub const fn k(&self) -> bool {
+        Self::extract_bits(Instruction::POS_K, 1, self.0) != 0
+    }
+
+    pub const fn bx(&self) -> u32 {
+        Self::extract_bits(Instruction::POS_BX, Instruction::SIZE_BX, self.0)
+    }
+
+    pub const fn sbx(&self) -> i32 {
+        self.bx() as i32 - Instruction::OFFSET_SBX
+    }
+
+    pub const fn ax(&self) -> u32 {
+        Self::extract_bits(Instruction::POS_AX, Instruction::SIZE_AX, self.0)
+    }
+
+    pub const fn sj(&self) -> i32 {
+        Self::extract_bits(Instruction::POS_SJ, Instruction::SIZE_SJ, self.0) as i32
+            - Instruction::OFFSET_SJ
+    }
+}
+
+impl From<&super::Instruction> for Instruction {
+    fn from(instr: &super::Instruction) -> Self {
+        Self(instr.raw())
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = self.op() as usize;
+        let Ok(format) = self.format() else {
+            return write!(f, "Instruction(opname: UNKNOWN({op}) raw: {:08x})", self.0);
+        };
+
+        write!(f, "Instruction(")?;
+        write!(f, "opname: {}", OPNAMES[op])?;
+        write!(f, " format: {:?},", format)?;
+
+        match format {
+            InstructionFormat::IABC => {
+                write!(f, " a: {},", self.a())?;
+                write!(f, " b: {},", self.b())?;
+                write!(f, " c: {},", self.c())?;
+                write!(f, " k: {},", self.k())?;
+            }
+            InstructionFormat::IABx => {
+                write!(f, " a: {},", self.a())?;
+                write!(f, " bx: {},", self.bx())?;
+            }
+            InstructionFormat::IAsBx => {
+                write!(f, " a: {},", self.a())?;
+                write!(f, " sbx: {},", self.sbx())?;
+            }
+            InstructionFormat::IAx => {
+                write!(f, " ax: {},", self.ax())?;
+            }
+            InstructionFormat::IsJ => {
+                write!(f, " sj: {},", self.sj())?;
+            }
+        }
+
+        write!(f, " raw: {:08x}", self.0)?;
+        write!(f, ")")
+    }
+}
+
+//////////////////////////////// Lookup Tables ////////////////////////////////
+
+#[rustfmt::skip]
+const OPMODES: [InstructionFormat; TOTAL_OPS as usize] = [
+    InstructionFormat::IABC,  // OP_MOVE
+    InstructionFormat::IAsBx, // OP_LOADI
+    InstructionFormat::IAsBx, // OP_LOADF
+    InstructionFormat::IABx,  // OP_LOADK
+    InstructionFormat::IABx,  // OP_LOADKX
+    InstructionFormat::IABC,  // OP_LOADFALSE
+    InstructionFormat::IABC,  // OP_LFALSESKIP
+    InstructionFormat::IABC,  // OP_LOADTRUE
+    InstructionFormat::IABC,  // OP_LOADNIL
+    InstructionFormat::IABC,  // OP_GETUPVAL
+    InstructionFormat::IABC,  // OP_SETUPVAL
+    InstructionFormat::IABC,  // OP_GETTABUP
+    InstructionFormat::IABC,  // OP_GETTABLE
+    InstructionFormat::IABC,  // OP_GETI
+    InstructionFormat::IABC,  // OP_GETFIELD
+    InstructionFormat::IABC,  // OP_SETTABUP
+    InstructionFormat::IABC,  // OP_SETTABLE
+    InstructionFormat::IABC,  // OP_SETI
+    InstructionFormat::IABC,  // OP_SETFIELD
+    InstructionFormat::IABC,  // OP_NEWTABLE
+    InstructionFormat::IABC,  // OP_SELF
+    InstructionFormat::IABC,  // OP_ADDI
+    InstructionFormat::IABC,  // OP_ADDK
+    InstructionFormat::IABC,  // OP_SUBK
+    InstructionFormat::IABC,  // OP_MULK
+    InstructionFormat::IABC,  // OP_MODK
+    InstructionFormat::IABC,  // OP_POWK
+    InstructionFormat::IABC,  // OP_DIVK
+    InstructionFormat::IABC,  // OP_IDIVK
+    InstructionFormat::IABC,  // OP_BANDK
+    InstructionFormat::IABC,  // OP_BORK
+    InstructionFormat::IABC,  // OP_BXORK
+    InstructionFormat::IABC,  // OP_SHRI
+    InstructionFormat::IABC,  // OP_SHLI
+    InstructionFormat::IABC,  // OP_ADD
+    InstructionFormat::IABC,  // OP_SUB
+    InstructionFormat::IABC,  // OP_MUL
+    InstructionFormat::IABC,  // OP_MOD
+    InstructionFormat::IABC,  // OP_POW
+    InstructionFormat::IABC,  // OP_DIV
+    InstructionFormat::IABC,  // OP_IDIV
+    InstructionFormat::IABC,  // OP_BAND
+    InstructionFormat::IABC,  // OP_BOR
+    InstructionFormat::IABC,  // OP_BXOR
+    InstructionFormat::IABC,  // OP_SHL
+    InstructionFormat::IABC,  // OP_SHR
+    InstructionFormat::IABC,  // OP_MMBIN
+    InstructionFormat::IABC,  // OP_MMBINI
+    InstructionFormat::IABC,  // OP_MMBINK
+    InstructionFormat::IABC,  // OP_UNM
+    InstructionFormat::IABC,  // OP_BNOT
+    InstructionFormat::IABC,  // OP_NOT
+    InstructionFormat::IABC,  // OP_LEN
+    InstructionFormat::IABC,  // OP_CONCAT
+    InstructionFormat::IABC,  // OP_CLOSE
+    InstructionFormat::IABC,  // OP_TBC
+    InstructionFormat::IsJ,   // OP_JMP
+    InstructionFormat::IABC,  // OP_EQ
+    InstructionFormat::IABC,  // OP_LT
+    InstructionFormat::IABC,  // OP_LE
+    InstructionFormat::IABC,  // OP_EQK
+    InstructionFormat::IABC,  // OP_EQI
+    InstructionFormat::IABC,  // OP_LTI
+    InstructionFormat::IABC,  // OP_LEI
+    InstructionFormat::IABC,  // OP_GTI
+    InstructionFormat::IABC,  // OP_GEI
+    InstructionFormat::IABC,  // OP_TEST
+    InstructionFormat::IABC,  // OP_TESTSET
+    InstructionFormat::IABC,  // OP_CALL
+    InstructionFormat::IABC,  // OP_TAILCALL
+    InstructionFormat::IABC,  // OP_RETURN
+    InstructionFormat::IABC,  // OP_RETURN0
+    InstructionFormat::IABC,  // OP_RETURN1
+    InstructionFormat::IABx,  // OP_FORLOOP
+    InstructionFormat::IABx,  // OP_FORPREP
+    InstructionFormat::IABx,  // OP_TFORPREP
+    InstructionFormat::IABC,  // OP_TFORCALL
+    InstructionFormat::IABx,  // OP_TFORLOOP
+    InstructionFormat::IABC,  // OP_SETLIST
+    InstructionFormat::IABx,  // OP_CLOSURE
+    InstructionFormat::IABC,  // OP_VARARG
+    InstructionFormat::IABC,  // OP_VARARGPREP
+    InstructionFormat::IAx,   // OP_EXTRAARG
+];
+
+#[rustfmt::skip]
+const OPNAMES: [&str; TOTAL_OPS as usize] = [
+    "MOVE",     "LOADI",      "LOADF",    "LOADK",    "LOADKX",   "LOADFALSE",
+    "LFALSESKIP", "LOADTRUE", "LOADNIL",  "GETUPVAL", "SETUPVAL", "GETTABUP",
+    "GETTABLE", "GETI",       "GETFIELD", "SETTABUP", "SETTABLE", "SETI",
+    "SETFIELD", "NEWTABLE",   "SELF",     "ADDI",     "ADDK",     "SUBK",
+    "MULK",     "MODK",       "POWK",     "DIVK",     "IDIVK",    "BANDK",
+    "BORK",     "BXORK",      "SHRI",     "SHLI",     "ADD",      "SUB",
+    "MUL",      "MOD",        "POW",      "DIV",      "IDIV",     "BAND",
+    "BOR",      "BXOR",       "SHL",      "SHR",      "MMBIN",    "MMBINI",
+    "MMBINK",   "UNM",        "BNOT",     "NOT",      "LEN",      "CONCAT",
+    "CLOSE",    "TBC",        "JMP",      "EQ",       "LT",       "LE",
+    "EQK",      "EQI",        "LTI",      "LEI",      "GTI",      "GEI",
+    "TEST",     "TESTSET",    "CALL",     "TAILCALL", "RETURN",   "RETURN0",
+    "RETURN1",  "FORLOOP",    "FORPREP",  "TFORPREP", "TFORCALL", "TFORLOOP",
+    "SETLIST",  "CLOSURE",    "VARARG",   "VARARGPREP", "EXTRAARG",
+];
@@ -0,0 +1,530 @@
+/*
+  Constants and data model for LuaJIT 2.x bytecode dumps
+*/
+
+use super::{Endianness, Lossy};
+use num_enum::TryFromPrimitive;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// lj_bcdump.h:41
+pub const MAGIC_NUMBER: &[u8] = b"\x1BLJ";
+
+// lj_bcdump.h:47-50 (header flags)
+pub const FLAG_BE: u32 = 0x01;
+pub const FLAG_STRIP: u32 = 0x02;
+pub const FLAG_FFI: u32 = 0x04;
+pub const FLAG_FR2: u32 = 0x08;
+
+// lj_bcdump.h:55-62 (constant tags)
+pub const KGC_CHILD: u32 = 0;
+pub const KGC_TAB: u32 = 1;
+pub const KGC_I64: u32 = 2;
+pub const KGC_U64: u32 = 3;
+pub const KGC_COMPLEX: u32 = 4;
+pub const KGC_STR: u32 = 5;
+
+pub const KTAB_NIL: u32 = 0;
+pub const KTAB_FALSE: u32 = 1;
+pub const KTAB_TRUE: u32 = 2;
+pub const KTAB_INT: u32 = 3;
+pub const KTAB_NUM: u32 = 4;
+pub const KTAB_STR: u32 = 5;
+
+// lj_obj.h:322 (prototype flags)
+pub const PROTO_CHILD: u8 = 0x01;
+pub const PROTO_VARARG: u8 = 0x02;
+pub const PROTO_FFI: u8 = 0x04;
+
+// lj_debug.h:50 (builtin variable names)
+pub const VARNAMES: [&str; 7] = [
+    "",
+    "(for index)",
+    "(for limit)",
+    "(for step)",
+    "(for generator)",
+    "(for state)",
+    "(for control)",
+];
+
+// lj_bc.h:21
+const BCBIAS_J: i32 = 0x8000;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, PartialEq, Clone, Copy, TryFromPrimitive)]
+#[repr(u8)]
+pub enum DumpVersion {
+    V1 = 1, // LuaJIT 2.0
+    V2 = 2, // LuaJIT 2.1
+}
+
+/// Operand types from the BCDEF table in lj_bc.h
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OperandMode {
+    None,
+    Dst,
+    Base,
+    Var,
+    RBase,
+    Uv,
+    Lit,
+    LitS,
+    Pri,
+    Num,
+    Str,
+    Tab,
+    Func,
+    CData,
+    Jump,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[allow(clippy::upper_case_acronyms)]
+#[rustfmt::skip]
+#[repr(u8)]
+pub enum Opcode {
+    ISLT,   ISGE,   ISLE,   ISGT,   ISEQV,  ISNEV,  ISEQS,  ISNES,
+    ISEQN,  ISNEN,  ISEQP,  ISNEP,  ISTC,   ISFC,   IST,    ISF,
+    ISTYPE, ISNUM,  MOV,    NOT,    UNM,    LEN,    ADDVN,  SUBVN,
+    MULVN,  DIVVN,  MODVN,  ADDNV,  SUBNV,  MULNV,  DIVNV,  MODNV,
+    ADDVV,  SUBVV,  MULVV,  DIVVV,  MODVV,  POW,    CAT,    KSTR,
+    KCDATA, KSHORT, KNUM,   KPRI,   KNIL,   UGET,   USETV,  USETS,
+    USETN,  USETP,  UCLO,   FNEW,   TNEW,   TDUP,   GGET,   GSET,
+    TGETV,  TGETS,  TGETB,  TGETR,  TSETV,  TSETS,  TSETB,  TSETM,
+    TSETR,  CALLM,  CALL,   CALLMT, CALLT,  ITERC,  ITERN,  VARG,
+    ISNEXT, RETM,   RET,    RET0,   RET1,   FORI,   JFORI,  FORL,
+    IFORL,  JFORL,  ITERL,  IITERL, JITERL, LOOP,   ILOOP,  JLOOP,
+    JMP,    FUNCF,  IFUNCF, JFUNCF, FUNCV,  IFUNCV, JFUNCV, FUNCC,
+    FUNCCW,
+}
+
+pub struct Header {
+    pub version: DumpVersion,
+    pub flags: u32,
+    pub chunk_name: Vec<u8>, // Empty when stripped
+}
+
+/// A value in a template table constant
+pub enum TableValue {
+    Nil,
+    Boolean(bool),
+    Integer(i32),
+    Number(f64),
+    String(Vec<u8>), // Raw bytes, which need not be UTF-8
+}
+
+#[derive(Debug)]
+pub struct TableConstant {
+    pub array: Vec<TableValue>,
+    pub hash: Vec<(TableValue, TableValue)>,
+}
+
+/// A garbage-collected constant (KGC)
+pub enum GcConstant {
+    Child(usize), // Index into `Prototype::prototypes`
+    Table(TableConstant),
+    Int64(i64),
+    UInt64(u64),
+    Complex(f64, f64),
+    String(Vec<u8>), // Raw bytes, which need not be UTF-8
+}
+
+/// A numeric constant (KN)
+#[derive(Debug)]
+pub enum NumConstant {
+    Integer(i32),
+    Number(f64),
+}
+
+pub struct LocalVariable {
+    pub varname: Vec<u8>,
+    pub startpc: u32,
+    pub endpc: u32,
+}
+
+pub struct DebugInfo {
+    pub lineinfo: Vec<u32>,
+    pub upvalues: Vec<Vec<u8>>,
+    pub locals: Vec<LocalVariable>,
+}
+
+#[derive(Debug)]
+pub struct Prototype {
+    pub flags: u8,
+    pub num_params: u8,
+    pub frame_size: u8,
+    pub first_line: u32,
+    pub num_lines: u32,
+    pub code: Vec<Instruction>,
+    pub upvalues: Vec<u16>,
+    pub gc_constants: Vec<GcConstant>, // Indexed by operand D (stored reversed in the dump)
+    pub num_constants: Vec<NumConstant>,
+    pub prototypes: Vec<Prototype>,
+    pub debug_info: DebugInfo,
+}
+
+/// A LuaJIT instruction word: B(8) C(8) A(8) OP(8), with D overlapping B and C
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction(u32);
+
+/// An instruction paired with its dump version, for display
+pub struct VersionedInstruction<'a> {
+    instr: &'a Instruction,
+    version: DumpVersion,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Header {
+    pub const fn endianness(&self) -> Endianness {
+        match self.flags & FLAG_BE {
+            0 => Endianness::Little,
+            _ => Endianness::Big,
+        }
+    }
+
+    pub const fn is_stripped(&self) -> bool {
+        self.flags & FLAG_STRIP != 0
+    }
+}
+
+impl std::fmt::Debug for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Header")
+            .field("version", &self.version)
+            .field("flags", &self.flags)
+            .field("chunk_name", &Lossy(&self.chunk_name))
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for TableValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TableValue::Nil => write!(f, "Nil"),
+            TableValue::Boolean(value) => f.debug_tuple("Boolean").field(value).finish(),
+            TableValue::Integer(value) => f.debug_tuple("Integer").field(value).finish(),
+            TableValue::Number(value) => f.debug_tuple("Number").field(value).finish(),
+            TableValue::String(bytes) => f.debug_tuple("String").field(&Lossy(bytes)).finish(),
+        }
+    }
+}
+
+impl std::fmt::Debug for GcConstant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GcConstant::Child(index) => f.debug_tuple("Child").field(index).finish(),
+            GcConstant::Table(table) => f.debug_tuple("Table").field(table).finish(),
+            GcConstant::Int64(value) => f.debug_tuple("Int64").field(value).finish(),
+            GcConstant::UInt64(value) => f.debug_tuple("UInt64").field(value).finish(),
+            GcConstant::Complex(re, im) => f.debug_tuple("Complex").field(re).field(im).finish(),
+            GcConstant::String(bytes) => f.debug_tuple("String").field(&Lossy(bytes)).finish(),
+        }
+    }
+}
+
+impl std::fmt::Debug for LocalVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalVariable")
+            .field("varname", &Lossy(&self.varname))
+            .field("startpc", &self.startpc)
+            .field("endpc", &self.endpc)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for DebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let upvalues: Vec<Lossy> = self.upvalues.iter().map(|name| Lossy(name)).collect();
+        f.debug_struct("DebugInfo")
+            .field("lineinfo", &self.lineinfo)
+            .field("upvalues", &upvalues)
+            .field("locals", &self.locals)
+            .finish()
+    }
+}
+
+impl OperandMode {
+    pub const fn name(self) -> &'static str {
+        match self {
+            OperandMode::None => "none",
+            OperandMode::Dst => "dst",
+            OperandMode::Base => "base",
+            OperandMode::Var => "var",
+            OperandMode::RBase => "rbase",
+            OperandMode::Uv => "uv",
+            OperandMode::Lit => "lit",
+            OperandMode::LitS => "lits",
+            OperandMode::Pri => "pri",
+            OperandMode::Num => "num",
+            OperandMode::Str => "str",
+            OperandMode::Tab => "tab",
+            OperandMode::Func => "func",
+            OperandMode::CData => "cdata",
+            OperandMode::Jump => "jump",
+        }
+    }
+}
+
+impl Prototype {
+    pub const fn is_vararg(&self) -> bool {
+        self.flags & PROTO_VARARG != 0
+    }
+}
+
+impl Opcode {
+    /// Decodes an opcode byte, accounting for the opcodes LuaJIT 2.0 lacks
+    pub fn decode(op: u8, version: DumpVersion) -> Option<Opcode> {
+        match version {
+            DumpVersion::V1 => V1_OPCODES.get(op as usize).copied(),
+            DumpVersion::V2 => Opcode::try_from(op).ok(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        OPNAMES[self as usize]
+    }
+
+    /// Operand modes (A, B, C or D); instructions without a B operand use the AD format
+    pub fn modes(self) -> (OperandMode, OperandMode, OperandMode) {
+        OPMODES[self as usize]
+    }
+}
+
+impl Instruction {
+    pub const fn new(instr: u32) -> Self {
+        Self(instr)
+    }
+
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn op(&self) -> u8 {
+        self.0 as u8
+    }
+
+    pub const fn a(&self) -> u32 {
+        (self.0 >> 8) & 0xff
+    }
+
+    pub const fn b(&self) -> u32 {
+        self.0 >> 24
+    }
+
+    pub const fn c(&self) -> u32 {
+        (self.0 >> 16) & 0xff
+    }
+
+    pub const fn d(&self) -> u32 {
+        self.0 >> 16
+    }
+
+    /// Jump offset encoded in D, relative to the next instruction
+    pub const fn jump(&self) -> i32 {
+        self.d() as i32 - BCBIAS_J
+    }
+
+    pub const fn display(&self, version: DumpVersion) -> VersionedInstruction<'_> {
+        VersionedInstruction {
+            instr: self,
+            version,
+        }
+    }
+
+    fn fmt_operand(
+        f: &mut std::fmt::Formatter<'_>,
+        name: &str,
+        mode: OperandMode,
+        value: u32,
+    ) -> std::fmt::Result {
+        match mode {
+            OperandMode::None => Ok(()),
+            OperandMode::LitS => write!(f, " {name}: lits({}),", value as u16 as i16),
+            OperandMode::Pri => match value {
+                0 => write!(f, " {name}: nil,"),
+                1 => write!(f, " {name}: false,"),
+                2 => write!(f, " {name}: true,"),
+                _ => write!(f, " {name}: pri({value}),"),
+            },
+            OperandMode::Jump => write!(f, " {name}: jump({:+}),", value as i32 - BCBIAS_J),
+            _ => write!(f, " {name}: {}({value}),", mode.name()),
+        }
+    }
+}
+
+impl std::fmt::Display for VersionedInstruction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let instr = self.instr;
+        let Some(opcode) = Opcode::decode(instr.op(), self.version) else {
+            return write!(
+                f,
+                "Instruction(opname: UNKNOWN({}) raw: {:08x})",
+                instr.op(),
+                instr.0
+            );
+        };
+        let (a_mode, b_mode, cd_mode) = opcode.modes();
+
+        write!(f, "Instruction(")?;
+        write!(f, "opname: {}", opcode.name())?;
+        Instruction::fmt_operand(f, "a", a_mode, instr.a())?;
+        if b_mode == OperandMode::None {
+            Instruction::fmt_operand(f, "d", cd_mode, instr.d())?;
+        } else {
+            Instruction::fmt_operand(f, "b", b_mode, instr.b())?;
+            Instruction::fmt_operand(f, "c", cd_mode, instr.c())?;
+        }
+
+        write!(f, " raw: {:08x}", instr.0)?;
+        write!(f, ")")
+    }
+}
+
+//////////////////////////////// Lookup Tables ////////////////////////////////
+
+use OperandMode as M;
+
+#[rustfmt::skip]
+const OPMODES: [(OperandMode, OperandMode, OperandMode); 97] = [
+    /*   A        B        C/D     */
+    (M::Var,   M::None,  M::Var),   // ISLT
+    (M::Var,   M::None,  M::Var),   // ISGE
+    (M::Var,   M::None,  M::Var),   // ISLE
+    (M::Var,   M::None,  M::Var),   // ISGT
+    (M::Var,   M::None,  M::Var),   // ISEQV
+    (M::Var,   M::None,  M::Var),   // ISNEV
+    (M::Var,   M::None,  M::Str),   // ISEQS
+    (M::Var,   M::None,  M::Str),   // ISNES
+    (M::Var,   M::None,  M::Num),   // ISEQN
+    (M::Var,   M::None,  M::Num),   // ISNEN
+    (M::Var,   M::None,  M::Pri),   // ISEQP
+    (M::Var,   M::None,  M::Pri),   // ISNEP
+    (M::Dst,   M::None,  M::Var),   // ISTC
+    (M::Dst,   M::None,  M::Var),   // ISFC
+    (M::None,  M::None,  M::Var),   // IST
+    (M::None,  M::None,  M::Var),   // ISF
+    (M::Var,   M::None,  M::Lit),   // ISTYPE
+    (M::Var,   M::None,  M::Lit),   // ISNUM
+    (M::Dst,   M::None,  M::Var),   // MOV
+    (M::Dst,   M::None,  M::Var),   // NOT
+    (M::Dst,   M::None,  M::Var),   // UNM
+    (M::Dst,   M::None,  M::Var),   // LEN
+    (M::Dst,   M::Var,   M::Num),   // ADDVN
+    (M::Dst,   M::Var,   M::Num),   // SUBVN
+    (M::Dst,   M::Var,   M::Num),   // MULVN
+    (M::Dst,   M::Var,   M::Num),   // DIVVN
+    (M::Dst,   M::Var,   M::Num),   // MODVN
+    (M::Dst,   M::Var,   M::Num),   // ADDNV
+    (M::Dst,   M::Var,   M::Num),   // SUBNV
+    (M::Dst,   M::Var,   M::Num),   // MULNV
+    (M::Dst,   M::Var,   M::Num),   // DIVNV
+    (M::Dst,   M::Var,   M::Num),   // MODNV
+    (M::Dst,   M::Var,   M::Var),   // ADDVV
+    (M::Dst,   M::Var,   M::Var),   // SUBVV
+    (M::Dst,   M::Var,   M::Var),   // MULVV
+    (M::Dst,   M::Var,   M::Var),   // DIVVV
+    (M::Dst,   M::Var,   M::Var),   // MODVV
+    (M::Dst,   M::Var,   M::Var),   // POW
+    (M::Dst,   M::RBase, M::RBase), // CAT
+    (M::Dst,   M::None,  M::Str),   // KSTR
+    (M::Dst,   M::None,  M::CData), // KCDATA
+    (M::Dst,   M::None,  M::LitS),  // KSHORT
+    (M::Dst,   M::None,  M::Num),   // KNUM
+    (M::Dst,   M::None,  M::Pri),   // KPRI
+    (M::Base,  M::None,  M::Base),  // KNIL
+    (M::Dst,   M::None,  M::Uv),    // UGET
+    (M::Uv,    M::None,  M::Var),   // USETV
+    (M::Uv,    M::None,  M::Str),   // USETS
+    (M::Uv,    M::None,  M::Num),   // USETN
+    (M::Uv,    M::None,  M::Pri),   // USETP
+    (M::RBase, M::None,  M::Jump),  // UCLO
+    (M::Dst,   M::None,  M::Func),  // FNEW
+    (M::Dst,   M::None,  M::Lit),   // TNEW
+    (M::Dst,   M::None,  M::Tab),   // TDUP
+    (M::Dst,   M::None,  M::Str),   // GGET
+    (M::Var,   M::None,  M::Str),   // GSET
+    (M::Dst,   M::Var,   M::Var),   // TGETV
+    (M::Dst,   M::Var,   M::Str),   // TGETS
+    (M::Dst,   M::Var,   M::Lit),   // TGETB
+    (M::Dst,   M::Var,   M::Var),   // TGETR
+    (M::Var,   M::Var,   M::Var),   // TSETV
+    (M::Var,   M::Var,   M::Str),   // TSETS
+    (M::Var,   M::Var,   M::Lit),   // TSETB
+    (M::Base,  M::None,  M::Num),   // TSETM
+    (M::Var,   M::Var,   M::Var),   // TSETR
+    (M::Base,  M::Lit,   M::Lit),   // CALLM
+    (M::Base,  M::Lit,   M::Lit),   // CALL
+    (M::Base,  M::None,  M::Lit),   // CALLMT
+    (M::Base,  M::None,  M::Lit),   // CALLT
+    (M::Base,  M::Lit,   M::Lit),   // ITERC
+    (M::Base,  M::Lit,   M::Lit),   // ITERN
+    (M::Base,  M::Lit,   M::Lit),   // VARG
+    (M::Base,  M::None,  M::Jump),  // ISNEXT
+    (M::Base,  M::None,  M::Lit),   // RETM
+    (M::RBase, M::None,  M::Lit),   // RET
+    (M::RBase, M::None,  M::Lit),   // RET0
+    (M::RBase, M::None,  M::Lit),   // RET1
+    (M::Base,  M::None,  M::Jump),  // FORI
+    (M::Base,  M::None,  M::Jump),  // JFORI
+    (M::Base,  M::None,  M::Jump),  // FORL
+    (M::Base,  M::None,  M::Jump),  // IFORL
+    (M::Base,  M::None,  M::Lit),   // JFORL
+    (M::Base,  M::None,  M::Jump),  // ITERL
+    (M::Base,  M::None,  M::Jump),  // IITERL
+    (M::Base,  M::None,  M::Lit),   // JITERL
+    (M::RBase, M::None,  M::Jump),  // LOOP
+    (M::RBase, M::None,  M::Jump),  // ILOOP
+    (M::RBase, M::None,  M::Lit),   // JLOOP
+    (M::RBase, M::None,  M::Jump),  // JMP
+    (M::RBase, M::None,  M::None),  // FUNCF
+    (M::RBase, M::None,  M::None),  // IFUNCF
+    (M::RBase, M::None,  M::Lit),   // JFUNCF
+    (M::RBase, M::None,  M::None),  // FUNCV
+    (M::RBase, M::None,  M::None),  // IFUNCV
+    (M::RBase, M::None,  M::Lit),   // JFUNCV
+    (M::RBase, M::None,  M::None),  // FUNCC
+    (M::RBase, M::None,  M::None),  // FUNCCW
+];
+
+#[rustfmt::skip]
+const OPNAMES: [&str; 97] = [
+    "ISLT",   "ISGE",   "ISLE",   "ISGT",   "ISEQV",  "ISNEV",  "ISEQS",  "ISNES",
+    "ISEQN",  "ISNEN",  "ISEQP",  "ISNEP",  "ISTC",   "ISFC",   "IST",    "ISF",
+    "ISTYPE", "ISNUM",  "MOV",    "NOT",    "UNM",    "LEN",    "ADDVN",  "SUBVN",
+    "MULVN",  "DIVVN",  "MODVN",  "ADDNV",  "SUBNV",  "MULNV",  "DIVNV",  "MODNV",
+    "ADDVV",  "SUBVV",  "MULVV",  "DIVVV",  "MODVV",  "POW",    "CAT",    "KSTR",
+    "KCDATA", "KSHORT", "KNUM",   "KPRI",   "KNIL",   "UGET",   "USETV",  "USETS",
+    "USETN",  "USETP",  "UCLO",   "FNEW",   "TNEW",   "TDUP",   "GGET",   "GSET",
+    "TGETV",  "TGETS",  "TGETB",  "TGETR",  "TSETV",  "TSETS",  "TSETB",  "TSETM",
+    "TSETR",  "CALLM",  "CALL",   "CALLMT", "CALLT",  "ITERC",  "ITERN",  "VARG",
+    "ISNEXT", "RETM",   "RET",    "RET0",   "RET1",   "FORI",   "JFORI",  "FORL",
+    "IFORL",  "JFORL",  "ITERL",  "IITERL", "JITERL", "LOOP",   "ILOOP",  "JLOOP",
+    "JMP",    "FUNCF",  "IFUNCF", "JFUNCF", "FUNCV",  "IFUNCV", "JFUNCV", "FUNCC",
+    "FUNCCW",
+];
+
+/// LuaJIT 2.0 numbering, which lacks ISTYPE, ISNUM, TGETR and TSETR
+#[rustfmt::skip]
+const V1_OPCODES: [Opcode; 93] = [
+    Opcode::ISLT,   Opcode::ISGE,   Opcode::ISLE,   Opcode::ISGT,   Opcode::ISEQV,
+    Opcode::ISNEV,  Opcode::ISEQS,  Opcode::ISNES,  Opcode::ISEQN,  Opcode::ISNEN,
+    Opcode::ISEQP,  Opcode::ISNEP,  Opcode::ISTC,   Opcode::ISFC,   Opcode::IST,
+    Opcode::ISF,    Opcode::MOV,    Opcode::NOT,    Opcode::UNM,    Opcode::LEN,
+    Opcode::ADDVN,  Opcode::SUBVN,  Opcode::MULVN,  Opcode::DIVVN,  Opcode::MODVN,
+    Opcode::ADDNV,  Opcode::SUBNV,  Opcode::MULNV,  Opcode::DIVNV,  Opcode::MODNV,
+    Opcode::ADDVV,  Opcode::SUBVV,  Opcode::MULVV,  Opcode::DIVVV,  Opcode::MODVV,
+    Opcode::POW,    Opcode::CAT,    Opcode::KSTR,   Opcode::KCDATA, Opcode::KSHORT,
+    Opcode::KNUM,   Opcode::KPRI,   Opcode::KNIL,   Opcode::UGET,   Opcode::USETV,
+    Opcode::USETS,  Opcode::USETN,  Opcode::USETP,  Opcode::UCLO,   Opcode::FNEW,
+    Opcode::TNEW,   Opcode::TDUP,   Opcode::GGET,   Opcode::GSET,   Opcode::TGETV,
+    Opcode::TGETS,  Opcode::TGETB,  Opcode::TSETV,  Opcode::TSETS,  Opcode::TSETB,
+    Opcode::TSETM,  Opcode::CALLM,  Opcode::CALL,   Opcode::CALLMT, Opcode::CALLT,
+    Opcode::ITERC,  Opcode::ITERN,  Opcode::VARG,   Opcode::ISNEXT, Opcode::RETM,
+    Opcode::RET,    Opcode::RET0,   Opcode::RET1,   Opcode::FORI,   Opcode::JFORI,
+    Opcode::FORL,   Opcode::IFORL,  Opcode::JFORL,  Opcode::ITERL,  Opcode::IITERL,
+    Opcode::JITERL, Opcode::LOOP,   Opcode::ILOOP,  Opcode::JLOOP,  Opcode::JMP,
+    Opcode::FUNCF,  Opcode::IFUNCF, Opcode::JFUNCF, Opcode::FUNCV,  Opcode::IFUNCV,
+    Opcode::JFUNCV, Opcode::FUNCC,  Opcode::FUNCCW,
+];
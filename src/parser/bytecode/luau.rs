@@ -0,0 +1,453 @@
+/*
+  Constants and data model for Luau bytecode (versions 3 to 6)
+*/
+
+use super::Lossy;
+use num_enum::TryFromPrimitive;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// Bytecode.h:LuauBytecodeTag
+pub const VERSION_MIN: u8 = 3;
+pub const VERSION_MAX: u8 = 6;
+pub const TYPE_VERSION_MIN: u8 = 1;
+pub const TYPE_VERSION_MAX: u8 = 3;
+
+// Bytecode.h:LuauBytecodeTag (constant tags)
+pub const CONSTANT_NIL: u8 = 0;
+pub const CONSTANT_BOOLEAN: u8 = 1;
+pub const CONSTANT_NUMBER: u8 = 2;
+pub const CONSTANT_STRING: u8 = 3;
+pub const CONSTANT_IMPORT: u8 = 4;
+pub const CONSTANT_TABLE: u8 = 5;
+pub const CONSTANT_CLOSURE: u8 = 6;
+pub const CONSTANT_VECTOR: u8 = 7;
+
+// Bytecode.h:LuauProtoFlag
+pub const PROTO_NATIVE_MODULE: u8 = 0x01;
+pub const PROTO_NATIVE_COLD: u8 = 0x02;
+pub const PROTO_NATIVE_FUNCTION: u8 = 0x04;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
+#[rustfmt::skip]
+#[repr(u8)]
+pub enum Opcode {
+    NOP,            BREAK,         LOADNIL,       LOADB,         LOADN,
+    LOADK,          MOVE,          GETGLOBAL,     SETGLOBAL,     GETUPVAL,
+    SETUPVAL,       CLOSEUPVALS,   GETIMPORT,     GETTABLE,      SETTABLE,
+    GETTABLEKS,     SETTABLEKS,    GETTABLEN,     SETTABLEN,     NEWCLOSURE,
+    NAMECALL,       CALL,          RETURN,        JUMP,          JUMPBACK,
+    JUMPIF,         JUMPIFNOT,     JUMPIFEQ,      JUMPIFLE,      JUMPIFLT,
+    JUMPIFNOTEQ,    JUMPIFNOTLE,   JUMPIFNOTLT,   ADD,           SUB,
+    MUL,            DIV,           MOD,           POW,           ADDK,
+    SUBK,           MULK,          DIVK,          MODK,          POWK,
+    AND,            OR,            ANDK,          ORK,           CONCAT,
+    NOT,            MINUS,         LENGTH,        NEWTABLE,      DUPTABLE,
+    SETLIST,        FORNPREP,      FORNLOOP,      FORGLOOP,      FORGPREP_INEXT,
+    FASTCALL3,      FORGPREP_NEXT, NATIVECALL,    GETVARARGS,    DUPCLOSURE,
+    PREPVARARGS,    LOADKX,        JUMPX,         FASTCALL,      COVERAGE,
+    CAPTURE,        SUBRK,         DIVRK,         FASTCALL1,     FASTCALL2,
+    FASTCALL2K,     FORGPREP,      JUMPXEQKNIL,   JUMPXEQKB,     JUMPXEQKN,
+    JUMPXEQKS,      IDIV,          IDIVK,
+}
+
+/// Operands used by an opcode; D is a signed 16-bit field, E a signed 24-bit one
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OperandFormat {
+    None,
+    A,
+    AB,
+    AC,
+    ABC,
+    AD,
+    D,
+    E,
+}
+
+pub struct Header {
+    pub version: u8,                        // Bytecode version (3 to 6)
+    pub types_version: u8,                  // Type information version (version 4+, 0 before)
+    pub strings: Vec<Vec<u8>>,              // String table, referenced 1-based by the protos
+    pub userdata_types: Vec<(u8, Vec<u8>)>, // Userdata type remapping (types version 3)
+}
+
+/// An import path (GETIMPORT) of up to three names, e.g. `math.floor`
+pub struct Import {
+    pub id: u32,            // Encoded id: count(2) k0(10) k1(10) k2(10)
+    pub path: Vec<Vec<u8>>, // Resolved names
+}
+
+/// Luau counterpart of `bytecode::Constant`, with the Luau-only kinds
+pub enum Constant {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(Vec<u8>),
+    Import(Import),
+    Table(Vec<usize>), // Constant indices of the template's keys
+    Closure(usize),    // Index into `Chunk::prototypes`
+    Vector(f32, f32, f32, f32),
+}
+
+pub struct LocalVariable {
+    pub varname: Vec<u8>,
+    pub startpc: u32,
+    pub endpc: u32,
+    pub reg: u8,
+}
+
+pub struct DebugInfo {
+    pub lineinfo: Vec<u32>, // Absolute line per instruction, empty without line info
+    pub locals: Vec<LocalVariable>,
+    pub upvalues: Vec<Vec<u8>>,
+}
+
+/// Luau counterpart of `bytecode::FunctionPrototype`. Children are referenced by index
+/// into `Chunk::prototypes` instead of being nested
+pub struct Prototype {
+    pub debug_name: Vec<u8>, // Empty for anonymous functions and the main chunk
+    pub line_defined: u32,
+    pub num_upvalues: u8,
+    pub num_params: u8,
+    pub is_vararg: bool,
+    pub max_stack_size: u8,
+    pub flags: u8,              // LuauProtoFlag bits (version 4+)
+    pub type_info: Vec<u8>,     // Raw type information blob (version 4+)
+    pub code: Vec<Instruction>, // Includes the AUX words following some opcodes
+    pub constants: Vec<Constant>,
+    pub prototypes: Vec<usize>,
+    pub debug_info: DebugInfo,
+}
+
+/// Every prototype of a module, in dump order, and the index of the main one
+#[derive(Debug)]
+pub struct Chunk {
+    pub prototypes: Vec<Prototype>,
+    pub main: usize,
+}
+
+/// A Luau instruction word: C(8) B(8) A(8) OP(8), with D overlapping B and C, and E
+/// overlapping A, B and C
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction(u32);
+
+/// An instruction paired with the AUX word that follows it, for display
+pub struct AuxInstruction<'a> {
+    instr: &'a Instruction,
+    aux: Option<u32>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Chunk {
+    pub fn main_prototype(&self) -> &Prototype {
+        &self.prototypes[self.main]
+    }
+}
+
+impl Import {
+    /// Decodes the constant indices of the path stored in an import id
+    pub fn indices(id: u32) -> Vec<usize> {
+        let count = (id >> 30) as usize;
+        (0..count)
+            .map(|i| ((id >> (20 - 10 * i)) & 0x3ff) as usize)
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let strings: Vec<Lossy> = self.strings.iter().map(|s| Lossy(s)).collect();
+        let userdata_types: Vec<(u8, Lossy)> = self
+            .userdata_types
+            .iter()
+            .map(|(index, name)| (*index, Lossy(name)))
+            .collect();
+        f.debug_struct("Header")
+            .field("version", &self.version)
+            .field("types_version", &self.types_version)
+            .field("strings", &strings)
+            .field("userdata_types", &userdata_types)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for Import {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path: Vec<Lossy> = self.path.iter().map(|name| Lossy(name)).collect();
+        f.debug_struct("Import")
+            .field("id", &self.id)
+            .field("path", &path)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Nil => write!(f, "Nil"),
+            Constant::Boolean(value) => f.debug_tuple("Boolean").field(value).finish(),
+            Constant::Number(value) => f.debug_tuple("Number").field(value).finish(),
+            Constant::String(bytes) => f.debug_tuple("String").field(&Lossy(bytes)).finish(),
+            Constant::Import(import) => f.debug_tuple("Import").field(import).finish(),
+            Constant::Table(keys) => f.debug_tuple("Table").field(keys).finish(),
+            Constant::Closure(index) => f.debug_tuple("Closure").field(index).finish(),
+            Constant::Vector(x, y, z, w) => f
+                .debug_tuple("Vector")
+                .field(x)
+                .field(y)
+                .field(z)
+                .field(w)
+                .finish(),
+        }
+    }
+}
+
+impl std::fmt::Debug for LocalVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalVariable")
+            .field("varname", &Lossy(&self.varname))
+            .field("startpc", &self.startpc)
+            .field("endpc", &self.endpc)
+            .field("reg", &self.reg)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for DebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let upvalues: Vec<Lossy> = self.upvalues.iter().map(|name| Lossy(name)).collect();
+        f.debug_struct("DebugInfo")
+            .field("lineinfo", &self.lineinfo)
+            .field("locals", &self.locals)
+            .field("upvalues", &upvalues)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for Prototype {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Prototype")
+            .field("debug_name", &Lossy(&self.debug_name))
+            .field("line_defined", &self.line_defined)
+            .field("num_upvalues", &self.num_upvalues)
+            .field("num_params", &self.num_params)
+            .field("is_vararg", &self.is_vararg)
+            .field("max_stack_size", &self.max_stack_size)
+            .field("flags", &self.flags)
+            .field("type_info", &self.type_info)
+            .field("code", &self.code)
+            .field("constants", &self.constants)
+            .field("prototypes", &self.prototypes)
+            .field("debug_info", &self.debug_info)
+            .finish()
+    }
+}
+
+impl Opcode {
+    pub fn name(self) -> &'static str {
+        OPNAMES[self as usize]
+    }
+
+    pub fn format(self) -> OperandFormat {
+        OPFORMATS[self as usize].0
+    }
+
+    /// Whether the opcode is followed by an AUX word
+    pub fn has_aux(self) -> bool {
+        OPFORMATS[self as usize].1
+    }
+}
+
+impl Instruction {
+    pub const fn new(instr: u32) -> Self {
+        Self(instr)
+    }
+
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn op(&self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn opcode(&self) -> Option<Opcode> {
+        Opcode::try_from(self.op()).ok()
+    }
+
+    pub const fn a(&self) -> u32 {
+        (self.0 >> 8) & 0xff
+    }
+
+    pub const fn b(&self) -> u32 {
+        (self.0 >> 16) & 0xff
+    }
+
+    pub const fn c(&self) -> u32 {
+        self.0 >> 24
+    }
+
+    pub const fn d(&self) -> i32 {
+        (self.0 as i32) >> 16
+    }
+
+    pub const fn e(&self) -> i32 {
+        (self.0 as i32) >> 8
+    }
+
+    pub const fn with_aux(&self, aux: Option<u32>) -> AuxInstruction<'_> {
+        AuxInstruction { instr: self, aux }
+    }
+}
+
+impl std::fmt::Display for AuxInstruction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let instr = self.instr;
+        let Some(opcode) = instr.opcode() else {
+            return write!(
+                f,
+                "Instruction(opname: UNKNOWN({}) raw: {:08x})",
+                instr.op(),
+                instr.0
+            );
+        };
+
+        write!(f, "Instruction(")?;
+        write!(f, "opname: {}", opcode.name())?;
+        match opcode.format() {
+            OperandFormat::None => {}
+            OperandFormat::A => write!(f, " a: {},", instr.a())?,
+            OperandFormat::AB => write!(f, " a: {}, b: {},", instr.a(), instr.b())?,
+            OperandFormat::AC => write!(f, " a: {}, c: {},", instr.a(), instr.c())?,
+            OperandFormat::ABC => {
+                write!(f, " a: {}, b: {}, c: {},", instr.a(), instr.b(), instr.c())?
+            }
+            OperandFormat::AD => write!(f, " a: {}, d: {},", instr.a(), instr.d())?,
+            OperandFormat::D => write!(f, " d: {},", instr.d())?,
+            OperandFormat::E => write!(f, " e: {},", instr.e())?,
+        }
+        if let Some(aux) = self.aux {
+            write!(f, " aux: {:08x},", aux)?;
+        }
+
+        write!(f, " raw: {:08x}", instr.0)?;
+        write!(f, ")")
+    }
+}
+
+//////////////////////////////// Lookup Tables ////////////////////////////////
+
+use OperandFormat as F;
+
+// Bytecode.h:LuauOpcode (operand layout, followed by an AUX word)
+#[rustfmt::skip]
+const OPFORMATS: [(OperandFormat, bool); 83] = [
+    (F::None, false), // NOP
+    (F::None, false), // BREAK
+    (F::A,    false), // LOADNIL
+    (F::ABC,  false), // LOADB
+    (F::AD,   false), // LOADN
+    (F::AD,   false), // LOADK
+    (F::AB,   false), // MOVE
+    (F::AC,   true),  // GETGLOBAL
+    (F::AC,   true),  // SETGLOBAL
+    (F::AB,   false), // GETUPVAL
+    (F::AB,   false), // SETUPVAL
+    (F::A,    false), // CLOSEUPVALS
+    (F::AD,   true),  // GETIMPORT
+    (F::ABC,  false), // GETTABLE
+    (F::ABC,  false), // SETTABLE
+    (F::ABC,  true),  // GETTABLEKS
+    (F::ABC,  true),  // SETTABLEKS
+    (F::ABC,  false), // GETTABLEN
+    (F::ABC,  false), // SETTABLEN
+    (F::AD,   false), // NEWCLOSURE
+    (F::ABC,  true),  // NAMECALL
+    (F::ABC,  false), // CALL
+    (F::AB,   false), // RETURN
+    (F::D,    false), // JUMP
+    (F::D,    false), // JUMPBACK
+    (F::AD,   false), // JUMPIF
+    (F::AD,   false), // JUMPIFNOT
+    (F::AD,   true),  // JUMPIFEQ
+    (F::AD,   true),  // JUMPIFLE
+    (F::AD,   true),  // JUMPIFLT
+    (F::AD,   true),  // JUMPIFNOTEQ
+    (F::AD,   true),  // JUMPIFNOTLE
+    (F::AD,   true),  // JUMPIFNOTLT
+    (F::ABC,  false), // ADD
+    (F::ABC,  false), // SUB
+    (F::ABC,  false), // MUL
+    (F::ABC,  false), // DIV
+    (F::ABC,  false), // MOD
+    (F::ABC,  false), // POW
+    (F::ABC,  false), // ADDK
+    (F::ABC,  false), // SUBK
+    (F::ABC,  false), // MULK
+    (F::ABC,  false), // DIVK
+    (F::ABC,  false), // MODK
+    (F::ABC,  false), // POWK
+    (F::ABC,  false), // AND
+    (F::ABC,  false), // OR
+    (F::ABC,  false), // ANDK
+    (F::ABC,  false), // ORK
+    (F::ABC,  false), // CONCAT
+    (F::AB,   false), // NOT
+    (F::AB,   false), // MINUS
+    (F::AB,   false), // LENGTH
+    (F::AB,   true),  // NEWTABLE
+    (F::AD,   false), // DUPTABLE
+    (F::ABC,  true),  // SETLIST
+    (F::AD,   false), // FORNPREP
+    (F::AD,   false), // FORNLOOP
+    (F::AD,   true),  // FORGLOOP
+    (F::AD,   false), // FORGPREP_INEXT
+    (F::ABC,  true),  // FASTCALL3
+    (F::AD,   false), // FORGPREP_NEXT
+    (F::None, false), // NATIVECALL
+    (F::AB,   false), // GETVARARGS
+    (F::AD,   false), // DUPCLOSURE
+    (F::A,    false), // PREPVARARGS
+    (F::A,    true),  // LOADKX
+    (F::E,    false), // JUMPX
+    (F::AC,   false), // FASTCALL
+    (F::E,    false), // COVERAGE
+    (F::AB,   false), // CAPTURE
+    (F::ABC,  false), // SUBRK
+    (F::ABC,  false), // DIVRK
+    (F::ABC,  false), // FASTCALL1
+    (F::ABC,  true),  // FASTCALL2
+    (F::ABC,  true),  // FASTCALL2K
+    (F::AD,   false), // FORGPREP
+    (F::AD,   true),  // JUMPXEQKNIL
+    (F::AD,   true),  // JUMPXEQKB
+    (F::AD,   true),  // JUMPXEQKN
+    (F::AD,   true),  // JUMPXEQKS
+    (F::ABC,  false), // IDIV
+    (F::ABC,  false), // IDIVK
+];
+
+#[rustfmt::skip]
+const OPNAMES: [&str; 83] = [
+    "NOP",         "BREAK",         "LOADNIL",     "LOADB",       "LOADN",
+    "LOADK",       "MOVE",          "GETGLOBAL",   "SETGLOBAL",   "GETUPVAL",
+    "SETUPVAL",    "CLOSEUPVALS",   "GETIMPORT",   "GETTABLE",    "SETTABLE",
+    "GETTABLEKS",  "SETTABLEKS",    "GETTABLEN",   "SETTABLEN",   "NEWCLOSURE",
+    "NAMECALL",    "CALL",          "RETURN",      "JUMP",        "JUMPBACK",
+    "JUMPIF",      "JUMPIFNOT",     "JUMPIFEQ",    "JUMPIFLE",    "JUMPIFLT",
+    "JUMPIFNOTEQ", "JUMPIFNOTLE",   "JUMPIFNOTLT", "ADD",         "SUB",
+    "MUL",         "DIV",           "MOD",         "POW",         "ADDK",
+    "SUBK",        "MULK",          "DIVK",        "MODK",        "POWK",
+    "AND",         "OR",            "ANDK",        "ORK",         "CONCAT",
+    "NOT",         "MINUS",         "LENGTH",      "NEWTABLE",    "DUPTABLE",
+    "SETLIST",     "FORNPREP",      "FORNLOOP",    "FORGLOOP",    "FORGPREP_INEXT",
+    "FASTCALL3",   "FORGPREP_NEXT", "NATIVECALL",  "GETVARARGS",  "DUPCLOSURE",
+    "PREPVARARGS", "LOADKX",        "JUMPX",       "FASTCALL",    "COVERAGE",
+    "CAPTURE",     "SUBRK",         "DIVRK",       "FASTCALL1",   "FASTCALL2",
+    "FASTCALL2K",  "FORGPREP",      "JUMPXEQKNIL", "JUMPXEQKB",   "JUMPXEQKN",
+    "JUMPXEQKS",   "IDIV",          "IDIVK",
+];
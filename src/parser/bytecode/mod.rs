@@ -0,0 +1,790 @@
+/*
+  Constants for Lua bytecode
+*/
+
+use num_enum::TryFromPrimitive;
+use std::borrow::Cow;
+
+pub mod decoded;
+pub mod lua52;
+pub mod lua53;
+pub mod lua54;
+pub mod luajit;
+pub mod luau;
+
+pub use decoded::{DecodedOp, RK};
+
+//////////////////////////////// Variables ////////////////////////////////
+
+// lopcodes.h:211
+const TOTAL_OPS: u8 = 38;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+pub enum Constant {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Integer(i64),
+    String(Vec<u8>), // Raw bytes, Lua strings need not be valid UTF-8
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[repr(u8)]
+pub enum Version {
+    Lua51 = 0x51,
+    Lua52 = 0x52,
+    Lua53 = 0x53,
+    Lua54 = 0x54,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[allow(clippy::upper_case_acronyms)]
+#[repr(u8)]
+pub enum InstructionFormat {
+    IABC,
+    IABx,
+    IAsBx,
+    IAx,
+    IsJ,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum OperandMask {
+    OpArgN, /* argument is not used */
+    OpArgU, /* argument is used */
+    OpArgR, /* argument is a register or a jump offset */
+    OpArgK, /* argument is a constant or register/constant */
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[allow(clippy::upper_case_acronyms)]
+#[rustfmt::skip]
+#[repr(u8)]
+pub enum Opcode {
+    MOVE,     LOADK,     LOADBOOL, LOADNIL,
+    GETUPVAL, GETGLOBAL, GETTABLE, SETGLOBAL,
+    SETUPVAL, SETTABLE,  NEWTABLE, SELF,
+    ADD,      SUB,       MUL,      DIV,
+    MOD,      POW,       UNM,      NOT,
+    LEN,      CONCAT,    JMP,      EQ,
+    LT,       LE,        TEST,     TESTSET,
+    CALL,     TAILCALL,  RETURN,   FORLOOP,
+    FORPREP,  TFORLOOP,  SETLIST,  CLOSE,
+    CLOSURE,  VARARG,
+}
+
+#[derive(Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LocalVariable {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::schema::lossy"))]
+    pub varname: Vec<u8>, // Raw bytes, like string constants
+    pub startpc: u32,
+    pub endpc: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct UpvalueDescriptor {
+    pub instack: bool, // Whether the upvalue is a register of the enclosing function
+    pub idx: u8,       // Register or upvalue index in the enclosing function
+    pub kind: u8,      // Variable kind (Lua 5.4, e.g. 2 for to-be-closed), 0 before
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AbsLineInfo {
+    pub pc: u32,
+    pub line: u32,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DebugInfo {
+    pub lineinfo: Vec<u32>, // Absolute line per instruction (resolved from deltas on Lua 5.4)
+    pub abslineinfo: Vec<AbsLineInfo>, // Lua 5.4 line anchors, empty before
+    pub locals: Vec<LocalVariable>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::schema::lossy_all"))]
+    pub upvalues: Vec<Vec<u8>>, // Raw bytes, like string constants
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Header {
+    pub version: Version,       // Lua version (0x51 for Lua 5.1)
+    pub format: u8,             // Bytecode format (0 for official Lua bytecode)
+    pub endianness: Endianness, // Byte order (Big or Little Endian)
+    pub size_int: u8,           // Size of an integer in bytes
+    pub size_size_t: u8,        // Size of a size_t value in bytes
+    pub size_instruction: u8,   // Size of an instruction in bytes
+    pub size_number: u8,        // Size of a number in bytes
+    pub size_integer: u8,       // Size of a lua_Integer in bytes (Lua 5.3+, 0 before)
+    pub integral_flag: bool,    // Whether numbers are stored as integers or floats
+    pub main_upvalues: u8,      // Upvalue count of the main closure (Lua 5.3+, 0 before)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FunctionPrototype {
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::schema::lossy_option")
+    )]
+    pub source_name: Option<Vec<u8>>, // Raw bytes, None for a NULL source (stripped or inherited)
+    pub line_defined: i32,
+    pub last_line_defined: i32,
+    pub num_upvalues: u8,
+    pub num_params: u8,
+    pub is_vararg: u8,
+    pub max_stack_size: u8,
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Constant>,
+    pub prototypes: Vec<FunctionPrototype>,
+    pub upvalue_descriptors: Vec<UpvalueDescriptor>, // Empty before Lua 5.2
+    pub debug_info: DebugInfo,
+}
+
+/// Opcode names and operand modes of one Lua version sharing the 5.1 instruction layout
+#[derive(Debug)]
+pub struct OpcodeTable {
+    pub names: &'static [&'static str],
+    pub modes: &'static [(InstructionFormat, OperandMask, OperandMask)],
+}
+
+/// A Lua 5.1 instruction whose opcode is known, with its operands unpacked
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DecodedInstruction {
+    pub opcode: Opcode,
+    pub format: InstructionFormat,
+    pub b_mode: OperandMask,
+    pub c_mode: OperandMask,
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    pub bx: u32,
+    pub sbx: i32,
+}
+
+/// Why an instruction word could not be decoded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecodeError {
+    UnknownOpcode(u8), // Opcode byte past the end of the version's opcode list
+    InvalidOperand {
+        opcode: Opcode,
+        operand: &'static str, // A, B or C, or "unused" for bits no operand covers
+        value: u32,
+    },
+}
+
+/// Bytes shown as lossy UTF-8 by `Debug`, like the names they hold
+struct Lossy<'a>(&'a [u8]);
+
+/// An instruction paired with the Lua version it belongs to, for display
+pub struct VersionedInstruction<'a> {
+    instr: &'a Instruction,
+    version: Version,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Instruction(u32);
+impl Instruction {
+    pub const SIZE_OP: u32 = 6;
+    pub const SIZE_C: u32 = 9;
+    pub const SIZE_B: u32 = 9;
+    pub const SIZE_A: u32 = 8;
+    pub const SIZE_BX: u32 = Instruction::SIZE_C + Instruction::SIZE_B;
+
+    pub const POS_OP: u32 = 0;
+    pub const POS_A: u32 = Instruction::POS_OP + Instruction::SIZE_OP;
+    pub const POS_C: u32 = Instruction::POS_A + Instruction::SIZE_A;
+    pub const POS_B: u32 = Instruction::POS_C + Instruction::SIZE_C;
+    pub const POS_BX: u32 = Instruction::POS_C;
+
+    // Lua 5.2+
+    pub const SIZE_AX: u32 = Instruction::SIZE_C + Instruction::SIZE_B + Instruction::SIZE_A;
+    pub const POS_AX: u32 = Instruction::POS_A;
+
+    pub const MAXARG_A: u32 = (1 << Instruction::SIZE_A) - 1;
+    pub const MAXARG_B: u32 = (1 << Instruction::SIZE_B) - 1;
+    pub const MAXARG_C: u32 = (1 << Instruction::SIZE_C) - 1;
+    pub const MAXARG_BX: u32 = (1 << Instruction::SIZE_BX) - 1;
+    pub const MAXARG_SBX: i32 = (Instruction::MAXARG_BX >> 1) as i32;
+
+    pub const fn new(instr: u32) -> Self {
+        Self(instr)
+    }
+
+    // Encoding, operands are expected to be in range (lopcodes.h:CREATE_ABC) //
+    pub const fn abc(op: u8, a: u32, b: u32, c: u32) -> Self {
+        Self(
+            (op as u32) << Instruction::POS_OP
+                | a << Instruction::POS_A
+                | b << Instruction::POS_B
+                | c << Instruction::POS_C,
+        )
+    }
+
+    pub const fn abx(op: u8, a: u32, bx: u32) -> Self {
+        Self(
+            (op as u32) << Instruction::POS_OP
+                | a << Instruction::POS_A
+                | bx << Instruction::POS_BX,
+        )
+    }
+
+    pub const fn asbx(op: u8, a: u32, sbx: i32) -> Self {
+        Self::abx(op, a, (sbx + Instruction::MAXARG_SBX) as u32)
+    }
+
+    pub const fn raw(&self) -> u32 {
+        self.0
+    }
+
+    // Utility Functions //
+    const fn extract_bits(start: u32, end: u32, value: u32) -> u32 {
+        assert!(start < end && end <= 32, "Invalid bit range");
+
+        let mask = (1 << (end - start)) - 1;
+        (value >> start) & mask
+    }
+    const fn convert_mode(mode: OperandMask) -> &'static str {
+        match mode {
+            OperandMask::OpArgN => "OpArgN",
+            OperandMask::OpArgU => "OpArgU",
+            OperandMask::OpArgR => "OpArgR",
+            OperandMask::OpArgK => "OpArgK",
+        }
+    }
+
+    // Instruction Info //
+    pub const fn op(&self) -> u8 {
+        Self::extract_bits(
+            Instruction::POS_OP,
+            Instruction::POS_OP + Instruction::SIZE_OP,
+            self.0,
+        ) as u8
+    }
+
+    pub fn opcode(&self) -> Result<Opcode, DecodeError> {
+        Opcode::try_from(self.op()).map_err(|_| DecodeError::UnknownOpcode(self.op()))
+    }
+
+    pub fn format(&self) -> Result<InstructionFormat, DecodeError> {
+        Ok(OPMODES[self.opcode()? as usize].0)
+    }
+
+    /// Decodes the opcode and its operand modes, failing on opcodes Lua 5.1 lacks
+    pub fn decode(&self) -> Result<DecodedInstruction, DecodeError> {
+        let opcode = self.opcode()?;
+        let (format, b_mode, c_mode) = OPMODES[opcode as usize];
+        Ok(DecodedInstruction {
+            opcode,
+            format,
+            b_mode,
+            c_mode,
+            a: self.a(),
+            b: self.b(),
+            c: self.c(),
+            bx: self.bx(),
+            sbx: self.sbx(),
+        })
+    }
+
+    // Operands //
+
+    /* A */
+    pub const fn a(&self) -> u32 {
+        Self::extract_bits(
+            Instruction::POS_A,
+            Instruction::POS_A + Instruction::SIZE_A,
+            self.0,
+        )
+    }
+
+    /* B */
+    pub const fn b(&self) -> u32 {
+        Self::extract_bits(
+            Instruction::POS_B,
+            Instruction::POS_B + Instruction::SIZE_B,
+            self.0,
+        )
+    }
+    pub const fn b_isk(&self) -> bool {
+        (Self::b(self) & (1 << (9 - 1))) != 0
+    }
+    pub const fn bk(&self) -> u32 {
+        Self::b(self) & !(1 << (9 - 1))
+    }
+    pub fn b_mode(&self) -> Result<OperandMask, DecodeError> {
+        Ok(OPMODES[self.opcode()? as usize].1)
+    }
+
+    /* C */
+    pub const fn c(&self) -> u32 {
+        Self::extract_bits(
+            Instruction::POS_C,
+            Instruction::POS_C + Instruction::SIZE_C,
+            self.0,
+        )
+    }
+    pub const fn c_isk(&self) -> bool {
+        (Self::c(self) & (1 << (9 - 1))) != 0
+    }
+    pub const fn ck(&self) -> u32 {
+        Self::c(self) & !(1 << (9 - 1))
+    }
+
+    pub fn c_mode(&self) -> Result<OperandMask, DecodeError> {
+        Ok(OPMODES[self.opcode()? as usize].2)
+    }
+
+    /* Special */
+    pub const fn bx(&self) -> u32 {
+        Self::extract_bits(
+            Instruction::POS_BX,
+            Instruction::POS_BX + Instruction::SIZE_BX,
+            self.0,
+        )
+    }
+
+    pub const fn sbx(&self) -> i32 {
+        self.bx() as i32 - Instruction::MAXARG_SBX
+    }
+
+    pub const fn ax(&self) -> u32 {
+        Self::extract_bits(
+            Instruction::POS_AX,
+            Instruction::POS_AX + Instruction::SIZE_AX,
+            self.0,
+        )
+    }
+
+    // Display //
+    pub const fn display(&self, version: Version) -> VersionedInstruction<'_> {
+        VersionedInstruction {
+            instr: self,
+            version,
+        }
+    }
+
+    fn fmt_with(&self, f: &mut std::fmt::Formatter<'_>, table: &OpcodeTable) -> std::fmt::Result {
+        let op = self.op() as usize;
+        let (Some(&opcode), Some(&(format, b_mode, c_mode))) =
+            (table.names.get(op), table.modes.get(op))
+        else {
+            return write!(f, "Instruction(opname: UNKNOWN({op}) raw: {:08x})", self.0);
+        };
+        let a = self.a();
+        let b = self.b();
+        let c = self.c();
+        let bx = self.bx();
+        let sbx = self.sbx();
+        let b_isk = self.b_isk();
+        let c_isk = self.c_isk();
+        let bk = self.bk();
+        let ck = self.ck();
+        let b_mode_str = Instruction::convert_mode(b_mode);
+        let c_mode_str = Instruction::convert_mode(c_mode);
+
+        write!(f, "Instruction(")?;
+        write!(f, "opname: {opcode}")?;
+        write!(f, " format: {:?},", format)?;
+
+        // TODO: Opcode-specific printers
+        match format {
+            InstructionFormat::IABC => {
+                if b_mode == OperandMask::OpArgK && b_isk {
+                    write!(f, " b: K({}),", bk)?;
+                } else {
+                    write!(f, " b: {},", b)?;
+                }
+                if c_mode == OperandMask::OpArgK && c_isk {
+                    write!(f, " c: K({}),", ck)?;
+                } else {
+                    write!(f, " c: {},", c)?;
+                }
+                write!(f, " a: {},", a)?;
+                write!(f, " b_mode: {},", b_mode_str)?;
+                write!(f, " c_mode: {},", c_mode_str)?;
+            }
+            InstructionFormat::IABx => {
+                if b_mode == OperandMask::OpArgK {
+                    write!(f, " bx: K({}),", bx)?;
+                } else {
+                    write!(f, " bx: {},", bx)?;
+                }
+                write!(f, " b_isk: {},", b_isk)?;
+            }
+            InstructionFormat::IAsBx => {
+                write!(f, " sbx: {},", sbx)?;
+                write!(f, " a: {},", a)?;
+            }
+            // sJ only exists in the Lua 5.4 layout
+            InstructionFormat::IAx | InstructionFormat::IsJ => {
+                write!(f, " ax: {},", self.ax())?;
+            }
+        }
+
+        // Size hints of the array and hash parts, as floating point bytes
+        if opcode == "NEWTABLE" {
+            write!(f, " array: {}, hash: {},", fb2int(b), fb2int(c))?;
+        }
+
+        write!(f, " raw: {:08x}", self.0)?;
+        write!(f, ")")
+    }
+}
+
+impl FunctionPrototype {
+    /// The source name as lossy UTF-8
+    pub fn source(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.source_name.as_deref().unwrap_or_default())
+    }
+
+    /// Whether the word at `pc` is the batch number following a Lua 5.1 `SETLIST` with
+    /// C = 0, rather than an instruction
+    pub fn is_setlist_batch(&self, pc: usize) -> bool {
+        pc.checked_sub(1)
+            .and_then(|prev| self.code.get(prev))
+            .is_some_and(|prev| prev.op() == Opcode::SETLIST as u8 && prev.c() == 0)
+    }
+
+    /// Upvalues of the closure made by a Lua 5.1 `CLOSURE` at `pc`, bound by the MOVE
+    /// (a register here) or GETUPVAL (an upvalue here) words after it (lvm.c:OP_CLOSURE)
+    pub fn closure_upvalues(&self, pc: usize) -> Option<Vec<UpvalueDescriptor>> {
+        let instr = self.code.get(pc)?;
+        if instr.op() != Opcode::CLOSURE as u8 {
+            return None;
+        }
+        let child = self.prototypes.get(instr.bx() as usize)?;
+        let bindings = self
+            .code
+            .get(pc + 1..pc + 1 + usize::from(child.num_upvalues))?;
+        bindings
+            .iter()
+            .map(|binding| {
+                let instack = match Opcode::try_from(binding.op()) {
+                    Ok(Opcode::MOVE) => true,
+                    Ok(Opcode::GETUPVAL) => false,
+                    _ => return None,
+                };
+                Some(UpvalueDescriptor {
+                    instack,
+                    idx: binding.b() as u8,
+                    kind: 0,
+                })
+            })
+            .collect()
+    }
+
+    /// Whether the word at `pc` is an operand of an earlier Lua 5.1 instruction rather
+    /// than an instruction: a closure upvalue binding or a `SETLIST` batch number
+    pub fn is_pseudo_instruction(&self, pc: usize) -> bool {
+        let is_binding = |pc: usize| {
+            matches!(
+                Opcode::try_from(self.code[pc].op()),
+                Ok(Opcode::MOVE | Opcode::GETUPVAL)
+            )
+        };
+        if self.is_setlist_batch(pc) {
+            return true;
+        }
+        if pc >= self.code.len() || !is_binding(pc) {
+            return false;
+        }
+
+        // Bindings run back to their CLOSURE
+        let mut closure = pc;
+        while closure > 0 && is_binding(closure) {
+            closure -= 1;
+        }
+        self.closure_upvalues(closure)
+            .is_some_and(|upvalues| pc - closure <= upvalues.len())
+    }
+}
+
+impl Header {
+    /// Lua 5.1 header written by luac on common 64-bit hosts
+    pub const fn lua51() -> Self {
+        Self {
+            version: Version::Lua51,
+            format: 0,
+            endianness: Endianness::Little,
+            size_int: 4,
+            size_size_t: 8,
+            size_instruction: 4,
+            size_number: 8,
+            size_integer: 0,
+            integral_flag: false,
+            main_upvalues: 0,
+        }
+    }
+}
+
+impl LocalVariable {
+    /// The name as lossy UTF-8
+    pub fn name(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.varname)
+    }
+}
+
+impl DebugInfo {
+    /// Name of upvalue `index` as lossy UTF-8, if it has one
+    pub fn upvalue_name(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.upvalues
+            .get(index)
+            .map(|name| String::from_utf8_lossy(name))
+    }
+}
+
+impl Constant {
+    /// The string value as lossy UTF-8, if this is a string
+    pub fn as_str(&self) -> Option<Cow<'_, str>> {
+        match self {
+            Constant::String(bytes) => Some(String::from_utf8_lossy(bytes)),
+            _ => None,
+        }
+    }
+}
+
+impl Version {
+    /// Opcode table for this version, if it uses the 5.1 instruction layout
+    pub const fn opcodes(self) -> Option<&'static OpcodeTable> {
+        match self {
+            Version::Lua51 => Some(&LUA51_OPCODES),
+            Version::Lua52 => Some(&lua52::OPCODES),
+            Version::Lua53 => Some(&lua53::OPCODES),
+            Version::Lua54 => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode(op) => write!(f, "unknown opcode {op}"),
+            DecodeError::InvalidOperand {
+                opcode,
+                operand,
+                value,
+            } => write!(f, "{opcode:?} has invalid {operand} operand {value}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl std::fmt::Debug for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constant::Nil => write!(f, "Nil"),
+            Constant::Boolean(value) => f.debug_tuple("Boolean").field(value).finish(),
+            Constant::Number(value) => f.debug_tuple("Number").field(value).finish(),
+            Constant::Integer(value) => f.debug_tuple("Integer").field(value).finish(),
+            Constant::String(bytes) => f.debug_tuple("String").field(&Lossy(bytes)).finish(),
+        }
+    }
+}
+
+impl std::fmt::Debug for Lossy<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&String::from_utf8_lossy(self.0), f)
+    }
+}
+
+impl std::fmt::Debug for LocalVariable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalVariable")
+            .field("varname", &Lossy(&self.varname))
+            .field("startpc", &self.startpc)
+            .field("endpc", &self.endpc)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for DebugInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let upvalues: Vec<Lossy> = self.upvalues.iter().map(|name| Lossy(name)).collect();
+        f.debug_struct("DebugInfo")
+            .field("lineinfo", &self.lineinfo)
+            .field("abslineinfo", &self.abslineinfo)
+            .field("locals", &self.locals)
+            .field("upvalues", &upvalues)
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for FunctionPrototype {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FunctionPrototype")
+            .field("source_name", &self.source_name.as_deref().map(Lossy))
+            .field("line_defined", &self.line_defined)
+            .field("last_line_defined", &self.last_line_defined)
+            .field("num_upvalues", &self.num_upvalues)
+            .field("num_params", &self.num_params)
+            .field("is_vararg", &self.is_vararg)
+            .field("max_stack_size", &self.max_stack_size)
+            .field("code", &self.code)
+            .field("constants", &self.constants)
+            .field("prototypes", &self.prototypes)
+            .field("upvalue_descriptors", &self.upvalue_descriptors)
+            .field("debug_info", &self.debug_info)
+            .finish()
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with(f, &LUA51_OPCODES)
+    }
+}
+
+impl std::fmt::Display for VersionedInstruction<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.version.opcodes() {
+            Some(table) => self.instr.fmt_with(f, table),
+            None => write!(f, "{}", lua54::Instruction::from(self.instr)),
+        }
+    }
+}
+
+//////////////////////////////// Lookup Tables ////////////////////////////////
+
+#[rustfmt::skip]
+const OPMODES: [(InstructionFormat, OperandMask, OperandMask); TOTAL_OPS as usize] = [
+    /*    Opcode Format            Operand B            Operand C         */
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_MOVE
+    (InstructionFormat::IABx, OperandMask::OpArgK, OperandMask::OpArgN),  // OP_LOADK
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_LOADBOOL
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_LOADNIL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_GETUPVAL
+    (InstructionFormat::IABx, OperandMask::OpArgK, OperandMask::OpArgN),  // OP_GETGLOBAL
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgK),  // OP_GETTABLE
+    (InstructionFormat::IABx, OperandMask::OpArgK, OperandMask::OpArgN),  // OP_SETGLOBAL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_SETUPVAL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SETTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_NEWTABLE
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgK),  // OP_SELF
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_ADD
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_SUB
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_MUL
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_DIV
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_MOD
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_POW
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_UNM
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_NOT
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgN),  // OP_LEN
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgR),  // OP_CONCAT
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_JMP
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_EQ
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_LT
+    (InstructionFormat::IABC, OperandMask::OpArgK, OperandMask::OpArgK),  // OP_LE
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgU),  // OP_TEST
+    (InstructionFormat::IABC, OperandMask::OpArgR, OperandMask::OpArgU),  // OP_TESTSET
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_CALL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_TAILCALL
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_RETURN
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_FORLOOP
+    (InstructionFormat::IAsBx, OperandMask::OpArgR, OperandMask::OpArgN), // OP_FORPREP
+    (InstructionFormat::IABC, OperandMask::OpArgN, OperandMask::OpArgU),  // OP_TFORLOOP
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgU),  // OP_SETLIST
+    (InstructionFormat::IABC, OperandMask::OpArgN, OperandMask::OpArgN),  // OP_CLOSE
+    (InstructionFormat::IABx, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_CLOSURE
+    (InstructionFormat::IABC, OperandMask::OpArgU, OperandMask::OpArgN),  // OP_VARARG
+];
+
+#[rustfmt::skip]
+const OPNAMES: [&str; TOTAL_OPS as usize] = [
+    "MOVE",     "LOADK",     "LOADBOOL", "LOADNIL",
+    "GETUPVAL", "GETGLOBAL", "GETTABLE", "SETGLOBAL",
+    "SETUPVAL", "SETTABLE",  "NEWTABLE", "SELF",
+    "ADD",      "SUB",       "MUL",      "DIV",
+    "MOD",      "POW",       "UNM",      "NOT",
+    "LEN",      "CONCAT",    "JMP",      "EQ",
+    "LT",       "LE",        "TEST",     "TESTSET",
+    "CALL",     "TAILCALL",  "RETURN",   "FORLOOP",
+    "FORPREP",  "TFORLOOP",  "SETLIST",  "CLOSE",
+    "CLOSURE",  "VARARG",
+];
+
+pub const LUA51_OPCODES: OpcodeTable = OpcodeTable {
+    names: &OPNAMES,
+    modes: &OPMODES,
+};
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Decodes a "floating point byte" `eeeeexxx`, `(1xxx) * 2^(eeeee - 1)` when the
+/// exponent is non-zero (lobject.c:luaO_fb2int)
+pub const fn fb2int(x: u32) -> u32 {
+    let e = (x >> 3) & 31;
+    match e {
+        0 => x,
+        _ => ((x & 7) + 8) << (e - 1),
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_floating_point_bytes() {
+        // lobject.c:luaO_int2fb rounds up, so the hint is at least the size asked for
+        assert_eq!(fb2int(0), 0);
+        assert_eq!(fb2int(7), 7);
+        assert_eq!(fb2int(8), 8);
+        assert_eq!(fb2int(15), 15);
+        assert_eq!(fb2int(20), 24);
+        assert_eq!(fb2int(26), 40);
+        assert_eq!(fb2int(0xff), 15 << 30);
+    }
+
+    #[test]
+    fn shows_newtable_size_hints() {
+        // NEWTABLE 0 20 26
+        let instr = Instruction::new(10 | (26 << 14) | (20 << 23));
+        let text = instr.display(Version::Lua51).to_string();
+        assert!(text.contains("opname: NEWTABLE"), "{text}");
+        assert!(text.contains(" array: 24, hash: 40,"), "{text}");
+    }
+
+    #[test]
+    fn binds_closure_upvalues() {
+        let proto = crate::assembler::assemble(
+            r#"
+            .func
+                .upvals 2 "a" "b"
+                    RETURN    0 1
+            .end
+                LOADNIL   0 0
+                CLOSURE   1 0
+                MOVE      0 0
+                GETUPVAL  0 3
+                RETURN    0 1
+            "#,
+        )
+        .expect("valid listing");
+
+        let upvalues = proto.closure_upvalues(1).expect("a CLOSURE with bindings");
+        let bindings: Vec<(bool, u8)> = upvalues.iter().map(|u| (u.instack, u.idx)).collect();
+        assert_eq!(bindings, [(true, 0), (false, 3)]);
+        assert_eq!(proto.closure_upvalues(0), None);
+
+        let pseudo: Vec<bool> = (0..5).map(|pc| proto.is_pseudo_instruction(pc)).collect();
+        assert_eq!(pseudo, [false, false, true, true, false]);
+    }
+}
@@ -0,0 +1,204 @@
+/*
+  Error types for the bytecode parsers
+*/
+
+use nom::IResult;
+use std::fmt;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// The reason parsing stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    BadMagic,
+    BadTail,
+    EndiannessMismatch,
+    FloatFormatMismatch,
+    UnsupportedVersion(u8),
+    UnsupportedFormat(u8),
+    InvalidSize { field: &'static str, value: u8 },
+    UnknownConstantTag(u8),
+    InvalidLength(i64),
+    MissingTerminator,
+    ValueTooLarge,
+    Truncated,
+    TrailingBytes(usize),
+    MissingChildPrototype,
+    UnbalancedPrototypes,
+    InvalidReference { field: &'static str, index: u32 },
+    CompileError(String),
+    Malformed(nom::error::ErrorKind),
+}
+
+/// One step of the path leading to the failing element
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Field(&'static str),
+    Index(&'static str, usize),
+}
+
+/// Error returned by `parse_lua_bytecode`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,   // What went wrong
+    pub offset: usize,          // Absolute byte offset in the input file
+    pub path: Vec<PathSegment>, // Outermost segment first (e.g. main > proto[3])
+}
+
+/// Error threaded through the nom parsers, converted into a `ParseError` at the top level
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputError<'a> {
+    pub input: &'a [u8],
+    pub kind: ParseErrorKind,
+    pub path: Vec<PathSegment>, // Innermost segment first, reversed once finished
+}
+
+pub type PResult<'a, T> = IResult<&'a [u8], T, InputError<'a>>;
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl<'a> InputError<'a> {
+    pub fn new(input: &'a [u8], kind: ParseErrorKind) -> Self {
+        Self {
+            input,
+            kind,
+            path: Vec::new(),
+        }
+    }
+
+    /// Converts into a `ParseError` whose offset is relative to `origin`
+    pub fn finish(self, origin: &[u8]) -> ParseError {
+        let start = origin.as_ptr() as usize;
+        let offset = (self.input.as_ptr() as usize)
+            .saturating_sub(start)
+            .min(origin.len());
+        let mut path = self.path;
+        path.reverse();
+
+        ParseError {
+            kind: self.kind,
+            offset,
+            path,
+        }
+    }
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for InputError<'a> {
+    fn from_error_kind(input: &'a [u8], kind: nom::error::ErrorKind) -> Self {
+        let kind = match kind {
+            nom::error::ErrorKind::Eof | nom::error::ErrorKind::Complete => {
+                ParseErrorKind::Truncated
+            }
+            nom::error::ErrorKind::TooLarge => ParseErrorKind::ValueTooLarge,
+            other => ParseErrorKind::Malformed(other),
+        };
+        Self::new(input, kind)
+    }
+
+    fn append(_: &'a [u8], _: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> nom::error::ContextError<&'a [u8]> for InputError<'a> {
+    fn add_context(_: &'a [u8], ctx: &'static str, mut other: Self) -> Self {
+        other.path.push(PathSegment::Field(ctx));
+        other
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::BadMagic => write!(f, "invalid magic number"),
+            ParseErrorKind::BadTail => write!(f, "corrupted header tail (LUAC_TAIL mismatch)"),
+            ParseErrorKind::EndiannessMismatch => write!(f, "endianness mismatch (LUAC_INT)"),
+            ParseErrorKind::FloatFormatMismatch => write!(f, "float format mismatch (LUAC_NUM)"),
+            ParseErrorKind::UnsupportedVersion(v) => write!(f, "unsupported Lua version 0x{v:02x}"),
+            ParseErrorKind::UnsupportedFormat(v) => {
+                write!(f, "unsupported format {v} (must be 0 (official))")
+            }
+            ParseErrorKind::InvalidSize { field, value } => {
+                write!(f, "invalid {field} size {value}")
+            }
+            ParseErrorKind::UnknownConstantTag(t) => write!(f, "unknown constant tag 0x{t:02x}"),
+            ParseErrorKind::InvalidLength(n) => write!(f, "invalid section length {n}"),
+            ParseErrorKind::MissingTerminator => write!(f, "string is missing its NUL terminator"),
+            ParseErrorKind::ValueTooLarge => write!(f, "value is too large"),
+            ParseErrorKind::Truncated => write!(f, "unexpected end of input"),
+            ParseErrorKind::TrailingBytes(n) => {
+                write!(f, "{n} trailing byte(s) after the main function")
+            }
+            ParseErrorKind::MissingChildPrototype => {
+                write!(f, "child constant without a preceding prototype")
+            }
+            ParseErrorKind::UnbalancedPrototypes => {
+                write!(f, "prototypes do not form a single tree")
+            }
+            ParseErrorKind::InvalidReference { field, index } => {
+                write!(f, "{field} reference {index} is out of range")
+            }
+            ParseErrorKind::CompileError(message) => {
+                write!(f, "chunk holds a compile error: {message}")
+            }
+            ParseErrorKind::Malformed(kind) => write!(f, "malformed input ({kind:?})"),
+        }
+    }
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Field(name) => write!(f, "{name}"),
+            PathSegment::Index(name, index) => write!(f, "{name}[{index}]"),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset 0x{:x}", self.kind, self.offset)?;
+        if !self.path.is_empty() {
+            write!(f, " (")?;
+            for (i, segment) in self.path.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " > ")?;
+                }
+                write!(f, "{segment}")?;
+            }
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Fails parsing at `input` with the given kind
+pub fn fail<T>(input: &[u8], kind: ParseErrorKind) -> PResult<'_, T> {
+    Err(nom::Err::Failure(InputError::new(input, kind)))
+}
+
+/// Records `segment` on the path of any error coming out of `result`
+pub fn within<T>(segment: PathSegment, result: PResult<'_, T>) -> PResult<'_, T> {
+    result.map_err(|err| {
+        err.map(|mut e| {
+            e.path.push(segment);
+            e
+        })
+    })
+}
+
+/// Converts a parser result into a `ParseError` located relative to `origin`
+pub fn finish<'a, T>(origin: &[u8], result: PResult<'a, T>) -> Result<(&'a [u8], T), ParseError> {
+    result.map_err(|err| match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => e.finish(origin),
+        nom::Err::Incomplete(_) => ParseError {
+            kind: ParseErrorKind::Truncated,
+            offset: origin.len(),
+            path: Vec::new(),
+        },
+    })
+}
@@ -0,0 +1,497 @@
+/*
+  Format detection and the registry of dialect front ends
+*/
+
+use super::bytecode::luajit::{self, DumpVersion};
+use super::bytecode::{Endianness, FunctionPrototype, Header, Version, lua52, lua53, luau};
+use super::error::{ParseError, ParseErrorKind};
+use super::parsers::luau::parse_luau_header;
+use super::parsers::parsers::parse_uleb128;
+use super::{parse_lua_bytecode, parse_luajit_bytecode, parse_luau_bytecode};
+use std::fmt;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+const LUA_SIGNATURE: &[u8] = b"\x1BLua";
+
+// lundump.h:VERSION (Lua 5.0)
+const LUA50_VERSION: u8 = 0x50;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// A bytecode dialect, as recognized from the leading bytes of a file
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Dialect {
+    Lua50,
+    Lua(Version),
+    LuaJIT(DumpVersion),
+    Luau(u8), // Bytecode version, 0 for a stored compile error
+    Unknown,
+}
+
+/// What `detect` found out about a file. Sizes the format does not record are 0
+#[derive(Debug, PartialEq, Clone)]
+pub struct DetectedFormat {
+    pub dialect: Dialect,
+    pub format: u8,                     // Format byte, 0 for official builds
+    pub endianness: Option<Endianness>, // None when the header does not settle it
+    pub size_int: u8,
+    pub size_size_t: u8,
+    pub size_instruction: u8,
+    pub size_number: u8,
+    pub size_integer: u8,
+}
+
+/// A parsed chunk of any supported dialect
+#[derive(Debug)]
+pub enum ParsedChunk {
+    Lua(Header, FunctionPrototype),
+    LuaJIT(luajit::Header, luajit::Prototype),
+    Luau(luau::Header, luau::Chunk),
+}
+
+/// A dialect plugged into the registry: it recognizes its own files and parses them
+pub trait Frontend {
+    /// Short name of the front end (e.g. "luajit")
+    fn name(&self) -> &'static str;
+
+    /// Inspects the leading bytes, returning `None` if the file is not of this dialect
+    fn detect(&self, input: &[u8]) -> Option<DetectedFormat>;
+
+    /// Parses the header and every function of a whole file
+    fn parse(&self, input: &[u8]) -> Result<ParsedChunk, ParseError>;
+}
+
+/// Official Lua 5.0 to 5.4
+pub struct LuaFrontend;
+
+/// LuaJIT 2.0 and 2.1
+pub struct LuaJITFrontend;
+
+/// Luau, which has no signature and is tried last
+pub struct LuauFrontend;
+
+/// Front ends in the order they are tried
+pub struct Registry {
+    frontends: Vec<Box<dyn Frontend>>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl Registry {
+    /// An empty registry
+    pub fn new() -> Self {
+        Self {
+            frontends: Vec::new(),
+        }
+    }
+
+    /// Adds a front end, tried after the ones already registered
+    pub fn register(&mut self, frontend: Box<dyn Frontend>) {
+        self.frontends.push(frontend);
+    }
+
+    pub fn frontends(&self) -> impl Iterator<Item = &dyn Frontend> {
+        self.frontends.iter().map(|f| f.as_ref())
+    }
+
+    /// Returns the first front end recognizing `input`, along with what it detected
+    pub fn find(&self, input: &[u8]) -> Option<(&dyn Frontend, DetectedFormat)> {
+        self.frontends()
+            .find_map(|frontend| frontend.detect(input).map(|format| (frontend, format)))
+    }
+
+    pub fn detect(&self, input: &[u8]) -> DetectedFormat {
+        match self.find(input) {
+            Some((_, format)) => format,
+            None => DetectedFormat::new(Dialect::Unknown),
+        }
+    }
+
+    /// Parses `input` with the front end that recognizes it
+    pub fn parse(&self, input: &[u8]) -> Result<ParsedChunk, ParseError> {
+        match self.find(input) {
+            Some((frontend, _)) => frontend.parse(input),
+            None => Err(ParseError {
+                kind: ParseErrorKind::BadMagic,
+                offset: 0,
+                path: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl Default for Registry {
+    /// The built-in front ends
+    fn default() -> Self {
+        let mut registry = Registry::new();
+        registry.register(Box::new(LuaFrontend));
+        registry.register(Box::new(LuaJITFrontend));
+        registry.register(Box::new(LuauFrontend));
+        registry
+    }
+}
+
+impl DetectedFormat {
+    fn new(dialect: Dialect) -> Self {
+        Self {
+            dialect,
+            format: 0,
+            endianness: None,
+            size_int: 0,
+            size_size_t: 0,
+            size_instruction: 0,
+            size_number: 0,
+            size_integer: 0,
+        }
+    }
+
+    /// Whether the header deviates from what the official tools write
+    pub fn is_non_standard(&self) -> bool {
+        self.format != 0
+    }
+}
+
+impl Frontend for LuaFrontend {
+    fn name(&self) -> &'static str {
+        "lua"
+    }
+
+    fn detect(&self, input: &[u8]) -> Option<DetectedFormat> {
+        let rest = input.strip_prefix(LUA_SIGNATURE)?;
+        let (&version, rest) = rest.split_first()?;
+        if version == LUA50_VERSION {
+            return detect_lua50(rest);
+        }
+
+        let version = Version::try_from(version).ok()?;
+        let mut detected = DetectedFormat::new(Dialect::Lua(version));
+        match version {
+            Version::Lua51 | Version::Lua52 => {
+                // lundump.c:luaU_header
+                let [format, endianness, int, size_t, instruction, number, ..] = *rest else {
+                    return Some(detected);
+                };
+                detected.format = format;
+                detected.endianness = Some(byte_order(endianness));
+                detected.size_int = int;
+                detected.size_size_t = size_t;
+                detected.size_instruction = instruction;
+                detected.size_number = number;
+            }
+            Version::Lua53 => {
+                // lundump.c:checkHeader
+                let tail = lua52::LUAC_TAIL.len();
+                let Some(&format) = rest.first() else {
+                    return Some(detected);
+                };
+                detected.format = format;
+                let Some(&[int, size_t, instruction, integer, number]) =
+                    rest.get(1 + tail..6 + tail)
+                else {
+                    return Some(detected);
+                };
+                detected.size_int = int;
+                detected.size_size_t = size_t;
+                detected.size_instruction = instruction;
+                detected.size_integer = integer;
+                detected.size_number = number;
+                detected.endianness = luac_int_order(rest.get(6 + tail..), integer);
+            }
+            Version::Lua54 => {
+                let tail = lua52::LUAC_TAIL.len();
+                let Some(&format) = rest.first() else {
+                    return Some(detected);
+                };
+                detected.format = format;
+                let Some(&[instruction, integer, number]) = rest.get(1 + tail..4 + tail) else {
+                    return Some(detected);
+                };
+                detected.size_instruction = instruction;
+                detected.size_integer = integer;
+                detected.size_number = number;
+                detected.endianness = luac_int_order(rest.get(4 + tail..), integer);
+            }
+        }
+        Some(detected)
+    }
+
+    fn parse(&self, input: &[u8]) -> Result<ParsedChunk, ParseError> {
+        parse_lua_bytecode(input).map(|(header, main)| ParsedChunk::Lua(header, main))
+    }
+}
+
+impl Frontend for LuaJITFrontend {
+    fn name(&self) -> &'static str {
+        "luajit"
+    }
+
+    fn detect(&self, input: &[u8]) -> Option<DetectedFormat> {
+        let rest = input.strip_prefix(luajit::MAGIC_NUMBER)?;
+        let (&version, rest) = rest.split_first()?;
+        let version = DumpVersion::try_from(version).ok()?;
+
+        let mut detected = DetectedFormat::new(Dialect::LuaJIT(version));
+        detected.size_instruction = 4;
+        detected.size_number = 8;
+        if let Ok((_, flags)) = parse_uleb128(rest) {
+            detected.endianness = Some(match flags & luajit::FLAG_BE {
+                0 => Endianness::Little,
+                _ => Endianness::Big,
+            });
+        }
+        Some(detected)
+    }
+
+    fn parse(&self, input: &[u8]) -> Result<ParsedChunk, ParseError> {
+        parse_luajit_bytecode(input).map(|(header, main)| ParsedChunk::LuaJIT(header, main))
+    }
+}
+
+impl Frontend for LuauFrontend {
+    fn name(&self) -> &'static str {
+        "luau"
+    }
+
+    fn detect(&self, input: &[u8]) -> Option<DetectedFormat> {
+        // Without a signature, the rest of the header has to make sense too
+        let version = match *input {
+            [0, ref message @ ..] if is_message(message) => 0,
+            [version @ luau::VERSION_MIN..=luau::VERSION_MAX, ..] => {
+                let (rest, _) = parse_luau_header(input).ok()?;
+                parse_uleb128(rest).ok()?;
+                version
+            }
+            _ => return None,
+        };
+
+        let mut detected = DetectedFormat::new(Dialect::Luau(version));
+        detected.endianness = Some(Endianness::Little);
+        detected.size_instruction = 4;
+        detected.size_number = 8;
+        Some(detected)
+    }
+
+    fn parse(&self, input: &[u8]) -> Result<ParsedChunk, ParseError> {
+        parse_luau_bytecode(input).map(|(header, chunk)| ParsedChunk::Luau(header, chunk))
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dialect::Lua50 => write!(f, "Lua 5.0"),
+            Dialect::Lua(version) => {
+                let version = *version as u8;
+                write!(f, "Lua {}.{}", version >> 4, version & 0xf)
+            }
+            Dialect::LuaJIT(DumpVersion::V1) => write!(f, "LuaJIT 2.0"),
+            Dialect::LuaJIT(DumpVersion::V2) => write!(f, "LuaJIT 2.1"),
+            Dialect::Luau(0) => write!(f, "Luau (compile error)"),
+            Dialect::Luau(version) => write!(f, "Luau v{version}"),
+            Dialect::Unknown => write!(f, "unknown format"),
+        }
+    }
+}
+
+impl fmt::Display for DetectedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.dialect)?;
+        if self.is_non_standard() {
+            write!(f, " (non-standard format {})", self.format)?;
+        }
+        match self.endianness {
+            Some(Endianness::Little) => write!(f, ", little endian")?,
+            Some(Endianness::Big) => write!(f, ", big endian")?,
+            None => write!(f, ", unknown endianness")?,
+        }
+
+        let sizes = [
+            ("int", self.size_int),
+            ("size_t", self.size_size_t),
+            ("instruction", self.size_instruction),
+            ("integer", self.size_integer),
+            ("number", self.size_number),
+        ];
+        for (name, size) in sizes {
+            if size != 0 {
+                write!(f, ", {name} {size}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Recognizes the format of `input` with the built-in front ends
+pub fn detect(input: &[u8]) -> DetectedFormat {
+    Registry::default().detect(input)
+}
+
+/// Parses `input` with whichever built-in front end recognizes it
+pub fn parse_any(input: &[u8]) -> Result<ParsedChunk, ParseError> {
+    Registry::default().parse(input)
+}
+
+/// Whether the text after a Luau version of 0 reads like a compile error message
+fn is_message(bytes: &[u8]) -> bool {
+    !bytes.is_empty()
+        && std::str::from_utf8(bytes).is_ok()
+        && bytes
+            .iter()
+            .all(|&b| b >= 0x20 || matches!(b, b'\t' | b'\n' | b'\r'))
+}
+
+const fn byte_order(flag: u8) -> Endianness {
+    match flag {
+        1 => Endianness::Little,
+        _ => Endianness::Big,
+    }
+}
+
+/// Lua 5.0 layout (lundump.c:LoadHeader), which has no format byte
+fn detect_lua50(rest: &[u8]) -> Option<DetectedFormat> {
+    let mut detected = DetectedFormat::new(Dialect::Lua50);
+    if let [
+        endianness,
+        int,
+        size_t,
+        instruction,
+        _op,
+        _a,
+        _b,
+        _c,
+        number,
+        ..,
+    ] = *rest
+    {
+        detected.endianness = Some(byte_order(endianness));
+        detected.size_int = int;
+        detected.size_size_t = size_t;
+        detected.size_instruction = instruction;
+        detected.size_number = number;
+    }
+    Some(detected)
+}
+
+/// Deduces the byte order from the LUAC_INT check value of Lua 5.3+
+fn luac_int_order(bytes: Option<&[u8]>, size_integer: u8) -> Option<Endianness> {
+    let bytes = bytes?.get(..size_integer as usize)?;
+    if bytes.len() > 8 {
+        return None;
+    }
+    let mut little = [0u8; 8];
+    let mut big = [0u8; 8];
+    little[..bytes.len()].copy_from_slice(bytes);
+    big[8 - bytes.len()..].copy_from_slice(bytes);
+
+    if i64::from_le_bytes(little) == lua53::LUAC_INT {
+        Some(Endianness::Little)
+    } else if i64::from_be_bytes(big) == lua53::LUAC_INT {
+        Some(Endianness::Big)
+    } else {
+        None
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialect(input: &[u8]) -> Dialect {
+        Registry::default().detect(input).dialect
+    }
+
+    fn concat(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn detects_lua_50_and_51() {
+        let lua50 = b"\x1bLua\x50\x01\x04\x08\x04\x06\x08\x09\x09\x08";
+        let detected = Registry::default().detect(lua50);
+        assert_eq!(detected.dialect, Dialect::Lua50);
+        assert_eq!(detected.size_size_t, 8);
+
+        let lua51 = b"\x1bLua\x51\x00\x00\x04\x04\x04\x08\x00";
+        let detected = Registry::default().detect(lua51);
+        assert_eq!(detected.dialect, Dialect::Lua(Version::Lua51));
+        assert_eq!(detected.endianness, Some(Endianness::Big));
+        assert_eq!(
+            (
+                detected.size_int,
+                detected.size_size_t,
+                detected.size_number
+            ),
+            (4, 4, 8)
+        );
+    }
+
+    #[test]
+    fn detects_lua_52_to_54() {
+        let lua52 = concat(&[b"\x1bLua\x52\x00\x01\x04\x08\x04\x08\x00", lua52::LUAC_TAIL]);
+        assert_eq!(dialect(&lua52), Dialect::Lua(Version::Lua52));
+
+        let int = lua53::LUAC_INT.to_le_bytes();
+        let num = lua53::LUAC_NUM.to_le_bytes();
+        let lua53 = concat(&[
+            b"\x1bLua\x53\x00",
+            lua52::LUAC_TAIL,
+            &[4, 8, 4, 8, 8],
+            &int,
+            &num,
+        ]);
+        let detected = Registry::default().detect(&lua53);
+        assert_eq!(detected.dialect, Dialect::Lua(Version::Lua53));
+        assert_eq!(detected.endianness, Some(Endianness::Little));
+        assert_eq!(detected.size_integer, 8);
+
+        let int = lua53::LUAC_INT.to_be_bytes();
+        let lua54 = concat(&[b"\x1bLua\x54\x00", lua52::LUAC_TAIL, &[4, 8, 8], &int]);
+        let detected = Registry::default().detect(&lua54);
+        assert_eq!(detected.dialect, Dialect::Lua(Version::Lua54));
+        assert_eq!(detected.endianness, Some(Endianness::Big));
+    }
+
+    #[test]
+    fn detects_luajit() {
+        assert_eq!(dialect(b"\x1bLJ\x01\x02"), Dialect::LuaJIT(DumpVersion::V1));
+        let detected = Registry::default().detect(b"\x1bLJ\x02\x0b");
+        assert_eq!(detected.dialect, Dialect::LuaJIT(DumpVersion::V2));
+        assert_eq!(detected.endianness, Some(Endianness::Big));
+        assert_eq!(dialect(b"\x1bLJ\x03\x02"), Dialect::Unknown);
+    }
+
+    #[test]
+    fn detects_luau() {
+        // Version, types version from 4 on, string table, then the prototype count
+        assert_eq!(dialect(b"\x03\x01\x01x\x00"), Dialect::Luau(3));
+        assert_eq!(
+            dialect(b"\x06\x03\x02\x01a\x02bc\x00\x01"),
+            Dialect::Luau(6)
+        );
+        assert_eq!(dialect(b"\x00:1: Expected identifier"), Dialect::Luau(0));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        let garbage: [&[u8]; 10] = [
+            b"",
+            b"hello",
+            b"\x1bLu",
+            b"\x1bLua\x60\x00",
+            b"\x00",
+            b"\x00\xff\xfe",
+            b"\x00\x01\x02\x03",
+            b"\x03\x05\x01a",    // Five strings, one present
+            b"\x04\x09\x00\x00", // Types version 9
+            b"\x03\x80\x80\x80", // Unterminated varint
+        ];
+        for input in garbage {
+            assert_eq!(dialect(input), Dialect::Unknown, "{input:?}");
+        }
+    }
+}
@@ -1,24 +1,334 @@
 pub mod bytecode;
+pub mod error;
+pub mod frontend;
 pub mod parsers;
 
-use bytecode::{FunctionPrototype, Header};
+use bytecode::{FunctionPrototype, Header, luajit, luau};
+use error::{PathSegment, finish, within};
 
+pub use error::{ParseError, ParseErrorKind};
+pub use frontend::{DetectedFormat, Dialect, Frontend, ParsedChunk, Registry, detect, parse_any};
 pub use parsers::function::parse_function;
 pub use parsers::header::parse_header;
+pub use parsers::luajit::parse_luajit;
+pub use parsers::luau::parse_luau;
 
-pub fn parse_lua_bytecode(
-    input: &[u8],
-) -> Result<(Header, FunctionPrototype), nom::Err<nom::error::Error<&[u8]>>> {
-    let (input, header) = parse_header(input)?;
-    let (input, prototype) = parse_function(input, &header)?;
+pub fn parse_lua_bytecode(input: &[u8]) -> Result<(Header, FunctionPrototype), ParseError> {
+    let origin = input;
+    let (input, header) = finish(
+        origin,
+        within(PathSegment::Field("header"), parse_header(input)),
+    )?;
+    let (input, prototype) = finish(
+        origin,
+        within(PathSegment::Field("main"), parse_function(input, &header)),
+    )?;
 
     // Check for any remaining bytes after parsing
     if !input.is_empty() {
-        return Err(nom::Err::Error(nom::error::Error::new(
-            input,
-            nom::error::ErrorKind::Eof,
-        )));
+        return Err(ParseError {
+            kind: ParseErrorKind::TrailingBytes(input.len()),
+            offset: origin.len() - input.len(),
+            path: Vec::new(),
+        });
     };
 
     Ok((header, prototype))
 }
+
+pub fn parse_luajit_bytecode(
+    input: &[u8],
+) -> Result<(luajit::Header, luajit::Prototype), ParseError> {
+    let origin = input;
+    let (input, (header, prototype)) = finish(origin, parse_luajit(input))?;
+
+    if !input.is_empty() {
+        return Err(ParseError {
+            kind: ParseErrorKind::TrailingBytes(input.len()),
+            offset: origin.len() - input.len(),
+            path: Vec::new(),
+        });
+    };
+
+    Ok((header, prototype))
+}
+
+pub fn parse_luau_bytecode(input: &[u8]) -> Result<(luau::Header, luau::Chunk), ParseError> {
+    let origin = input;
+    let (input, (header, chunk)) = finish(origin, parse_luau(input))?;
+
+    if !input.is_empty() {
+        return Err(ParseError {
+            kind: ParseErrorKind::TrailingBytes(input.len()),
+            offset: origin.len() - input.len(),
+            path: Vec::new(),
+        });
+    };
+
+    Ok((header, chunk))
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytecode::{Constant, Endianness};
+
+    /// Layout of a Lua 5.1 dump, as given by its header
+    struct Layout {
+        little: bool,
+        size_int: u8,
+        size_size_t: u8,
+        size_number: u8,
+        integral: bool,
+    }
+
+    impl Layout {
+        fn word(&self, out: &mut Vec<u8>, width: u8, value: u64) {
+            let bytes = match self.little {
+                true => value.to_le_bytes(),
+                false => value.to_be_bytes(),
+            };
+            match (width, self.little) {
+                (4, true) => out.extend(&bytes[..4]),
+                (4, false) => out.extend(&bytes[4..]),
+                _ => out.extend(bytes),
+            }
+        }
+
+        fn int(&self, out: &mut Vec<u8>, value: i32) {
+            self.word(out, self.size_int, value as i64 as u64);
+        }
+
+        fn string(&self, out: &mut Vec<u8>, text: &str) {
+            self.word(out, self.size_size_t, text.len() as u64 + 1);
+            out.extend(text.as_bytes());
+            out.push(0);
+        }
+
+        /// `local x = 3 return x` with a number constant of 3
+        fn chunk(&self) -> Vec<u8> {
+            let mut out = b"\x1bLua\x51\x00".to_vec();
+            out.push(u8::from(self.little));
+            out.extend([self.size_int, self.size_size_t, 4, self.size_number]);
+            out.push(u8::from(self.integral));
+
+            self.string(&mut out, "@test.lua");
+            self.int(&mut out, 0);
+            self.int(&mut out, 0);
+            out.extend([0, 0, 2, 2]);
+
+            self.int(&mut out, 3);
+            for word in [0x0000_0001, 0x0100_001e, 0x0080_001e] {
+                self.word(&mut out, 4, word);
+            }
+
+            self.int(&mut out, 1);
+            out.push(3);
+            match (self.integral, self.size_number) {
+                (true, width) => self.word(&mut out, width, 3),
+                (false, 4) => self.word(&mut out, 4, u64::from(3f32.to_bits())),
+                (false, _) => self.word(&mut out, 8, 3f64.to_bits()),
+            }
+
+            self.int(&mut out, 0);
+            self.int(&mut out, 3);
+            for _ in 0..3 {
+                self.int(&mut out, 1);
+            }
+            self.int(&mut out, 1);
+            self.string(&mut out, "x");
+            self.int(&mut out, 1);
+            self.int(&mut out, 3);
+            self.int(&mut out, 0);
+            out
+        }
+    }
+
+    fn check(layout: Layout) {
+        let (header, main) = parse_lua_bytecode(&layout.chunk()).expect("valid chunk");
+        let endianness = match layout.little {
+            true => Endianness::Little,
+            false => Endianness::Big,
+        };
+        assert_eq!(header.endianness, endianness);
+        assert_eq!(header.size_int, layout.size_int);
+        assert_eq!(header.size_size_t, layout.size_size_t);
+        assert_eq!(header.size_number, layout.size_number);
+        assert_eq!(header.integral_flag, layout.integral);
+
+        assert_eq!(main.source_name.as_deref(), Some(&b"@test.lua"[..]));
+        assert_eq!(main.max_stack_size, 2);
+        let code: Vec<u32> = main.code.iter().map(|i| i.raw()).collect();
+        assert_eq!(code, [0x0000_0001, 0x0100_001e, 0x0080_001e]);
+        match (layout.integral, &main.constants[..]) {
+            (true, [Constant::Integer(3)]) => {}
+            (false, [Constant::Number(value)]) if *value == 3.0 => {}
+            (_, constants) => panic!("unexpected constants {constants:?}"),
+        }
+        assert_eq!(main.debug_info.lineinfo, [1, 1, 1]);
+        let local = &main.debug_info.locals[0];
+        assert_eq!(
+            (local.varname.as_slice(), local.startpc, local.endpc),
+            (&b"x"[..], 1, 3)
+        );
+    }
+
+    #[test]
+    fn parses_x86_64_layout() {
+        check(Layout {
+            little: true,
+            size_int: 4,
+            size_size_t: 8,
+            size_number: 8,
+            integral: false,
+        });
+    }
+
+    #[test]
+    fn parses_32_bit_size_t() {
+        check(Layout {
+            little: true,
+            size_int: 4,
+            size_size_t: 4,
+            size_number: 8,
+            integral: false,
+        });
+    }
+
+    #[test]
+    fn parses_big_endian_layout() {
+        check(Layout {
+            little: false,
+            size_int: 4,
+            size_size_t: 4,
+            size_number: 8,
+            integral: false,
+        });
+    }
+
+    #[test]
+    fn parses_float_numbers() {
+        check(Layout {
+            little: true,
+            size_int: 4,
+            size_size_t: 4,
+            size_number: 4,
+            integral: false,
+        });
+    }
+
+    #[test]
+    fn parses_int32_numbers() {
+        check(Layout {
+            little: true,
+            size_int: 4,
+            size_size_t: 4,
+            size_number: 4,
+            integral: true,
+        });
+    }
+
+    #[test]
+    fn parses_int64_numbers_and_ints() {
+        check(Layout {
+            little: true,
+            size_int: 8,
+            size_size_t: 8,
+            size_number: 8,
+            integral: true,
+        });
+    }
+
+    #[test]
+    fn rejects_unsupported_instruction_size() {
+        let mut chunk = Layout {
+            little: true,
+            size_int: 4,
+            size_size_t: 8,
+            size_number: 8,
+            integral: false,
+        }
+        .chunk();
+        chunk[9] = 8;
+        let err = parse_lua_bytecode(&chunk).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ParseErrorKind::InvalidSize {
+                field: "instruction",
+                value: 8
+            }
+        );
+        assert_eq!(err.offset, 9);
+    }
+
+    fn x86_64_chunk() -> Vec<u8> {
+        Layout {
+            little: true,
+            size_int: 4,
+            size_size_t: 8,
+            size_number: 8,
+            integral: false,
+        }
+        .chunk()
+    }
+
+    #[test]
+    fn reports_bad_magic_in_the_header() {
+        let mut chunk = x86_64_chunk();
+        chunk[1] = b'l';
+        let err = parse_lua_bytecode(&chunk).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::BadMagic);
+        assert_eq!(err.offset, 0);
+        assert_eq!(
+            err.to_string(),
+            "invalid magic number at offset 0x0 (header)"
+        );
+    }
+
+    #[test]
+    fn reports_truncated_constant() {
+        // Header 12, source 18, line range 8, sizes 4, code 4 + 12 and the constant
+        // count put K0's tag at 62 and its number at 63
+        let chunk = &x86_64_chunk()[..66];
+        let err = parse_lua_bytecode(chunk).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::Truncated);
+        assert_eq!(err.offset, 63);
+        assert_eq!(
+            err.path,
+            [
+                PathSegment::Field("main"),
+                PathSegment::Index("constants", 0)
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "unexpected end of input at offset 0x3f (main > constants[0])"
+        );
+    }
+
+    #[test]
+    fn reports_unknown_constant_tag() {
+        let mut chunk = x86_64_chunk();
+        chunk[62] = 9;
+        let err = parse_lua_bytecode(&chunk).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnknownConstantTag(9));
+        assert_eq!(err.offset, 62);
+        assert_eq!(
+            err.to_string(),
+            "unknown constant tag 0x09 at offset 0x3e (main > constants[0])"
+        );
+    }
+
+    #[test]
+    fn reports_trailing_bytes() {
+        let mut chunk = x86_64_chunk();
+        let end = chunk.len();
+        chunk.extend([0, 0]);
+        let err = parse_lua_bytecode(&chunk).unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::TrailingBytes(2));
+        assert_eq!(err.offset, end);
+        assert!(err.path.is_empty());
+    }
+}
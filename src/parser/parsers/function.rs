@@ -1,9 +1,12 @@
 use super::super::bytecode::FunctionPrototype;
-use super::super::bytecode::Header;
-use super::super::bytecode::{DebugInfo, LocalVariable};
-use super::parsers::{parse_constant, parse_instruction, parse_integer, parse_string};
+use super::super::bytecode::{AbsLineInfo, DebugInfo, LocalVariable, UpvalueDescriptor, lua54};
+use super::super::bytecode::{Header, Version};
+use super::super::error::{PResult, ParseErrorKind, PathSegment, fail, within};
+use super::parsers::{
+    parse_constant, parse_instruction, parse_integer, parse_nullable_string, parse_string,
+};
 use log::debug;
-use nom::{error::ErrorKind, multi::count, number::complete::u8, IResult, Parser};
+use nom::number::complete::{i8, u8};
 
 /// Parsing functions module
 mod parsers {
@@ -12,21 +15,32 @@ mod parsers {
     pub fn parse_section<'a, T, F>(
         input: &'a [u8],
         header: &Header,
+        name: &'static str,
         parser: F,
-    ) -> IResult<&'a [u8], Vec<T>>
+    ) -> PResult<'a, Vec<T>>
     where
-        F: Fn(&'a [u8]) -> IResult<&'a [u8], T>,
+        F: Fn(&'a [u8]) -> PResult<'a, T>,
     {
-        let (input, len) = parse_integer(input, header)?;
-        let len = usize::try_from(len)
-            .map_err(|_| nom::Err::Failure(nom::error::Error::new(input, ErrorKind::TooLarge)))?;
-        count(parser, len).parse(input)
+        let (mut input, len) = within(PathSegment::Field(name), parse_integer(input, header))?;
+        let Ok(len) = usize::try_from(len) else {
+            return fail(input, ParseErrorKind::InvalidLength(len.into()));
+        };
+
+        // Every element takes at least one byte, so cap the preallocation by the remaining input
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for index in 0..len {
+            let (rest, item) = within(PathSegment::Index(name, index), parser(input))?;
+            items.push(item);
+            input = rest;
+        }
+
+        Ok((input, items))
     }
 
     pub fn parse_local_variable<'a>(
         input: &'a [u8],
         header: &Header,
-    ) -> IResult<&'a [u8], LocalVariable> {
+    ) -> PResult<'a, LocalVariable> {
         let (input, varname) = parse_string(input, header)?;
         let (input, startpc) = parse_integer(input, header)?;
         let (input, endpc) = parse_integer(input, header)?;
@@ -41,13 +55,108 @@ mod parsers {
         ))
     }
 
-    pub fn parse_debug_info<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], DebugInfo> {
-        let (input, lineinfo) = parse_section(input, header, |i| parse_integer(i, header))?;
-        let (input, locals) = parse_section(input, header, |i| parse_local_variable(i, header))?;
-        let (input, upvalues) = parse_section(input, header, |i| parse_string(i, header))?;
+    pub fn parse_upvalue_descriptor(input: &[u8]) -> PResult<'_, UpvalueDescriptor> {
+        let (input, instack) = u8(input)?;
+        let (input, idx) = u8(input)?;
+
+        Ok((
+            input,
+            UpvalueDescriptor {
+                instack: instack != 0,
+                idx,
+                kind: 0,
+            },
+        ))
+    }
+
+    pub fn parse_upvalue_descriptor_54(input: &[u8]) -> PResult<'_, UpvalueDescriptor> {
+        let (input, instack) = u8(input)?;
+        let (input, idx) = u8(input)?;
+        let (input, kind) = u8(input)?;
+
+        Ok((
+            input,
+            UpvalueDescriptor {
+                instack: instack != 0,
+                idx,
+                kind,
+            },
+        ))
+    }
+
+    pub fn parse_abs_line_info<'a>(input: &'a [u8], header: &Header) -> PResult<'a, AbsLineInfo> {
+        let (input, pc) = parse_integer(input, header)?;
+        let (input, line) = parse_integer(input, header)?;
+
+        Ok((
+            input,
+            AbsLineInfo {
+                pc: pc as u32,
+                line: line as u32,
+            },
+        ))
+    }
+
+    /// Resolves Lua 5.4 line deltas into absolute lines (ldebug.c:luaG_getfuncline)
+    pub fn resolve_lines(
+        deltas: &[i8],
+        abslineinfo: &[AbsLineInfo],
+        line_defined: i32,
+    ) -> Vec<u32> {
+        let mut line = i64::from(line_defined);
+        let mut anchors = abslineinfo.iter().peekable();
+
+        deltas
+            .iter()
+            .enumerate()
+            .map(|(pc, &delta)| {
+                if delta == lua54::ABSLINEINFO {
+                    if let Some(anchor) = anchors.next_if(|a| a.pc as usize == pc) {
+                        line = i64::from(anchor.line);
+                    }
+                } else {
+                    line += i64::from(delta);
+                }
+                line as u32
+            })
+            .collect()
+    }
+
+    pub fn parse_debug_info_54<'a>(
+        input: &'a [u8],
+        header: &Header,
+        line_defined: i32,
+    ) -> PResult<'a, DebugInfo> {
+        let (input, deltas) = parse_section(input, header, "lineinfo", i8)?;
+        let (input, abslineinfo) = parse_section(input, header, "abslineinfo", |i| {
+            parse_abs_line_info(i, header)
+        })?;
+        let (input, locals) =
+            parse_section(input, header, "locals", |i| parse_local_variable(i, header))?;
+        let (input, upvalues) =
+            parse_section(input, header, "upvalues", |i| parse_string(i, header))?;
+
+        let debug_info = DebugInfo {
+            lineinfo: resolve_lines(&deltas, &abslineinfo, line_defined),
+            abslineinfo,
+            locals,
+            upvalues,
+        };
+
+        Ok((input, debug_info))
+    }
+
+    pub fn parse_debug_info<'a>(input: &'a [u8], header: &Header) -> PResult<'a, DebugInfo> {
+        let (input, lineinfo) =
+            parse_section(input, header, "lineinfo", |i| parse_integer(i, header))?;
+        let (input, locals) =
+            parse_section(input, header, "locals", |i| parse_local_variable(i, header))?;
+        let (input, upvalues) =
+            parse_section(input, header, "upvalues", |i| parse_string(i, header))?;
 
         let debug_info = DebugInfo {
             lineinfo: lineinfo.into_iter().map(|v| v as u32).collect(),
+            abslineinfo: Vec::new(),
             locals,
             upvalues,
         };
@@ -59,11 +168,23 @@ mod parsers {
 use parsers::*;
 
 /// Parse a Lua function prototype
-pub fn parse_function<'a>(
-    input: &'a [u8],
-    header: &Header,
-) -> IResult<&'a [u8], FunctionPrototype> {
-    let (input, source_name) = parse_string(input, header)?;
+pub fn parse_function<'a>(input: &'a [u8], header: &Header) -> PResult<'a, FunctionPrototype> {
+    let (input, proto) = match header.version {
+        Version::Lua51 => parse_function_51(input, header)?,
+        Version::Lua52 => parse_function_52(input, header)?,
+        // Nested 5.3+ prototypes inherit their parent's source, so they recurse on their own
+        Version::Lua53 => return parse_function_53(input, header, None),
+        Version::Lua54 => return parse_function_54(input, header, None),
+    };
+
+    debug!("Parsed function prototype: {:#?}", proto);
+
+    Ok((input, proto))
+}
+
+/// Lua 5.1 layout (lundump.c:LoadFunction)
+fn parse_function_51<'a>(input: &'a [u8], header: &Header) -> PResult<'a, FunctionPrototype> {
+    let (input, source_name) = parse_nullable_string(input, header)?;
     let (input, line_defined) = parse_integer(input, header)?;
     let (input, last_line_defined) = parse_integer(input, header)?;
     let (input, num_upvalues) = u8(input)?;
@@ -71,9 +192,10 @@ pub fn parse_function<'a>(
     let (input, is_vararg) = u8(input)?;
     let (input, max_stack_size) = u8(input)?;
 
-    let (input, code) = parse_section(input, header, |i| parse_instruction(i, header))?;
-    let (input, constants) = parse_section(input, header, |i| parse_constant(i, header))?;
-    let (input, prototypes) = parse_section(input, header, |i| parse_function(i, header))?;
+    let (input, code) = parse_section(input, header, "code", |i| parse_instruction(i, header))?;
+    let (input, constants) =
+        parse_section(input, header, "constants", |i| parse_constant(i, header))?;
+    let (input, prototypes) = parse_section(input, header, "proto", |i| parse_function(i, header))?;
     let (input, debug_info) = parse_debug_info(input, header)?;
 
     let proto = FunctionPrototype {
@@ -87,6 +209,144 @@ pub fn parse_function<'a>(
         code,
         constants,
         prototypes,
+        upvalue_descriptors: Vec::new(),
+        debug_info,
+    };
+
+    Ok((input, proto))
+}
+
+/// Lua 5.2 layout: upvalue descriptors follow the prototypes and the source moves to the debug section
+fn parse_function_52<'a>(input: &'a [u8], header: &Header) -> PResult<'a, FunctionPrototype> {
+    let (input, line_defined) = parse_integer(input, header)?;
+    let (input, last_line_defined) = parse_integer(input, header)?;
+    let (input, num_params) = u8(input)?;
+    let (input, is_vararg) = u8(input)?;
+    let (input, max_stack_size) = u8(input)?;
+
+    let (input, code) = parse_section(input, header, "code", |i| parse_instruction(i, header))?;
+    let (input, constants) =
+        parse_section(input, header, "constants", |i| parse_constant(i, header))?;
+    let (input, prototypes) = parse_section(input, header, "proto", |i| parse_function(i, header))?;
+    let (input, upvalue_descriptors) = parse_section(
+        input,
+        header,
+        "upvalue_descriptors",
+        parse_upvalue_descriptor,
+    )?;
+    let (input, source_name) = within(
+        PathSegment::Field("source"),
+        parse_nullable_string(input, header),
+    )?;
+    let (input, debug_info) = parse_debug_info(input, header)?;
+
+    let proto = FunctionPrototype {
+        source_name,
+        line_defined,
+        last_line_defined,
+        num_upvalues: upvalue_descriptors.len() as u8,
+        num_params,
+        is_vararg,
+        max_stack_size,
+        code,
+        constants,
+        prototypes,
+        upvalue_descriptors,
+        debug_info,
+    };
+
+    Ok((input, proto))
+}
+
+/// Lua 5.3 layout: the source comes first again (empty means "same as the parent") and
+/// upvalue descriptors precede the prototypes
+fn parse_function_53<'a>(
+    input: &'a [u8],
+    header: &Header,
+    parent_source: Option<&[u8]>,
+) -> PResult<'a, FunctionPrototype> {
+    let (input, source_name) = parse_nullable_string(input, header)?;
+    let source_name = source_name.or_else(|| parent_source.map(<[u8]>::to_vec));
+    let (input, line_defined) = parse_integer(input, header)?;
+    let (input, last_line_defined) = parse_integer(input, header)?;
+    let (input, num_params) = u8(input)?;
+    let (input, is_vararg) = u8(input)?;
+    let (input, max_stack_size) = u8(input)?;
+
+    let (input, code) = parse_section(input, header, "code", |i| parse_instruction(i, header))?;
+    let (input, constants) =
+        parse_section(input, header, "constants", |i| parse_constant(i, header))?;
+    let (input, upvalue_descriptors) = parse_section(
+        input,
+        header,
+        "upvalue_descriptors",
+        parse_upvalue_descriptor,
+    )?;
+    let (input, prototypes) = parse_section(input, header, "proto", |i| {
+        parse_function_53(i, header, source_name.as_deref())
+    })?;
+    let (input, debug_info) = parse_debug_info(input, header)?;
+
+    let proto = FunctionPrototype {
+        source_name,
+        line_defined,
+        last_line_defined,
+        num_upvalues: upvalue_descriptors.len() as u8,
+        num_params,
+        is_vararg,
+        max_stack_size,
+        code,
+        constants,
+        prototypes,
+        upvalue_descriptors,
+        debug_info,
+    };
+
+    debug!("Parsed function prototype: {:#?}", proto);
+
+    Ok((input, proto))
+}
+
+/// Lua 5.4 layout: like 5.3, with an upvalue kind byte and delta-encoded line info
+fn parse_function_54<'a>(
+    input: &'a [u8],
+    header: &Header,
+    parent_source: Option<&[u8]>,
+) -> PResult<'a, FunctionPrototype> {
+    let (input, source_name) = parse_nullable_string(input, header)?;
+    let source_name = source_name.or_else(|| parent_source.map(<[u8]>::to_vec));
+    let (input, line_defined) = parse_integer(input, header)?;
+    let (input, last_line_defined) = parse_integer(input, header)?;
+    let (input, num_params) = u8(input)?;
+    let (input, is_vararg) = u8(input)?;
+    let (input, max_stack_size) = u8(input)?;
+
+    let (input, code) = parse_section(input, header, "code", |i| parse_instruction(i, header))?;
+    let (input, constants) =
+        parse_section(input, header, "constants", |i| parse_constant(i, header))?;
+    let (input, upvalue_descriptors) = parse_section(
+        input,
+        header,
+        "upvalue_descriptors",
+        parse_upvalue_descriptor_54,
+    )?;
+    let (input, prototypes) = parse_section(input, header, "proto", |i| {
+        parse_function_54(i, header, source_name.as_deref())
+    })?;
+    let (input, debug_info) = parse_debug_info_54(input, header, line_defined)?;
+
+    let proto = FunctionPrototype {
+        source_name,
+        line_defined,
+        last_line_defined,
+        num_upvalues: upvalue_descriptors.len() as u8,
+        num_params,
+        is_vararg,
+        max_stack_size,
+        code,
+        constants,
+        prototypes,
+        upvalue_descriptors,
         debug_info,
     };
 
@@ -94,3 +354,293 @@ pub fn parse_function<'a>(
 
     Ok((input, proto))
 }
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::bytecode::{Constant, lua52, lua53, lua54};
+    use crate::parser::parse_lua_bytecode;
+
+    // Hand-assembled chunks in the layout each luac writes (ldump.c) for x86-64: little
+    // endian, 4-byte int and 8-byte size_t, lua_Number and lua_Integer
+
+    fn int(out: &mut Vec<u8>, value: u32) {
+        out.extend(value.to_le_bytes());
+    }
+
+    /// A counted section of ints or instruction words
+    fn ints(out: &mut Vec<u8>, words: &[u32]) {
+        int(out, words.len() as u32);
+        for word in words {
+            int(out, *word);
+        }
+    }
+
+    /// A Lua 5.1/5.2 string: size_t length counting the NUL, 0 for NULL
+    fn string_52(out: &mut Vec<u8>, text: &[u8]) {
+        out.extend((text.len() as u64 + 1).to_le_bytes());
+        out.extend(text);
+        out.push(0);
+    }
+
+    /// A Lua 5.3 string: a byte holding the length plus one, 0xFF then a size_t for long
+    /// strings, 0 for NULL
+    fn string_53(out: &mut Vec<u8>, text: Option<&[u8]>) {
+        match text {
+            None => out.push(0),
+            Some(text) if text.len() < 0xFE => {
+                out.push(text.len() as u8 + 1);
+                out.extend(text);
+            }
+            Some(text) => {
+                out.push(0xFF);
+                out.extend((text.len() as u64 + 1).to_le_bytes());
+                out.extend(text);
+            }
+        }
+    }
+
+    /// A Lua 5.4 varint: 7-bit groups, most significant first, the last one flagged
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        let mut groups = vec![0x80 | (value & 0x7f) as u8];
+        value >>= 7;
+        while value != 0 {
+            groups.push((value & 0x7f) as u8);
+            value >>= 7;
+        }
+        out.extend(groups.iter().rev());
+    }
+
+    /// A Lua 5.4 string: a varint holding the length plus one, 0 for NULL
+    fn string_54(out: &mut Vec<u8>, text: Option<&[u8]>) {
+        match text {
+            None => varint(out, 0),
+            Some(text) => {
+                varint(out, text.len() as u64 + 1);
+                out.extend(text);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_lua_52_layout() {
+        // function f() return 1.5 end
+        let mut chunk = b"\x1bLua\x52\x00\x01\x04\x08\x04\x08\x00".to_vec();
+        chunk.extend(lua52::LUAC_TAIL);
+
+        int(&mut chunk, 0);
+        int(&mut chunk, 0);
+        chunk.extend([0, 1, 2]);
+        ints(&mut chunk, &[0x0000_0025, 0x8000_0008, 0x0080_001f]);
+        int(&mut chunk, 1);
+        chunk.push(4);
+        string_52(&mut chunk, b"f");
+
+        int(&mut chunk, 1);
+        int(&mut chunk, 1);
+        int(&mut chunk, 1);
+        chunk.extend([0, 0, 2]);
+        ints(&mut chunk, &[0x0000_0001, 0x0100_001f, 0x0080_001f]);
+        int(&mut chunk, 1);
+        chunk.push(3);
+        chunk.extend(1.5f64.to_le_bytes());
+        int(&mut chunk, 0); // Prototypes
+        int(&mut chunk, 0); // Upvalues
+        string_52(&mut chunk, b"@t.lua");
+        ints(&mut chunk, &[1, 1, 1]); // Line info
+        int(&mut chunk, 0); // Locals
+        int(&mut chunk, 0); // Upvalue names
+
+        int(&mut chunk, 1);
+        chunk.extend([1, 0]); // _ENV is the loader's register 0
+        string_52(&mut chunk, b"@t.lua");
+        ints(&mut chunk, &[1, 1, 1]);
+        int(&mut chunk, 0);
+        int(&mut chunk, 1);
+        string_52(&mut chunk, b"_ENV");
+
+        let (header, main) = parse_lua_bytecode(&chunk).expect("valid chunk");
+        assert_eq!((header.size_int, header.size_size_t), (4, 8));
+        assert_eq!(main.source_name.as_deref(), Some(&b"@t.lua"[..]));
+        assert_eq!(main.is_vararg, 1);
+        assert_eq!(main.num_upvalues, 1);
+        let upvalue = &main.upvalue_descriptors[0];
+        assert_eq!((upvalue.instack, upvalue.idx), (true, 0));
+        assert_eq!(main.debug_info.upvalues, [b"_ENV"]);
+        assert!(matches!(&main.constants[..], [Constant::String(s)] if s == b"f"));
+
+        let f = &main.prototypes[0];
+        assert_eq!((f.line_defined, f.last_line_defined), (1, 1));
+        assert_eq!(f.source_name.as_deref(), Some(&b"@t.lua"[..]));
+        assert_eq!(f.num_upvalues, 0);
+        assert!(matches!(f.constants[..], [Constant::Number(n)] if n == 1.5));
+        assert_eq!(f.debug_info.lineinfo, [1, 1, 1]);
+    }
+
+    #[test]
+    fn parses_lua_53_layout() {
+        // local function f() end; return 2^53 + 1, 0.1, ("a"):rep(300)
+        let mut chunk = b"\x1bLua\x53\x00".to_vec();
+        chunk.extend(lua53::LUAC_DATA);
+        chunk.extend([4, 8, 4, 8, 8]);
+        chunk.extend(lua53::LUAC_INT.to_le_bytes());
+        chunk.extend(lua53::LUAC_NUM.to_le_bytes());
+        chunk.push(1);
+
+        string_53(&mut chunk, Some(b"@t.lua"));
+        int(&mut chunk, 0);
+        int(&mut chunk, 0);
+        chunk.extend([0, 1, 4]);
+        ints(&mut chunk, &[0x0000_002c, 0x0200_0026]);
+        int(&mut chunk, 3);
+        chunk.push(lua53::TAG_NUMINT);
+        chunk.extend(((1i64 << 53) + 1).to_le_bytes());
+        chunk.push(lua53::TAG_NUMFLT);
+        chunk.extend(0.1f64.to_le_bytes());
+        chunk.push(lua53::TAG_LNGSTR);
+        string_53(&mut chunk, Some(&[b'a'; 300]));
+        int(&mut chunk, 1);
+        chunk.extend([1, 0]);
+
+        int(&mut chunk, 1);
+        string_53(&mut chunk, None); // Same source as the parent
+        int(&mut chunk, 1);
+        int(&mut chunk, 1);
+        chunk.extend([0, 0, 2]);
+        ints(&mut chunk, &[0x0080_0026]);
+        int(&mut chunk, 0); // Constants
+        int(&mut chunk, 0); // Upvalues
+        int(&mut chunk, 0); // Prototypes
+        ints(&mut chunk, &[1]);
+        int(&mut chunk, 0);
+        int(&mut chunk, 0);
+
+        ints(&mut chunk, &[1, 1]);
+        int(&mut chunk, 1);
+        string_53(&mut chunk, Some(b"f"));
+        int(&mut chunk, 1);
+        int(&mut chunk, 2);
+        int(&mut chunk, 1);
+        string_53(&mut chunk, Some(b"_ENV"));
+
+        let (header, main) = parse_lua_bytecode(&chunk).expect("valid chunk");
+        assert_eq!((header.size_integer, header.size_number), (8, 8));
+        assert_eq!(header.main_upvalues, 1);
+        assert_eq!(main.source_name.as_deref(), Some(&b"@t.lua"[..]));
+        match &main.constants[..] {
+            [
+                Constant::Integer(i),
+                Constant::Number(n),
+                Constant::String(s),
+            ] => {
+                assert_eq!(*i, 9_007_199_254_740_993);
+                assert_eq!(*n, 0.1);
+                assert_eq!(s.as_slice(), &[b'a'; 300]);
+            }
+            constants => panic!("unexpected constants {constants:?}"),
+        }
+        let upvalue = &main.upvalue_descriptors[0];
+        assert_eq!((upvalue.instack, upvalue.idx), (true, 0));
+        let local = &main.debug_info.locals[0];
+        assert_eq!(
+            (local.varname.as_slice(), local.startpc, local.endpc),
+            (&b"f"[..], 1, 2)
+        );
+
+        let f = &main.prototypes[0];
+        assert_eq!(f.source_name.as_deref(), Some(&b"@t.lua"[..]));
+        assert!(f.constants.is_empty());
+        assert_eq!(f.debug_info.lineinfo, [1]);
+    }
+
+    #[test]
+    fn parses_lua_54_layout() {
+        // Constants true, -1, 2.5 and "k", and a function on lines 200 to 300, which take
+        // two-byte varints, whose second instruction is anchored on line 500
+        let mut chunk = b"\x1bLua\x54\x00".to_vec();
+        chunk.extend(lua53::LUAC_DATA);
+        chunk.extend([4, 8, 8]);
+        chunk.extend(lua53::LUAC_INT.to_le_bytes());
+        chunk.extend(lua53::LUAC_NUM.to_le_bytes());
+        chunk.push(1);
+
+        string_54(&mut chunk, Some(b"@t.lua"));
+        varint(&mut chunk, 0);
+        varint(&mut chunk, 0);
+        chunk.extend([0, 1, 2]);
+        varint(&mut chunk, 3);
+        for word in [0x0000_0051u32, 0x0000_004f, 0x0101_0046] {
+            int(&mut chunk, word);
+        }
+        varint(&mut chunk, 4);
+        chunk.push(lua54::TAG_TRUE);
+        chunk.push(lua54::TAG_NUMINT);
+        chunk.extend((-1i64).to_le_bytes());
+        chunk.push(lua54::TAG_NUMFLT);
+        chunk.extend(2.5f64.to_le_bytes());
+        chunk.push(lua54::TAG_SHRSTR);
+        string_54(&mut chunk, Some(b"k"));
+        varint(&mut chunk, 1);
+        chunk.extend([1, 0, 0]);
+
+        varint(&mut chunk, 1);
+        string_54(&mut chunk, None); // Same source as the parent
+        varint(&mut chunk, 200);
+        varint(&mut chunk, 300);
+        chunk.extend([1, 0, 2]);
+        varint(&mut chunk, 3);
+        for _ in 0..3 {
+            int(&mut chunk, 0x0001_0047);
+        }
+        varint(&mut chunk, 0); // Constants
+        varint(&mut chunk, 0); // Upvalues
+        varint(&mut chunk, 0); // Prototypes
+        varint(&mut chunk, 3);
+        chunk.extend([0, lua54::ABSLINEINFO as u8, 1]);
+        varint(&mut chunk, 1);
+        varint(&mut chunk, 1);
+        varint(&mut chunk, 500);
+        varint(&mut chunk, 1);
+        string_54(&mut chunk, Some(b"a"));
+        varint(&mut chunk, 0);
+        varint(&mut chunk, 3);
+        varint(&mut chunk, 0);
+
+        varint(&mut chunk, 3);
+        chunk.extend([1, 0, 0]);
+        varint(&mut chunk, 0);
+        varint(&mut chunk, 0);
+        varint(&mut chunk, 1);
+        string_54(&mut chunk, Some(b"_ENV"));
+
+        let (header, main) = parse_lua_bytecode(&chunk).expect("valid chunk");
+        assert_eq!((header.size_integer, header.size_number), (8, 8));
+        assert_eq!(main.source_name.as_deref(), Some(&b"@t.lua"[..]));
+        assert!(matches!(
+            main.constants[..],
+            [
+                Constant::Boolean(true),
+                Constant::Integer(-1),
+                Constant::Number(n),
+                Constant::String(ref s),
+            ] if n == 2.5 && s == b"k"
+        ));
+        let upvalue = &main.upvalue_descriptors[0];
+        assert_eq!((upvalue.instack, upvalue.idx, upvalue.kind), (true, 0, 0));
+        assert_eq!(main.debug_info.lineinfo, [1, 1, 1]);
+        assert_eq!(main.debug_info.upvalues, [b"_ENV"]);
+
+        let f = &main.prototypes[0];
+        assert_eq!(f.source_name.as_deref(), Some(&b"@t.lua"[..]));
+        assert_eq!((f.line_defined, f.last_line_defined), (200, 300));
+        assert_eq!(f.debug_info.lineinfo, [200, 500, 501]);
+        let anchor = &f.debug_info.abslineinfo[0];
+        assert_eq!((anchor.pc, anchor.line), (1, 500));
+        let local = &f.debug_info.locals[0];
+        assert_eq!(
+            (local.varname.as_slice(), local.startpc, local.endpc),
+            (&b"a"[..], 0, 3)
+        );
+    }
+}
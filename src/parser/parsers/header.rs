@@ -1,56 +1,48 @@
-use super::super::bytecode::{Endianness, Header};
+use super::super::bytecode::{Endianness, Header, Version, lua52, lua53};
+use super::super::error::{PResult, ParseErrorKind, fail};
+use super::parsers::parse_float;
 use log::debug;
-use nom::{
-    bytes::complete::tag,
-    combinator::{map, verify},
-    error::context,
-    IResult, Parser,
-};
+use nom::{Parser, bytes::complete::take, combinator::map, error::context, number::complete::u8};
 
 // Constants for validation
 const MAGIC_NUMBER: &[u8] = b"\x1BLua";
-const EXPECTED_VERSION: u8 = 0x51;
 const EXPECTED_FORMAT: u8 = 0;
-const EXPECTED_SIZE_INT: u8 = 4;
-const EXPECTED_SIZE_SIZE_T: u8 = 8;
-const EXPECTED_SIZE_INSTRUCTION: u8 = 4;
-const EXPECTED_SIZE_NUMBER: u8 = 8;
-
-// Constants for errors
-const ERROR_INVALID_MAGIC_NUMBER: &str = "invalid magic number";
-const ERROR_INVALID_VERSION: &str = "invalid Lua version (must be 0x51)";
-const ERROR_INVALID_FORMAT: &str = "unsupported format (must be 0 (official))";
-const ERROR_INVALID_SIZE_INT: &str = "invalid int size";
-const ERROR_INVALID_SIZE_SIZE_T: &str = "invalid size_t size";
-const ERROR_INVALID_SIZE_INSTRUCTION: &str = "invalid instruction size";
-const ERROR_INVALID_SIZE_NUMBER: &str = "invalid number size";
+const SUPPORTED_SIZES_INT: &[u8] = &[4, 8];
+const SUPPORTED_SIZES_SIZE_T: &[u8] = &[4, 8];
+const SUPPORTED_SIZES_INSTRUCTION: &[u8] = &[4];
+const SUPPORTED_SIZES_NUMBER: &[u8] = &[4, 8]; // float/int32 or double/int64
+const SUPPORTED_SIZES_INTEGER: &[u8] = &[4, 8];
 
 /// Parsing functions module
 mod parsers {
     use super::*;
 
-    pub fn parse_magic_number(input: &[u8]) -> IResult<&[u8], &[u8]> {
-        context(ERROR_INVALID_MAGIC_NUMBER, tag(MAGIC_NUMBER)).parse(input)
+    pub fn parse_magic_number(input: &[u8]) -> PResult<'_, &[u8]> {
+        let (rest, magic) = take(MAGIC_NUMBER.len())(input)?;
+        if magic != MAGIC_NUMBER {
+            return fail(input, ParseErrorKind::BadMagic);
+        }
+        Ok((rest, magic))
     }
 
-    pub fn parse_version(input: &[u8]) -> IResult<&[u8], u8> {
-        context(
-            ERROR_INVALID_VERSION,
-            verify(nom::number::complete::u8, |&v| v == EXPECTED_VERSION),
-        )
-        .parse(input)
+    pub fn parse_version(input: &[u8]) -> PResult<'_, Version> {
+        let (rest, version) = u8(input)?;
+        match Version::try_from(version) {
+            Ok(version) => Ok((rest, version)),
+            Err(_) => fail(input, ParseErrorKind::UnsupportedVersion(version)),
+        }
     }
 
-    pub fn parse_format(input: &[u8]) -> IResult<&[u8], u8> {
-        context(
-            ERROR_INVALID_FORMAT,
-            verify(nom::number::complete::u8, |&f| f == EXPECTED_FORMAT),
-        )
-        .parse(input)
+    pub fn parse_format(input: &[u8]) -> PResult<'_, u8> {
+        let (rest, format) = u8(input)?;
+        if format != EXPECTED_FORMAT {
+            return fail(input, ParseErrorKind::UnsupportedFormat(format));
+        }
+        Ok((rest, format))
     }
 
-    pub fn parse_endianness(input: &[u8]) -> IResult<&[u8], Endianness> {
-        map(nom::number::complete::u8, |b| match b {
+    pub fn parse_endianness(input: &[u8]) -> PResult<'_, Endianness> {
+        map(u8, |b| match b {
             1 => Endianness::Little,
             _ => Endianness::Big,
         })
@@ -58,50 +50,162 @@ mod parsers {
     }
 
     pub fn parse_size<'a>(
-        name: &'static str,
-        expected: u8,
+        field: &'static str,
+        supported: &[u8],
         input: &'a [u8],
-    ) -> IResult<&'a [u8], u8> {
-        context(
-            name,
-            verify(nom::number::complete::u8, move |&v| v == expected),
-        )
-        .parse(input)
+    ) -> PResult<'a, u8> {
+        let (rest, value) = context(field, u8).parse(input)?;
+        if !supported.contains(&value) {
+            return fail(input, ParseErrorKind::InvalidSize { field, value });
+        }
+        Ok((rest, value))
+    }
+
+    pub fn parse_integral_flag(input: &[u8]) -> PResult<'_, bool> {
+        map(u8, |b| b != 0).parse(input)
+    }
+
+    /// Lua 5.2+ embeds LUAC_TAIL/LUAC_DATA to catch files mangled by text-mode conversions
+    pub fn parse_tail(input: &[u8]) -> PResult<'_, &[u8]> {
+        let (rest, tail) = context("tail", take(lua52::LUAC_TAIL.len())).parse(input)?;
+        if tail != lua52::LUAC_TAIL {
+            return fail(input, ParseErrorKind::BadTail);
+        }
+        Ok((rest, tail))
     }
 
-    pub fn parse_integral_flag(input: &[u8]) -> IResult<&[u8], bool> {
-        map(nom::number::complete::u8, |b| b != 0).parse(input)
+    /// Lua 5.3 has no endianness byte, the byte order is deduced from the LUAC_INT check value
+    pub fn parse_luac_int<'a>(input: &'a [u8], size_integer: u8) -> PResult<'a, Endianness> {
+        let (rest, bytes) = context("luac_int", take(size_integer)).parse(input)?;
+        let mut little = [0u8; 8];
+        let mut big = [0u8; 8];
+        little[..bytes.len()].copy_from_slice(bytes);
+        big[8 - bytes.len()..].copy_from_slice(bytes);
+
+        if i64::from_le_bytes(little) == lua53::LUAC_INT {
+            Ok((rest, Endianness::Little))
+        } else if i64::from_be_bytes(big) == lua53::LUAC_INT {
+            Ok((rest, Endianness::Big))
+        } else {
+            fail(input, ParseErrorKind::EndiannessMismatch)
+        }
+    }
+
+    pub fn parse_luac_num<'a>(input: &'a [u8], header: &Header) -> PResult<'a, f64> {
+        let (rest, number) = context("luac_num", |i| parse_float(i, header)).parse(input)?;
+        if number != lua53::LUAC_NUM {
+            return fail(input, ParseErrorKind::FloatFormatMismatch);
+        }
+        Ok((rest, number))
+    }
+
+    /// Lua 5.1 and 5.2 layout (lundump.c:luaU_header)
+    pub fn parse_header_51(input: &[u8], version: Version) -> PResult<'_, Header> {
+        let (input, format) = parse_format(input)?;
+        let (input, endianness) = parse_endianness(input)?;
+
+        let (input, size_int) = parse_size("int", SUPPORTED_SIZES_INT, input)?;
+        let (input, size_size_t) = parse_size("size_t", SUPPORTED_SIZES_SIZE_T, input)?;
+        let (input, size_instruction) =
+            parse_size("instruction", SUPPORTED_SIZES_INSTRUCTION, input)?;
+        let (input, size_number) = parse_size("number", SUPPORTED_SIZES_NUMBER, input)?;
+        let (input, integral_flag) = parse_integral_flag(input)?;
+        let (input, _) = match version {
+            Version::Lua51 => (input, &[][..]),
+            _ => parse_tail(input)?,
+        };
+
+        let header = Header {
+            version,
+            format,
+            endianness,
+            size_int,
+            size_size_t,
+            size_instruction,
+            size_number,
+            size_integer: 0,
+            integral_flag,
+            main_upvalues: 0,
+        };
+
+        Ok((input, header))
+    }
+
+    /// Lua 5.3 layout (lundump.c:checkHeader), followed by the main closure's upvalue count
+    pub fn parse_header_53(input: &[u8], version: Version) -> PResult<'_, Header> {
+        let (input, format) = parse_format(input)?;
+        let (input, _) = parse_tail(input)?;
+
+        let (input, size_int) = parse_size("int", SUPPORTED_SIZES_INT, input)?;
+        let (input, size_size_t) = parse_size("size_t", SUPPORTED_SIZES_SIZE_T, input)?;
+        let (input, size_instruction) =
+            parse_size("instruction", SUPPORTED_SIZES_INSTRUCTION, input)?;
+        let (input, size_integer) = parse_size("integer", SUPPORTED_SIZES_INTEGER, input)?;
+        let (input, size_number) = parse_size("number", SUPPORTED_SIZES_NUMBER, input)?;
+        let (input, endianness) = parse_luac_int(input, size_integer)?;
+
+        let mut header = Header {
+            version,
+            format,
+            endianness,
+            size_int,
+            size_size_t,
+            size_instruction,
+            size_number,
+            size_integer,
+            integral_flag: false,
+            main_upvalues: 0,
+        };
+
+        let (input, _) = parse_luac_num(input, &header)?;
+        let (input, main_upvalues) = context("main_upvalues", u8).parse(input)?;
+        header.main_upvalues = main_upvalues;
+
+        Ok((input, header))
+    }
+
+    /// Lua 5.4 layout: ints and size_t are varints, so their sizes are no longer recorded
+    pub fn parse_header_54(input: &[u8], version: Version) -> PResult<'_, Header> {
+        let (input, format) = parse_format(input)?;
+        let (input, _) = parse_tail(input)?;
+
+        let (input, size_instruction) =
+            parse_size("instruction", SUPPORTED_SIZES_INSTRUCTION, input)?;
+        let (input, size_integer) = parse_size("integer", SUPPORTED_SIZES_INTEGER, input)?;
+        let (input, size_number) = parse_size("number", SUPPORTED_SIZES_NUMBER, input)?;
+        let (input, endianness) = parse_luac_int(input, size_integer)?;
+
+        let mut header = Header {
+            version,
+            format,
+            endianness,
+            size_int: 0,
+            size_size_t: 0,
+            size_instruction,
+            size_number,
+            size_integer,
+            integral_flag: false,
+            main_upvalues: 0,
+        };
+
+        let (input, _) = parse_luac_num(input, &header)?;
+        let (input, main_upvalues) = context("main_upvalues", u8).parse(input)?;
+        header.main_upvalues = main_upvalues;
+
+        Ok((input, header))
     }
 }
 
 use parsers::*;
 
 /// Parse the header of the Lua bytecode
-pub fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
+pub fn parse_header(input: &[u8]) -> PResult<'_, Header> {
     let (input, _) = parse_magic_number(input)?;
     let (input, version) = parse_version(input)?;
-    let (input, format) = parse_format(input)?;
-    let (input, endianness) = parse_endianness(input)?;
-
-    let (input, size_int) = parse_size(ERROR_INVALID_SIZE_INT, EXPECTED_SIZE_INT, input)?;
-    let (input, size_size_t) = parse_size(ERROR_INVALID_SIZE_SIZE_T, EXPECTED_SIZE_SIZE_T, input)?;
-    let (input, size_instruction) = parse_size(
-        ERROR_INVALID_SIZE_INSTRUCTION,
-        EXPECTED_SIZE_INSTRUCTION,
-        input,
-    )?;
-    let (input, size_number) = parse_size(ERROR_INVALID_SIZE_NUMBER, EXPECTED_SIZE_NUMBER, input)?;
-    let (input, integral_flag) = parse_integral_flag(input)?;
-
-    let header = Header {
-        version,
-        format,
-        endianness,
-        size_int,
-        size_size_t,
-        size_instruction,
-        size_number,
-        integral_flag,
+    let (input, header) = match version {
+        Version::Lua51 | Version::Lua52 => parse_header_51(input, version)?,
+        Version::Lua53 => parse_header_53(input, version)?,
+        Version::Lua54 => parse_header_54(input, version)?,
     };
 
     debug!("Parsed header: {:#?}", header);
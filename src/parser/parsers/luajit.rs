@@ -0,0 +1,571 @@
+use super::super::bytecode::Endianness;
+use super::super::bytecode::luajit::{
+    self, DebugInfo, DumpVersion, GcConstant, Header, Instruction, LocalVariable, NumConstant,
+    Prototype, TableConstant, TableValue,
+};
+use super::super::error::{PResult, ParseErrorKind, PathSegment, fail, within};
+use super::parsers::{parse_counted, parse_uleb128};
+use log::debug;
+use nom::{
+    Parser,
+    bytes::complete::{take, take_until},
+    number::complete::{be_u16, be_u32, le_u16, le_u32, u8},
+};
+
+/// Parsing functions module
+mod parsers {
+    use super::*;
+
+    /// Parses a 33-bit LEB128 value whose lowest bit is a flag (lj_buf.c:lj_buf_ruleb128_33)
+    pub fn parse_uleb128_33(input: &[u8]) -> PResult<'_, (u32, bool)> {
+        let (mut rest, first) = u8(input)?;
+        let flag = first & 1 != 0;
+        let mut value = u64::from(first >> 1);
+
+        if value >= 0x40 {
+            value &= 0x3f;
+            let mut shift = 6;
+            loop {
+                let (next, byte) = u8(rest)?;
+                if shift > 27 {
+                    return fail(input, ParseErrorKind::ValueTooLarge);
+                }
+                value |= u64::from(byte & 0x7f) << shift;
+                shift += 7;
+                rest = next;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+        }
+
+        match u32::try_from(value) {
+            Ok(value) => Ok((rest, (value, flag))),
+            Err(_) => fail(input, ParseErrorKind::ValueTooLarge),
+        }
+    }
+
+    /// Parses a number split into two LEB128 halves
+    pub fn parse_split_number(input: &[u8]) -> PResult<'_, u64> {
+        let (input, lo) = parse_uleb128(input)?;
+        let (input, hi) = parse_uleb128(input)?;
+        Ok((input, (u64::from(hi) << 32) | u64::from(lo)))
+    }
+
+    pub fn parse_bytes_string(input: &[u8], len: u32) -> PResult<'_, Vec<u8>> {
+        let (input, bytes) = take(len)(input)?;
+        Ok((input, bytes.to_vec()))
+    }
+
+    /// Parses a NUL-terminated string, as used in the debug info
+    pub fn parse_cstring(input: &[u8]) -> PResult<'_, Vec<u8>> {
+        let (rest, bytes) = take_until(&b"\x00"[..]).parse(input)?;
+        Ok((&rest[1..], bytes.to_vec()))
+    }
+
+    pub fn parse_table_value(input: &[u8]) -> PResult<'_, TableValue> {
+        let (rest, tag) = parse_uleb128(input)?;
+        match tag {
+            luajit::KTAB_NIL => Ok((rest, TableValue::Nil)),
+            luajit::KTAB_FALSE => Ok((rest, TableValue::Boolean(false))),
+            luajit::KTAB_TRUE => Ok((rest, TableValue::Boolean(true))),
+            luajit::KTAB_INT => {
+                let (rest, value) = parse_uleb128(rest)?;
+                Ok((rest, TableValue::Integer(value as i32)))
+            }
+            luajit::KTAB_NUM => {
+                let (rest, bits) = parse_split_number(rest)?;
+                Ok((rest, TableValue::Number(f64::from_bits(bits))))
+            }
+            _ => {
+                let (rest, s) = parse_bytes_string(rest, tag - luajit::KTAB_STR)?;
+                Ok((rest, TableValue::String(s)))
+            }
+        }
+    }
+
+    pub fn parse_table(input: &[u8]) -> PResult<'_, TableConstant> {
+        let (input, narray) = parse_uleb128(input)?;
+        let (input, nhash) = parse_uleb128(input)?;
+
+        let (input, array) = parse_counted(input, "array", narray, parse_table_value)?;
+        let (input, hash) = parse_counted(input, "hash", nhash, |i| {
+            let (i, key) = parse_table_value(i)?;
+            let (i, value) = parse_table_value(i)?;
+            Ok((i, (key, value)))
+        })?;
+
+        Ok((input, TableConstant { array, hash }))
+    }
+
+    /// Parses a GC constant, moving referenced children from the prototype stack
+    pub fn parse_gc_constant<'a>(
+        input: &'a [u8],
+        stack: &mut Vec<Prototype>,
+        children: &mut Vec<Prototype>,
+    ) -> PResult<'a, GcConstant> {
+        let (rest, tag) = parse_uleb128(input)?;
+        match tag {
+            luajit::KGC_CHILD => match stack.pop() {
+                Some(child) => {
+                    children.push(child);
+                    Ok((rest, GcConstant::Child(children.len() - 1)))
+                }
+                None => fail(input, ParseErrorKind::MissingChildPrototype),
+            },
+            luajit::KGC_TAB => {
+                let (rest, table) = parse_table(rest)?;
+                Ok((rest, GcConstant::Table(table)))
+            }
+            luajit::KGC_I64 => {
+                let (rest, bits) = parse_split_number(rest)?;
+                Ok((rest, GcConstant::Int64(bits as i64)))
+            }
+            luajit::KGC_U64 => {
+                let (rest, bits) = parse_split_number(rest)?;
+                Ok((rest, GcConstant::UInt64(bits)))
+            }
+            luajit::KGC_COMPLEX => {
+                let (rest, re) = parse_split_number(rest)?;
+                let (rest, im) = parse_split_number(rest)?;
+                Ok((
+                    rest,
+                    GcConstant::Complex(f64::from_bits(re), f64::from_bits(im)),
+                ))
+            }
+            _ => {
+                let (rest, s) = parse_bytes_string(rest, tag - luajit::KGC_STR)?;
+                Ok((rest, GcConstant::String(s)))
+            }
+        }
+    }
+
+    pub fn parse_num_constant(input: &[u8]) -> PResult<'_, NumConstant> {
+        let (input, (lo, is_number)) = parse_uleb128_33(input)?;
+        if !is_number {
+            return Ok((input, NumConstant::Integer(lo as i32)));
+        }
+
+        let (input, hi) = parse_uleb128(input)?;
+        let bits = (u64::from(hi) << 32) | u64::from(lo);
+        Ok((input, NumConstant::Number(f64::from_bits(bits))))
+    }
+
+    pub fn parse_line(input: &[u8], num_lines: u32, endianness: Endianness) -> PResult<'_, u32> {
+        match (num_lines, endianness) {
+            (0..256, _) => u8.map(u32::from).parse(input),
+            (256..65536, Endianness::Big) => be_u16.map(u32::from).parse(input),
+            (256..65536, Endianness::Little) => le_u16.map(u32::from).parse(input),
+            (_, Endianness::Big) => be_u32(input),
+            (_, Endianness::Little) => le_u32(input),
+        }
+    }
+
+    pub fn parse_locals(mut input: &[u8]) -> PResult<'_, Vec<LocalVariable>> {
+        let mut locals = Vec::new();
+        let mut startpc = 0;
+
+        loop {
+            let (rest, kind) = u8(input)?;
+            let (rest, varname) = match kind {
+                0 => return Ok((rest, locals)),
+                1..7 => (rest, luajit::VARNAMES[kind as usize].as_bytes().to_vec()),
+                _ => parse_cstring(input)?,
+            };
+            let (rest, start_delta) = parse_uleb128(rest)?;
+            let (rest, length) = parse_uleb128(rest)?;
+
+            startpc += start_delta;
+            locals.push(LocalVariable {
+                varname,
+                startpc,
+                endpc: startpc + length,
+            });
+            input = rest;
+        }
+    }
+
+    pub fn parse_debug_info<'a>(
+        input: &'a [u8],
+        proto: &Prototype,
+        endianness: Endianness,
+    ) -> PResult<'a, DebugInfo> {
+        let (input, lineinfo) = parse_counted(input, "lineinfo", proto.code.len() as u32, |i| {
+            parse_line(i, proto.num_lines, endianness)
+        })?;
+        let (input, upvalues) = parse_counted(
+            input,
+            "upvalues",
+            proto.upvalues.len() as u32,
+            parse_cstring,
+        )?;
+        let (input, locals) = within(PathSegment::Field("locals"), parse_locals(input))?;
+
+        let debug_info = DebugInfo {
+            lineinfo: lineinfo.into_iter().map(|l| proto.first_line + l).collect(),
+            upvalues,
+            locals,
+        };
+
+        Ok((input, debug_info))
+    }
+}
+
+use parsers::*;
+
+/// Parse the header of a LuaJIT bytecode dump (lj_bcread.c:bcread_header)
+pub fn parse_luajit_header(input: &[u8]) -> PResult<'_, Header> {
+    let (rest, magic) = take(luajit::MAGIC_NUMBER.len())(input)?;
+    if magic != luajit::MAGIC_NUMBER {
+        return fail(input, ParseErrorKind::BadMagic);
+    }
+
+    let (next, version) = u8(rest)?;
+    let Ok(version) = DumpVersion::try_from(version) else {
+        return fail(rest, ParseErrorKind::UnsupportedVersion(version));
+    };
+    let rest = next;
+    let (rest, flags) = parse_uleb128(rest)?;
+
+    let (rest, chunk_name) = match flags & luajit::FLAG_STRIP {
+        0 => {
+            let (rest, len) = parse_uleb128(rest)?;
+            parse_bytes_string(rest, len)?
+        }
+        _ => (rest, Vec::new()),
+    };
+
+    let header = Header {
+        version,
+        flags,
+        chunk_name,
+    };
+
+    debug!("Parsed LuaJIT header: {:#?}", header);
+
+    Ok((rest, header))
+}
+
+/// Parse one prototype (lj_bcread.c:lj_bcread_proto). Children are popped from `stack`,
+/// since the dump lists every prototype after all of its children
+pub fn parse_prototype<'a>(
+    input: &'a [u8],
+    header: &Header,
+    stack: &mut Vec<Prototype>,
+) -> PResult<'a, Prototype> {
+    let endianness = header.endianness();
+
+    let (input, flags) = u8(input)?;
+    let (input, num_params) = u8(input)?;
+    let (input, frame_size) = u8(input)?;
+    let (input, size_uv) = u8(input)?;
+    let (input, size_kgc) = parse_uleb128(input)?;
+    let (input, size_kn) = parse_uleb128(input)?;
+    let (input, size_bc) = parse_uleb128(input)?;
+
+    let (input, size_dbg, first_line, num_lines) = match header.is_stripped() {
+        true => (input, 0, 0, 0),
+        false => {
+            let (input, size_dbg) = parse_uleb128(input)?;
+            match size_dbg {
+                0 => (input, 0, 0, 0),
+                _ => {
+                    let (input, first_line) = parse_uleb128(input)?;
+                    let (input, num_lines) = parse_uleb128(input)?;
+                    (input, size_dbg, first_line, num_lines)
+                }
+            }
+        }
+    };
+
+    let (input, code) = parse_counted(input, "code", size_bc, |i| {
+        match endianness {
+            Endianness::Big => be_u32(i),
+            Endianness::Little => le_u32(i),
+        }
+        .map(|(i, word)| (i, Instruction::new(word)))
+    })?;
+    let (input, upvalues) =
+        parse_counted(
+            input,
+            "upvalues",
+            u32::from(size_uv),
+            |i| match endianness {
+                Endianness::Big => be_u16(i),
+                Endianness::Little => le_u16(i),
+            },
+        )?;
+
+    let mut prototypes = Vec::new();
+    let (input, mut gc_constants) = parse_counted(input, "kgc", size_kgc, |i| {
+        parse_gc_constant(i, stack, &mut prototypes)
+    })?;
+    gc_constants.reverse();
+
+    let (input, num_constants) = parse_counted(input, "kn", size_kn, parse_num_constant)?;
+
+    let mut proto = Prototype {
+        flags,
+        num_params,
+        frame_size,
+        first_line,
+        num_lines,
+        code,
+        upvalues,
+        gc_constants,
+        num_constants,
+        prototypes,
+        debug_info: DebugInfo {
+            lineinfo: Vec::new(),
+            upvalues: Vec::new(),
+            locals: Vec::new(),
+        },
+    };
+
+    let (input, debug) = take(size_dbg)(input)?;
+    if !debug.is_empty() {
+        let (_, debug_info) = within(
+            PathSegment::Field("debug"),
+            parse_debug_info(debug, &proto, endianness),
+        )?;
+        proto.debug_info = debug_info;
+    }
+
+    Ok((input, proto))
+}
+
+/// Parse a whole LuaJIT dump, returning the header and the main prototype
+pub fn parse_luajit(input: &[u8]) -> PResult<'_, (Header, Prototype)> {
+    let (mut input, header) = within(PathSegment::Field("header"), parse_luajit_header(input))?;
+    let mut stack = Vec::new();
+
+    for index in 0.. {
+        let (rest, len) = parse_uleb128(input)?;
+        if len == 0 {
+            input = rest;
+            break;
+        }
+
+        let (rest, body) = take(len)(rest)?;
+        let (_, proto) = within(
+            PathSegment::Index("proto", index),
+            parse_prototype(body, &header, &mut stack),
+        )?;
+        stack.push(proto);
+        input = rest;
+    }
+
+    match (stack.pop(), stack.is_empty()) {
+        (Some(main), true) => {
+            debug!("Parsed LuaJIT prototype: {:#?}", main);
+            Ok((input, (header, main)))
+        }
+        _ => fail(input, ParseErrorKind::UnbalancedPrototypes),
+    }
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_luajit_bytecode;
+
+    // Hand-assembled dumps, in the layout `luajit -b` writes (lj_bcwrite.c) on a
+    // little-endian GC64 build, which sets FR2
+    const FNEW: u32 = 51;
+    const TDUP: u32 = 53;
+    const KSTR: u32 = 39;
+    const RET0: u32 = 75;
+    const RET1: u32 = 76;
+
+    fn uleb(out: &mut Vec<u8>, mut value: u32) {
+        while value >= 0x80 {
+            out.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn ad(op: u32, a: u32, d: u32) -> u32 {
+        op | (a << 8) | (d << 16)
+    }
+
+    /// A prototype as lj_bcwrite.c:bcwrite_proto lays it out. `kgc` and `kn` are
+    /// already encoded, the GC constants from the highest index down
+    struct Proto<'a> {
+        flags: u8,
+        params: u8,
+        frame: u8,
+        code: &'a [u32],
+        kgc: (u32, &'a [u8]),
+        kn: (u32, &'a [u8]),
+        debug: Option<(u32, u32, &'a [u8])>, // First line, line count, debug info
+    }
+
+    impl Proto<'_> {
+        fn write(&self, out: &mut Vec<u8>) {
+            let mut body = vec![self.flags, self.params, self.frame, 0];
+            uleb(&mut body, self.kgc.0);
+            uleb(&mut body, self.kn.0);
+            uleb(&mut body, self.code.len() as u32);
+            if let Some((first_line, num_lines, debug)) = self.debug {
+                uleb(&mut body, debug.len() as u32);
+                uleb(&mut body, first_line);
+                uleb(&mut body, num_lines);
+            }
+            for word in self.code {
+                body.extend(word.to_le_bytes());
+            }
+            body.extend(self.kgc.1);
+            body.extend(self.kn.1);
+            if let Some((.., debug)) = self.debug {
+                body.extend(debug);
+            }
+            uleb(out, body.len() as u32);
+            out.extend(body);
+        }
+    }
+
+    #[test]
+    fn parses_nested_prototypes_with_debug_info() {
+        // local f = function(a) local function h() end return a end
+        // local g = function() end
+        // local t = {1, ["k\xfe"] = true}
+        // local s = "s\xff"
+        let mut dump = b"\x1bLJ\x02".to_vec();
+        uleb(&mut dump, luajit::FLAG_FR2);
+        uleb(&mut dump, 8);
+        dump.extend(b"t\xe9st.lua");
+
+        Proto {
+            flags: 0,
+            params: 0,
+            frame: 1,
+            code: &[ad(RET0, 0, 1)],
+            kgc: (0, &[]),
+            kn: (0, &[]),
+            debug: Some((1, 0, &[0, 0])),
+        }
+        .write(&mut dump);
+        Proto {
+            flags: luajit::PROTO_CHILD,
+            params: 1,
+            frame: 2,
+            code: &[ad(FNEW, 1, 0), ad(RET1, 0, 2)],
+            kgc: (1, &[0]),
+            kn: (0, &[]),
+            debug: Some((1, 0, &[0, 0, b'a', 0, 0, 2, b'h', 0, 1, 1, 0])),
+        }
+        .write(&mut dump);
+        Proto {
+            flags: 0,
+            params: 0,
+            frame: 1,
+            code: &[ad(RET0, 0, 1)],
+            kgc: (0, &[]),
+            kn: (0, &[]),
+            debug: Some((2, 0, &[0, 0])),
+        }
+        .write(&mut dump);
+        Proto {
+            flags: luajit::PROTO_CHILD | luajit::PROTO_VARARG,
+            params: 0,
+            frame: 4,
+            code: &[
+                ad(FNEW, 0, 0),
+                ad(FNEW, 1, 1),
+                ad(TDUP, 2, 2),
+                ad(KSTR, 3, 3),
+                ad(RET0, 0, 1),
+            ],
+            // "s\xff", then {nil, 1; ["k\xfe"] = true}, then the two children
+            kgc: (4, b"\x07s\xff\x01\x02\x01\x00\x03\x01\x07k\xfe\x02\x00\x00"),
+            kn: (0, &[]),
+            debug: Some((
+                0,
+                4,
+                b"\x01\x02\x03\x04\x04f\x00\x01\x04g\x00\x01\x03t\x00\x01\x02s\x00\x01\x01\x00",
+            )),
+        }
+        .write(&mut dump);
+        dump.push(0);
+
+        let (header, main) = parse_luajit_bytecode(&dump).expect("valid dump");
+        assert_eq!(header.version, DumpVersion::V2);
+        assert_eq!(header.flags, luajit::FLAG_FR2);
+        assert_eq!(header.chunk_name, b"t\xe9st.lua");
+
+        let [f, g] = [0, 1].map(|d| match main.gc_constants[d] {
+            GcConstant::Child(index) => &main.prototypes[index],
+            ref other => panic!("D{d} is {other:?}"),
+        });
+        assert_eq!((f.num_params, f.prototypes.len()), (1, 1));
+        assert_eq!((g.num_params, g.prototypes.len()), (0, 0));
+        assert_eq!(f.prototypes[0].debug_info.lineinfo, [1]);
+
+        let GcConstant::Table(table) = &main.gc_constants[2] else {
+            panic!("D2 is {:?}", main.gc_constants[2]);
+        };
+        assert!(matches!(
+            table.array[..],
+            [TableValue::Nil, TableValue::Integer(1)]
+        ));
+        assert!(matches!(
+            &table.hash[..],
+            [(TableValue::String(key), TableValue::Boolean(true))] if key == b"k\xfe"
+        ));
+        assert!(matches!(&main.gc_constants[3], GcConstant::String(s) if s == b"s\xff"));
+
+        assert_eq!(main.debug_info.lineinfo, [1, 2, 3, 4, 4]);
+        let names: Vec<&[u8]> = main
+            .debug_info
+            .locals
+            .iter()
+            .map(|l| l.varname.as_slice())
+            .collect();
+        assert_eq!(names, [b"f", b"g", b"t", b"s"]);
+        let a = &f.debug_info.locals[0];
+        assert_eq!(
+            (a.varname.as_slice(), a.startpc, a.endpc),
+            (&b"a"[..], 0, 2)
+        );
+        let h = &f.debug_info.locals[1];
+        assert_eq!(
+            (h.varname.as_slice(), h.startpc, h.endpc),
+            (&b"h"[..], 1, 2)
+        );
+    }
+
+    #[test]
+    fn parses_stripped_dump() {
+        // local s, n, x = "x", 5, 0.5 (luajit -b -s)
+        let mut dump = b"\x1bLJ\x02".to_vec();
+        uleb(&mut dump, luajit::FLAG_FR2 | luajit::FLAG_STRIP);
+        let mut kn = vec![5 << 1, 1];
+        uleb(&mut kn, 0.5f64.to_bits().wrapping_shr(32) as u32);
+        Proto {
+            flags: luajit::PROTO_VARARG,
+            params: 0,
+            frame: 3,
+            code: &[ad(KSTR, 0, 0), ad(RET0, 0, 1)],
+            kgc: (1, b"\x06x"),
+            kn: (2, &kn),
+            debug: None,
+        }
+        .write(&mut dump);
+        dump.push(0);
+
+        let (header, main) = parse_luajit_bytecode(&dump).expect("valid dump");
+        assert!(header.is_stripped());
+        assert!(header.chunk_name.is_empty());
+        assert!(matches!(&main.gc_constants[..], [GcConstant::String(s)] if s == b"x"));
+        assert!(matches!(
+            main.num_constants[..],
+            [NumConstant::Integer(5), NumConstant::Number(n)] if n == 0.5
+        ));
+        assert_eq!(main.code.len(), 2);
+        assert!(main.debug_info.lineinfo.is_empty());
+        assert!(main.debug_info.locals.is_empty());
+    }
+}
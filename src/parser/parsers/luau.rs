@@ -0,0 +1,481 @@
+use super::super::bytecode::luau::{
+    self, Chunk, Constant, DebugInfo, Header, Import, Instruction, LocalVariable, Prototype,
+};
+use super::super::error::{PResult, ParseErrorKind, PathSegment, fail, within};
+use super::parsers::{parse_counted, parse_uleb128};
+use log::debug;
+use nom::{
+    Parser,
+    bytes::complete::take,
+    number::complete::{le_f32, le_f64, le_i32, le_u32, u8},
+};
+
+/// Parsing functions module
+mod parsers {
+    use super::*;
+
+    /// Parses a varint-length-prefixed string from the string table
+    pub fn parse_table_string(input: &[u8]) -> PResult<'_, Vec<u8>> {
+        let (input, len) = parse_uleb128(input)?;
+        let (input, bytes) = take(len)(input)?;
+        Ok((input, bytes.to_vec()))
+    }
+
+    /// Parses a 1-based reference into the string table, 0 standing for no string
+    pub fn parse_string_ref<'a>(
+        input: &'a [u8],
+        strings: &[Vec<u8>],
+        field: &'static str,
+    ) -> PResult<'a, Option<Vec<u8>>> {
+        let (rest, index) = parse_uleb128(input)?;
+        match index {
+            0 => Ok((rest, None)),
+            _ => match strings.get(index as usize - 1) {
+                Some(s) => Ok((rest, Some(s.clone()))),
+                None => fail(input, ParseErrorKind::InvalidReference { field, index }),
+            },
+        }
+    }
+
+    /// Parses a reference to an already parsed prototype
+    pub fn parse_proto_ref<'a>(input: &'a [u8], parsed: usize) -> PResult<'a, usize> {
+        let (rest, index) = parse_uleb128(input)?;
+        if index as usize >= parsed {
+            return fail(
+                input,
+                ParseErrorKind::InvalidReference {
+                    field: "proto",
+                    index,
+                },
+            );
+        }
+        Ok((rest, index as usize))
+    }
+
+    /// Parses the userdata type remapping of types version 3, terminated by a zero index
+    pub fn parse_userdata_types<'a>(
+        mut input: &'a [u8],
+        strings: &[Vec<u8>],
+    ) -> PResult<'a, Vec<(u8, Vec<u8>)>> {
+        let mut types = Vec::new();
+        loop {
+            let (rest, index) = u8(input)?;
+            if index == 0 {
+                return Ok((rest, types));
+            }
+            let (rest, name) = parse_string_ref(rest, strings, "userdata")?;
+            types.push((index, name.unwrap_or_default()));
+            input = rest;
+        }
+    }
+
+    pub fn parse_constant<'a>(
+        input: &'a [u8],
+        strings: &[Vec<u8>],
+        parsed: usize,
+    ) -> PResult<'a, Constant> {
+        let (rest, tag) = u8(input)?;
+        match tag {
+            luau::CONSTANT_NIL => Ok((rest, Constant::Nil)),
+            luau::CONSTANT_BOOLEAN => u8.map(|b| Constant::Boolean(b != 0)).parse(rest),
+            luau::CONSTANT_NUMBER => le_f64.map(Constant::Number).parse(rest),
+            luau::CONSTANT_STRING => match parse_string_ref(rest, strings, "string")? {
+                (rest, Some(s)) => Ok((rest, Constant::String(s))),
+                (_, None) => fail(
+                    rest,
+                    ParseErrorKind::InvalidReference {
+                        field: "string",
+                        index: 0,
+                    },
+                ),
+            },
+            luau::CONSTANT_IMPORT => le_u32
+                .map(|id| {
+                    Constant::Import(Import {
+                        id,
+                        path: Vec::new(),
+                    })
+                })
+                .parse(rest),
+            luau::CONSTANT_TABLE => {
+                let (rest, count) = parse_uleb128(rest)?;
+                let (rest, keys) = parse_counted(rest, "keys", count, |i| {
+                    parse_uleb128.map(|k| k as usize).parse(i)
+                })?;
+                Ok((rest, Constant::Table(keys)))
+            }
+            luau::CONSTANT_CLOSURE => {
+                parse_proto_ref(rest, parsed).map(|(rest, index)| (rest, Constant::Closure(index)))
+            }
+            luau::CONSTANT_VECTOR => {
+                let (rest, (x, y, z, w)) = (le_f32, le_f32, le_f32, le_f32).parse(rest)?;
+                Ok((rest, Constant::Vector(x, y, z, w)))
+            }
+            _ => fail(input, ParseErrorKind::UnknownConstantTag(tag)),
+        }
+    }
+
+    /// Resolves the names of every import, which reference string constants
+    pub fn resolve_imports<'a>(input: &'a [u8], constants: &mut [Constant]) -> PResult<'a, ()> {
+        for index in 0..constants.len() {
+            let Constant::Import(import) = &constants[index] else {
+                continue;
+            };
+            let mut path = Vec::new();
+            for k in Import::indices(import.id) {
+                match constants.get(k) {
+                    Some(Constant::String(name)) => path.push(name.clone()),
+                    _ => {
+                        return within(
+                            PathSegment::Index("constants", index),
+                            fail(
+                                input,
+                                ParseErrorKind::InvalidReference {
+                                    field: "import",
+                                    index: k as u32,
+                                },
+                            ),
+                        );
+                    }
+                }
+            }
+            if let Constant::Import(import) = &mut constants[index] {
+                import.path = path;
+            }
+        }
+        Ok((input, ()))
+    }
+
+    /// Parses the line info: per-instruction offsets on top of a line per 2^gap interval
+    pub fn parse_lineinfo(input: &[u8], size_code: usize) -> PResult<'_, Vec<u32>> {
+        let (input, gap_log2) = u8(input)?;
+        if gap_log2 >= 32 {
+            return fail(input, ParseErrorKind::ValueTooLarge);
+        }
+        let intervals = match size_code {
+            0 => 0,
+            _ => ((size_code - 1) >> gap_log2) + 1,
+        };
+
+        let mut offset = 0u8;
+        let (input, offsets) = parse_counted(input, "lineinfo", size_code as u32, |i| {
+            u8.map(|delta| {
+                offset = offset.wrapping_add(delta);
+                offset
+            })
+            .parse(i)
+        })?;
+        let mut line = 0i32;
+        let (input, anchors) = parse_counted(input, "abslineinfo", intervals as u32, |i| {
+            le_i32
+                .map(|delta| {
+                    line = line.wrapping_add(delta);
+                    line
+                })
+                .parse(i)
+        })?;
+
+        let lineinfo = offsets
+            .iter()
+            .enumerate()
+            .map(|(pc, &offset)| (anchors[pc >> gap_log2] + i32::from(offset)) as u32)
+            .collect();
+        Ok((input, lineinfo))
+    }
+
+    pub fn parse_local_variable<'a>(
+        input: &'a [u8],
+        strings: &[Vec<u8>],
+    ) -> PResult<'a, LocalVariable> {
+        let (input, varname) = parse_string_ref(input, strings, "local")?;
+        let (input, startpc) = parse_uleb128(input)?;
+        let (input, endpc) = parse_uleb128(input)?;
+        let (input, reg) = u8(input)?;
+
+        let local = LocalVariable {
+            varname: varname.unwrap_or_default(),
+            startpc,
+            endpc,
+            reg,
+        };
+        Ok((input, local))
+    }
+
+    pub fn parse_debug_info<'a>(
+        input: &'a [u8],
+        strings: &[Vec<u8>],
+    ) -> PResult<'a, (Vec<LocalVariable>, Vec<Vec<u8>>)> {
+        let (input, count) = parse_uleb128(input)?;
+        let (input, locals) =
+            parse_counted(input, "locals", count, |i| parse_local_variable(i, strings))?;
+        let (input, count) = parse_uleb128(input)?;
+        let (input, upvalues) = parse_counted(input, "upvalues", count, |i| {
+            parse_string_ref(i, strings, "upvalue").map(|(i, name)| (i, name.unwrap_or_default()))
+        })?;
+        Ok((input, (locals, upvalues)))
+    }
+}
+
+use parsers::*;
+
+/// Parse the header of a Luau bytecode blob, including its string table
+pub fn parse_luau_header(input: &[u8]) -> PResult<'_, Header> {
+    let (rest, version) = u8(input)?;
+    if version == 0 {
+        // A failed compilation stores the error message in place of the bytecode
+        let message = String::from_utf8_lossy(rest).into_owned();
+        return fail(input, ParseErrorKind::CompileError(message));
+    }
+    if !(luau::VERSION_MIN..=luau::VERSION_MAX).contains(&version) {
+        return fail(input, ParseErrorKind::UnsupportedVersion(version));
+    }
+
+    let (rest, types_version) = match version {
+        4.. => {
+            let (next, types_version) = u8(rest)?;
+            if !(luau::TYPE_VERSION_MIN..=luau::TYPE_VERSION_MAX).contains(&types_version) {
+                return within(
+                    PathSegment::Field("types_version"),
+                    fail(rest, ParseErrorKind::UnsupportedVersion(types_version)),
+                );
+            }
+            (next, types_version)
+        }
+        _ => (rest, 0),
+    };
+
+    let (rest, count) = parse_uleb128(rest)?;
+    let (rest, strings) = parse_counted(rest, "strings", count, parse_table_string)?;
+
+    let (rest, userdata_types) = match types_version {
+        3 => within(
+            PathSegment::Field("userdata_types"),
+            parse_userdata_types(rest, &strings),
+        )?,
+        _ => (rest, Vec::new()),
+    };
+
+    let header = Header {
+        version,
+        types_version,
+        strings,
+        userdata_types,
+    };
+
+    debug!("Parsed Luau header: {:#?}", header);
+
+    Ok((rest, header))
+}
+
+/// Parse one prototype (lvmload.cpp:luau_load). `parsed` is the number of prototypes read
+/// so far, which children and closure constants must refer to
+pub fn parse_prototype<'a>(
+    input: &'a [u8],
+    header: &Header,
+    parsed: usize,
+) -> PResult<'a, Prototype> {
+    let strings = &header.strings;
+
+    let (input, max_stack_size) = u8(input)?;
+    let (input, num_params) = u8(input)?;
+    let (input, num_upvalues) = u8(input)?;
+    let (input, is_vararg) = u8(input)?;
+
+    let (input, flags, type_info) = match header.version {
+        4.. => {
+            let (input, flags) = u8(input)?;
+            let (input, size) = parse_uleb128(input)?;
+            let (input, type_info) = take(size)(input)?;
+            (input, flags, type_info.to_vec())
+        }
+        _ => (input, 0, Vec::new()),
+    };
+
+    let (input, count) = parse_uleb128(input)?;
+    let (input, code) = parse_counted(input, "code", count, |i| {
+        le_u32.map(Instruction::new).parse(i)
+    })?;
+
+    let (input, count) = parse_uleb128(input)?;
+    let constants_start = input;
+    let (input, mut constants) = parse_counted(input, "constants", count, |i| {
+        parse_constant(i, strings, parsed)
+    })?;
+    resolve_imports(constants_start, &mut constants)?;
+
+    let (input, count) = parse_uleb128(input)?;
+    let (input, prototypes) =
+        parse_counted(input, "prototypes", count, |i| parse_proto_ref(i, parsed))?;
+
+    let (input, line_defined) = parse_uleb128(input)?;
+    let (input, debug_name) = parse_string_ref(input, strings, "debug_name")?;
+
+    let (input, has_lines) = u8(input)?;
+    let (input, lineinfo) = match has_lines {
+        0 => (input, Vec::new()),
+        _ => parse_lineinfo(input, code.len())?,
+    };
+
+    let (input, has_debug) = u8(input)?;
+    let (input, (locals, upvalues)) = match has_debug {
+        0 => (input, (Vec::new(), Vec::new())),
+        _ => within(
+            PathSegment::Field("debug"),
+            parse_debug_info(input, strings),
+        )?,
+    };
+
+    let proto = Prototype {
+        debug_name: debug_name.unwrap_or_default(),
+        line_defined,
+        num_upvalues,
+        num_params,
+        is_vararg: is_vararg != 0,
+        max_stack_size,
+        flags,
+        type_info,
+        code,
+        constants,
+        prototypes,
+        debug_info: DebugInfo {
+            lineinfo,
+            locals,
+            upvalues,
+        },
+    };
+
+    Ok((input, proto))
+}
+
+/// Parse a whole Luau bytecode blob, returning the header and every prototype
+pub fn parse_luau(input: &[u8]) -> PResult<'_, (Header, Chunk)> {
+    let (input, header) = within(PathSegment::Field("header"), parse_luau_header(input))?;
+
+    let (mut input, count) = parse_uleb128(input)?;
+    let mut prototypes = Vec::with_capacity((count as usize).min(input.len()));
+    for index in 0..count as usize {
+        let (rest, proto) = within(
+            PathSegment::Index("proto", index),
+            parse_prototype(input, &header, prototypes.len()),
+        )?;
+        prototypes.push(proto);
+        input = rest;
+    }
+
+    let (input, main) = within(
+        PathSegment::Field("main"),
+        parse_proto_ref(input, prototypes.len()),
+    )?;
+    let chunk = Chunk { prototypes, main };
+
+    debug!("Parsed Luau chunk: {:#?}", chunk);
+
+    Ok((input, (header, chunk)))
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_luau_bytecode;
+
+    // Hand-assembled blobs in the layout BytecodeBuilder.cpp writes. Every count and
+    // index is below 0x80, so each varint is a single byte
+    const PREPVARARGS: u32 = 65;
+    const GETIMPORT: u32 = 12;
+    const LOADK: u32 = 5;
+    const CALL: u32 = 21;
+    const RETURN: u32 = 22;
+    const DUPCLOSURE: u32 = 64;
+
+    fn abc(op: u32, a: u32, b: u32, c: u32) -> [u8; 4] {
+        (op | (a << 8) | (b << 16) | (c << 24)).to_le_bytes()
+    }
+
+    fn ad(op: u32, a: u32, d: u32) -> [u8; 4] {
+        (op | (a << 8) | (d << 16)).to_le_bytes()
+    }
+
+    #[test]
+    fn parses_version_3_with_imports_and_line_gaps() {
+        // local x = "x\xff"; math.floor(x)
+        let import = (2 << 30) | (1 << 10);
+        let mut blob = vec![3];
+        blob.extend(b"\x03\x04math\x05floor\x02x\xff");
+        blob.push(1); // Prototypes
+        blob.extend([2, 0, 0, 1]); // Stack, params, upvalues, vararg
+        blob.push(6);
+        blob.extend(abc(PREPVARARGS, 0, 0, 0));
+        blob.extend(ad(GETIMPORT, 0, 2));
+        blob.extend(u32::to_le_bytes(import));
+        blob.extend(ad(LOADK, 1, 3));
+        blob.extend(abc(CALL, 0, 2, 1));
+        blob.extend(abc(RETURN, 0, 1, 0));
+        blob.extend([4, 3, 1, 3, 2, 4]);
+        blob.extend(u32::to_le_bytes(import));
+        blob.extend([3, 3]);
+        blob.extend([0, 0, 0]); // Children, line defined, debug name
+        // A line per four instructions: 1 and 4, offset by 0 0 1 1 and 1 2
+        blob.extend([1, 2, 0, 0, 1, 0, 0, 1]);
+        blob.extend(i32::to_le_bytes(1));
+        blob.extend(i32::to_le_bytes(3));
+        blob.extend([1, 1, 3, 2, 6, 1, 0]); // One local, no upvalue
+        blob.push(0); // Main
+
+        let (header, chunk) = parse_luau_bytecode(&blob).expect("valid blob");
+        assert_eq!((header.version, header.types_version), (3, 0));
+        assert_eq!(header.strings, [&b"math"[..], b"floor", b"x\xff"]);
+
+        let main = chunk.main_prototype();
+        assert!(main.is_vararg);
+        assert_eq!(main.code.len(), 6);
+        let Constant::Import(import) = &main.constants[2] else {
+            panic!("K2 is {:?}", main.constants[2]);
+        };
+        assert_eq!(import.path, [&b"math"[..], b"floor"]);
+        assert!(matches!(&main.constants[3], Constant::String(s) if s == b"x\xff"));
+
+        assert_eq!(main.debug_info.lineinfo, [1, 1, 2, 2, 5, 6]);
+        let x = &main.debug_info.locals[0];
+        assert_eq!(x.varname, b"x\xff");
+        assert_eq!((x.startpc, x.endpc, x.reg), (2, 6, 1));
+    }
+
+    #[test]
+    fn parses_version_6_with_types_and_closures() {
+        // function f(v: vec) end, with "vec" mapped as userdata type 1
+        let mut blob = vec![6, 3];
+        blob.extend(b"\x02\x01f\x03vec");
+        blob.extend([1, 2, 0]); // Userdata type 1 is string 2
+        blob.push(2); // Prototypes
+
+        blob.extend([1, 1, 0, 0, 0, 3, 1, 1, 0]); // Flags, then types
+        blob.push(1);
+        blob.extend(abc(RETURN, 0, 1, 0));
+        blob.extend([0, 0, 1, 1, 0, 0]); // Named f, defined on line 1
+
+        blob.extend([1, 0, 0, 1]);
+        blob.extend([luau::PROTO_NATIVE_MODULE, 0]);
+        blob.push(3);
+        blob.extend(abc(PREPVARARGS, 0, 0, 0));
+        blob.extend(ad(DUPCLOSURE, 0, 0));
+        blob.extend(abc(RETURN, 0, 1, 0));
+        blob.extend([1, luau::CONSTANT_CLOSURE, 0]);
+        blob.extend([1, 0, 0, 0, 0, 0]);
+        blob.push(1); // Main
+
+        let (header, chunk) = parse_luau_bytecode(&blob).expect("valid blob");
+        assert_eq!((header.version, header.types_version), (6, 3));
+        assert_eq!(header.userdata_types, [(1, b"vec".to_vec())]);
+
+        let (f, main) = (&chunk.prototypes[0], chunk.main_prototype());
+        assert_eq!(chunk.main, 1);
+        assert_eq!((f.debug_name.as_slice(), f.line_defined), (&b"f"[..], 1));
+        assert_eq!((f.num_params, f.type_info.as_slice()), (1, &[1, 1, 0][..]));
+        assert_eq!(main.flags, luau::PROTO_NATIVE_MODULE);
+        assert!(matches!(main.constants[..], [Constant::Closure(0)]));
+        assert_eq!(main.prototypes, [0]);
+        assert!(main.debug_info.lineinfo.is_empty());
+    }
+}
@@ -1,3 +1,6 @@
 pub mod function;
 pub mod header;
+pub mod luajit;
+pub mod luau;
+#[allow(clippy::module_inception)]
 pub mod parsers;
@@ -1,98 +1,287 @@
-use super::super::bytecode::{Constant, Endianness, Header, Instruction};
+use super::super::bytecode::{Constant, Endianness, Header, Instruction, Version, lua53, lua54};
+use super::super::error::{PResult, ParseErrorKind, PathSegment, fail, within};
 use nom::{
-    bytes::complete::{tag, take},
-    combinator::{map, map_res},
-    error::ErrorKind,
+    Parser,
+    bytes::complete::take,
+    combinator::map,
     number::complete::{be_u32, be_u64, le_u32, le_u64, u8},
-    IResult, Parser,
 };
 
-/// Parses a 32-bit integer with specified endianness
-pub fn parse_integer<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], i32> {
-    match header.endianness {
-        Endianness::Big => be_u32.map(|v| v as i32).parse(input),
-        Endianness::Little => le_u32.map(|v| v as i32).parse(input),
+/// Parses an unsigned 4 or 8 byte word with the header's endianness
+pub fn parse_word<'a>(
+    input: &'a [u8],
+    header: &Header,
+    field: &'static str,
+    width: u8,
+) -> PResult<'a, u64> {
+    match (width, header.endianness) {
+        (4, Endianness::Big) => be_u32.map(u64::from).parse(input),
+        (4, Endianness::Little) => le_u32.map(u64::from).parse(input),
+        (8, Endianness::Big) => be_u64.parse(input),
+        (8, Endianness::Little) => le_u64.parse(input),
+        (value, _) => fail(input, ParseErrorKind::InvalidSize { field, value }),
     }
 }
 
-/// Parses a size_t value according to header specifications
-pub fn parse_size_t<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], u64> {
-    match (header.size_size_t, header.endianness) {
-        (4, Endianness::Big) => be_u32.map(|v| v as u64).parse(input),
-        (4, Endianness::Little) => le_u32.map(|v| v as u64).parse(input),
-        (8, Endianness::Big) => be_u64.parse(input),
-        (8, Endianness::Little) => le_u64.parse(input),
-        _ => Err(nom::Err::Failure(nom::error::Error::new(
-            input,
-            ErrorKind::Verify,
-        ))),
+/// Parses a Lua 5.4 varint: big-endian groups of 7 bits, the last byte has its high bit set
+pub fn parse_varint(input: &[u8], limit: u64) -> PResult<'_, u64> {
+    let mut value: u64 = 0;
+    let mut rest = input;
+    loop {
+        let (next, byte) = u8(rest)?;
+        if value >= limit >> 7 {
+            return fail(input, ParseErrorKind::ValueTooLarge);
+        }
+        value = (value << 7) | u64::from(byte & 0x7f);
+        rest = next;
+        if byte & 0x80 != 0 {
+            return Ok((rest, value));
+        }
+    }
+}
+
+/// Parses an unsigned LEB128 value (LuaJIT lj_buf_ruleb128, Luau readVarInt)
+pub fn parse_uleb128(input: &[u8]) -> PResult<'_, u32> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    let mut rest = input;
+    loop {
+        let (next, byte) = u8(rest)?;
+        if shift > 28 {
+            return fail(input, ParseErrorKind::ValueTooLarge);
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        shift += 7;
+        rest = next;
+        if byte < 0x80 {
+            break;
+        }
+    }
+
+    match u32::try_from(value) {
+        Ok(value) => Ok((rest, value)),
+        Err(_) => fail(input, ParseErrorKind::ValueTooLarge),
+    }
+}
+
+/// Parses `count` elements, recording the element index on errors
+pub fn parse_counted<'a, T, F>(
+    mut input: &'a [u8],
+    name: &'static str,
+    count: u32,
+    mut parser: F,
+) -> PResult<'a, Vec<T>>
+where
+    F: FnMut(&'a [u8]) -> PResult<'a, T>,
+{
+    let mut items = Vec::with_capacity((count as usize).min(input.len()));
+    for index in 0..count as usize {
+        let (rest, item) = within(PathSegment::Index(name, index), parser(input))?;
+        items.push(item);
+        input = rest;
+    }
+    Ok((input, items))
+}
+
+/// Parses a C `int` of `size_int` bytes (a varint on Lua 5.4)
+pub fn parse_integer<'a>(input: &'a [u8], header: &Header) -> PResult<'a, i32> {
+    if header.version >= Version::Lua54 {
+        return map(|i| parse_varint(i, i32::MAX as u64), |v| v as i32).parse(input);
     }
+
+    let (rest, word) = parse_word(input, header, "int", header.size_int)?;
+    let value = match header.size_int {
+        4 => word as u32 as i32,
+        _ => match i32::try_from(word as i64) {
+            Ok(value) => value,
+            Err(_) => return fail(input, ParseErrorKind::ValueTooLarge),
+        },
+    };
+    Ok((rest, value))
+}
+
+/// Parses a size_t value according to header specifications (a varint on Lua 5.4)
+pub fn parse_size_t<'a>(input: &'a [u8], header: &Header) -> PResult<'a, u64> {
+    if header.version >= Version::Lua54 {
+        return parse_varint(input, u64::MAX);
+    }
+
+    parse_word(input, header, "size_t", header.size_size_t)
+}
+
+/// Parses a length-prefixed string with null terminator into owned bytes, a NULL string
+/// reading as empty
+pub fn parse_string<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Vec<u8>> {
+    map(|i| parse_bytes(i, header), <[u8]>::to_vec).parse(input)
 }
 
-/// Parses a length-prefixed string with null terminator
-pub fn parse_string<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], String> {
+/// Parses a length-prefixed string that may be NULL (a length of 0), as sources are
+pub fn parse_nullable_string<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Option<Vec<u8>>> {
+    map(
+        |i| parse_nullable_bytes(i, header),
+        |bytes| bytes.map(<[u8]>::to_vec),
+    )
+    .parse(input)
+}
+
+/// Parses the raw bytes of a length-prefixed string with null terminator
+pub fn parse_bytes<'a>(input: &'a [u8], header: &Header) -> PResult<'a, &'a [u8]> {
+    map(
+        |i| parse_nullable_bytes(i, header),
+        Option::unwrap_or_default,
+    )
+    .parse(input)
+}
+
+/// Parses the raw bytes of a length-prefixed string, None standing for NULL
+fn parse_nullable_bytes<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Option<&'a [u8]>> {
+    if header.version >= Version::Lua53 {
+        return parse_bytes_53(input, header);
+    }
+
     let (input, len) = parse_size_t(input, header)?;
     if len == 0 {
-        return Ok((input, String::new()));
+        return Ok((input, None));
+    }
+
+    let Ok(len) = usize::try_from(len - 1) else {
+        return fail(input, ParseErrorKind::ValueTooLarge);
+    };
+
+    let (rest, bytes) = take(len)(input)?;
+    match rest.split_first() {
+        Some((0, rest)) => Ok((rest, Some(bytes))),
+        Some(_) => fail(rest, ParseErrorKind::MissingTerminator),
+        None => fail(rest, ParseErrorKind::Truncated),
     }
+}
 
-    let len_minus_1 = len
-        .checked_sub(1)
-        .ok_or_else(|| nom::Err::Failure(nom::error::Error::new(input, ErrorKind::Verify)))?;
+/// Parses a Lua 5.3+ string, stored without a terminator. Lua 5.3 uses a one byte length
+/// (0xFF escapes to a size_t), Lua 5.4 a varint
+fn parse_bytes_53<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Option<&'a [u8]>> {
+    let (input, len) = match header.version {
+        Version::Lua53 => match u8(input)? {
+            (input, 0xFF) => parse_size_t(input, header)?,
+            (input, len) => (input, u64::from(len)),
+        },
+        _ => parse_size_t(input, header)?,
+    };
+    if len == 0 {
+        return Ok((input, None));
+    }
 
-    let len_usize = usize::try_from(len_minus_1)
-        .map_err(|_| nom::Err::Failure(nom::error::Error::new(input, ErrorKind::TooLarge)))?;
+    let Ok(len) = usize::try_from(len - 1) else {
+        return fail(input, ParseErrorKind::ValueTooLarge);
+    };
 
-    let (input, bytes) = take(len_usize)(input)?;
-    let (input, _) = tag(&b"\x00"[..])(input)?;
+    map(take(len), Some).parse(input)
+}
 
-    Ok((input, String::from_utf8_lossy(bytes).into_owned()))
+/// Parses a string constant, keeping its bytes as they are
+fn parse_string_constant<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Constant> {
+    map(
+        |i| parse_bytes(i, header),
+        |bytes| Constant::String(bytes.to_vec()),
+    )
+    .parse(input)
 }
 
 /// Parses a single instruction (4 bytes) with specified endianness
-pub fn parse_instruction<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Instruction> {
-    let (input, instruction) = match header.endianness {
-        Endianness::Big => map(be_u32, |v| v as u32).parse(input),
-        Endianness::Little => map(le_u32, |v| v as u32).parse(input),
-    }?;
+pub fn parse_instruction<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Instruction> {
+    let (input, instruction) = parse_word(input, header, "instruction", header.size_instruction)?;
 
-    let instr = Instruction::new(instruction);
+    let instr = Instruction::new(instruction as u32);
     Ok((input, instr))
 }
 
-/// Parses a constant number according to header's integral flag
-pub fn parse_number<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], f64> {
-    let parse_bytes = |bytes: &'a [u8]| -> Result<f64, nom::error::Error<&[u8]>> {
-        let arr = bytes
-            .try_into()
-            .map_err(|_| nom::error::Error::new(bytes, ErrorKind::LengthValue))?;
-        Ok(if header.integral_flag {
-            match header.endianness {
-                Endianness::Big => i64::from_be_bytes(arr) as f64,
-                Endianness::Little => i64::from_le_bytes(arr) as f64,
-            }
-        } else {
-            match header.endianness {
-                Endianness::Big => f64::from_be_bytes(arr),
-                Endianness::Little => f64::from_le_bytes(arr),
-            }
-        })
+/// Parses a lua_Number of `size_number` bytes as a float
+pub fn parse_float<'a>(input: &'a [u8], header: &Header) -> PResult<'a, f64> {
+    let (rest, word) = parse_word(input, header, "number", header.size_number)?;
+
+    let number = match header.size_number {
+        4 => f32::from_bits(word as u32) as f64,
+        _ => f64::from_bits(word),
+    };
+
+    Ok((rest, number))
+}
+
+/// Parses a signed integer of `width` bytes without losing precision
+fn parse_signed<'a>(
+    input: &'a [u8],
+    header: &Header,
+    field: &'static str,
+    width: u8,
+) -> PResult<'a, i64> {
+    let (rest, word) = parse_word(input, header, field, width)?;
+
+    let value = match width {
+        4 => word as u32 as i32 as i64,
+        _ => word as i64,
     };
 
-    map_res(take(header.size_number), parse_bytes).parse(input)
+    Ok((rest, value))
+}
+
+/// Parses a Lua 5.3 lua_Integer of `size_integer` bytes
+pub fn parse_lua_integer<'a>(input: &'a [u8], header: &Header) -> PResult<'a, i64> {
+    parse_signed(input, header, "integer", header.size_integer)
+}
+
+/// Parses a pre-5.3 lua_Number, keeping integral builds as exact integers
+pub fn parse_number<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Constant> {
+    if header.integral_flag {
+        map(
+            |i| parse_signed(i, header, "number", header.size_number),
+            Constant::Integer,
+        )
+        .parse(input)
+    } else {
+        map(|i| parse_float(i, header), Constant::Number).parse(input)
+    }
 }
 
 /// Parses a constant value from the bytecode
-pub fn parse_constant<'a>(input: &'a [u8], header: &Header) -> IResult<&'a [u8], Constant> {
-    let (input, tag_byte) = u8(input)?;
+pub fn parse_constant<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Constant> {
+    match header.version {
+        Version::Lua51 | Version::Lua52 => {}
+        Version::Lua53 => return parse_constant_53(input, header),
+        Version::Lua54 => return parse_constant_54(input, header),
+    }
+
+    let (rest, tag_byte) = u8(input)?;
+    match tag_byte {
+        0x00 => Ok((rest, Constant::Nil)),
+        0x01 => map(u8, |v| Constant::Boolean(v != 0)).parse(rest),
+        0x03 => parse_number(rest, header),
+        0x04 => parse_string_constant(rest, header),
+        _ => fail(input, ParseErrorKind::UnknownConstantTag(tag_byte)),
+    }
+}
+
+/// Parses a Lua 5.3 constant, where integers and floats carry distinct tags
+pub fn parse_constant_53<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Constant> {
+    let (rest, tag_byte) = u8(input)?;
+    match tag_byte {
+        lua53::TAG_NIL => Ok((rest, Constant::Nil)),
+        lua53::TAG_BOOLEAN => map(u8, |v| Constant::Boolean(v != 0)).parse(rest),
+        lua53::TAG_NUMFLT => map(|i| parse_float(i, header), Constant::Number).parse(rest),
+        lua53::TAG_NUMINT => map(|i| parse_lua_integer(i, header), Constant::Integer).parse(rest),
+        lua53::TAG_SHRSTR | lua53::TAG_LNGSTR => parse_string_constant(rest, header),
+        _ => fail(input, ParseErrorKind::UnknownConstantTag(tag_byte)),
+    }
+}
+
+/// Parses a Lua 5.4 constant, where booleans are split into two tags and number tags are swapped
+pub fn parse_constant_54<'a>(input: &'a [u8], header: &Header) -> PResult<'a, Constant> {
+    let (rest, tag_byte) = u8(input)?;
     match tag_byte {
-        0x00 => Ok((input, Constant::Nil)),
-        0x01 => map(u8, |v| Constant::Boolean(v != 0)).parse(input),
-        0x03 => map(|i| parse_number(i, header), Constant::Number).parse(input),
-        0x04 => map(|i| parse_string(i, header), Constant::String).parse(input),
-        _ => Err(nom::Err::Error(nom::error::Error::new(
-            input,
-            ErrorKind::Tag,
-        ))),
+        lua54::TAG_NIL => Ok((rest, Constant::Nil)),
+        lua54::TAG_FALSE => Ok((rest, Constant::Boolean(false))),
+        lua54::TAG_TRUE => Ok((rest, Constant::Boolean(true))),
+        lua54::TAG_NUMFLT => map(|i| parse_float(i, header), Constant::Number).parse(rest),
+        lua54::TAG_NUMINT => map(|i| parse_lua_integer(i, header), Constant::Integer).parse(rest),
+        lua54::TAG_SHRSTR | lua54::TAG_LNGSTR => parse_string_constant(rest, header),
+        _ => fail(input, ParseErrorKind::UnknownConstantTag(tag_byte)),
     }
 }
@@ -0,0 +1,500 @@
+/*
+  Machine-readable form of a parsed Lua 5.1 chunk, for `--format json`
+
+  A document is one object per chunk, its layout only ever grows new fields
+  within a schema version:
+
+  {
+    "schema": 1,
+    "header": { "version": "Lua51", "format": 0, "endianness": "Little",
+                "size_int": 4, "size_size_t": 8, "size_instruction": 4,
+                "size_number": 8, "size_integer": 0, "integral_flag": false,
+                "main_upvalues": 0 },
+    "main": <function>
+  }
+
+  <function>:
+  {
+    "source": "@file.lua",            // "" when stripped or inherited
+    "line_defined": 0, "last_line_defined": 0,
+    "num_upvalues": 0, "num_params": 0, "is_vararg": 2, "max_stack_size": 2,
+    "code": [<instruction>...],
+    "constants": [<constant>...],    // Indexed from 0, like K operands
+    "locals": [{ "varname": "x", "startpc": 1, "endpc": 4 }...],
+    "upvalues": ["name"...],          // Debug names, empty when stripped
+    "functions": [<function>...]      // Indexed by CLOSURE's Bx
+  }
+
+  <instruction>:
+  {
+    "pc": 0,                          // From 0, one less than luac -l shows
+    "line": 1,                        // null without line info
+    "raw": 16449,                     // The instruction word
+    "kind": "instruction",            // Or "closure_upvalue" / "setlist_batch" for
+                                      // the operand words after CLOSURE and SETLIST
+    "opcode": "LOADK",                // null for unknown opcodes and batch words
+    "operands": { "op": "LoadK", "dst": 1, "k": 1 },
+    "target": 5                       // pc jumped to by sBx jumps, null otherwise
+  }
+
+  Operands follow `DecodedOp`: "op" names the variant, the other fields are its
+  operands, and RK operands are {"register": n} or {"constant": n}. They are null
+  when the word does not decode, for instance with a set unused operand.
+
+  <constant>:
+  { "type": "nil" } | { "type": "boolean", "value": true }
+  | { "type": "number", "value": 1.5 } | { "type": "integer", "value": 3 }
+  | { "type": "string", "value": "text", "hex": "ff00" }
+
+  Numbers that are not finite have no JSON form and come out as null. Strings that
+  are not valid UTF-8 hold a lossy "value" and their exact bytes in "hex", which is
+  left out otherwise. Source, local and upvalue names are always lossy strings.
+*/
+
+use crate::parser::bytecode::{
+    Constant, DecodedOp, FunctionPrototype, Header, InstructionFormat, LUA51_OPCODES, LocalVariable,
+};
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt::Write;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// A whole chunk, the root of a document
+#[derive(Debug, Serialize)]
+pub struct Chunk<'a> {
+    pub schema: u32,
+    pub header: &'a Header,
+    pub main: Function<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Function<'a> {
+    pub source: Cow<'a, str>,
+    pub line_defined: i32,
+    pub last_line_defined: i32,
+    pub num_upvalues: u8,
+    pub num_params: u8,
+    pub is_vararg: u8,
+    pub max_stack_size: u8,
+    pub code: Vec<Code>,
+    pub constants: &'a [Constant],
+    pub locals: &'a [LocalVariable],
+    pub upvalues: Vec<Cow<'a, str>>,
+    pub functions: Vec<Function<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Code {
+    pub pc: usize,
+    pub line: Option<u32>,
+    pub raw: u32,
+    pub kind: CodeKind,
+    pub opcode: Option<&'static str>,
+    pub operands: Option<DecodedOp>,
+    pub target: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeKind {
+    Instruction,
+    ClosureUpvalue, // MOVE or GETUPVAL binding an upvalue of the preceding CLOSURE
+    SetlistBatch,   // Batch number of a SETLIST whose C is 0
+}
+
+/// Serialized shape of a constant
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ConstantRepr<'a> {
+    Nil,
+    Boolean {
+        value: bool,
+    },
+    Number {
+        value: f64,
+    },
+    Integer {
+        value: i64,
+    },
+    String {
+        value: Cow<'a, str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        hex: Option<String>,
+    },
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl<'a> Chunk<'a> {
+    pub fn new(header: &'a Header, proto: &'a FunctionPrototype) -> Self {
+        Self {
+            schema: SCHEMA_VERSION,
+            header,
+            main: Function::new(proto),
+        }
+    }
+}
+
+impl<'a> Function<'a> {
+    pub fn new(proto: &'a FunctionPrototype) -> Self {
+        Self {
+            source: proto.source(),
+            line_defined: proto.line_defined,
+            last_line_defined: proto.last_line_defined,
+            num_upvalues: proto.num_upvalues,
+            num_params: proto.num_params,
+            is_vararg: proto.is_vararg,
+            max_stack_size: proto.max_stack_size,
+            code: (0..proto.code.len())
+                .map(|pc| Code::new(proto, pc))
+                .collect(),
+            constants: &proto.constants,
+            locals: &proto.debug_info.locals,
+            upvalues: (proto.debug_info.upvalues.iter())
+                .map(|name| String::from_utf8_lossy(name))
+                .collect(),
+            functions: proto.prototypes.iter().map(Function::new).collect(),
+        }
+    }
+}
+
+impl Code {
+    pub fn new(proto: &FunctionPrototype, pc: usize) -> Self {
+        let instr = &proto.code[pc];
+        let kind = match proto.is_setlist_batch(pc) {
+            true => CodeKind::SetlistBatch,
+            false if proto.is_pseudo_instruction(pc) => CodeKind::ClosureUpvalue,
+            false => CodeKind::Instruction,
+        };
+        let opcode = instr
+            .opcode()
+            .ok()
+            .filter(|_| kind != CodeKind::SetlistBatch);
+        let is_jump =
+            opcode.is_some_and(|op| LUA51_OPCODES.modes[op as usize].0 == InstructionFormat::IAsBx);
+
+        Self {
+            pc,
+            line: proto.debug_info.lineinfo.get(pc).copied(),
+            raw: instr.raw(),
+            kind,
+            opcode: opcode.map(|op| LUA51_OPCODES.names[op as usize]),
+            operands: opcode.and_then(|_| DecodedOp::decode(instr).ok()),
+            target: is_jump.then(|| pc as i64 + 1 + i64::from(instr.sbx())),
+        }
+    }
+}
+
+impl Serialize for Constant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match self {
+            Constant::Nil => ConstantRepr::Nil,
+            Constant::Boolean(value) => ConstantRepr::Boolean { value: *value },
+            Constant::Number(value) => ConstantRepr::Number { value: *value },
+            Constant::Integer(value) => ConstantRepr::Integer { value: *value },
+            Constant::String(bytes) => ConstantRepr::String {
+                value: String::from_utf8_lossy(bytes),
+                hex: std::str::from_utf8(bytes).is_err().then(|| hex(bytes)),
+            },
+        };
+        repr.serialize(serializer)
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Serializes a name kept as raw bytes as a lossy string
+pub fn lossy<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    String::from_utf8_lossy(bytes).serialize(serializer)
+}
+
+/// Serializes an optional name kept as raw bytes as a lossy string or null
+pub fn lossy_option<S: Serializer>(
+    bytes: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    bytes
+        .as_deref()
+        .map(String::from_utf8_lossy)
+        .serialize(serializer)
+}
+
+/// Serializes a list of names kept as raw bytes as lossy strings
+pub fn lossy_all<S: Serializer>(names: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(names.iter().map(|name| String::from_utf8_lossy(name)))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        let _ = write!(out, "{b:02x}");
+    }
+    out
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    /// `disasm --format json` of the listing below, pretty-printed
+    const SNAPSHOT: &str = r#"{
+  "schema": 1,
+  "header": {
+    "version": "Lua51",
+    "format": 0,
+    "endianness": "Little",
+    "size_int": 4,
+    "size_size_t": 8,
+    "size_instruction": 4,
+    "size_number": 8,
+    "size_integer": 0,
+    "integral_flag": false,
+    "main_upvalues": 0
+  },
+  "main": {
+    "source": "@json.lua",
+    "line_defined": 0,
+    "last_line_defined": 0,
+    "num_upvalues": 0,
+    "num_params": 0,
+    "is_vararg": 0,
+    "max_stack_size": 4,
+    "code": [
+      {
+        "pc": 0,
+        "line": 1,
+        "raw": 10,
+        "kind": "instruction",
+        "opcode": "NEWTABLE",
+        "operands": {
+          "op": "NewTable",
+          "dst": 0,
+          "array": 0,
+          "hash": 0
+        },
+        "target": null
+      },
+      {
+        "pc": 1,
+        "line": 1,
+        "raw": 16449,
+        "kind": "instruction",
+        "opcode": "LOADK",
+        "operands": {
+          "op": "LoadK",
+          "dst": 1,
+          "k": 1
+        },
+        "target": null
+      },
+      {
+        "pc": 2,
+        "line": 1,
+        "raw": 8388642,
+        "kind": "instruction",
+        "opcode": "SETLIST",
+        "operands": {
+          "op": "SetList",
+          "table": 0,
+          "count": 1,
+          "batch": 0
+        },
+        "target": null
+      },
+      {
+        "pc": 3,
+        "line": 1,
+        "raw": 300,
+        "kind": "setlist_batch",
+        "opcode": null,
+        "operands": null,
+        "target": null
+      },
+      {
+        "pc": 4,
+        "line": 2,
+        "raw": 100,
+        "kind": "instruction",
+        "opcode": "CLOSURE",
+        "operands": {
+          "op": "Closure",
+          "dst": 1,
+          "proto": 0
+        },
+        "target": null
+      },
+      {
+        "pc": 5,
+        "line": 2,
+        "raw": 0,
+        "kind": "closure_upvalue",
+        "opcode": "MOVE",
+        "operands": {
+          "op": "Move",
+          "dst": 0,
+          "src": 0
+        },
+        "target": null
+      },
+      {
+        "pc": 6,
+        "line": 2,
+        "raw": 4227159,
+        "kind": "instruction",
+        "opcode": "EQ",
+        "operands": {
+          "op": "Eq",
+          "expect": true,
+          "lhs": {
+            "register": 0
+          },
+          "rhs": {
+            "constant": 2
+          }
+        },
+        "target": null
+      },
+      {
+        "pc": 7,
+        "line": 2,
+        "raw": 2147483670,
+        "kind": "instruction",
+        "opcode": "JMP",
+        "operands": {
+          "op": "Jmp",
+          "offset": 1
+        },
+        "target": 9
+      },
+      {
+        "pc": 8,
+        "line": 2,
+        "raw": 71,
+        "kind": "instruction",
+        "opcode": "SETGLOBAL",
+        "operands": {
+          "op": "SetGlobal",
+          "src": 1,
+          "k": 0
+        },
+        "target": null
+      },
+      {
+        "pc": 9,
+        "line": 2,
+        "raw": 8388638,
+        "kind": "instruction",
+        "opcode": "RETURN",
+        "operands": {
+          "op": "Return",
+          "base": 0,
+          "count": 0
+        },
+        "target": null
+      }
+    ],
+    "constants": [
+      {
+        "type": "string",
+        "value": "x�",
+        "hex": "78ff"
+      },
+      {
+        "type": "integer",
+        "value": 2
+      },
+      {
+        "type": "number",
+        "value": 0.5
+      },
+      {
+        "type": "boolean",
+        "value": true
+      }
+    ],
+    "locals": [
+      {
+        "varname": "t",
+        "startpc": 3,
+        "endpc": 7
+      }
+    ],
+    "upvalues": [],
+    "functions": [
+      {
+        "source": "",
+        "line_defined": 0,
+        "last_line_defined": 0,
+        "num_upvalues": 1,
+        "num_params": 0,
+        "is_vararg": 0,
+        "max_stack_size": 2,
+        "code": [
+          {
+            "pc": 0,
+            "line": null,
+            "raw": 8388638,
+            "kind": "instruction",
+            "opcode": "RETURN",
+            "operands": {
+              "op": "Return",
+              "base": 0,
+              "count": 0
+            },
+            "target": null
+          }
+        ],
+        "constants": [],
+        "locals": [],
+        "upvalues": [
+          "t"
+        ],
+        "functions": []
+      }
+    ]
+  }
+}"#;
+
+    #[test]
+    fn matches_snapshot() {
+        let main = assemble(
+            r#".source "@json.lua"
+.stack 4
+.const "x\255"
+.const 2
+.const 0.5
+.const true
+.local "t" 3 7
+.func
+    .upvals 1 "t"
+    RETURN    0 1
+.end
+.line 1
+    NEWTABLE  0 0 0
+    LOADK     1 K1
+    SETLIST   0 1 0
+    .word 300
+.line 2
+    CLOSURE   1 0
+    MOVE      0 0
+    EQ        1 0 K2
+    JMP       L9
+    SETGLOBAL 1 K0
+L9:
+    RETURN    0 1
+"#,
+        )
+        .expect("valid listing");
+        let header = Header::lua51();
+        let json = serde_json::to_string_pretty(&Chunk::new(&header, &main)).unwrap();
+        assert_eq!(json, SNAPSHOT);
+    }
+}
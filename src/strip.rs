@@ -0,0 +1,186 @@
+/*
+  Removal of debug information from function prototypes, as `luac -s` does, with
+  a choice of what to keep
+*/
+
+use crate::parser::bytecode::{FunctionPrototype, Header};
+use crate::writer::{WriteError, write_lua_bytecode};
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// What `strip_with` removes from every function, each field `true` to drop that part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StripOptions {
+    pub lines: bool,    // Line info, which error messages and tracebacks point at
+    pub locals: bool,   // Local names and their pc ranges
+    pub upvalues: bool, // Upvalue names
+    pub source: bool,   // Source names, shown as "?" in place of the file when dropped
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl StripOptions {
+    /// Everything, like `luac -s`
+    pub const ALL: Self = Self {
+        lines: true,
+        locals: true,
+        upvalues: true,
+        source: true,
+    };
+
+    /// Names only, keeping the file and line of error messages and tracebacks
+    pub const KEEP_LINES: Self = Self {
+        lines: false,
+        source: false,
+        ..Self::ALL
+    };
+}
+
+impl Default for StripOptions {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Drops the source name, line info, local names and upvalue names of a function and
+/// all of its children. The writer dumps the result like ldump.c with `strip` set
+pub fn strip(proto: &mut FunctionPrototype) {
+    strip_with(proto, StripOptions::ALL);
+}
+
+/// Drops the parts of the debug information `options` selects from a function and all
+/// of its children
+pub fn strip_with(proto: &mut FunctionPrototype, options: StripOptions) {
+    let debug = &mut proto.debug_info;
+    if options.lines {
+        debug.lineinfo.clear();
+        debug.abslineinfo.clear();
+    }
+    if options.locals {
+        debug.locals.clear();
+    }
+    if options.upvalues {
+        debug.upvalues.clear();
+    }
+    if options.source {
+        proto.source_name = None;
+    }
+
+    for child in &mut proto.prototypes {
+        strip_with(child, options);
+    }
+}
+
+/// Strips a parsed chunk and dumps it again
+pub fn strip_chunk(
+    header: &Header,
+    mut proto: FunctionPrototype,
+    options: StripOptions,
+) -> Result<Vec<u8>, WriteError> {
+    strip_with(&mut proto, options);
+    write_lua_bytecode(header, &proto)
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::parser::parse_lua_bytecode;
+
+    /// `local function f(a) return f end` with every kind of debug information, in the
+    /// main function and its child
+    const LISTING: &str = r#".source "@s.lua"
+.vararg 2
+.local "f" 1 3
+.func
+    .linedefined 1
+    .lastlinedefined 3
+    .params 1
+    .upvals 1 "f"
+    .local "a" 0 2
+    .line 2
+        GETUPVAL  1 0
+        RETURN    1 2
+.end
+.line 1
+    CLOSURE   0 0
+    MOVE      0 0
+.line 3
+    RETURN    0 1
+"#;
+
+    fn ints(out: &mut Vec<u8>, values: &[u32]) {
+        for value in values {
+            out.extend(value.to_le_bytes());
+        }
+    }
+
+    fn stripped(options: StripOptions) -> FunctionPrototype {
+        let main = assemble(LISTING).expect("valid listing");
+        let bytecode = strip_chunk(&Header::lua51(), main, options).expect("writable chunk");
+        let (_, main) = parse_lua_bytecode(&bytecode).expect("valid chunk");
+        main
+    }
+
+    #[test]
+    fn matches_luac_s() {
+        // What `luac -s` writes on x86-64: NULL sources and empty debug sections
+        let mut expected = b"\x1bLua\x51\x00\x01\x04\x08\x04\x08\x00".to_vec();
+        ints(&mut expected, &[0, 0, 0, 0]);
+        expected.extend([0, 0, 2, 2]);
+        ints(
+            &mut expected,
+            &[3, 0x0000_0024, 0x0000_0000, 0x0080_001e, 0, 1],
+        );
+        ints(&mut expected, &[0, 0, 1, 3]);
+        expected.extend([1, 1, 0, 2]);
+        ints(&mut expected, &[2, 0x0000_0044, 0x0100_005e]);
+        ints(&mut expected, &[0, 0, 0, 0, 0, 0, 0, 0]);
+
+        let main = assemble(LISTING).expect("valid listing");
+        let bytecode = strip_chunk(&Header::lua51(), main, StripOptions::ALL);
+        assert_eq!(bytecode, Ok(expected));
+    }
+
+    #[test]
+    fn strips_every_function() {
+        let main = stripped(StripOptions::ALL);
+        for proto in [&main, &main.prototypes[0]] {
+            assert_eq!(proto.source_name, None);
+            assert!(proto.debug_info.lineinfo.is_empty());
+            assert!(proto.debug_info.locals.is_empty());
+            assert!(proto.debug_info.upvalues.is_empty());
+        }
+        assert_eq!(main.prototypes[0].num_upvalues, 1);
+    }
+
+    #[test]
+    fn keeps_lines() {
+        let main = stripped(StripOptions::KEEP_LINES);
+        assert_eq!(main.source_name.as_deref(), Some(&b"@s.lua"[..]));
+        assert_eq!(main.debug_info.lineinfo, [1, 1, 3]);
+        let f = &main.prototypes[0];
+        assert_eq!(f.debug_info.lineinfo, [2, 2]);
+        assert!(f.debug_info.locals.is_empty());
+        assert!(f.debug_info.upvalues.is_empty());
+    }
+
+    #[test]
+    fn keeps_names() {
+        let main = stripped(StripOptions {
+            locals: false,
+            upvalues: false,
+            ..StripOptions::ALL
+        });
+        assert_eq!(main.source_name, None);
+        assert!(main.debug_info.lineinfo.is_empty());
+        assert_eq!(main.debug_info.locals[0].varname, b"f");
+        let f = &main.prototypes[0];
+        assert_eq!(f.debug_info.locals[0].varname, b"a");
+        assert_eq!(f.debug_info.upvalues, [b"f"]);
+    }
+}
@@ -0,0 +1,443 @@
+/*
+  Serializes a Lua 5.1 header and function prototype back into a luac dump (ldump.c)
+*/
+
+use crate::parser::bytecode::{Constant, Endianness, FunctionPrototype, Header, Version};
+use std::fmt;
+
+//////////////////////////////// Variables ////////////////////////////////
+
+const MAGIC_NUMBER: &[u8] = b"\x1BLua";
+
+// lua.h:73, constant tags
+const TAG_NIL: u8 = 0;
+const TAG_BOOLEAN: u8 = 1;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+
+//////////////////////////////// Structs ////////////////////////////////
+
+/// Error returned by `write_lua_bytecode`
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteError {
+    UnsupportedVersion(Version),
+    InvalidSize { field: &'static str, value: u8 },
+    ValueTooLarge(&'static str),
+    InexactNumber(f64), // A float constant in a chunk whose numbers are integers
+}
+
+/// Output buffer of a dump, laid out as described by its header
+struct Writer<'a> {
+    header: &'a Header,
+    out: Vec<u8>,
+}
+
+//////////////////////////////// Implementations ////////////////////////////////
+
+impl<'a> Writer<'a> {
+    fn new(header: &'a Header) -> Self {
+        Self {
+            header,
+            out: Vec::new(),
+        }
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.out.push(value);
+    }
+
+    /// Writes an unsigned 4 or 8 byte word with the header's endianness
+    fn word(&mut self, field: &'static str, width: u8, value: u64) -> Result<(), WriteError> {
+        match (width, self.header.endianness) {
+            (4, Endianness::Big) => self.out.extend((value as u32).to_be_bytes()),
+            (4, Endianness::Little) => self.out.extend((value as u32).to_le_bytes()),
+            (8, Endianness::Big) => self.out.extend(value.to_be_bytes()),
+            (8, Endianness::Little) => self.out.extend(value.to_le_bytes()),
+            (value, _) => return Err(WriteError::InvalidSize { field, value }),
+        }
+        Ok(())
+    }
+
+    /// Writes a signed value of `width` bytes, failing when it does not fit
+    fn signed(&mut self, field: &'static str, width: u8, value: i64) -> Result<(), WriteError> {
+        if width == 4 && i32::try_from(value).is_err() {
+            return Err(WriteError::ValueTooLarge(field));
+        }
+        self.word(field, width, value as u64)
+    }
+
+    /// Writes a C `int` of `size_int` bytes (ldump.c:DumpInt)
+    fn integer(&mut self, field: &'static str, value: i64) -> Result<(), WriteError> {
+        // lundump.c reads ints back through a C int, so wider values are rejected either way
+        if i32::try_from(value).is_err() {
+            return Err(WriteError::ValueTooLarge(field));
+        }
+        self.word(field, self.header.size_int, value as u64)
+    }
+
+    /// Writes the element count of a section
+    fn count(&mut self, field: &'static str, len: usize) -> Result<(), WriteError> {
+        let len = i64::try_from(len).map_err(|_| WriteError::ValueTooLarge(field))?;
+        self.integer(field, len)
+    }
+
+    /// Writes a size_t length, its bytes and a null terminator, with a length of 0
+    /// standing for a NULL string (ldump.c:DumpString)
+    fn string(&mut self, bytes: Option<&[u8]>) -> Result<(), WriteError> {
+        let size_size_t = self.header.size_size_t;
+        let Some(bytes) = bytes else {
+            return self.word("size_t", size_size_t, 0);
+        };
+        let len =
+            u64::try_from(bytes.len() + 1).map_err(|_| WriteError::ValueTooLarge("size_t"))?;
+        if size_size_t == 4 && u32::try_from(len).is_err() {
+            return Err(WriteError::ValueTooLarge("size_t"));
+        }
+        self.word("size_t", size_size_t, len)?;
+        self.out.extend(bytes);
+        self.byte(0);
+        Ok(())
+    }
+
+    /// Writes a lua_Number from a float, which integral builds need to be a whole number
+    fn number(&mut self, value: f64) -> Result<(), WriteError> {
+        if !self.header.integral_flag {
+            return self.float(value);
+        }
+        let integer = value as i64;
+        if integer as f64 != value {
+            return Err(WriteError::InexactNumber(value));
+        }
+        self.signed("number", self.header.size_number, integer)
+    }
+
+    /// Writes a lua_Number from an integer, which float builds convert
+    fn number_integer(&mut self, value: i64) -> Result<(), WriteError> {
+        match self.header.integral_flag {
+            true => self.signed("number", self.header.size_number, value),
+            false => self.float(value as f64),
+        }
+    }
+
+    /// Writes a float of `size_number` bytes
+    fn float(&mut self, value: f64) -> Result<(), WriteError> {
+        let bits = match self.header.size_number {
+            4 => u64::from((value as f32).to_bits()),
+            _ => value.to_bits(),
+        };
+        self.word("number", self.header.size_number, bits)
+    }
+
+    /// ldump.c:DumpConstants
+    fn constant(&mut self, constant: &Constant) -> Result<(), WriteError> {
+        match constant {
+            Constant::Nil => self.byte(TAG_NIL),
+            Constant::Boolean(value) => {
+                self.byte(TAG_BOOLEAN);
+                self.byte(u8::from(*value));
+            }
+            Constant::Number(value) => {
+                self.byte(TAG_NUMBER);
+                self.number(*value)?;
+            }
+            Constant::Integer(value) => {
+                self.byte(TAG_NUMBER);
+                self.number_integer(*value)?;
+            }
+            Constant::String(bytes) => {
+                self.byte(TAG_STRING);
+                self.string(Some(bytes))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// ldump.c:DumpHeader
+    fn header(&mut self) {
+        let header = self.header;
+        self.out.extend(MAGIC_NUMBER);
+        self.byte(header.version as u8);
+        self.byte(header.format);
+        self.byte(match header.endianness {
+            Endianness::Big => 0,
+            Endianness::Little => 1,
+        });
+        self.byte(header.size_int);
+        self.byte(header.size_size_t);
+        self.byte(header.size_instruction);
+        self.byte(header.size_number);
+        self.byte(u8::from(header.integral_flag));
+    }
+
+    /// ldump.c:DumpFunction. Nested functions sharing their parent's source, and stripped
+    /// ones, have a NULL source, which stays distinct from an empty one
+    fn function(&mut self, proto: &FunctionPrototype) -> Result<(), WriteError> {
+        self.string(proto.source_name.as_deref())?;
+        self.integer("line_defined", i64::from(proto.line_defined))?;
+        self.integer("last_line_defined", i64::from(proto.last_line_defined))?;
+        self.byte(proto.num_upvalues);
+        self.byte(proto.num_params);
+        self.byte(proto.is_vararg);
+        self.byte(proto.max_stack_size);
+
+        self.count("code", proto.code.len())?;
+        for instr in &proto.code {
+            self.word(
+                "instruction",
+                self.header.size_instruction,
+                instr.raw().into(),
+            )?;
+        }
+
+        self.count("constants", proto.constants.len())?;
+        for constant in &proto.constants {
+            self.constant(constant)?;
+        }
+
+        self.count("proto", proto.prototypes.len())?;
+        for child in &proto.prototypes {
+            self.function(child)?;
+        }
+
+        // Lines and pcs are kept unsigned, but are C ints in the dump
+        let debug_info = &proto.debug_info;
+        self.count("lineinfo", debug_info.lineinfo.len())?;
+        for &line in &debug_info.lineinfo {
+            self.integer("lineinfo", i64::from(line as i32))?;
+        }
+        self.count("locals", debug_info.locals.len())?;
+        for local in &debug_info.locals {
+            self.string(Some(&local.varname))?;
+            self.integer("startpc", i64::from(local.startpc as i32))?;
+            self.integer("endpc", i64::from(local.endpc as i32))?;
+        }
+        self.count("upvalues", debug_info.upvalues.len())?;
+        for name in &debug_info.upvalues {
+            self.string(Some(name))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteError::UnsupportedVersion(version) => {
+                write!(f, "cannot write Lua version 0x{:02x}", *version as u8)
+            }
+            WriteError::InvalidSize { field, value } => {
+                write!(f, "invalid {field} size {value}")
+            }
+            WriteError::ValueTooLarge(field) => write!(f, "{field} value is too large"),
+            WriteError::InexactNumber(value) => {
+                write!(f, "number {value} cannot be stored in an integral chunk")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WriteError {}
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+/// Serializes a Lua 5.1 chunk. Dumps parsed by `parse_lua_bytecode` are written back
+/// byte for byte
+pub fn write_lua_bytecode(
+    header: &Header,
+    proto: &FunctionPrototype,
+) -> Result<Vec<u8>, WriteError> {
+    if header.version != Version::Lua51 {
+        return Err(WriteError::UnsupportedVersion(header.version));
+    }
+
+    let mut writer = Writer::new(header);
+    writer.header();
+    writer.function(proto)?;
+    Ok(writer.out)
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_lua_bytecode;
+
+    /// Layout of a Lua 5.1 dump, as given by its header
+    struct Layout {
+        little: bool,
+        size_size_t: u8,
+        size_number: u8,
+    }
+
+    const X86_64: Layout = Layout {
+        little: true,
+        size_size_t: 8,
+        size_number: 8,
+    };
+
+    impl Layout {
+        fn word(&self, out: &mut Vec<u8>, width: u8, value: u64) {
+            let bytes = match self.little {
+                true => value.to_le_bytes(),
+                false => value.to_be_bytes(),
+            };
+            match (width, self.little) {
+                (4, true) => out.extend(&bytes[..4]),
+                (4, false) => out.extend(&bytes[4..]),
+                _ => out.extend(bytes),
+            }
+        }
+
+        fn int(&self, out: &mut Vec<u8>, value: u32) {
+            self.word(out, 4, value.into());
+        }
+
+        fn string(&self, out: &mut Vec<u8>, bytes: Option<&[u8]>) {
+            let Some(bytes) = bytes else {
+                return self.word(out, self.size_size_t, 0);
+            };
+            self.word(out, self.size_size_t, bytes.len() as u64 + 1);
+            out.extend(bytes);
+            out.push(0);
+        }
+
+        fn words(&self, out: &mut Vec<u8>, words: &[u32]) {
+            self.int(out, words.len() as u32);
+            for &word in words {
+                self.int(out, word);
+            }
+        }
+
+        /// A main function with every constant type, a closure sharing its source, and
+        /// source, local and upvalue names that are not valid UTF-8
+        fn chunk(&self, child_source: Option<&[u8]>) -> Vec<u8> {
+            let mut out = b"\x1bLua\x51\x00".to_vec();
+            out.push(u8::from(self.little));
+            out.extend([4, self.size_size_t, 4, self.size_number, 0]);
+
+            self.string(&mut out, Some(b"@\xffsource.lua"));
+            self.int(&mut out, 0);
+            self.int(&mut out, 0);
+            out.extend([0, 0, 2, 2]);
+            // LOADK 0 K3, CLOSURE 1 0, MOVE 0 0, RETURN 0 1
+            self.words(
+                &mut out,
+                &[0x0000_00c1, 0x0000_0064, 0x0000_0000, 0x0080_001e],
+            );
+            self.int(&mut out, 4);
+            out.push(TAG_NIL);
+            out.extend([TAG_BOOLEAN, 1]);
+            out.push(TAG_NUMBER);
+            match self.size_number {
+                4 => self.word(&mut out, 4, 1.5f32.to_bits().into()),
+                _ => self.word(&mut out, 8, 1.5f64.to_bits()),
+            }
+            out.push(TAG_STRING);
+            self.string(&mut out, Some(b"\xfe\x00a"));
+
+            self.int(&mut out, 1);
+            self.string(&mut out, child_source);
+            self.int(&mut out, 1);
+            self.int(&mut out, 3);
+            out.extend([1, 0, 0, 2]);
+            // GETUPVAL 0 0, RETURN 0 2
+            self.words(&mut out, &[0x0000_0004, 0x0100_001e]);
+            self.int(&mut out, 0);
+            self.int(&mut out, 0);
+            self.words(&mut out, &[2, 3]);
+            self.int(&mut out, 0);
+            self.int(&mut out, 1);
+            self.string(&mut out, Some(b"up\xff"));
+
+            self.words(&mut out, &[1, 1, 1, 4]);
+            self.int(&mut out, 1);
+            self.string(&mut out, Some(b"l\xc3"));
+            self.int(&mut out, 1);
+            self.int(&mut out, 4);
+            self.int(&mut out, 0);
+            out
+        }
+    }
+
+    fn round_trip(input: &[u8]) {
+        let (header, main) = parse_lua_bytecode(input).expect("valid chunk");
+        let output = write_lua_bytecode(&header, &main).expect("writable chunk");
+        assert_eq!(output, input);
+
+        let (_, reparsed) = parse_lua_bytecode(&output).expect("valid chunk");
+        assert_eq!(format!("{reparsed:?}"), format!("{main:?}"));
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        round_trip(&X86_64.chunk(None));
+    }
+
+    #[test]
+    fn round_trips_big_endian() {
+        round_trip(
+            &Layout {
+                little: false,
+                ..X86_64
+            }
+            .chunk(None),
+        );
+    }
+
+    #[test]
+    fn round_trips_4_byte_size_t() {
+        round_trip(
+            &Layout {
+                size_size_t: 4,
+                ..X86_64
+            }
+            .chunk(None),
+        );
+    }
+
+    #[test]
+    fn round_trips_float_numbers() {
+        let input = Layout {
+            size_number: 4,
+            ..X86_64
+        }
+        .chunk(None);
+        round_trip(&input);
+        let (_, main) = parse_lua_bytecode(&input).expect("valid chunk");
+        assert!(matches!(main.constants[2], Constant::Number(n) if n == 1.5));
+    }
+
+    #[test]
+    fn keeps_empty_source_apart_from_null() {
+        let null = X86_64.chunk(None);
+        let empty = X86_64.chunk(Some(b""));
+        assert_ne!(null, empty);
+
+        let (_, main) = parse_lua_bytecode(&null).expect("valid chunk");
+        assert_eq!(main.prototypes[0].source_name, None);
+        let (_, main) = parse_lua_bytecode(&empty).expect("valid chunk");
+        assert_eq!(main.prototypes[0].source_name.as_deref(), Some(&b""[..]));
+        round_trip(&empty);
+    }
+
+    #[test]
+    fn keeps_names_as_raw_bytes() {
+        let (_, main) = parse_lua_bytecode(&X86_64.chunk(None)).expect("valid chunk");
+        assert_eq!(main.source_name.as_deref(), Some(&b"@\xffsource.lua"[..]));
+        assert_eq!(main.debug_info.locals[0].varname, b"l\xc3");
+        assert_eq!(main.prototypes[0].debug_info.upvalues, [b"up\xff"]);
+        assert!(matches!(&main.constants[3], Constant::String(bytes) if bytes == b"\xfe\x00a"));
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let (mut header, main) = parse_lua_bytecode(&X86_64.chunk(None)).expect("valid chunk");
+        header.version = Version::Lua54;
+        assert_eq!(
+            write_lua_bytecode(&header, &main),
+            Err(WriteError::UnsupportedVersion(Version::Lua54))
+        );
+    }
+}
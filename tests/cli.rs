@@ -0,0 +1,185 @@
+/*
+  Runs the command-line tool on chunks built with its own `assemble` subcommand,
+  checking output and exit codes
+*/
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+//////////////////////////////// Variables ////////////////////////////////
+
+const HELLO: &str = r#".source "@hello.lua"
+.vararg 2
+.const "print"
+.const "Hello, world!"
+.line 1
+    GETGLOBAL 0 K0
+    LOADK     1 K1
+    CALL      0 2 1
+    RETURN    0 1
+"#;
+
+// The LOADK reads a constant that does not exist
+const INVALID: &str = r#".vararg 2
+.const "print"
+    GETGLOBAL 0 K0
+    LOADK     1 K5
+    CALL      0 2 1
+    RETURN    0 1
+"#;
+
+// A closure with a source, lines, and local and upvalue names
+const DEBUG_INFO: &str = r#".source "@s.lua"
+.vararg 2
+.local "f" 1 3
+.func
+    .params 1
+    .upvals 1 "f"
+    .local "a" 0 2
+    .line 2
+        GETUPVAL  1 0
+        RETURN    1 2
+.end
+.line 1
+    CLOSURE   0 0
+    MOVE      0 0
+.line 3
+    RETURN    0 1
+"#;
+
+//////////////////////////////// Helpers ////////////////////////////////
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rluadecomp"))
+        .args(args)
+        .output()
+        .expect("runnable binary")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+/// Assembles `listing` into `<name>.luac`, returning its path
+fn assemble(name: &str, listing: &str) -> String {
+    let source = path(&format!("{name}.s"));
+    let chunk = path(&format!("{name}.luac"));
+    std::fs::write(&source, listing).expect("writable listing");
+    let output = run(&[
+        "assemble",
+        source.to_str().unwrap(),
+        "-o",
+        chunk.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{output:?}");
+    chunk.to_str().unwrap().to_string()
+}
+
+//////////////////////////////// Tests ////////////////////////////////
+
+#[test]
+fn decompiles() {
+    let chunk = assemble("decompile", HELLO);
+    let output = run(&["decompile", &chunk]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "print(\"Hello, world!\")\n");
+}
+
+#[test]
+fn decompile_rejects_invalid_bytecode() {
+    let chunk = assemble("decompile_invalid", INVALID);
+    let output = run(&["decompile", &chunk]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("invalid bytecode"));
+}
+
+#[test]
+fn verifies() {
+    let chunk = assemble("verify", HELLO);
+    let output = run(&["verify", &chunk]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "");
+
+    let chunk = assemble("verify_invalid", INVALID);
+    let output = run(&["verify", &chunk]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout(&output).ends_with("main at pc 1: Bx constant 5 is out of range\n"));
+}
+
+#[test]
+fn diffs() {
+    let old = assemble("diff_old", HELLO);
+    let output = run(&["diff", &old, &old]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "");
+
+    let new = assemble("diff_new", &HELLO.replace("world", "there"));
+    let output = run(&["diff", &old, &new]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        "main: constant K1 String(\"Hello, world!\") != String(\"Hello, there!\")\n"
+    );
+}
+
+#[test]
+fn lists_strings() {
+    let chunk = assemble("strings", HELLO);
+    let output = run(&["strings", &chunk]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stdout(&output),
+        "main K0 \"print\"\nmain K1 \"Hello, world!\"\n"
+    );
+}
+
+#[test]
+fn fails_on_unreadable_input() {
+    let garbage = path("garbage.luac");
+    std::fs::write(&garbage, "not bytecode").unwrap();
+    let output = run(&["decompile", garbage.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+
+    let missing = path("missing.luac");
+    let output = run(&["verify", missing.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn strips_what_is_not_kept() {
+    let chunk = assemble("strip", DEBUG_INFO);
+    let debug_lines = [
+        ".source \"@s.lua\"",
+        ".line 1",
+        "    .line 2",
+        ".local \"f\" 1 3",
+        "    .local \"a\" 0 2",
+        "    .upvals 1 \"f\"",
+    ];
+    let cases: [(&[&str], &[&str]); 5] = [
+        (&[], &[]),
+        (
+            &["--keep-lines"],
+            &[debug_lines[0], debug_lines[1], debug_lines[2]],
+        ),
+        (&["--keep-source"], &[debug_lines[0]]),
+        (&["--keep-locals"], &[debug_lines[3], debug_lines[4]]),
+        (&["--keep-upvalues"], &[debug_lines[5]]),
+    ];
+    for (index, (flags, kept)) in cases.into_iter().enumerate() {
+        let stripped = path(&format!("stripped{index}.luac"));
+        let stripped = stripped.to_str().unwrap();
+        let output = run(&[&["strip", &chunk, "-o", stripped], flags].concat());
+        assert_eq!(output.status.code(), Some(0), "{output:?}");
+
+        let listing = stdout(&run(&["disasm", "--format", "asm", stripped]));
+        for line in debug_lines {
+            let present = listing.lines().any(|l| l == line);
+            assert_eq!(present, kept.contains(&line), "{flags:?} {line}");
+        }
+    }
+}